@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use crate::Backtest::BacktestConfig::CBacktestConfig;
+use crate::Backtest::Broker::{CAccount, CBarQuote, CFill, COrder, CTrade};
+use crate::Backtest::Metrics::{cal_drawdown, CBacktestMetrics};
+use crate::Backtest::Strategy::{CStrategyContext, Strategy};
+use crate::Chan::CChan;
+use crate::Common::CEnum::KlType;
+use crate::Common::CTime::CTime;
+use crate::Common::ChanException::{CChanException, ErrCode};
+use crate::KLine::KLine_Unit::CKLineUnit;
+
+#[derive(Clone, Debug)]
+pub struct CEquityPoint {
+    pub idx: i32,
+    pub time: CTime,
+    pub cash: f64,
+    pub market_value: f64,
+    pub equity: f64,
+    pub drawdown: f64,
+}
+
+pub struct CBacktestResult {
+    pub orders: Vec<COrder>,
+    pub fills: Vec<CFill>,
+    pub trades: Vec<CTrade>,
+    pub equity_curve: Vec<CEquityPoint>,
+    pub metrics: CBacktestMetrics,
+}
+
+// 逐根K线回放：先把K线喂给 CChan，再用这根K线撮合上一根K线收盘后产生的委托，
+// 最后把截至这根K线的买卖点交给策略生成新委托。不依赖随机数和时钟，结果可复现
+pub struct CBacktest<S: Strategy> {
+    pub config: CBacktestConfig,
+    pub strategy: S,
+}
+
+impl<S: Strategy> CBacktest<S> {
+    pub fn new(config: CBacktestConfig, strategy: S) -> Self {
+        CBacktest { config, strategy }
+    }
+
+    // chan 需以 trigger_step=true 创建，lv 为其最高级别；klu_lst 按时间顺序
+    pub fn run(
+        &mut self,
+        chan: &mut CChan,
+        lv: KlType,
+        klu_lst: Vec<CKLineUnit>,
+    ) -> Result<CBacktestResult, CChanException> {
+        let mut account = CAccount::new(self.config.clone());
+        let mut equity_curve = Vec::new();
+
+        for klu in klu_lst {
+            chan.trigger_load(HashMap::from([(lv, vec![klu])]))?;
+            let kl_list = chan.get(lv).ok_or_else(|| {
+                CChanException::new(format!("{}级别没有数据", lv), ErrCode::NoData)
+            })?;
+            let bar = match kl_list.lst.last() {
+                Some(klc) => CBarQuote::from(&*klc.borrow().lst.last().unwrap().borrow()),
+                None => continue,
+            };
+
+            account.match_bar(&bar);
+            let orders = self
+                .strategy
+                .on_bar(&CStrategyContext::new(kl_list, &bar, &account));
+            for order in orders {
+                account.submit(order, &bar);
+            }
+
+            equity_curve.push(CEquityPoint {
+                idx: bar.idx,
+                time: bar.time.clone(),
+                cash: account.cash,
+                market_value: account.volume * bar.close,
+                equity: account.equity(bar.close),
+                drawdown: 0.0,
+            });
+        }
+
+        let equity: Vec<f64> = equity_curve.iter().map(|p| p.equity).collect();
+        for (point, dd) in equity_curve.iter_mut().zip(cal_drawdown(&equity)) {
+            point.drawdown = dd;
+        }
+        // 期末未平仓的持仓按收盘价计入净值，但不算作已完成交易
+        let trade_pnl: Vec<f64> = account.trades.iter().map(|t| t.pnl).collect();
+        let metrics = CBacktestMetrics::new(&equity, &trade_pnl, self.config.annual_bar_cnt);
+
+        Ok(CBacktestResult {
+            orders: account.orders,
+            fills: account.fills,
+            trades: account.trades,
+            equity_curve,
+            metrics,
+        })
+    }
+}
+
+impl CBacktestResult {
+    // 输出 trades.csv / equity.csv / metrics.json
+    pub fn to_csv(&self, directory: &str) -> Result<(), CChanException> {
+        let io_err = |path: &str, e: String| {
+            CChanException::new(format!("write {} fail: {}", path, e), ErrCode::CommonError)
+        };
+        std::fs::create_dir_all(directory).map_err(|e| io_err(directory, e.to_string()))?;
+
+        let path = format!("{}/trades.csv", directory);
+        let mut wtr = csv::Writer::from_path(&path).map_err(|e| io_err(&path, e.to_string()))?;
+        wtr.write_record([
+            "entry_idx",
+            "entry_time",
+            "exit_idx",
+            "exit_time",
+            "entry_price",
+            "exit_price",
+            "volume",
+            "fee",
+            "pnl",
+            "ret",
+        ])
+        .map_err(|e| io_err(&path, e.to_string()))?;
+        for t in &self.trades {
+            wtr.write_record([
+                t.entry_idx.to_string(),
+                t.entry_time.to_string(),
+                t.exit_idx.to_string(),
+                t.exit_time.to_string(),
+                t.entry_price.to_string(),
+                t.exit_price.to_string(),
+                t.volume.to_string(),
+                t.fee.to_string(),
+                t.pnl.to_string(),
+                t.ret.to_string(),
+            ])
+            .map_err(|e| io_err(&path, e.to_string()))?;
+        }
+        wtr.flush().map_err(|e| io_err(&path, e.to_string()))?;
+
+        let path = format!("{}/equity.csv", directory);
+        let mut wtr = csv::Writer::from_path(&path).map_err(|e| io_err(&path, e.to_string()))?;
+        wtr.write_record(["idx", "time", "cash", "market_value", "equity", "drawdown"])
+            .map_err(|e| io_err(&path, e.to_string()))?;
+        for p in &self.equity_curve {
+            wtr.write_record([
+                p.idx.to_string(),
+                p.time.to_string(),
+                p.cash.to_string(),
+                p.market_value.to_string(),
+                p.equity.to_string(),
+                p.drawdown.to_string(),
+            ])
+            .map_err(|e| io_err(&path, e.to_string()))?;
+        }
+        wtr.flush().map_err(|e| io_err(&path, e.to_string()))?;
+
+        let path = format!("{}/metrics.json", directory);
+        std::fs::write(&path, self.metrics.to_json().to_string())
+            .map_err(|e| io_err(&path, e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Backtest::Broker::OrderStatus;
+    use crate::ChanConfig::CChanConfig;
+    use crate::Common::CEnum::{AUTYPE, DATA_SRC};
+    use crate::Common::TradeInfo::CTradeInfo;
+    use crate::Math::Demark::CDemarkIndex;
+    use serde_json::Value;
+
+    // 记录每根K线看到的买卖点事件，有买点事件且空仓时买入，有卖点事件且持仓时卖出
+    #[derive(Default)]
+    struct RecordStrategy {
+        log: Vec<(i32, String, String, String)>,
+        future_event_cnt: usize,
+    }
+
+    impl Strategy for RecordStrategy {
+        fn on_bar(&mut self, ctx: &CStrategyContext) -> Vec<COrder> {
+            for (record, event) in &ctx.bsp_events {
+                if event.klu_idx > ctx.bar.idx || record.klu_idx > ctx.bar.idx {
+                    self.future_event_cnt += 1;
+                }
+                self.log.push((
+                    ctx.bar.idx,
+                    record.id.clone(),
+                    format!("{:?}", event.state),
+                    event.reason.clone(),
+                ));
+            }
+            if ctx.account.has_pending_order() {
+                return Vec::new();
+            }
+            let has_buy = ctx.bsp_events.iter().any(|(r, _)| r.is_buy);
+            let has_sell = ctx.bsp_events.iter().any(|(r, _)| !r.is_buy);
+            if has_buy && ctx.account.volume == 0.0 {
+                let volume = ctx.account.equity(ctx.bar.close) * 0.5 / ctx.bar.close;
+                vec![COrder::market(true, volume, "buy")]
+            } else if has_sell && ctx.account.volume > 0.0 {
+                vec![COrder::market(false, ctx.account.volume, "sell")]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
+    fn klu_lst(n: usize) -> Vec<CKLineUnit> {
+        let mut pre_close = 10.0;
+        (0..n)
+            .map(|i| {
+                let close = 10.0 + 2.0 * (i as f64 * 0.3).sin() + 0.01 * i as f64;
+                let klu = CKLineUnit {
+                    kl_type: None,
+                    time: CTime::new(
+                        2024,
+                        1 + (i / 28) as u32,
+                        1 + (i % 28) as u32,
+                        0,
+                        0,
+                        0,
+                        false,
+                    ),
+                    close,
+                    open: pre_close,
+                    high: close.max(pre_close) + 0.1,
+                    low: close.min(pre_close) - 0.1,
+                    trade_info: CTradeInfo::new(&HashMap::new()),
+                    demark: CDemarkIndex::new(),
+                    sub_kl_list: Vec::new(),
+                    sup_kl: None,
+                    klc: None,
+                    trend: HashMap::new(),
+                    limit_flag: 0,
+                    pre: None,
+                    next: None,
+                    idx: -1,
+                    macd: None,
+                    boll: None,
+                    rsi: None,
+                    kdj: None,
+                    metric: HashMap::new(),
+                };
+                pre_close = close;
+                klu
+            })
+            .collect()
+    }
+
+    fn run_once() -> (CBacktestResult, RecordStrategy) {
+        let conf = HashMap::from([("trigger_step".to_string(), Value::from(true))]);
+        let mut chan = CChan::new(
+            "test".to_string(),
+            None,
+            None,
+            DATA_SRC::CSV,
+            Some(vec![KlType::K_DAY]),
+            Some(CChanConfig::new(Some(conf)).unwrap()),
+            AUTYPE::QFQ,
+        )
+        .unwrap();
+        let mut backtest = CBacktest::new(CBacktestConfig::default(), RecordStrategy::default());
+        let res = backtest
+            .run(&mut chan, KlType::K_DAY, klu_lst(300))
+            .unwrap();
+        (res, backtest.strategy)
+    }
+
+    #[test]
+    fn test_replay_deterministic() {
+        let (res1, strategy1) = run_once();
+        let (res2, strategy2) = run_once();
+
+        // 策略只能看到当前K线及之前发生的事件
+        assert_eq!(strategy1.future_event_cnt, 0);
+        assert!(!strategy1.log.is_empty());
+        assert_eq!(strategy1.log, strategy2.log);
+
+        let orders = |res: &CBacktestResult| {
+            res.orders
+                .iter()
+                .map(|o| (o.id, o.is_buy, o.volume, o.create_idx, o.status.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(orders(&res1), orders(&res2));
+        assert!(res1.orders.iter().any(|o| o.status == OrderStatus::Filled));
+        let equity = |res: &CBacktestResult| {
+            res.equity_curve
+                .iter()
+                .map(|p| (p.idx, p.cash, p.equity, p.drawdown))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(equity(&res1), equity(&res2));
+        assert_eq!(res1.trades.len(), res2.trades.len());
+    }
+}
@@ -0,0 +1,43 @@
+#[derive(Clone, Debug)]
+pub struct CBacktestConfig {
+    pub init_cash: f64,
+    // 佣金按成交额比例收取，不足 min_fee 按 min_fee 收
+    pub fee_rate: f64,
+    pub min_fee: f64,
+    // 印花税，只在卖出时收取
+    pub stamp_tax_rate: f64,
+    // 市价单成交价相对开盘价的滑点比例，买入上浮、卖出下浮
+    pub slippage_rate: f64,
+    // 每手股数，买入按整手成交，卖出时不足一手的零股可以一次卖完
+    pub lot_size: f64,
+    // 当天买入的持仓当天不能卖出
+    pub t_plus_one: bool,
+    // 一年的K线根数，用于年化夏普比率
+    pub annual_bar_cnt: f64,
+}
+
+impl Default for CBacktestConfig {
+    fn default() -> Self {
+        CBacktestConfig {
+            init_cash: 1_000_000.0,
+            fee_rate: 0.0003,
+            min_fee: 5.0,
+            stamp_tax_rate: 0.001,
+            slippage_rate: 0.001,
+            lot_size: 100.0,
+            t_plus_one: true,
+            annual_bar_cnt: 252.0,
+        }
+    }
+}
+
+impl CBacktestConfig {
+    pub fn cal_fee(&self, amount: f64, is_buy: bool) -> f64 {
+        let fee = (amount * self.fee_rate).max(self.min_fee);
+        if is_buy {
+            fee
+        } else {
+            fee + amount * self.stamp_tax_rate
+        }
+    }
+}
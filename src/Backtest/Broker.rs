@@ -0,0 +1,379 @@
+use crate::Backtest::BacktestConfig::CBacktestConfig;
+use crate::Common::CTime::CTime;
+use crate::KLine::KLine_Unit::CKLineUnit;
+
+// 撮合只用到 OHLC，单独抽出来和 CKLineUnit 解耦
+#[derive(Clone, Debug)]
+pub struct CBarQuote {
+    pub idx: i32,
+    pub time: CTime,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+impl From<&CKLineUnit> for CBarQuote {
+    fn from(klu: &CKLineUnit) -> Self {
+        CBarQuote {
+            idx: klu.idx,
+            time: klu.time.clone(),
+            open: klu.open,
+            high: klu.high,
+            low: klu.low,
+            close: klu.close,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderType {
+    // 下一根K线开盘价加滑点成交
+    Market,
+    // 只在下一根K线有效，开盘价更优时按开盘价成交
+    Limit(f64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrderStatus {
+    Pending,
+    Filled,
+    Expired,
+    Rejected(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct COrder {
+    pub id: usize,
+    pub is_buy: bool,
+    // 委托股数，买入按整手向下取整；卖出超过可卖数量时按可卖数量成交
+    pub volume: f64,
+    pub order_type: OrderType,
+    pub create_idx: i32,
+    pub create_time: Option<CTime>,
+    pub reason: String,
+    pub status: OrderStatus,
+}
+
+impl COrder {
+    pub fn market(is_buy: bool, volume: f64, reason: &str) -> Self {
+        COrder {
+            id: 0,
+            is_buy,
+            volume,
+            order_type: OrderType::Market,
+            create_idx: -1,
+            create_time: None,
+            reason: reason.to_string(),
+            status: OrderStatus::Pending,
+        }
+    }
+
+    pub fn limit(is_buy: bool, volume: f64, price: f64, reason: &str) -> Self {
+        COrder {
+            order_type: OrderType::Limit(price),
+            ..COrder::market(is_buy, volume, reason)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CFill {
+    pub order_id: usize,
+    pub is_buy: bool,
+    pub idx: i32,
+    pub time: CTime,
+    pub price: f64,
+    pub volume: f64,
+    pub fee: f64,
+}
+
+// 从空仓开仓到清仓算一笔交易，中间的加减仓都合并进来
+#[derive(Clone, Debug)]
+pub struct CTrade {
+    pub entry_idx: i32,
+    pub entry_time: CTime,
+    pub exit_idx: i32,
+    pub exit_time: CTime,
+    // 买入/卖出成交均价
+    pub entry_price: f64,
+    pub exit_price: f64,
+    pub volume: f64,
+    pub fee: f64,
+    // 扣除费用后的盈亏，ret 为相对买入金额的收益率
+    pub pnl: f64,
+    pub ret: f64,
+}
+
+struct COpenTrade {
+    entry_idx: i32,
+    entry_time: CTime,
+    buy_amount: f64,
+    buy_volume: f64,
+    sell_amount: f64,
+    fee: f64,
+}
+
+// 只做多的模拟账户，委托在下一根K线撮合
+pub struct CAccount {
+    pub config: CBacktestConfig,
+    pub cash: f64,
+    pub volume: f64,
+    // T+1 下当天买入、当天不能卖的数量
+    pub frozen_volume: f64,
+    frozen_date: String,
+    pub orders: Vec<COrder>,
+    pub fills: Vec<CFill>,
+    pub trades: Vec<CTrade>,
+    open_trade: Option<COpenTrade>,
+}
+
+impl CAccount {
+    pub fn new(config: CBacktestConfig) -> Self {
+        CAccount {
+            cash: config.init_cash,
+            config,
+            volume: 0.0,
+            frozen_volume: 0.0,
+            frozen_date: String::new(),
+            orders: Vec::new(),
+            fills: Vec::new(),
+            trades: Vec::new(),
+            open_trade: None,
+        }
+    }
+
+    pub fn sellable_volume(&self) -> f64 {
+        self.volume - self.frozen_volume
+    }
+
+    pub fn equity(&self, price: f64) -> f64 {
+        self.cash + self.volume * price
+    }
+
+    pub fn has_pending_order(&self) -> bool {
+        self.orders.iter().any(|o| o.status == OrderStatus::Pending)
+    }
+
+    pub fn submit(&mut self, mut order: COrder, bar: &CBarQuote) -> usize {
+        order.id = self.orders.len();
+        order.create_idx = bar.idx;
+        order.create_time = Some(bar.time.clone());
+        order.status = OrderStatus::Pending;
+        self.orders.push(order);
+        self.orders.len() - 1
+    }
+
+    // 用 bar 撮合之前K线提交的委托，按提交顺序处理
+    pub fn match_bar(&mut self, bar: &CBarQuote) {
+        let date = bar.time.to_date_str("");
+        if date != self.frozen_date {
+            self.frozen_volume = 0.0;
+        }
+        for i in 0..self.orders.len() {
+            if self.orders[i].status != OrderStatus::Pending || self.orders[i].create_idx >= bar.idx
+            {
+                continue;
+            }
+            let order = self.orders[i].clone();
+            self.orders[i].status = self.match_order(&order, bar, &date);
+        }
+    }
+
+    fn fill_price(&self, order: &COrder, bar: &CBarQuote) -> Option<f64> {
+        match (order.order_type, order.is_buy) {
+            (OrderType::Market, true) => Some(bar.open * (1.0 + self.config.slippage_rate)),
+            (OrderType::Market, false) => Some(bar.open * (1.0 - self.config.slippage_rate)),
+            (OrderType::Limit(price), true) if bar.open <= price => Some(bar.open),
+            (OrderType::Limit(price), true) if bar.low <= price => Some(price),
+            (OrderType::Limit(price), false) if bar.open >= price => Some(bar.open),
+            (OrderType::Limit(price), false) if bar.high >= price => Some(price),
+            _ => None,
+        }
+    }
+
+    fn match_order(&mut self, order: &COrder, bar: &CBarQuote, date: &str) -> OrderStatus {
+        let price = match self.fill_price(order, bar) {
+            Some(price) => price,
+            None => return OrderStatus::Expired,
+        };
+        let lot = self.config.lot_size;
+        let volume = if order.is_buy {
+            let max_volume =
+                (self.cash / (price * (1.0 + self.config.fee_rate)) / lot).floor() * lot;
+            let mut volume = ((order.volume / lot).floor() * lot).min(max_volume);
+            while volume > 0.0
+                && volume * price + self.config.cal_fee(volume * price, true) > self.cash
+            {
+                volume -= lot;
+            }
+            if volume <= 0.0 {
+                return OrderStatus::Rejected("资金不足一手".to_string());
+            }
+            volume
+        } else {
+            let sellable = self.sellable_volume();
+            if sellable <= 0.0 {
+                return OrderStatus::Rejected(if self.volume > 0.0 {
+                    "T+1当天买入不能卖出".to_string()
+                } else {
+                    "没有持仓".to_string()
+                });
+            }
+            // 零股只能一次性卖出
+            let volume = if order.volume >= sellable {
+                sellable
+            } else {
+                (order.volume / lot).floor() * lot
+            };
+            if volume <= 0.0 {
+                return OrderStatus::Rejected("卖出数量不足一手".to_string());
+            }
+            volume
+        };
+        self.fill(order, bar, price, volume, date);
+        OrderStatus::Filled
+    }
+
+    fn fill(&mut self, order: &COrder, bar: &CBarQuote, price: f64, volume: f64, date: &str) {
+        let amount = price * volume;
+        let fee = self.config.cal_fee(amount, order.is_buy);
+        if order.is_buy {
+            self.cash -= amount + fee;
+            self.volume += volume;
+            if self.config.t_plus_one {
+                self.frozen_volume += volume;
+                self.frozen_date = date.to_string();
+            }
+            let open_trade = self.open_trade.get_or_insert_with(|| COpenTrade {
+                entry_idx: bar.idx,
+                entry_time: bar.time.clone(),
+                buy_amount: 0.0,
+                buy_volume: 0.0,
+                sell_amount: 0.0,
+                fee: 0.0,
+            });
+            open_trade.buy_amount += amount;
+            open_trade.buy_volume += volume;
+            open_trade.fee += fee;
+        } else {
+            self.cash += amount - fee;
+            self.volume -= volume;
+            if let Some(open_trade) = self.open_trade.as_mut() {
+                open_trade.sell_amount += amount;
+                open_trade.fee += fee;
+            }
+        }
+        self.fills.push(CFill {
+            order_id: order.id,
+            is_buy: order.is_buy,
+            idx: bar.idx,
+            time: bar.time.clone(),
+            price,
+            volume,
+            fee,
+        });
+
+        if self.volume <= 0.0 {
+            if let Some(t) = self.open_trade.take() {
+                let pnl = t.sell_amount - t.buy_amount - t.fee;
+                self.trades.push(CTrade {
+                    entry_idx: t.entry_idx,
+                    entry_time: t.entry_time,
+                    exit_idx: bar.idx,
+                    exit_time: bar.time.clone(),
+                    entry_price: t.buy_amount / t.buy_volume,
+                    exit_price: t.sell_amount / t.buy_volume,
+                    volume: t.buy_volume,
+                    fee: t.fee,
+                    pnl,
+                    ret: pnl / t.buy_amount,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(idx: i32, day: u32, hour: u32, open: f64, high: f64, low: f64, close: f64) -> CBarQuote {
+        CBarQuote {
+            idx,
+            time: CTime::new(2024, 1, day, hour, 0, 0, false),
+            open,
+            high,
+            low,
+            close,
+        }
+    }
+
+    fn config() -> CBacktestConfig {
+        CBacktestConfig {
+            init_cash: 10_000.0,
+            fee_rate: 0.001,
+            min_fee: 1.0,
+            stamp_tax_rate: 0.0,
+            slippage_rate: 0.0,
+            lot_size: 100.0,
+            t_plus_one: true,
+            annual_bar_cnt: 252.0,
+        }
+    }
+
+    #[test]
+    fn test_market_order_lot_and_fee() {
+        let mut account = CAccount::new(config());
+        let b0 = bar(0, 2, 0, 10.0, 10.0, 10.0, 10.0);
+        account.submit(COrder::market(true, 1250.0, "buy"), &b0);
+        // 提交当根不撮合
+        account.match_bar(&b0);
+        assert!(account.has_pending_order());
+
+        account.match_bar(&bar(1, 3, 0, 10.0, 10.5, 9.5, 10.2));
+        assert_eq!(account.orders[0].status, OrderStatus::Filled);
+        assert_eq!(account.volume, 900.0);
+        assert!((account.cash - (10_000.0 - 9000.0 - 9.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_t_plus_one() {
+        let mut account = CAccount::new(config());
+        let b0 = bar(0, 2, 10, 10.0, 10.0, 10.0, 10.0);
+        account.submit(COrder::market(true, 500.0, "buy"), &b0);
+        let b1 = bar(1, 2, 11, 10.0, 10.0, 10.0, 10.0);
+        account.match_bar(&b1);
+        account.submit(COrder::market(false, 500.0, "sell"), &b1);
+        // 同一天的下一根K线还不能卖
+        account.match_bar(&bar(2, 2, 14, 10.0, 10.0, 10.0, 10.0));
+        assert_eq!(
+            account.orders[1].status,
+            OrderStatus::Rejected("T+1当天买入不能卖出".to_string())
+        );
+
+        let b3 = bar(3, 2, 15, 10.0, 10.0, 10.0, 10.0);
+        account.submit(COrder::market(false, 500.0, "sell"), &b3);
+        account.match_bar(&bar(4, 3, 10, 11.0, 11.0, 11.0, 11.0));
+        assert_eq!(account.orders[2].status, OrderStatus::Filled);
+        assert_eq!(account.volume, 0.0);
+        assert_eq!(account.trades.len(), 1);
+        let trade = &account.trades[0];
+        assert!((trade.pnl - (500.0 - 5.0 - 5.5)).abs() < 1e-9);
+        assert_eq!(trade.entry_idx, 1);
+        assert_eq!(trade.exit_idx, 4);
+    }
+
+    #[test]
+    fn test_limit_order() {
+        let mut account = CAccount::new(config());
+        let b0 = bar(0, 2, 0, 10.0, 10.0, 10.0, 10.0);
+        account.submit(COrder::limit(true, 100.0, 9.0, "low"), &b0);
+        account.submit(COrder::limit(true, 100.0, 9.8, "mid"), &b0);
+        account.submit(COrder::limit(true, 100.0, 11.0, "high"), &b0);
+        account.match_bar(&bar(1, 3, 0, 10.0, 10.5, 9.5, 10.2));
+        assert_eq!(account.orders[0].status, OrderStatus::Expired);
+        assert_eq!(account.fills[0].price, 9.8);
+        assert_eq!(account.fills[1].price, 10.0);
+    }
+}
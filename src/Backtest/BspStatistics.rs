@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::BuySellPoint::BS_Point::CBSPoint;
+use crate::Common::types::SharedCell;
+use crate::Common::ChanException::{CChanException, ErrCode};
+use crate::KLine::KLine_List::CKLineList;
+
+pub struct CBspStatConfig {
+    // 向后看的K线根数，按从小到大排列
+    pub horizons: Vec<usize>,
+}
+
+impl Default for CBspStatConfig {
+    fn default() -> Self {
+        CBspStatConfig {
+            horizons: vec![1, 3, 5, 10, 20],
+        }
+    }
+}
+
+// 分组维度：K线级别、笔/线段级别（bi/seg/seg2...）、买卖点类型、买/卖
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CBspStatKey {
+    pub kl_type: String,
+    pub line_level: String,
+    pub bsp_type: String,
+    pub is_buy: bool,
+}
+
+// 单个买卖点的前瞻表现，收益率已按方向调整
+#[derive(Clone, Debug)]
+pub struct CBspSample {
+    pub key: CBspStatKey,
+    // 与 horizons 一一对应，后续K线不足时为 None
+    pub rets: Vec<Option<f64>>,
+    // 最大 horizon 内的最大不利/有利幅度（比例，>=0）
+    pub mae: f64,
+    pub mfe: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct CBspStatRow {
+    pub key: CBspStatKey,
+    pub cnt: usize,
+    // 与 horizons 一一对应，没有样本时为 None
+    pub avg_ret: Vec<Option<f64>>,
+    pub hit_rate: Vec<Option<f64>>,
+    pub avg_mae: f64,
+    pub avg_mfe: f64,
+}
+
+// bars 为入场之后的K线 (high, low, close)
+pub fn forward_stat(
+    is_buy: bool,
+    entry_price: f64,
+    bars: &[(f64, f64, f64)],
+    horizons: &[usize],
+) -> (Vec<Option<f64>>, f64, f64) {
+    let sign = if is_buy { 1.0 } else { -1.0 };
+    let rets = horizons
+        .iter()
+        .map(|&h| {
+            h.checked_sub(1)
+                .and_then(|i| bars.get(i))
+                .map(|&(_, _, close)| sign * (close / entry_price - 1.0))
+        })
+        .collect();
+    let max_h = horizons.iter().copied().max().unwrap_or(0).min(bars.len());
+    let mut mae: f64 = 0.0;
+    let mut mfe: f64 = 0.0;
+    for &(high, low, _) in &bars[..max_h] {
+        let (favorable, adverse) = if is_buy {
+            (high / entry_price - 1.0, 1.0 - low / entry_price)
+        } else {
+            (1.0 - low / entry_price, high / entry_price - 1.0)
+        };
+        mfe = mfe.max(favorable);
+        mae = mae.max(adverse);
+    }
+    (rets, mae, mfe)
+}
+
+pub fn aggregate(samples: &[CBspSample], horizon_cnt: usize) -> Vec<CBspStatRow> {
+    let mut groups: BTreeMap<&CBspStatKey, Vec<&CBspSample>> = BTreeMap::new();
+    for sample in samples {
+        groups.entry(&sample.key).or_default().push(sample);
+    }
+    groups
+        .into_iter()
+        .map(|(key, group)| {
+            let n = group.len() as f64;
+            let (avg_ret, hit_rate) = (0..horizon_cnt)
+                .map(|i| {
+                    let rets: Vec<f64> = group.iter().filter_map(|s| s.rets[i]).collect();
+                    if rets.is_empty() {
+                        return (None, None);
+                    }
+                    let cnt = rets.len() as f64;
+                    (
+                        Some(rets.iter().sum::<f64>() / cnt),
+                        Some(rets.iter().filter(|&&r| r > 0.0).count() as f64 / cnt),
+                    )
+                })
+                .unzip();
+            CBspStatRow {
+                key: key.clone(),
+                cnt: group.len(),
+                avg_ret,
+                hit_rate,
+                avg_mae: group.iter().map(|s| s.mae).sum::<f64>() / n,
+                avg_mfe: group.iter().map(|s| s.mfe).sum::<f64>() / n,
+            }
+        })
+        .collect()
+}
+
+// 按最终的买卖点统计，入场价为买卖点所在K线收盘价；
+// 最终买卖点含有未来信息，需要严格无未来函数的评估请用 CBspLabeler 或回测
+pub struct CBspStatistics {
+    pub config: CBspStatConfig,
+    pub samples: Vec<CBspSample>,
+}
+
+impl CBspStatistics {
+    pub fn new(config: CBspStatConfig) -> Self {
+        CBspStatistics {
+            config,
+            samples: Vec::new(),
+        }
+    }
+
+    // 多级别时对每个级别的 CKLineList 调用一次
+    pub fn add_kl_list(&mut self, kl_list: &CKLineList) {
+        let bars: Vec<(f64, f64, f64)> = kl_list
+            .klu_iter(0)
+            .map(|klu| (klu.high, klu.low, klu.close))
+            .collect();
+        let pos_dict: BTreeMap<i32, usize> = kl_list
+            .klu_iter(0)
+            .enumerate()
+            .map(|(pos, klu)| (klu.idx, pos))
+            .collect();
+
+        let mut bsp_lst: Vec<(String, SharedCell<CBSPoint>)> = kl_list
+            .bs_point_lst
+            .iter()
+            .map(|bsp| ("bi".to_string(), bsp.clone()))
+            .collect();
+        for tier in &kl_list.seg_tiers {
+            bsp_lst.extend(
+                tier.bs_point_lst
+                    .iter()
+                    .map(|bsp| (tier.name_prefix(), bsp.clone())),
+            );
+        }
+
+        for (line_level, bsp) in bsp_lst {
+            let bsp = bsp.borrow();
+            let klu = bsp.klu.borrow();
+            let pos = match pos_dict.get(&klu.idx) {
+                Some(&pos) => pos,
+                None => continue,
+            };
+            let (rets, mae, mfe) = forward_stat(
+                bsp.is_buy,
+                klu.close,
+                &bars[pos + 1..],
+                &self.config.horizons,
+            );
+            for bsp_type in &bsp.bsp_type {
+                self.samples.push(CBspSample {
+                    key: CBspStatKey {
+                        kl_type: kl_list.kl_type.clone(),
+                        line_level: line_level.clone(),
+                        bsp_type: bsp_type.to_string(),
+                        is_buy: bsp.is_buy,
+                    },
+                    rets: rets.clone(),
+                    mae,
+                    mfe,
+                });
+            }
+        }
+    }
+
+    pub fn report(&self) -> Vec<CBspStatRow> {
+        aggregate(&self.samples, self.config.horizons.len())
+    }
+
+    fn headers(&self) -> Vec<String> {
+        let mut headers: Vec<String> = [
+            "kl_type",
+            "line_level",
+            "bsp_type",
+            "is_buy",
+            "cnt",
+            "avg_mae",
+            "avg_mfe",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        for h in &self.config.horizons {
+            headers.push(format!("ret_{}", h));
+            headers.push(format!("hit_rate_{}", h));
+        }
+        headers
+    }
+
+    pub fn to_json(&self) -> Value {
+        let rows: Vec<Value> = self
+            .report()
+            .iter()
+            .map(|row| {
+                let mut obj = json!({
+                    "kl_type": row.key.kl_type,
+                    "line_level": row.key.line_level,
+                    "bsp_type": row.key.bsp_type,
+                    "is_buy": row.key.is_buy,
+                    "cnt": row.cnt,
+                    "avg_mae": row.avg_mae,
+                    "avg_mfe": row.avg_mfe,
+                });
+                for (i, h) in self.config.horizons.iter().enumerate() {
+                    obj[format!("ret_{}", h)] = json!(row.avg_ret[i]);
+                    obj[format!("hit_rate_{}", h)] = json!(row.hit_rate[i]);
+                }
+                obj
+            })
+            .collect();
+        Value::Array(rows)
+    }
+
+    pub fn to_csv(&self, path: &str) -> Result<(), CChanException> {
+        let io_err = |e: csv::Error| {
+            CChanException::new(format!("write {} fail: {}", path, e), ErrCode::CommonError)
+        };
+        let mut wtr = csv::Writer::from_path(path).map_err(io_err)?;
+        wtr.write_record(self.headers()).map_err(io_err)?;
+        for row in self.report() {
+            let mut record = vec![
+                row.key.kl_type.clone(),
+                row.key.line_level.clone(),
+                row.key.bsp_type.clone(),
+                row.key.is_buy.to_string(),
+                row.cnt.to_string(),
+                row.avg_mae.to_string(),
+                row.avg_mfe.to_string(),
+            ];
+            for i in 0..self.config.horizons.len() {
+                record.push(row.avg_ret[i].map_or(String::new(), |v| v.to_string()));
+                record.push(row.hit_rate[i].map_or(String::new(), |v| v.to_string()));
+            }
+            wtr.write_record(&record).map_err(io_err)?;
+        }
+        wtr.flush().map_err(|e| {
+            CChanException::new(format!("write {} fail: {}", path, e), ErrCode::CommonError)
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(bsp_type: &str, is_buy: bool) -> CBspStatKey {
+        CBspStatKey {
+            kl_type: "K_DAY".to_string(),
+            line_level: "bi".to_string(),
+            bsp_type: bsp_type.to_string(),
+            is_buy,
+        }
+    }
+
+    #[test]
+    fn test_forward_stat() {
+        let bars = [(10.5, 9.5, 10.2), (11.0, 9.0, 10.8), (12.0, 10.5, 11.5)];
+        let (rets, mae, mfe) = forward_stat(true, 10.0, &bars, &[1, 3, 5]);
+        assert!((rets[0].unwrap() - 0.02).abs() < 1e-9);
+        assert!((rets[1].unwrap() - 0.15).abs() < 1e-9);
+        assert_eq!(rets[2], None);
+        assert!((mae - 0.1).abs() < 1e-9);
+        assert!((mfe - 0.2).abs() < 1e-9);
+
+        let (rets, mae, _) = forward_stat(false, 10.0, &bars, &[1]);
+        assert!((rets[0].unwrap() + 0.02).abs() < 1e-9);
+        assert!((mae - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let samples = vec![
+            CBspSample {
+                key: key("T1", true),
+                rets: vec![Some(0.1), None],
+                mae: 0.02,
+                mfe: 0.1,
+            },
+            CBspSample {
+                key: key("T2", true),
+                rets: vec![Some(-0.05), Some(0.01)],
+                mae: 0.06,
+                mfe: 0.01,
+            },
+            CBspSample {
+                key: key("T1", true),
+                rets: vec![Some(-0.02), Some(0.03)],
+                mae: 0.04,
+                mfe: 0.05,
+            },
+        ];
+        let rows = aggregate(&samples, 2);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].key, key("T1", true));
+        assert_eq!(rows[0].cnt, 2);
+        assert!((rows[0].avg_ret[0].unwrap() - 0.04).abs() < 1e-9);
+        assert_eq!(rows[0].hit_rate[0], Some(0.5));
+        assert_eq!(rows[0].hit_rate[1], Some(1.0));
+        assert!((rows[0].avg_mae - 0.03).abs() < 1e-9);
+        assert_eq!(rows[1].hit_rate[0], Some(0.0));
+    }
+}
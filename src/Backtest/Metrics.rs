@@ -0,0 +1,132 @@
+use serde_json::{json, Value};
+
+#[derive(Clone, Debug)]
+pub struct CBacktestMetrics {
+    pub total_return: f64,
+    pub max_drawdown: f64,
+    pub trade_cnt: usize,
+    // 没有已平仓交易时为 None
+    pub win_rate: Option<f64>,
+    // 没有亏损交易时为 None
+    pub profit_factor: Option<f64>,
+    // 净值没有波动时为 None
+    pub sharpe: Option<f64>,
+}
+
+impl CBacktestMetrics {
+    pub fn new(equity: &[f64], trade_pnl: &[f64], annual_bar_cnt: f64) -> Self {
+        let total_return = match (equity.first(), equity.last()) {
+            (Some(first), Some(last)) if *first > 0.0 => last / first - 1.0,
+            _ => 0.0,
+        };
+        CBacktestMetrics {
+            total_return,
+            max_drawdown: max_drawdown(equity),
+            trade_cnt: trade_pnl.len(),
+            win_rate: win_rate(trade_pnl),
+            profit_factor: profit_factor(trade_pnl),
+            sharpe: sharpe(equity, annual_bar_cnt),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "total_return": self.total_return,
+            "max_drawdown": self.max_drawdown,
+            "trade_cnt": self.trade_cnt,
+            "win_rate": self.win_rate,
+            "profit_factor": self.profit_factor,
+            "sharpe": self.sharpe,
+        })
+    }
+}
+
+// 每个点相对之前最高净值的回撤比例（>=0）
+pub fn cal_drawdown(equity: &[f64]) -> Vec<f64> {
+    let mut peak = f64::MIN;
+    equity
+        .iter()
+        .map(|&v| {
+            peak = peak.max(v);
+            if peak > 0.0 {
+                1.0 - v / peak
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+pub fn max_drawdown(equity: &[f64]) -> f64 {
+    cal_drawdown(equity).into_iter().fold(0.0, f64::max)
+}
+
+pub fn win_rate(trade_pnl: &[f64]) -> Option<f64> {
+    if trade_pnl.is_empty() {
+        return None;
+    }
+    Some(trade_pnl.iter().filter(|&&pnl| pnl > 0.0).count() as f64 / trade_pnl.len() as f64)
+}
+
+// 总盈利 / 总亏损
+pub fn profit_factor(trade_pnl: &[f64]) -> Option<f64> {
+    let gain: f64 = trade_pnl.iter().filter(|&&pnl| pnl > 0.0).sum();
+    let loss: f64 = -trade_pnl.iter().filter(|&&pnl| pnl < 0.0).sum::<f64>();
+    if loss > 0.0 {
+        Some(gain / loss)
+    } else {
+        None
+    }
+}
+
+// 逐K线收益率的年化夏普，无风险利率按0，标准差用样本标准差
+pub fn sharpe(equity: &[f64], annual_bar_cnt: f64) -> Option<f64> {
+    let rets: Vec<f64> = equity
+        .windows(2)
+        .filter(|w| w[0] > 0.0)
+        .map(|w| w[1] / w[0] - 1.0)
+        .collect();
+    if rets.len() < 2 {
+        return None;
+    }
+    let n = rets.len() as f64;
+    let mean = rets.iter().sum::<f64>() / n;
+    let var = rets.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    if var <= 0.0 {
+        return None;
+    }
+    Some(mean / var.sqrt() * annual_bar_cnt.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drawdown() {
+        let equity = [100.0, 120.0, 90.0, 110.0, 130.0, 117.0];
+        let dd = cal_drawdown(&equity);
+        assert_eq!(dd[1], 0.0);
+        assert!((dd[2] - 0.25).abs() < 1e-12);
+        assert!((dd[5] - 0.1).abs() < 1e-12);
+        assert!((max_drawdown(&equity) - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_trade_stats() {
+        let pnl = [100.0, -50.0, 30.0, -25.0];
+        assert_eq!(win_rate(&pnl), Some(0.5));
+        assert!((profit_factor(&pnl).unwrap() - 130.0 / 75.0).abs() < 1e-12);
+        assert_eq!(profit_factor(&[10.0]), None);
+        assert_eq!(win_rate(&[]), None);
+    }
+
+    #[test]
+    fn test_sharpe() {
+        assert_eq!(sharpe(&[100.0, 100.0, 100.0], 252.0), None);
+        assert_eq!(sharpe(&[100.0, 110.0], 252.0), None);
+        // 收益率 [0.1, -0.05]，均值0.025，样本标准差 0.15/sqrt(2)
+        let s = sharpe(&[100.0, 110.0, 104.5], 1.0).unwrap();
+        assert!((s - 0.025 / (0.15 / 2f64.sqrt())).abs() < 1e-9);
+    }
+}
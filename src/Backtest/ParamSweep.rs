@@ -0,0 +1,573 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+
+use crate::Backtest::Backtest::CBacktest;
+use crate::Backtest::BacktestConfig::CBacktestConfig;
+use crate::Backtest::BspStatistics::{CBspStatConfig, CBspStatistics};
+use crate::Backtest::Metrics::CBacktestMetrics;
+use crate::Backtest::Strategy::CBspStrategy;
+use crate::Chan::CChan;
+use crate::ChanConfig::CChanConfig;
+use crate::Common::CEnum::{KlType, AUTYPE, DATA_SRC};
+use crate::Common::ChanException::{CChanException, ErrCode};
+use crate::KLine::KLine_Unit::CKLineUnit;
+
+pub type ParamSet = BTreeMap<String, Value>;
+
+// 待搜索的参数，每个参数给出候选值；base 为所有组合共用的其余配置
+pub struct CParamSpace {
+    pub base: HashMap<String, Value>,
+    pub params: Vec<(String, Vec<Value>)>,
+}
+
+impl CParamSpace {
+    pub fn new(base: HashMap<String, Value>) -> Self {
+        CParamSpace {
+            base,
+            params: Vec::new(),
+        }
+    }
+
+    pub fn add(mut self, name: &str, values: Vec<Value>) -> Self {
+        self.params.push((name.to_string(), values));
+        self
+    }
+
+    // 所有参数的笛卡尔积，按参数添加顺序展开
+    pub fn grid(&self) -> Vec<ParamSet> {
+        let mut res = vec![ParamSet::new()];
+        for (name, values) in &self.params {
+            res = res
+                .iter()
+                .flat_map(|p| {
+                    values.iter().map(move |v| {
+                        let mut p = p.clone();
+                        p.insert(name.clone(), v.clone());
+                        p
+                    })
+                })
+                .collect();
+        }
+        res
+    }
+
+    // 随机搜索，同一个 seed 结果相同；组合去重，总组合数不足 n 时返回全部不重复组合
+    pub fn random(&self, n: usize, seed: u64) -> Vec<ParamSet> {
+        let total: usize = self.params.iter().map(|(_, v)| v.len()).product();
+        let mut rng = XorShift64::new(seed);
+        let mut seen = HashSet::new();
+        let mut res = Vec::new();
+        while res.len() < n.min(total) {
+            let p: ParamSet = self
+                .params
+                .iter()
+                .map(|(name, values)| (name.clone(), values[rng.next_below(values.len())].clone()))
+                .collect();
+            if seen.insert(json!(p).to_string()) {
+                res.push(p);
+            }
+        }
+        res
+    }
+
+    pub fn to_conf(&self, params: &ParamSet) -> HashMap<String, Value> {
+        let mut conf = self.base.clone();
+        conf.extend(params.iter().map(|(k, v)| (k.clone(), v.clone())));
+        conf
+    }
+}
+
+struct XorShift64(u64);
+
+impl XorShift64 {
+    fn new(seed: u64) -> Self {
+        XorShift64(seed.max(1))
+    }
+
+    fn next_below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+// 锚定式 walk-forward：K线等分为 n_splits+1 段，第 i 折用前 i+1 段做样本内，第 i+2 段做样本外
+pub fn walk_forward_splits(n_bars: usize, n_splits: usize) -> Vec<(Range<usize>, Range<usize>)> {
+    let block = n_bars / (n_splits + 1);
+    if block == 0 {
+        return Vec::new();
+    }
+    (0..n_splits)
+        .map(|i| {
+            let train_end = (i + 1) * block;
+            let test_end = if i + 1 == n_splits {
+                n_bars
+            } else {
+                train_end + block
+            };
+            (0..train_end, train_end..test_end)
+        })
+        .collect()
+}
+
+// 在一段K线上评估一个配置，分数越大越好；会在多个线程里同时调用
+pub trait SweepScorer: Sync {
+    fn score(
+        &self,
+        code: &str,
+        conf: HashMap<String, Value>,
+        klu_lst: Vec<CKLineUnit>,
+    ) -> Result<f64, CChanException>;
+}
+
+fn new_step_chan(
+    code: &str,
+    mut conf: HashMap<String, Value>,
+    lv: KlType,
+) -> Result<CChan, CChanException> {
+    conf.insert("trigger_step".to_string(), Value::from(true));
+    CChan::new(
+        code.to_string(),
+        None,
+        None,
+        DATA_SRC::CSV,
+        Some(vec![lv]),
+        Some(CChanConfig::new(Some(conf))?),
+        AUTYPE::QFQ,
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScoreMetric {
+    TotalReturn,
+    Sharpe,
+    ProfitFactor,
+    WinRate,
+}
+
+impl ScoreMetric {
+    // 指标无定义（没有交易、净值无波动等）时记为0
+    pub fn pick(&self, metrics: &CBacktestMetrics) -> f64 {
+        match self {
+            ScoreMetric::TotalReturn => metrics.total_return,
+            ScoreMetric::Sharpe => metrics.sharpe.unwrap_or(0.0),
+            ScoreMetric::ProfitFactor => metrics.profit_factor.unwrap_or(0.0),
+            ScoreMetric::WinRate => metrics.win_rate.unwrap_or(0.0),
+        }
+    }
+}
+
+// 用默认的 CBspStrategy 回测打分
+pub struct CBacktestScorer {
+    pub lv: KlType,
+    pub bt_config: CBacktestConfig,
+    pub metric: ScoreMetric,
+}
+
+impl SweepScorer for CBacktestScorer {
+    fn score(
+        &self,
+        code: &str,
+        conf: HashMap<String, Value>,
+        klu_lst: Vec<CKLineUnit>,
+    ) -> Result<f64, CChanException> {
+        let mut chan = new_step_chan(code, conf, self.lv)?;
+        let mut backtest = CBacktest::new(self.bt_config.clone(), CBspStrategy::default());
+        let res = backtest.run(&mut chan, self.lv, klu_lst)?;
+        Ok(self.metric.pick(&res.metrics))
+    }
+}
+
+// 用所有买卖点在 horizon 根K线后的平均收益（已按方向调整）打分
+pub struct CBspStatScorer {
+    pub lv: KlType,
+    pub horizon: usize,
+}
+
+impl SweepScorer for CBspStatScorer {
+    fn score(
+        &self,
+        code: &str,
+        conf: HashMap<String, Value>,
+        klu_lst: Vec<CKLineUnit>,
+    ) -> Result<f64, CChanException> {
+        let mut chan = new_step_chan(code, conf, self.lv)?;
+        chan.trigger_load(HashMap::from([(self.lv, klu_lst)]))?;
+        let kl_list = chan.get(self.lv).ok_or_else(|| {
+            CChanException::new(format!("{}级别没有数据", self.lv), ErrCode::NoData)
+        })?;
+        let mut stat = CBspStatistics::new(CBspStatConfig {
+            horizons: vec![self.horizon],
+        });
+        stat.add_kl_list(kl_list);
+        let rets: Vec<f64> = stat.samples.iter().filter_map(|s| s.rets[0]).collect();
+        if rets.is_empty() {
+            return Ok(0.0);
+        }
+        Ok(rets.iter().sum::<f64>() / rets.len() as f64)
+    }
+}
+
+pub struct CSweepConfig {
+    pub n_splits: usize,
+    pub n_jobs: usize,
+}
+
+impl Default for CSweepConfig {
+    fn default() -> Self {
+        CSweepConfig {
+            n_splits: 3,
+            n_jobs: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CSweepResult {
+    pub params: ParamSet,
+    // 每一折样本内/样本外分数（对各品种取平均），该折所有品种都失败时为 None
+    pub is_scores: Vec<Option<f64>>,
+    pub oos_scores: Vec<Option<f64>>,
+    pub is_score: f64,
+    pub oos_score: f64,
+    pub err_cnt: usize,
+}
+
+pub struct CSweepReport {
+    // 按样本内分数从高到低排列
+    pub results: Vec<CSweepResult>,
+    // 每一折按样本内分数选出的参数（results 的下标）及其样本外分数
+    pub walk_forward: Vec<(usize, Option<f64>)>,
+    pub walk_forward_score: Option<f64>,
+}
+
+fn mean(v: &[f64]) -> Option<f64> {
+    if v.is_empty() {
+        None
+    } else {
+        Some(v.iter().sum::<f64>() / v.len() as f64)
+    }
+}
+
+// raw[参数][品种] 为该品种每一折的 (样本内, 样本外) 分数，品种整体失败时为 Err
+pub fn summarize(
+    params_lst: Vec<ParamSet>,
+    raw: Vec<Vec<Result<Vec<(f64, f64)>, CChanException>>>,
+    n_splits: usize,
+) -> CSweepReport {
+    let mut results: Vec<CSweepResult> = params_lst
+        .into_iter()
+        .zip(raw)
+        .map(|(params, symbol_scores)| {
+            let err_cnt = symbol_scores.iter().filter(|r| r.is_err()).count();
+            let ok: Vec<&Vec<(f64, f64)>> = symbol_scores.iter().flatten().collect();
+            let fold_mean = |pick: fn(&(f64, f64)) -> f64| -> Vec<Option<f64>> {
+                (0..n_splits)
+                    .map(|i| {
+                        mean(
+                            &ok.iter()
+                                .filter_map(|s| s.get(i).map(pick))
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                    .collect()
+            };
+            let is_scores = fold_mean(|s| s.0);
+            let oos_scores = fold_mean(|s| s.1);
+            CSweepResult {
+                is_score: mean(&is_scores.iter().flatten().copied().collect::<Vec<_>>())
+                    .unwrap_or(f64::MIN),
+                oos_score: mean(&oos_scores.iter().flatten().copied().collect::<Vec<_>>())
+                    .unwrap_or(f64::MIN),
+                params,
+                is_scores,
+                oos_scores,
+                err_cnt,
+            }
+        })
+        .collect();
+    // 排序稳定，分数相同时保持参数生成顺序
+    results.sort_by(|a, b| b.is_score.total_cmp(&a.is_score));
+
+    let walk_forward: Vec<(usize, Option<f64>)> = (0..n_splits)
+        .filter_map(|i| {
+            let best = results
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, r)| r.is_scores[i].map(|s| (idx, s)))
+                .fold(None, |best: Option<(usize, f64)>, (idx, s)| match best {
+                    Some((_, best_s)) if best_s >= s => best,
+                    _ => Some((idx, s)),
+                })?;
+            Some((best.0, results[best.0].oos_scores[i]))
+        })
+        .collect();
+    let walk_forward_score = mean(
+        &walk_forward
+            .iter()
+            .filter_map(|(_, s)| *s)
+            .collect::<Vec<_>>(),
+    );
+    CSweepReport {
+        results,
+        walk_forward,
+        walk_forward_score,
+    }
+}
+
+// 对每个参数组合、每个品种、每一折分别在样本内和样本外K线上打分
+// CKLineUnit 不能跨线程，loader 在工作线程里按品种加载K线；任务按品种排列，
+// 工作线程缓存最近加载的品种，每个线程对同一品种最多加载一次
+pub fn run_sweep<L, S>(
+    space: &CParamSpace,
+    params_lst: Vec<ParamSet>,
+    codes: &[String],
+    loader: L,
+    scorer: &S,
+    config: &CSweepConfig,
+) -> CSweepReport
+where
+    L: Fn(&str) -> Result<Vec<CKLineUnit>, CChanException> + Sync,
+    S: SweepScorer,
+{
+    let jobs: Vec<(usize, usize)> = (0..codes.len())
+        .flat_map(|c| (0..params_lst.len()).map(move |p| (p, c)))
+        .collect();
+    // outputs[参数][品种]
+    let outputs: Mutex<Vec<Vec<Option<Result<Vec<(f64, f64)>, CChanException>>>>> = Mutex::new(
+        (0..params_lst.len())
+            .map(|_| (0..codes.len()).map(|_| None).collect())
+            .collect(),
+    );
+    let next_job = AtomicUsize::new(0);
+
+    let run_job =
+        |p: usize, c: usize, klu_lst: &[CKLineUnit]| -> Result<Vec<(f64, f64)>, CChanException> {
+            let code = &codes[c];
+            let splits = walk_forward_splits(klu_lst.len(), config.n_splits);
+            if splits.is_empty() {
+                return Err(CChanException::new(
+                    format!(
+                        "{} K线数量{}不足以切分{}折",
+                        code,
+                        klu_lst.len(),
+                        config.n_splits
+                    ),
+                    ErrCode::ParaError,
+                ));
+            }
+            let conf = space.to_conf(&params_lst[p]);
+            // 样本内、样本外各自从头计算缠论结构，样本外不沿用样本内的K线
+            splits
+                .into_iter()
+                .map(|(train, test)| {
+                    let is_score = scorer.score(code, conf.clone(), klu_lst[train].to_vec())?;
+                    let oos_score = scorer.score(code, conf.clone(), klu_lst[test].to_vec())?;
+                    Ok((is_score, oos_score))
+                })
+                .collect()
+        };
+
+    std::thread::scope(|s| {
+        for _ in 0..config.n_jobs.max(1) {
+            s.spawn(|| {
+                let mut cache: Option<(usize, Result<Vec<CKLineUnit>, CChanException>)> = None;
+                loop {
+                    let i = next_job.fetch_add(1, Ordering::SeqCst);
+                    if i >= jobs.len() {
+                        break;
+                    }
+                    let (p, c) = jobs[i];
+                    if cache.as_ref().map_or(true, |(cached_c, _)| *cached_c != c) {
+                        cache = Some((c, loader(&codes[c])));
+                    }
+                    let res = match &cache.as_ref().unwrap().1 {
+                        Ok(klu_lst) => run_job(p, c, klu_lst),
+                        Err(e) => Err(CChanException::new(e.msg.clone(), e.errcode)),
+                    };
+                    outputs.lock().unwrap()[p][c] = Some(res);
+                }
+            });
+        }
+    });
+
+    let raw = outputs
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|param_outputs| param_outputs.into_iter().map(Option::unwrap).collect())
+        .collect();
+    summarize(params_lst, raw, config.n_splits)
+}
+
+impl CSweepReport {
+    pub fn to_json(&self) -> Value {
+        let results: Vec<Value> = self
+            .results
+            .iter()
+            .enumerate()
+            .map(|(rank, r)| {
+                json!({
+                    "rank": rank + 1,
+                    "params": r.params,
+                    "is_score": r.is_score,
+                    "oos_score": r.oos_score,
+                    "is_scores": r.is_scores,
+                    "oos_scores": r.oos_scores,
+                    "err_cnt": r.err_cnt,
+                })
+            })
+            .collect();
+        let walk_forward: Vec<Value> = self
+            .walk_forward
+            .iter()
+            .map(|(idx, oos)| json!({"params": self.results[*idx].params, "oos_score": oos}))
+            .collect();
+        json!({
+            "results": results,
+            "walk_forward": walk_forward,
+            "walk_forward_score": self.walk_forward_score,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Common::CTime::CTime;
+    use crate::Common::TradeInfo::CTradeInfo;
+    use crate::Math::Demark::CDemarkIndex;
+
+    fn space() -> CParamSpace {
+        CParamSpace::new(HashMap::new())
+            .add("divergence_rate", vec![json!(0.8), json!(0.9), json!(1.0)])
+            .add("min_zs_cnt", vec![json!(0), json!(1)])
+    }
+
+    #[test]
+    fn test_grid_and_random() {
+        let space = space();
+        let grid = space.grid();
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid[1]["divergence_rate"], json!(0.8));
+        assert_eq!(grid[1]["min_zs_cnt"], json!(1));
+
+        let r1 = space.random(4, 42);
+        assert_eq!(r1.len(), 4);
+        assert_eq!(r1, space.random(4, 42));
+        assert_eq!(space.random(100, 7).len(), 6);
+    }
+
+    #[test]
+    fn test_walk_forward_splits() {
+        let splits = walk_forward_splits(100, 3);
+        assert_eq!(
+            splits,
+            vec![(0..25, 25..50), (0..50, 50..75), (0..75, 75..100)]
+        );
+        assert!(walk_forward_splits(2, 3).is_empty());
+    }
+
+    #[test]
+    fn test_summarize() {
+        let grid = space().grid()[..2].to_vec();
+        let raw = vec![
+            vec![Ok(vec![(1.0, 0.1), (2.0, -0.5)])],
+            vec![
+                Ok(vec![(1.5, 0.3), (0.5, 0.2)]),
+                Err(CChanException::new("x".to_string(), ErrCode::NoData)),
+            ],
+        ];
+        let report = summarize(grid.clone(), raw, 2);
+        assert_eq!(report.results[0].params, grid[0]);
+        assert!((report.results[0].is_score - 1.5).abs() < 1e-9);
+        assert_eq!(report.results[1].err_cnt, 1);
+        // 第一折选 grid[1]（1.5 > 1.0），第二折选 grid[0]（2.0 > 0.5）
+        assert_eq!(report.walk_forward, vec![(1, Some(0.3)), (0, Some(-0.5))]);
+        assert!((report.walk_forward_score.unwrap() + 0.1).abs() < 1e-9);
+    }
+
+    fn klu_lst(n: usize) -> Vec<CKLineUnit> {
+        (0..n)
+            .map(|i| CKLineUnit {
+                kl_type: None,
+                time: CTime::new(
+                    2024,
+                    1 + (i / 28) as u32,
+                    1 + (i % 28) as u32,
+                    0,
+                    0,
+                    0,
+                    false,
+                ),
+                close: 10.0,
+                open: 10.0,
+                high: 10.0,
+                low: 10.0,
+                trade_info: CTradeInfo::new(&HashMap::new()),
+                demark: CDemarkIndex::new(),
+                sub_kl_list: Vec::new(),
+                sup_kl: None,
+                klc: None,
+                trend: HashMap::new(),
+                limit_flag: 0,
+                pre: None,
+                next: None,
+                idx: -1,
+                macd: None,
+                boll: None,
+                rsi: None,
+                kdj: None,
+                metric: HashMap::new(),
+            })
+            .collect()
+    }
+
+    // 用K线根数打分，不跑缠论
+    struct LenScorer;
+
+    impl SweepScorer for LenScorer {
+        fn score(
+            &self,
+            _code: &str,
+            _conf: HashMap<String, Value>,
+            klu_lst: Vec<CKLineUnit>,
+        ) -> Result<f64, CChanException> {
+            Ok(klu_lst.len() as f64)
+        }
+    }
+
+    #[test]
+    fn test_run_sweep_load_once() {
+        let codes: Vec<String> = ["a", "b", "c"].iter().map(|c| c.to_string()).collect();
+        let load_cnt = AtomicUsize::new(0);
+        let loader = |code: &str| {
+            load_cnt.fetch_add(1, Ordering::SeqCst);
+            if code == "c" {
+                Err(CChanException::new("no data".to_string(), ErrCode::NoData))
+            } else {
+                Ok(klu_lst(40))
+            }
+        };
+        let params_lst = space().grid();
+        let config = CSweepConfig {
+            n_splits: 3,
+            n_jobs: 2,
+        };
+        let report = run_sweep(&space(), params_lst, &codes, loader, &LenScorer, &config);
+
+        // 每个线程对同一品种最多加载一次
+        assert!(load_cnt.load(Ordering::SeqCst) <= codes.len() * config.n_jobs);
+        assert_eq!(report.results.len(), 6);
+        for r in &report.results {
+            assert_eq!(r.err_cnt, 1);
+            assert_eq!(r.is_scores, vec![Some(10.0), Some(20.0), Some(30.0)]);
+            assert_eq!(r.oos_scores, vec![Some(10.0), Some(10.0), Some(10.0)]);
+        }
+    }
+}
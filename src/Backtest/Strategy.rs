@@ -0,0 +1,137 @@
+use crate::Backtest::Broker::{CAccount, CBarQuote, COrder};
+use crate::BuySellPoint::BSPRisk::CBspRisk;
+use crate::BuySellPoint::BSPointHistory::{CBSPointEvent, CBSPointRecord};
+use crate::Common::CEnum::BspState;
+use crate::KLine::KLine_List::CKLineList;
+
+// 每根K线收盘后交给策略的信息，只包含截至当前K线已知的数据
+pub struct CStrategyContext<'a> {
+    pub kl_list: &'a CKLineList,
+    pub bar: &'a CBarQuote,
+    // 当前K线上发生的买卖点事件（出现、确认、失效）
+    pub bsp_events: Vec<(&'a CBSPointRecord, &'a CBSPointEvent)>,
+    pub account: &'a CAccount,
+}
+
+impl<'a> CStrategyContext<'a> {
+    pub fn new(kl_list: &'a CKLineList, bar: &'a CBarQuote, account: &'a CAccount) -> Self {
+        let bsp_events = kl_list
+            .bs_point_lst
+            .history
+            .cur_events()
+            .filter(|(_, e)| e.klu_idx == bar.idx)
+            .collect();
+        CStrategyContext {
+            kl_list,
+            bar,
+            bsp_events,
+            account,
+        }
+    }
+}
+
+// 返回的委托在下一根K线撮合
+pub trait Strategy {
+    fn on_bar(&mut self, ctx: &CStrategyContext) -> Vec<COrder>;
+}
+
+// 买点出现时按权益比例买入，卖点出现时清仓
+pub struct CBspStrategy {
+    // 参与交易的买卖点类型（如 "T1", "T2S"），为空表示全部
+    pub buy_types: Vec<String>,
+    pub sell_types: Vec<String>,
+    // 每次买入占当前权益的比例
+    pub position_rate: f64,
+    // 只在买卖点确认后才交易
+    pub only_confirmed: bool,
+    // 持仓期间按买点的结构止损/止盈位离场
+    pub use_risk_levels: bool,
+    // 当前持仓对应买点的止损/止盈位
+    pub risk: Option<CBspRisk>,
+}
+
+impl Default for CBspStrategy {
+    fn default() -> Self {
+        CBspStrategy {
+            buy_types: Vec::new(),
+            sell_types: Vec::new(),
+            position_rate: 1.0,
+            only_confirmed: false,
+            use_risk_levels: false,
+            risk: None,
+        }
+    }
+}
+
+impl CBspStrategy {
+    fn risk_exit(&self, close: f64) -> Option<&'static str> {
+        let risk = self.risk.as_ref()?;
+        if risk.stop_loss().is_some_and(|l| close <= l.price) {
+            Some("stop loss")
+        } else if risk.take_profit().is_some_and(|l| close >= l.price) {
+            Some("take profit")
+        } else {
+            None
+        }
+    }
+
+    fn is_signal(&self, record: &CBSPointRecord, event: &CBSPointEvent) -> bool {
+        let expect_state = if self.only_confirmed {
+            BspState::CONFIRMED
+        } else {
+            BspState::PROVISIONAL
+        };
+        if event.state != expect_state {
+            return false;
+        }
+        let types = if record.is_buy {
+            &self.buy_types
+        } else {
+            &self.sell_types
+        };
+        types.is_empty()
+            || event
+                .bsp_type
+                .split(',')
+                .any(|t| types.iter().any(|x| x.eq_ignore_ascii_case(t)))
+    }
+}
+
+impl Strategy for CBspStrategy {
+    fn on_bar(&mut self, ctx: &CStrategyContext) -> Vec<COrder> {
+        if ctx.account.has_pending_order() {
+            return Vec::new();
+        }
+        if ctx.account.volume == 0.0 {
+            self.risk = None;
+        } else if let Some(reason) = self.risk_exit(ctx.bar.close) {
+            self.risk = None;
+            return vec![COrder::market(false, ctx.account.volume, reason)];
+        }
+        let signal = ctx
+            .bsp_events
+            .iter()
+            .filter(|(record, event)| self.is_signal(record, event))
+            .last();
+        let (record, _) = match signal {
+            Some(signal) => signal,
+            None => return Vec::new(),
+        };
+        if record.is_buy && ctx.account.volume == 0.0 {
+            if self.use_risk_levels {
+                self.risk = Some(record.bsp.borrow().risk.clone());
+            }
+            let volume = ctx.account.equity(ctx.bar.close) * self.position_rate / ctx.bar.close;
+            vec![COrder::market(true, volume, &format!("bsp {}", record.id))]
+        } else if !record.is_buy && ctx.account.volume > 0.0 {
+            self.risk = None;
+            vec![COrder::market(
+                false,
+                ctx.account.volume,
+                &format!("bsp {}", record.id),
+            )]
+        } else {
+            Vec::new()
+        }
+    }
+}
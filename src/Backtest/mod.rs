@@ -0,0 +1,7 @@
+pub mod Backtest;
+pub mod BacktestConfig;
+pub mod BspStatistics;
+pub mod Broker;
+pub mod Metrics;
+pub mod ParamSweep;
+pub mod Strategy;
@@ -1,9 +1,10 @@
 use crate::BuySellPoint::BS_Point::CBSPoint;
 use crate::Common::types::SharedCell;
-use crate::Common::CEnum::{BiDir, BiType, DataField, FxType, MacdAlgo};
+use crate::Common::CEnum::{BiDir, BiType, FxType, MacdAlgo};
 use crate::Common::ChanException::{CChanException, ErrCode};
 use crate::KLine::KLine::CKLine;
 use crate::KLine::KLine_Unit::CKLineUnit;
+use crate::Math::MacdMetric::{amp_metric, line_macd_metric, slope_metric, trade_metric};
 use crate::Seg::Seg::CSeg;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -258,181 +259,87 @@ impl CBi {
         macd_algo: MacdAlgo,
         is_reverse: bool,
     ) -> Result<f64, CChanException> {
-        match macd_algo {
-            MacdAlgo::Area => self.cal_macd_half(is_reverse),
-            MacdAlgo::Peak => self.cal_macd_peak(),
-            MacdAlgo::FullArea => self.cal_macd_area(),
-            MacdAlgo::Diff => self.cal_macd_diff(),
-            MacdAlgo::Slope => self.cal_macd_slope(),
-            MacdAlgo::Amp => self.cal_macd_amp(),
-            MacdAlgo::Amount => self.cal_macd_trade_metric(DataField::FieldTurnover, false),
-            MacdAlgo::Volumn => self.cal_macd_trade_metric(DataField::FieldVolume, false),
-            MacdAlgo::VolumnAvg => self.cal_macd_trade_metric(DataField::FieldVolume, true),
-            MacdAlgo::AmountAvg => self.cal_macd_trade_metric(DataField::FieldTurnover, true),
-            MacdAlgo::TurnrateAvg => self.cal_macd_trade_metric(DataField::FieldTurnrate, true),
-            MacdAlgo::Rsi => self.cal_rsi(),
-            _ => Err(CChanException::new(
-                format!(
-                    "unsupport macd_algo={:?}, should be one of area/full_area/peak/diff/slope/amp",
-                    macd_algo
-                ),
-                ErrCode::ParaError,
-            )),
-        }
+        line_macd_metric(
+            macd_algo,
+            is_reverse,
+            self.is_down(),
+            &self.klu_lst(),
+            &self.get_begin_klu().borrow(),
+            &self.get_end_klu().borrow(),
+        )
     }
 
+    // 以下为单项指标，与 cal_macd_metric 走同一套 MacdMetric 计算
     pub fn cal_rsi(&self) -> Result<f64, CChanException> {
-        let mut rsi_lst = Vec::new();
-        for klc in self.klc_lst() {
-            for klu in klc.borrow().lst.iter() {
-                rsi_lst.push(klu.rsi);
-            }
-        }
-        if self.is_down() {
-            Ok(10000.0 / (rsi_lst.iter().fold(f64::INFINITY, |a, &b| a.min(b)) + 1e-7))
-        } else {
-            Ok(*rsi_lst
-                .iter()
-                .max_by(|a, b| a.partial_cmp(b).unwrap())
-                .unwrap())
-        }
+        self.cal_macd_metric(MacdAlgo::Rsi, false)
     }
 
     pub fn cal_macd_area(&self) -> Result<f64, CChanException> {
-        let mut s = 1e-7;
-        for klc in self.klc_lst() {
-            for klu in klc.borrow().lst.iter() {
-                s += klu.macd.macd.abs();
-            }
-        }
-        Ok(s)
+        self.cal_macd_metric(MacdAlgo::FullArea, false)
     }
 
     pub fn cal_macd_peak(&self) -> Result<f64, CChanException> {
-        let mut peak = 1e-7;
-        for klc in self.klc_lst() {
-            for klu in klc.borrow().lst.iter() {
-                if klu.macd.macd.abs() > peak {
-                    if self.is_down() && klu.macd.macd < 0.0 {
-                        peak = klu.macd.macd.abs();
-                    } else if self.is_up() && klu.macd.macd > 0.0 {
-                        peak = klu.macd.macd.abs();
-                    }
-                }
-            }
-        }
-        Ok(peak)
+        self.cal_macd_metric(MacdAlgo::Peak, false)
     }
 
     pub fn cal_macd_half(&self, is_reverse: bool) -> Result<f64, CChanException> {
-        if is_reverse {
-            self.cal_macd_half_reverse()
-        } else {
-            self.cal_macd_half_obverse()
-        }
+        self.cal_macd_metric(MacdAlgo::Area, is_reverse)
     }
 
     pub fn cal_macd_half_obverse(&self) -> Result<f64, CChanException> {
-        let mut s = 1e-7;
-        let begin_klu = self.get_begin_klu();
-        let peak_macd = begin_klu.borrow().macd.macd;
-        for klc in self.klc_lst() {
-            for klu in klc.borrow().lst.iter() {
-                if klu.idx < begin_klu.borrow().idx {
-                    continue;
-                }
-                if klu.macd.macd * peak_macd > 0.0 {
-                    s += klu.macd.macd.abs();
-                } else {
-                    return Ok(s);
-                }
-            }
-        }
-        Ok(s)
+        self.cal_macd_metric(MacdAlgo::HalfObverse, false)
     }
 
     pub fn cal_macd_half_reverse(&self) -> Result<f64, CChanException> {
-        let mut s = 1e-7;
-        let begin_klu = self.get_end_klu();
-        let peak_macd = begin_klu.borrow().macd.macd;
-        for klc in self.klc_lst_re() {
-            for klu in klc.borrow().lst.iter().rev() {
-                if klu.idx > begin_klu.borrow().idx {
-                    continue;
-                }
-                if klu.macd.macd * peak_macd > 0.0 {
-                    s += klu.macd.macd.abs();
-                } else {
-                    return Ok(s);
-                }
-            }
-        }
-        Ok(s)
+        self.cal_macd_metric(MacdAlgo::HalfReverse, false)
     }
 
     pub fn cal_macd_diff(&self) -> Result<f64, CChanException> {
-        let mut max = f64::NEG_INFINITY;
-        let mut min = f64::INFINITY;
-        for klc in self.klc_lst() {
-            for klu in klc.borrow().lst.iter() {
-                let macd = klu.macd.macd;
-                if macd > max {
-                    max = macd;
-                }
-                if macd < min {
-                    min = macd;
-                }
-            }
-        }
-        Ok(max - min)
+        self.cal_macd_metric(MacdAlgo::Diff, false)
+    }
+
+    // metric 为 DataField 中的成交量/成交额/换手率字段
+    pub fn cal_macd_trade_metric(
+        &self,
+        metric: &str,
+        cal_avg: bool,
+    ) -> Result<f64, CChanException> {
+        let values: Vec<Option<f64>> = self
+            .klu_lst()
+            .iter()
+            .map(|klu| {
+                klu.borrow()
+                    .trade_info
+                    .metric
+                    .get(metric)
+                    .copied()
+                    .flatten()
+            })
+            .collect();
+        Ok(trade_metric(&values, cal_avg))
     }
 
     pub fn cal_macd_slope(&self) -> Result<f64, CChanException> {
-        let begin_klu = self.get_begin_klu();
-        let end_klu = self.get_end_klu();
-        if self.is_up() {
-            Ok((end_klu.borrow().high - begin_klu.borrow().low)
-                / end_klu.borrow().high
-                / (end_klu.borrow().idx - begin_klu.borrow().idx + 1) as f64)
-        } else {
-            Ok((begin_klu.borrow().high - end_klu.borrow().low)
-                / begin_klu.borrow().high
-                / (end_klu.borrow().idx - begin_klu.borrow().idx + 1) as f64)
-        }
+        Ok(slope_metric(
+            &self.get_begin_klu().borrow(),
+            &self.get_end_klu().borrow(),
+            self.is_up(),
+        ))
     }
 
     pub fn cal_macd_amp(&self) -> Result<f64, CChanException> {
-        let begin_klu = self.get_begin_klu();
-        let end_klu = self.get_end_klu();
-        if self.is_down() {
-            Ok((begin_klu.borrow().high - end_klu.borrow().low) / begin_klu.borrow().high)
-        } else {
-            Ok((end_klu.borrow().high - begin_klu.borrow().low) / begin_klu.borrow().low)
-        }
+        Ok(amp_metric(
+            &self.get_begin_klu().borrow(),
+            &self.get_end_klu().borrow(),
+            self.is_down(),
+        ))
     }
 
-    pub fn cal_macd_trade_metric(
-        &self,
-        metric: DataField,
-        cal_avg: bool,
-    ) -> Result<f64, CChanException> {
-        let mut s = 0.0;
-        let mut count = 0;
-        for klc in self.klc_lst() {
-            for klu in klc.borrow().lst.iter() {
-                if let Some(metric_res) = klu.trade_info.metric.get(&metric.to_string()) {
-                    s += metric_res;
-                    count += 1;
-                } else {
-                    return Ok(0.0);
-                }
-            }
-        }
-        if cal_avg && count > 0 {
-            Ok(s / count as f64)
-        } else {
-            Ok(s)
-        }
+    // 笔内所有K线，从起点合并K线到终点合并K线
+    fn klu_lst(&self) -> Vec<SharedCell<CKLineUnit>> {
+        self.klc_lst()
+            .flat_map(|klc| klc.borrow().lst.clone())
+            .collect()
     }
 
     // Helper methods for iterating over KLines
@@ -442,13 +349,6 @@ impl CBi {
             end_idx: self.end_klc.borrow().idx,
         }
     }
-
-    fn klc_lst_re(&self) -> impl Iterator<Item = SharedCell<CKLine>> {
-        KlcReverseIterator {
-            current: Some(Rc::clone(&self.end_klc)),
-            begin_idx: self.begin_klc.borrow().idx,
-        }
-    }
 }
 
 struct KlcIterator {
@@ -470,23 +370,3 @@ impl Iterator for KlcIterator {
         }
     }
 }
-
-struct KlcReverseIterator {
-    current: Option<SharedCell<CKLine>>,
-    begin_idx: i32,
-}
-
-impl Iterator for KlcReverseIterator {
-    type Item = SharedCell<CKLine>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let current = self.current.take()?;
-        let current_idx = current.borrow().idx;
-        if current_idx >= self.begin_idx {
-            self.current = current.borrow().pre.clone();
-            Some(current)
-        } else {
-            None
-        }
-    }
-}
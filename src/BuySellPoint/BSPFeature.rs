@@ -0,0 +1,392 @@
+use crate::Common::types::{LineType, SharedCell};
+use crate::Common::CEnum::{DataField, TrendType};
+use crate::KLine::KLine_Unit::CKLineUnit;
+use crate::Math::Demark::BiDir;
+use crate::Seg::Seg::CSeg;
+use crate::ZS::ZS::CZS;
+use std::collections::HashMap;
+
+// 每个买卖点都会计算的标准特征，所有比例类特征都已按价格归一化，可以跨品种使用
+//
+// 笔（线段买卖点即线段，下同）：
+//   bsp_bi_amp             最后一笔的振幅
+//   bsp_bi_amp_rate        最后一笔的振幅 / 买卖点价格
+//   bsp_bi_klu_cnt         最后一笔的K线数
+//   bsp_bi_amp_ratio       最后一笔振幅 / 前一根同向笔振幅
+//   bsp_bi_klu_cnt_ratio   最后一笔K线数 / 前一根同向笔K线数
+//   bsp_bi_volume_ratio    最后一笔成交量 / 前一根同向笔成交量
+// 中枢（仅笔买卖点，取所在线段）：
+//   bsp_zs_cnt             所在线段内的中枢个数
+//   bsp_zs_height_rate     最后一个中枢高度 (high-low) / 中枢中点
+//   bsp_zs_width           最后一个中枢的K线跨度
+//   bsp_zs_dis_rate        买卖点价格与最后一个中枢中点的距离 / 中枢中点
+// 指标（买卖点所在K线）：
+//   bsp_macd_dif / bsp_macd_dea / bsp_macd  MACD 三项，均除以收盘价
+//   bsp_rsi
+//   bsp_kdj_k / bsp_kdj_d / bsp_kdj_j
+//   bsp_boll_pos           (close-down)/(up-down)，0为下轨，1为上轨
+//   bsp_boll_width_rate    (up-down)/mid
+//   bsp_demark_setup       与买卖点同向（买点看下跌）的 TD setup 计数，没有为0
+//   bsp_demark_countdown   同上的 TD countdown 计数
+//   bsp_{mean|max|min}{t}_dis_rate  (close - 滚动均值/最大/最小) / close
+//   bsp_std{t}_rate        滚动标准差 / close
+//   bsp_demark_tdst_dis_rate  (close - 同向序列的 TDST) / close，不在 countdown 中时没有
+//   bsp_{name}[_{key}]     扩展指标及自定义指标（MetricValue::Value/Dict）的值，name 即 MetricModel::name，
+//                          带周期，如 bsp_atr14、bsp_ema20、bsp_adx14_plus_di、bsp_keltner20_10_up
+// 趋势线（所在的上一级线段，买点看支撑线，卖点看压力线）：
+//   bsp_trend_line_dis_rate  (价格 - 趋势线在该K线的值) / 价格
+//   bsp_channel_pos          价格在通道中的位置，0为内侧趋势线，1为平行线
+// 背驰特征 diver_{macd_algo}_ratio / diver_score 在配置 divergence_report 开启时由一类买卖点另外加入
+pub fn cal_bsp_std_features(line: &LineType, is_buy: bool) -> HashMap<String, f64> {
+    let mut features = HashMap::new();
+    let end_klu = line_end_klu(line);
+    let price = line_end_val(line);
+    let amp = line_amp(line);
+    let klu_cnt = line_klu_cnt(line);
+
+    features.insert("bsp_bi_amp".to_string(), amp);
+    features.insert("bsp_bi_amp_rate".to_string(), amp / price);
+    features.insert("bsp_bi_klu_cnt".to_string(), klu_cnt as f64);
+    if let Some(pre_line) = pre_same_dir_line(line) {
+        let pre_amp = line_amp(&pre_line);
+        if pre_amp > 0.0 {
+            features.insert("bsp_bi_amp_ratio".to_string(), amp / pre_amp);
+        }
+        features.insert(
+            "bsp_bi_klu_cnt_ratio".to_string(),
+            klu_cnt as f64 / line_klu_cnt(&pre_line) as f64,
+        );
+        let pre_volume = line_volume(&pre_line);
+        if pre_volume > 0.0 {
+            features.insert(
+                "bsp_bi_volume_ratio".to_string(),
+                line_volume(line) / pre_volume,
+            );
+        }
+    }
+
+    let x = end_klu.borrow().idx;
+    match line {
+        LineType::Bi(bi) => {
+            if let Some(seg) = &bi.borrow().parent_seg {
+                let seg = seg.borrow();
+                features.insert("bsp_zs_cnt".to_string(), seg.zs_lst.len() as f64);
+                if let Some(zs) = seg.zs_lst.last() {
+                    features.extend(zs_features(&zs.borrow(), price));
+                }
+                features.extend(trend_line_features(&seg, is_buy, x, price));
+            }
+        }
+        LineType::Seg(s) => {
+            if let Some(seg) = &s.borrow().parent_seg {
+                features.extend(trend_line_features(&seg.borrow(), is_buy, x, price));
+            }
+        }
+        LineType::Tier(s) => {
+            if let Some(seg) = &s.borrow().parent_seg {
+                features.extend(trend_line_features(&seg.borrow(), is_buy, x, price));
+            }
+        }
+    }
+
+    features.extend(klu_indicator_features(&end_klu.borrow(), is_buy));
+    features
+}
+
+fn zs_features(zs: &CZS, price: f64) -> HashMap<String, f64> {
+    let mut features = HashMap::from([
+        (
+            "bsp_zs_height_rate".to_string(),
+            (zs.high - zs.low) / zs.mid,
+        ),
+        ("bsp_zs_dis_rate".to_string(), (price - zs.mid) / zs.mid),
+    ]);
+    if let (Some(begin), Some(end)) = (&zs.begin, &zs.end) {
+        features.insert(
+            "bsp_zs_width".to_string(),
+            (end.borrow().idx - begin.borrow().idx + 1) as f64,
+        );
+    }
+    features
+}
+
+fn klu_indicator_features(klu: &CKLineUnit, is_buy: bool) -> HashMap<String, f64> {
+    let mut features = HashMap::new();
+    if let Some(macd) = &klu.macd {
+        features.insert("bsp_macd_dif".to_string(), macd.dif / klu.close);
+        features.insert("bsp_macd_dea".to_string(), macd.dea / klu.close);
+        features.insert("bsp_macd".to_string(), macd.macd / klu.close);
+    }
+    if let Some(rsi) = klu.rsi {
+        features.insert("bsp_rsi".to_string(), rsi);
+    }
+    if let Some(kdj) = &klu.kdj {
+        features.insert("bsp_kdj_k".to_string(), kdj.k);
+        features.insert("bsp_kdj_d".to_string(), kdj.d);
+        features.insert("bsp_kdj_j".to_string(), kdj.j);
+    }
+    if let Some(boll) = &klu.boll {
+        if boll.up > boll.down {
+            features.insert(
+                "bsp_boll_pos".to_string(),
+                (klu.close - boll.down) / (boll.up - boll.down),
+            );
+        }
+        features.insert(
+            "bsp_boll_width_rate".to_string(),
+            (boll.up - boll.down) / boll.mid,
+        );
+    }
+    // 下跌 setup 对应买点，上涨 setup 对应卖点
+    let demark_dir = if is_buy { BiDir::Down } else { BiDir::Up };
+    features.insert(
+        "bsp_demark_setup".to_string(),
+        klu.demark.max_setup_idx(demark_dir) as f64,
+    );
+    features.insert(
+        "bsp_demark_countdown".to_string(),
+        klu.demark.max_countdown_idx(demark_dir) as f64,
+    );
+    if let Some(tdst) = klu.demark.get_tdst_peak(demark_dir) {
+        features.insert(
+            "bsp_demark_tdst_dis_rate".to_string(),
+            (klu.close - tdst) / klu.close,
+        );
+    }
+    for (&(trend_type, t), &v) in klu.trend.iter() {
+        let name = trend_type.to_string().to_lowercase();
+        if trend_type == TrendType::STD {
+            features.insert(format!("bsp_{}{}_rate", name, t), v / klu.close);
+        } else {
+            features.insert(
+                format!("bsp_{}{}_dis_rate", name, t),
+                (klu.close - v) / klu.close,
+            );
+        }
+    }
+    for (name, value) in klu.metric.iter().filter(|(_, v)| !v.is_builtin()) {
+        features.extend(
+            value
+                .to_dict(name)
+                .into_iter()
+                .map(|(k, v)| (format!("bsp_{}", k), v)),
+        );
+    }
+    features
+}
+
+// 所在线段的趋势线和通道，买点看支撑线，卖点看压力线
+fn trend_line_features<T>(seg: &CSeg<T>, is_buy: bool, x: i32, price: f64) -> HashMap<String, f64> {
+    let mut features = HashMap::new();
+    let trend_line = if is_buy {
+        &seg.support_trend_line
+    } else {
+        &seg.resistance_trend_line
+    };
+    if let Some(line_val) = trend_line
+        .as_ref()
+        .and_then(|t| t.line)
+        .and_then(|line| line.y_at(x))
+    {
+        features.insert(
+            "bsp_trend_line_dis_rate".to_string(),
+            (price - line_val) / price,
+        );
+    }
+    if let Some(pos) = seg.channel.as_ref().and_then(|c| c.position(x, price)) {
+        features.insert("bsp_channel_pos".to_string(), pos);
+    }
+    features
+}
+
+fn line_end_klu(line: &LineType) -> SharedCell<CKLineUnit> {
+    match line {
+        LineType::Bi(b) => b.borrow().get_end_klu(),
+        LineType::Seg(s) => s.borrow().get_end_klu(),
+        LineType::Tier(s) => s.borrow().get_end_klu(),
+    }
+}
+
+fn line_begin_klu(line: &LineType) -> SharedCell<CKLineUnit> {
+    match line {
+        LineType::Bi(b) => b.borrow().get_begin_klu(),
+        LineType::Seg(s) => s.borrow().get_begin_klu(),
+        LineType::Tier(s) => s.borrow().get_begin_klu(),
+    }
+}
+
+pub fn line_end_val(line: &LineType) -> f64 {
+    match line {
+        LineType::Bi(b) => b.borrow().get_end_val(),
+        LineType::Seg(s) => s.borrow().get_end_val(),
+        LineType::Tier(s) => s.borrow().get_end_val(),
+    }
+}
+
+fn line_amp(line: &LineType) -> f64 {
+    match line {
+        LineType::Bi(b) => b.borrow().amp(),
+        LineType::Seg(s) => s.borrow().amp(),
+        LineType::Tier(s) => s.borrow().amp(),
+    }
+}
+
+fn line_klu_cnt(line: &LineType) -> i32 {
+    line_end_klu(line).borrow().idx - line_begin_klu(line).borrow().idx + 1
+}
+
+// 前一根同向的笔/线段，即 pre.pre
+fn pre_same_dir_line(line: &LineType) -> Option<LineType> {
+    match line {
+        LineType::Bi(b) => {
+            let pre = b.borrow().pre.clone()?;
+            let pre_pre = pre.borrow().pre.clone()?;
+            Some(LineType::Bi(pre_pre))
+        }
+        LineType::Seg(s) => {
+            let pre = s.borrow().pre.clone()?;
+            let pre_pre = pre.borrow().pre.clone()?;
+            Some(LineType::Seg(pre_pre))
+        }
+        LineType::Tier(s) => {
+            let pre = s.borrow().pre.clone()?;
+            let pre_pre = pre.borrow().pre.clone()?;
+            Some(LineType::Tier(pre_pre))
+        }
+    }
+}
+
+pub fn line_volume(line: &LineType) -> f64 {
+    let end_idx = line_end_klu(line).borrow().idx;
+    let mut volume = 0.0;
+    let mut klu = Some(line_begin_klu(line));
+    while let Some(cur) = klu {
+        if cur.borrow().idx > end_idx {
+            break;
+        }
+        volume += cur
+            .borrow()
+            .trade_info
+            .metric
+            .get(DataField::FIELD_VOLUME)
+            .copied()
+            .flatten()
+            .unwrap_or(0.0);
+        klu = cur.borrow().next.clone();
+    }
+    volume
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Common::CTime::CTime;
+    use crate::Common::TradeInfo::CTradeInfo;
+    use crate::Math::Demark::CDemarkIndex;
+    use crate::Math::MetricModel::{MetricModel, MetricValue};
+    use crate::Math::ADX::CADX;
+    use crate::Math::ATR::CATR;
+    use crate::Math::BOLL::BOLLMetric;
+    use crate::Math::KDJ::KDJItem;
+    use crate::Math::MACD::CMACDItem;
+
+    fn klu(close: f64) -> CKLineUnit {
+        CKLineUnit {
+            kl_type: None,
+            time: CTime::new(2024, 1, 2, 0, 0, 0, false),
+            close,
+            open: close,
+            high: close,
+            low: close,
+            trade_info: CTradeInfo::new(&HashMap::new()),
+            demark: CDemarkIndex::new(),
+            sub_kl_list: Vec::new(),
+            sup_kl: None,
+            klc: None,
+            trend: HashMap::new(),
+            limit_flag: 0,
+            pre: None,
+            next: None,
+            idx: 0,
+            macd: None,
+            boll: None,
+            rsi: None,
+            kdj: None,
+            metric: HashMap::new(),
+        }
+    }
+
+    fn assert_feat(features: &HashMap<String, f64>, name: &str, expect: f64) {
+        let v = features[name];
+        assert!(
+            (v - expect).abs() < 1e-9,
+            "{}={} expect {}",
+            name,
+            v,
+            expect
+        );
+    }
+
+    #[test]
+    fn test_klu_indicator_features() {
+        let mut klu = klu(10.0);
+        // 没有任何指标时只有 demark 计数
+        let features = klu_indicator_features(&klu, true);
+        assert_eq!(features.len(), 2);
+        assert_feat(&features, "bsp_demark_setup", 0.0);
+        assert_feat(&features, "bsp_demark_countdown", 0.0);
+
+        klu.macd = Some(CMACDItem::new(0.0, 0.0, 0.3, 0.1));
+        klu.rsi = Some(30.0);
+        klu.kdj = Some(KDJItem::new(20.0, 30.0, 0.0));
+        klu.boll = Some(BOLLMetric {
+            theta: 1.0,
+            up: 12.0,
+            down: 8.0,
+            mid: 10.0,
+        });
+        klu.trend.insert((TrendType::MEAN, 5), 8.0);
+        klu.trend.insert((TrendType::MAX, 5), 12.5);
+        klu.trend.insert((TrendType::STD, 5), 0.5);
+        // key 与指标注册时的 name 一致
+        klu.metric
+            .insert(CATR::new(14).name(), MetricValue::Value(0.4));
+        klu.metric.insert(
+            CADX::new(14).name(),
+            MetricValue::Dict(HashMap::from([("plus_di".to_string(), 25.0)])),
+        );
+        // 内置指标已经写入字段，不会重复生成 bsp_{name}
+        klu.metric.insert("rsi".to_string(), MetricValue::Rsi(30.0));
+
+        let features = klu_indicator_features(&klu, true);
+        assert_feat(&features, "bsp_macd_dif", 0.03);
+        assert_feat(&features, "bsp_macd_dea", 0.01);
+        assert_feat(&features, "bsp_macd", 0.04);
+        assert_feat(&features, "bsp_rsi", 30.0);
+        assert_feat(&features, "bsp_kdj_k", 20.0);
+        assert_feat(&features, "bsp_boll_pos", 0.5);
+        assert_feat(&features, "bsp_boll_width_rate", 0.4);
+        assert_feat(&features, "bsp_mean5_dis_rate", 0.2);
+        assert_feat(&features, "bsp_max5_dis_rate", -0.25);
+        // 标准差不是价位，直接按收盘价归一化
+        assert_feat(&features, "bsp_std5_rate", 0.05);
+        assert!(!features.contains_key("bsp_std5_dis_rate"));
+        assert_feat(&features, "bsp_atr14", 0.4);
+        assert_feat(&features, "bsp_adx14_plus_di", 25.0);
+        assert!(!features.contains_key("bsp_rsi_rsi"));
+        assert_eq!(features.len(), 16);
+    }
+
+    #[test]
+    fn test_zs_features() {
+        let mut zs = CZS::new(None, true);
+        zs.low = 9.0;
+        zs.high = 11.0;
+        zs.mid = 10.0;
+        let features = zs_features(&zs, 12.0);
+        assert_feat(&features, "bsp_zs_height_rate", 0.2);
+        assert_feat(&features, "bsp_zs_dis_rate", 0.2);
+        // 没有起止K线时不给宽度
+        assert!(!features.contains_key("bsp_zs_width"));
+    }
+}
@@ -0,0 +1,328 @@
+use crate::BuySellPoint::BSPFeature::line_end_val;
+use crate::BuySellPoint::BS_Point::CBSPoint;
+use crate::Common::types::LineType;
+use crate::Common::CEnum::{BspType, RiskReason};
+use crate::Math::TrendLine::CTrendLine;
+use crate::Seg::Seg::CSeg;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CRiskLevel {
+    pub price: f64,
+    pub reason: RiskReason,
+}
+
+// 买卖点的止损/止盈候选位，入场价为买卖点所在K线收盘价
+// 买点止损在入场价下方、止盈在上方，卖点相反；均按离入场价由近到远排列
+#[derive(Clone, Debug)]
+pub struct CBspRisk {
+    pub is_buy: bool,
+    pub entry: f64,
+    pub stops: Vec<CRiskLevel>,
+    pub targets: Vec<CRiskLevel>,
+}
+
+impl CBspRisk {
+    pub fn new(is_buy: bool, entry: f64, stops: Vec<CRiskLevel>, targets: Vec<CRiskLevel>) -> Self {
+        let sign = if is_buy { 1.0 } else { -1.0 };
+        // 只保留在正确一侧的价位，同一价格只保留先加入的依据
+        let arrange = |lst: Vec<CRiskLevel>, side: f64| {
+            let mut lst: Vec<CRiskLevel> = lst
+                .into_iter()
+                .filter(|l| l.price.is_finite() && (l.price - entry) * side > 0.0)
+                .collect();
+            lst.sort_by(|a, b| (a.price - entry).abs().total_cmp(&(b.price - entry).abs()));
+            lst.dedup_by(|a, b| a.price == b.price);
+            lst
+        };
+        CBspRisk {
+            is_buy,
+            entry,
+            stops: arrange(stops, -sign),
+            targets: arrange(targets, sign),
+        }
+    }
+
+    pub fn stop_loss(&self) -> Option<&CRiskLevel> {
+        self.stops.first()
+    }
+
+    pub fn take_profit(&self) -> Option<&CRiskLevel> {
+        self.targets.first()
+    }
+
+    // 最近止盈距离 / 最近止损距离
+    pub fn risk_reward(&self) -> Option<f64> {
+        let stop = self.stop_loss()?;
+        let target = self.take_profit()?;
+        Some((target.price - self.entry).abs() / (self.entry - stop.price).abs())
+    }
+
+    // 导出用的列
+    pub fn to_columns(&self) -> Vec<(String, String)> {
+        let level2str = |l: Option<&CRiskLevel>| {
+            l.map_or(("None".to_string(), "None".to_string()), |l| {
+                (l.price.to_string(), l.reason.to_string())
+            })
+        };
+        let (stop_loss, stop_reason) = level2str(self.stop_loss());
+        let (take_profit, target_reason) = level2str(self.take_profit());
+        vec![
+            ("stop_loss".to_string(), stop_loss),
+            ("stop_reason".to_string(), stop_reason),
+            ("take_profit".to_string(), take_profit),
+            ("target_reason".to_string(), target_reason),
+            (
+                "risk_reward".to_string(),
+                self.risk_reward()
+                    .map_or("None".to_string(), |v| v.to_string()),
+            ),
+        ]
+    }
+}
+
+// 买卖点所在线段里和止损/止盈有关的价位
+#[derive(Default)]
+struct CSegLevels {
+    // 最后一个中枢的 (low, high, peak_low, peak_high)
+    zs: Option<(f64, f64, f64, f64)>,
+    // 上一线段的 (low, high)
+    pre_seg: Option<(f64, f64)>,
+    // 之前线段中时间上最近的一个中枢的 (low, high)
+    pre_zs: Option<(f64, f64)>,
+    // 支撑/压力趋势线在买卖点K线处的值
+    support_line: Option<f64>,
+    resistance_line: Option<f64>,
+}
+
+fn seg_levels<T>(seg: &CSeg<T>, x: i32) -> CSegLevels {
+    let line_val = |trend_line: &Option<CTrendLine>| {
+        trend_line
+            .as_ref()
+            .and_then(|t| t.line)
+            .and_then(|line| line.y_at(x))
+    };
+    // 往前找到第一个有中枢的线段即停止
+    let mut pre_zs = None;
+    let mut pre_seg = seg.pre.clone();
+    while let Some(cur) = pre_seg {
+        if let Some(zs) = cur.borrow().zs_lst.last() {
+            let zs = zs.borrow();
+            pre_zs = Some((zs.low, zs.high));
+            break;
+        }
+        pre_seg = cur.borrow().pre.clone();
+    }
+    CSegLevels {
+        zs: seg.zs_lst.last().map(|zs| {
+            let zs = zs.borrow();
+            (zs.low, zs.high, zs.peak_low, zs.peak_high)
+        }),
+        pre_seg: seg.pre.as_ref().map(|pre| {
+            let pre = pre.borrow();
+            (pre._low(), pre._high())
+        }),
+        pre_zs,
+        support_line: line_val(&seg.support_trend_line),
+        resistance_line: line_val(&seg.resistance_trend_line),
+    }
+}
+
+// 止损：一类买卖点笔的端点、所在线段最后一个中枢的 low/high/peak_low/peak_high、上一线段极值、同侧趋势线
+// 止盈：最后一个中枢的 ZD/ZG、之前最近一个中枢的边界、对侧趋势线
+pub fn cal_bsp_risk(bsp: &CBSPoint) -> CBspRisk {
+    let entry = bsp.klu.borrow().close;
+    let x = bsp.klu.borrow().idx;
+
+    let is_bsp1 = bsp
+        .bsp_type
+        .iter()
+        .any(|t| matches!(t, BspType::T1 | BspType::T1P));
+    let bsp1_val = if is_bsp1 {
+        Some(line_end_val(&bsp.bi))
+    } else {
+        bsp.relate_bsp1
+            .as_ref()
+            .map(|bsp1| line_end_val(&bsp1.borrow().bi))
+    };
+
+    let levels = match &bsp.bi {
+        LineType::Bi(bi) => bi
+            .borrow()
+            .parent_seg
+            .as_ref()
+            .map(|seg| seg_levels(&seg.borrow(), x)),
+        LineType::Seg(s) => s
+            .borrow()
+            .parent_seg
+            .as_ref()
+            .map(|seg| seg_levels(&seg.borrow(), x)),
+        LineType::Tier(s) => s
+            .borrow()
+            .parent_seg
+            .as_ref()
+            .map(|seg| seg_levels(&seg.borrow(), x)),
+    };
+
+    risk_from_levels(bsp.is_buy, entry, bsp1_val, &levels.unwrap_or_default())
+}
+
+fn risk_from_levels(
+    is_buy: bool,
+    entry: f64,
+    bsp1_val: Option<f64>,
+    levels: &CSegLevels,
+) -> CBspRisk {
+    let mut stops = Vec::new();
+    let mut targets = Vec::new();
+
+    if let Some(price) = bsp1_val {
+        stops.push(CRiskLevel {
+            price,
+            reason: RiskReason::BSP1_BI,
+        });
+    }
+
+    if let Some((low, high, peak_low, peak_high)) = levels.zs {
+        for (price, reason) in [
+            (low, RiskReason::ZS_LOW),
+            (high, RiskReason::ZS_HIGH),
+            (peak_low, RiskReason::ZS_PEAK_LOW),
+            (peak_high, RiskReason::ZS_PEAK_HIGH),
+        ] {
+            stops.push(CRiskLevel { price, reason });
+        }
+        targets.push(CRiskLevel {
+            price: low,
+            reason: RiskReason::ZS_LOW,
+        });
+        targets.push(CRiskLevel {
+            price: high,
+            reason: RiskReason::ZS_HIGH,
+        });
+    }
+
+    if let Some((low, high)) = levels.pre_seg {
+        stops.push(CRiskLevel {
+            price: if is_buy { low } else { high },
+            reason: RiskReason::PRE_SEG,
+        });
+    }
+
+    // 之前的中枢：买点取其下沿作为上方压力，卖点取其上沿
+    if let Some((low, high)) = levels.pre_zs {
+        targets.push(CRiskLevel {
+            price: if is_buy { low } else { high },
+            reason: RiskReason::NEXT_ZS,
+        });
+    }
+
+    let (stop_line, target_line) = if is_buy {
+        (levels.support_line, levels.resistance_line)
+    } else {
+        (levels.resistance_line, levels.support_line)
+    };
+    for (price, lst) in [(stop_line, &mut stops), (target_line, &mut targets)] {
+        if let Some(price) = price {
+            lst.push(CRiskLevel {
+                price,
+                reason: RiskReason::TREND_LINE,
+            });
+        }
+    }
+
+    CBspRisk::new(is_buy, entry, stops, targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(price: f64, reason: RiskReason) -> CRiskLevel {
+        CRiskLevel { price, reason }
+    }
+
+    #[test]
+    fn test_bsp_risk_arrange() {
+        let risk = CBspRisk::new(
+            true,
+            10.0,
+            vec![
+                level(9.0, RiskReason::ZS_LOW),
+                level(9.5, RiskReason::BSP1_BI),
+                level(10.5, RiskReason::ZS_HIGH),
+                level(9.5, RiskReason::PRE_SEG),
+            ],
+            vec![
+                level(12.0, RiskReason::NEXT_ZS),
+                level(11.0, RiskReason::ZS_HIGH),
+                level(9.8, RiskReason::ZS_LOW),
+            ],
+        );
+        assert_eq!(
+            risk.stops,
+            vec![
+                level(9.5, RiskReason::BSP1_BI),
+                level(9.0, RiskReason::ZS_LOW)
+            ]
+        );
+        assert_eq!(risk.take_profit(), Some(&level(11.0, RiskReason::ZS_HIGH)));
+        assert_eq!(risk.targets.len(), 2);
+        assert!((risk.risk_reward().unwrap() - 2.0).abs() < 1e-9);
+
+        let risk = CBspRisk::new(
+            false,
+            10.0,
+            vec![level(10.5, RiskReason::ZS_HIGH)],
+            vec![level(10.2, RiskReason::ZS_LOW)],
+        );
+        assert_eq!(risk.stop_loss().unwrap().price, 10.5);
+        assert!(risk.take_profit().is_none());
+        assert!(risk.risk_reward().is_none());
+    }
+
+    #[test]
+    fn test_cal_bsp_risk_levels() {
+        let levels = CSegLevels {
+            zs: Some((9.0, 11.0, 8.5, 11.5)),
+            pre_seg: Some((8.0, 13.0)),
+            pre_zs: Some((12.0, 14.0)),
+            support_line: Some(9.2),
+            resistance_line: Some(12.5),
+        };
+        // 三买：入场价在中枢上方
+        let risk = risk_from_levels(true, 11.8, Some(8.2), &levels);
+        let prices = |lst: &[CRiskLevel]| lst.iter().map(|l| l.price).collect::<Vec<_>>();
+        assert_eq!(
+            prices(&risk.stops),
+            vec![11.5, 11.0, 9.2, 9.0, 8.5, 8.2, 8.0]
+        );
+        assert_eq!(
+            risk.stop_loss(),
+            Some(&level(11.5, RiskReason::ZS_PEAK_HIGH))
+        );
+        // 之前的中枢只取最近一个，买点用其下沿
+        assert_eq!(
+            risk.targets,
+            vec![
+                level(12.0, RiskReason::NEXT_ZS),
+                level(12.5, RiskReason::TREND_LINE)
+            ]
+        );
+
+        // 卖点：止损在上方，之前中枢取上沿
+        let risk = risk_from_levels(false, 10.0, None, &levels);
+        assert_eq!(prices(&risk.stops), vec![11.0, 11.5, 12.5, 13.0]);
+        assert_eq!(
+            risk.targets,
+            vec![
+                level(9.2, RiskReason::TREND_LINE),
+                level(9.0, RiskReason::ZS_LOW)
+            ]
+        );
+
+        // 没有所在线段时只有一类买卖点的止损
+        let risk = risk_from_levels(true, 10.0, Some(9.0), &CSegLevels::default());
+        assert_eq!(risk.stops, vec![level(9.0, RiskReason::BSP1_BI)]);
+        assert!(risk.targets.is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::Bi::Bi::CBi;
+use crate::BuySellPoint::BSPFeature::line_volume;
+use crate::Common::types::{LineType, SharedCell};
+use crate::Seg::SegListComm::CSegListComm;
+use crate::ZS::ZSList::CZSList;
+use std::collections::HashMap;
+
+// 规则命中的一笔：买卖点落在该笔的终点，方向由笔的方向决定（向下笔为买点）
+pub struct CBspRuleHit {
+    pub bi: SharedCell<CBi>,
+    pub features: HashMap<String, f64>,
+}
+
+// 自定义买卖点规则，通过 CChanConfig::add_bsp_rule 注册，只在笔级别和内置买卖点一起计算
+// 产生的买卖点类型为 BspType::Custom(name)，需要在 bs_type 中配置 custom:name 才会输出
+pub trait BspRule {
+    fn name(&self) -> String;
+
+    fn cal(
+        &self,
+        bi_list: &[SharedCell<CBi>],
+        seg_list: &CSegListComm<CBi>,
+        zs_list: &CZSList,
+    ) -> Vec<CBspRuleHit>;
+}
+
+// 放量突破中枢后回抽不回中枢：
+// 离开中枢的那一笔成交量 >= 中枢内笔平均成交量 * volume_ratio，且之后反向的一笔不回到中枢内
+pub struct CZsBreakoutVolumeRule {
+    pub name: String,
+    pub volume_ratio: f64,
+}
+
+impl CZsBreakoutVolumeRule {
+    pub fn new(volume_ratio: f64) -> Self {
+        CZsBreakoutVolumeRule {
+            name: "zs_breakout_vol".to_string(),
+            volume_ratio,
+        }
+    }
+}
+
+impl CZsBreakoutVolumeRule {
+    // 突破笔相对中枢内笔平均成交量的放量倍数，不足 volume_ratio 时返回 None
+    fn breakout_volume_ratio(&self, zs_volume: f64, break_volume: f64) -> Option<f64> {
+        if zs_volume <= 0.0 {
+            return None;
+        }
+        let ratio = break_volume / zs_volume;
+        if ratio < self.volume_ratio {
+            return None;
+        }
+        Some(ratio)
+    }
+}
+
+// 突破笔终点在中枢外，且回抽笔不回到中枢内
+fn is_pullback_out_of_zs(
+    zs_low: f64,
+    zs_high: f64,
+    break_is_up: bool,
+    break_end_val: f64,
+    pullback_low: f64,
+    pullback_high: f64,
+) -> bool {
+    if break_is_up {
+        break_end_val > zs_high && pullback_low > zs_high
+    } else {
+        break_end_val < zs_low && pullback_high < zs_low
+    }
+}
+
+impl BspRule for CZsBreakoutVolumeRule {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn cal(
+        &self,
+        bi_list: &[SharedCell<CBi>],
+        _seg_list: &CSegListComm<CBi>,
+        zs_list: &CZSList,
+    ) -> Vec<CBspRuleHit> {
+        let mut hits = Vec::new();
+        for zs in zs_list.iter() {
+            let zs = zs.borrow();
+            if zs.is_one_bi_zs() || zs.bi_lst.is_empty() {
+                continue;
+            }
+            let end_bi_idx = match &zs.end_bi {
+                Some(LineType::Bi(bi)) => bi.borrow().idx as usize,
+                _ => continue,
+            };
+            // 中枢后第一笔为突破笔，再后一笔为回抽笔
+            let (break_bi, pullback_bi) =
+                match (bi_list.get(end_bi_idx + 1), bi_list.get(end_bi_idx + 2)) {
+                    (Some(b1), Some(b2)) => (b1, b2),
+                    _ => continue,
+                };
+            let zs_volume = zs.bi_lst.iter().map(line_volume).sum::<f64>() / zs.bi_lst.len() as f64;
+            let break_volume = line_volume(&LineType::Bi(break_bi.clone()));
+            let volume_ratio = match self.breakout_volume_ratio(zs_volume, break_volume) {
+                Some(ratio) => ratio,
+                None => continue,
+            };
+            let pullback = pullback_bi.borrow();
+            let break_bi = break_bi.borrow();
+            if is_pullback_out_of_zs(
+                zs.low,
+                zs.high,
+                break_bi.is_up(),
+                break_bi.get_end_val(),
+                pullback.low(),
+                pullback.high(),
+            ) {
+                hits.push(CBspRuleHit {
+                    bi: pullback_bi.clone(),
+                    features: HashMap::from([
+                        ("breakout_volume_ratio".to_string(), volume_ratio),
+                        (
+                            "breakout_zs_height_rate".to_string(),
+                            (zs.high - zs.low) / zs.mid,
+                        ),
+                    ]),
+                });
+            }
+        }
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breakout_volume_ratio() {
+        let rule = CZsBreakoutVolumeRule::new(1.5);
+        assert_eq!(rule.name(), "zs_breakout_vol");
+        assert_eq!(rule.breakout_volume_ratio(100.0, 200.0), Some(2.0));
+        assert_eq!(rule.breakout_volume_ratio(100.0, 150.0), Some(1.5));
+        assert_eq!(rule.breakout_volume_ratio(100.0, 149.0), None);
+        // 中枢内没有成交量时不判断
+        assert_eq!(rule.breakout_volume_ratio(0.0, 200.0), None);
+    }
+
+    #[test]
+    fn test_is_pullback_out_of_zs() {
+        // 中枢 [10, 12]
+        // 向上突破到15，回抽低点12.5不回中枢
+        assert!(is_pullback_out_of_zs(10.0, 12.0, true, 15.0, 12.5, 15.0));
+        // 回抽到11.5回到中枢内
+        assert!(!is_pullback_out_of_zs(10.0, 12.0, true, 15.0, 11.5, 15.0));
+        // 回抽低点恰好等于中枢上沿也算回到中枢
+        assert!(!is_pullback_out_of_zs(10.0, 12.0, true, 15.0, 12.0, 15.0));
+        // 突破笔没有离开中枢
+        assert!(!is_pullback_out_of_zs(10.0, 12.0, true, 11.8, 12.5, 11.8));
+        // 向下突破
+        assert!(is_pullback_out_of_zs(10.0, 12.0, false, 8.0, 8.0, 9.5));
+        assert!(!is_pullback_out_of_zs(10.0, 12.0, false, 8.0, 8.0, 10.5));
+    }
+}
@@ -1,18 +1,64 @@
-use crate::Common::func_util::parse_inf;
+use crate::Common::func_util::nearest_key;
 use crate::Common::CEnum::{BspType, MacdAlgo};
+use crate::Common::ChanException::{CChanException, ErrCode};
+use serde_json::Value;
 use std::collections::HashMap;
 
+// 买卖点配置支持的全部参数名
+pub const BSP_CONF_KEYS: [&str; 16] = [
+    "divergence_rate",
+    "min_zs_cnt",
+    "bsp1_only_multibi_zs",
+    "max_bs2_rate",
+    "macd_algo",
+    "bs1_peak",
+    "bs_type",
+    "bsp2_follow_1",
+    "bsp3_follow_1",
+    "bsp3_peak",
+    "bsp2s_follow_2",
+    "max_bsp2s_lv",
+    "strict_bsp3",
+    "score_thred",
+    "std_filter",
+    "divergence_report",
+];
+
+const BSP_TYPE_NAMES: [&str; 6] = ["1", "1p", "2", "2s", "3a", "3b"];
+
+// bs_type 中自定义买卖点写作 custom:规则名，例如 "1,2,custom:zs_breakout_vol"
+pub const CUSTOM_BSP_PREFIX: &str = "custom:";
+
+const MACD_ALGO_NAMES: [&str; 14] = [
+    "area",
+    "peak",
+    "full_area",
+    "diff",
+    "slope",
+    "amp",
+    "amount",
+    "volumn",
+    "amount_avg",
+    "volumn_avg",
+    "turnrate_avg",
+    "rsi",
+    "half_obverse",
+    "half_reverse",
+];
+
+#[derive(Clone)]
 pub struct CBSPointConfig {
-    b_conf: CPointConfig,
-    s_conf: CPointConfig,
+    pub b_conf: CPointConfig,
+    pub s_conf: CPointConfig,
 }
 
 impl CBSPointConfig {
-    pub fn new(args: HashMap<String, String>) -> Self {
-        CBSPointConfig {
-            b_conf: CPointConfig::new(&args),
-            s_conf: CPointConfig::new(&args),
-        }
+    pub fn new(args: &HashMap<String, Value>) -> Result<Self, CChanException> {
+        let conf = CPointConfig::new(args)?;
+        Ok(CBSPointConfig {
+            b_conf: conf.clone(),
+            s_conf: conf,
+        })
     }
 
     pub fn get_bs_config(&self, is_buy: bool) -> &CPointConfig {
@@ -22,8 +68,39 @@ impl CBSPointConfig {
             &self.s_conf
         }
     }
+
+    // 买卖两侧配置里出现过的自定义买卖点类型
+    pub fn custom_types(&self) -> Vec<BspType> {
+        let mut types: Vec<BspType> = Vec::new();
+        for t in self
+            .b_conf
+            .target_types
+            .iter()
+            .chain(&self.s_conf.target_types)
+        {
+            if t.is_custom() && !types.contains(t) {
+                types.push(t.clone());
+            }
+        }
+        types
+    }
+
+    pub fn remove_custom_types(&mut self) {
+        self.b_conf.remove_custom_types();
+        self.s_conf.remove_custom_types();
+    }
+}
+
+impl Default for CBSPointConfig {
+    fn default() -> Self {
+        CBSPointConfig {
+            b_conf: CPointConfig::default(),
+            s_conf: CPointConfig::default(),
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct CPointConfig {
     pub divergence_rate: f64,
     pub min_zs_cnt: i32,
@@ -39,82 +116,404 @@ pub struct CPointConfig {
     pub bsp2s_follow_2: bool,
     pub max_bsp2s_lv: Option<i32>,
     pub strict_bsp3: bool,
+    // 配置了打分模型时，分数低于该值的买卖点不加入列表
+    pub score_thred: Option<f64>,
+    // (窗口, 最小值)：买卖点K线收盘价的滚动标准差 / 收盘价 低于最小值时不加入列表
+    pub std_filter: Option<(usize, f64)>,
+    // 一类买卖点计算全部力度指标的背驰报告并加入特征，关闭时只算 macd_algo
+    pub divergence_report: bool,
 }
 
-impl CPointConfig {
-    pub fn new(args: &HashMap<String, String>) -> Self {
-        let mut config = CPointConfig {
-            divergence_rate: args["divergence_rate"].parse().unwrap(),
-            min_zs_cnt: args["min_zs_cnt"].parse().unwrap(),
-            bsp1_only_multibi_zs: args["bsp1_only_multibi_zs"].parse().unwrap(),
-            max_bs2_rate: args["max_bs2_rate"].parse().unwrap(),
-            macd_algo: MacdAlgo::Area, // Temporary value, will be set later
-            bs1_peak: args["bs1_peak"].parse().unwrap(),
-            tmp_target_types: args["bs_type"]
-                .split(',')
-                .map(|s| s.trim().to_string())
+impl Default for CPointConfig {
+    fn default() -> Self {
+        let tmp_target_types: Vec<String> = BSP_TYPE_NAMES.iter().map(|s| s.to_string()).collect();
+        CPointConfig {
+            divergence_rate: f64::INFINITY,
+            min_zs_cnt: 1,
+            bsp1_only_multibi_zs: true,
+            max_bs2_rate: 0.9999,
+            macd_algo: MacdAlgo::Peak,
+            bs1_peak: true,
+            target_types: tmp_target_types
+                .iter()
+                .filter_map(|t| bsp_type_from_name(t))
                 .collect(),
-            target_types: Vec::new(),
-            bsp2_follow_1: args["bsp2_follow_1"].parse().unwrap(),
-            bsp3_follow_1: args["bsp3_follow_1"].parse().unwrap(),
-            bsp3_peak: args["bsp3_peak"].parse().unwrap(),
-            bsp2s_follow_2: args["bsp2s_follow_2"].parse().unwrap(),
-            max_bsp2s_lv: args["max_bsp2s_lv"].parse().ok(),
-            strict_bsp3: args["strict_bsp3"].parse().unwrap(),
-        };
-
-        assert!(config.max_bs2_rate <= 1.0);
-        config.set_macd_algo(&args["macd_algo"]);
-        config.parse_target_type();
+            tmp_target_types,
+            bsp2_follow_1: true,
+            bsp3_follow_1: true,
+            bsp3_peak: false,
+            bsp2s_follow_2: false,
+            max_bsp2s_lv: None,
+            strict_bsp3: false,
+            score_thred: None,
+            std_filter: None,
+            divergence_report: false,
+        }
+    }
+}
 
-        config
+impl CPointConfig {
+    // 一次性检查所有参数，把全部错误合在一起返回
+    pub fn new(args: &HashMap<String, Value>) -> Result<Self, CChanException> {
+        let mut config = CPointConfig::default();
+        let mut keys: Vec<&String> = args.keys().collect();
+        keys.sort();
+        let errors: Vec<String> = keys
+            .into_iter()
+            .filter_map(|k| config.set_value(k, &args[k]).err())
+            .collect();
+        if !errors.is_empty() {
+            return Err(config_error(&errors));
+        }
+        Ok(config)
     }
 
-    pub fn parse_target_type(&mut self) {
-        let d: HashMap<String, BspType> = BspType::iter().map(|x| (x.to_string(), x)).collect();
+    pub fn parse_target_type(&mut self) -> Result<(), CChanException> {
+        let mut target_types = Vec::new();
+        let mut errors = Vec::new();
         for target_t in &self.tmp_target_types {
-            assert!(["1", "2", "3a", "2s", "1p", "3b"].contains(&target_t.as_str()));
+            match bsp_type_from_name(target_t) {
+                Some(bsp_type) => target_types.push(bsp_type),
+                None => errors.push(unknown_value_msg("bs_type", target_t, &BSP_TYPE_NAMES)),
+            }
         }
-        self.target_types = self.tmp_target_types.iter().map(|t| d[t].clone()).collect();
-    }
-
-    pub fn set_macd_algo(&mut self, macd_algo: &str) {
-        let d = HashMap::from([
-            ("area", MacdAlgo::Area),
-            ("peak", MacdAlgo::Peak),
-            ("full_area", MacdAlgo::FullArea),
-            ("diff", MacdAlgo::Diff),
-            ("slope", MacdAlgo::Slope),
-            ("amp", MacdAlgo::Amp),
-            ("amount", MacdAlgo::Amount),
-            ("volumn", MacdAlgo::Volumn),
-            ("amount_avg", MacdAlgo::AmountAvg),
-            ("volumn_avg", MacdAlgo::VolumnAvg),
-            ("turnrate_avg", MacdAlgo::AmountAvg),
-            ("rsi", MacdAlgo::Rsi),
-        ]);
-        self.macd_algo = d[macd_algo].clone();
-    }
-
-    pub fn set(&mut self, k: &str, v: &str) {
-        let v = parse_inf(v);
-        if k == "macd_algo" {
-            self.set_macd_algo(v);
-        } else {
-            match k {
-                "divergence_rate" => self.divergence_rate = v.parse().unwrap(),
-                "min_zs_cnt" => self.min_zs_cnt = v.parse().unwrap(),
-                "bsp1_only_multibi_zs" => self.bsp1_only_multibi_zs = v.parse().unwrap(),
-                "max_bs2_rate" => self.max_bs2_rate = v.parse().unwrap(),
-                "bs1_peak" => self.bs1_peak = v.parse().unwrap(),
-                "bsp2_follow_1" => self.bsp2_follow_1 = v.parse().unwrap(),
-                "bsp3_follow_1" => self.bsp3_follow_1 = v.parse().unwrap(),
-                "bsp3_peak" => self.bsp3_peak = v.parse().unwrap(),
-                "bsp2s_follow_2" => self.bsp2s_follow_2 = v.parse().unwrap(),
-                "max_bsp2s_lv" => self.max_bsp2s_lv = v.parse().ok(),
-                "strict_bsp3" => self.strict_bsp3 = v.parse().unwrap(),
-                _ => panic!("Unknown key: {}", k),
+        if !errors.is_empty() {
+            return Err(config_error(&errors));
+        }
+        self.target_types = target_types;
+        Ok(())
+    }
+
+    pub fn remove_custom_types(&mut self) {
+        self.target_types.retain(|t| !t.is_custom());
+        self.tmp_target_types
+            .retain(|t| !t.starts_with(CUSTOM_BSP_PREFIX));
+    }
+
+    pub fn set_macd_algo(&mut self, macd_algo: &str) -> Result<(), CChanException> {
+        self.set_value("macd_algo", &Value::from(macd_algo))
+            .map_err(|e| config_error(&[e]))
+    }
+
+    pub fn set(&mut self, k: &str, v: Value) -> Result<(), CChanException> {
+        self.set_value(k, &v).map_err(|e| config_error(&[e]))
+    }
+
+    // 设置单个参数并做取值范围检查，出错时返回错误描述
+    fn set_value(&mut self, k: &str, v: &Value) -> Result<(), String> {
+        match k {
+            "divergence_rate" => {
+                // serde_json 不能表示 inf，null 视为 inf
+                let rate = if v.is_null() {
+                    f64::INFINITY
+                } else {
+                    value_to_f64(k, v)?
+                };
+                if rate.is_nan() || rate <= 0.0 {
+                    return Err(format!("divergence_rate={} must be > 0", rate));
+                }
+                self.divergence_rate = rate;
+            }
+            "min_zs_cnt" => {
+                let cnt = value_to_i32(k, v)?;
+                if cnt < 0 {
+                    return Err(format!("min_zs_cnt={} must be >= 0", cnt));
+                }
+                self.min_zs_cnt = cnt;
+            }
+            "max_bs2_rate" => {
+                let rate = value_to_f64(k, v)?;
+                if !(rate > 0.0 && rate <= 1.0) {
+                    return Err(format!("max_bs2_rate={} must be in (0, 1]", rate));
+                }
+                self.max_bs2_rate = rate;
+            }
+            "macd_algo" => {
+                let name = value_to_str(k, v)?;
+                self.macd_algo = macd_algo_from_name(&name)
+                    .ok_or_else(|| unknown_value_msg(k, &name, &MACD_ALGO_NAMES))?;
+            }
+            "bs_type" => {
+                let names: Vec<String> = value_to_str(k, v)?
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .collect();
+                let errors: Vec<String> = names
+                    .iter()
+                    .filter(|t| bsp_type_from_name(t).is_none())
+                    .map(|t| unknown_value_msg(k, t, &BSP_TYPE_NAMES))
+                    .collect();
+                if !errors.is_empty() {
+                    return Err(errors.join("; "));
+                }
+                self.target_types = names.iter().filter_map(|t| bsp_type_from_name(t)).collect();
+                self.tmp_target_types = names;
+            }
+            "max_bsp2s_lv" => {
+                self.max_bsp2s_lv = if v.is_null() {
+                    None
+                } else {
+                    let lv = value_to_i32(k, v)?;
+                    if lv < 1 {
+                        return Err(format!("max_bsp2s_lv={} must be >= 1 or null", lv));
+                    }
+                    Some(lv)
+                };
+            }
+            "score_thred" => {
+                self.score_thred = if v.is_null() {
+                    None
+                } else {
+                    Some(value_to_f64(k, v)?)
+                };
+            }
+            "std_filter" => {
+                self.std_filter = match v {
+                    Value::Null => None,
+                    Value::Array(arr) if arr.len() == 2 => {
+                        let t = value_to_i32(k, &arr[0])?;
+                        let min_rate = value_to_f64(k, &arr[1])?;
+                        if t < 2 || min_rate < 0.0 {
+                            return Err(format!(
+                                "std_filter={} should be [window >= 2, min_rate >= 0]",
+                                v
+                            ));
+                        }
+                        Some((t as usize, min_rate))
+                    }
+                    _ => {
+                        return Err(format!(
+                            "std_filter={} should be null or [window, min_rate]",
+                            v
+                        ))
+                    }
+                };
             }
+            "bsp1_only_multibi_zs" => self.bsp1_only_multibi_zs = value_to_bool(k, v)?,
+            "bs1_peak" => self.bs1_peak = value_to_bool(k, v)?,
+            "bsp2_follow_1" => self.bsp2_follow_1 = value_to_bool(k, v)?,
+            "bsp3_follow_1" => self.bsp3_follow_1 = value_to_bool(k, v)?,
+            "bsp3_peak" => self.bsp3_peak = value_to_bool(k, v)?,
+            "bsp2s_follow_2" => self.bsp2s_follow_2 = value_to_bool(k, v)?,
+            "strict_bsp3" => self.strict_bsp3 = value_to_bool(k, v)?,
+            "divergence_report" => self.divergence_report = value_to_bool(k, v)?,
+            _ => return Err(unknown_key_msg(k, &BSP_CONF_KEYS)),
         }
+        Ok(())
+    }
+}
+
+fn config_error(errors: &[String]) -> CChanException {
+    CChanException::new(
+        format!("invalid bsp config: {}", errors.join("; ")),
+        ErrCode::ConfigError,
+    )
+}
+
+pub fn unknown_key_msg(k: &str, candidates: &[&str]) -> String {
+    match nearest_key(k, candidates) {
+        Some(similar) => format!("unknown para = {}, did you mean {}?", k, similar),
+        None => format!("unknown para = {}", k),
+    }
+}
+
+fn unknown_value_msg(k: &str, v: &str, candidates: &[&str]) -> String {
+    match nearest_key(v, candidates) {
+        Some(similar) => format!("{}: unknown value {}, did you mean {}?", k, v, similar),
+        None => format!(
+            "{}: unknown value {}, should be one of {}",
+            k,
+            v,
+            candidates.join(",")
+        ),
+    }
+}
+
+fn value_to_f64(k: &str, v: &Value) -> Result<f64, String> {
+    match v {
+        Value::Number(n) => n
+            .as_f64()
+            .ok_or_else(|| format!("{}={} is not a number", k, v)),
+        Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "inf" | "+inf" | "infinity" | "float('inf')" => Ok(f64::INFINITY),
+            "-inf" | "-infinity" | "float('-inf')" => Ok(f64::NEG_INFINITY),
+            other => other
+                .parse()
+                .map_err(|_| format!("{}={} is not a number", k, s)),
+        },
+        _ => Err(format!("{}={} is not a number", k, v)),
+    }
+}
+
+fn value_to_i32(k: &str, v: &Value) -> Result<i32, String> {
+    match v {
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i32::try_from(i).map_err(|_| format!("{}={} is out of i32 range", k, v)),
+            None if n.is_u64() => Err(format!("{}={} is out of i32 range", k, v)),
+            None => Err(format!("{}={} is not an integer", k, v)),
+        },
+        Value::String(s) => s
+            .trim()
+            .parse()
+            .map_err(|_| format!("{}={} is not an integer", k, s)),
+        _ => Err(format!("{}={} is not an integer", k, v)),
+    }
+}
+
+fn value_to_bool(k: &str, v: &Value) -> Result<bool, String> {
+    match v {
+        Value::Bool(b) => Ok(*b),
+        Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!("{}={} is not a bool", k, s)),
+        },
+        _ => Err(format!("{}={} is not a bool", k, v)),
+    }
+}
+
+fn value_to_str(k: &str, v: &Value) -> Result<String, String> {
+    v.as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| format!("{}={} is not a string", k, v))
+}
+
+fn bsp_type_from_name(name: &str) -> Option<BspType> {
+    if let Some(rule_name) = name.strip_prefix(CUSTOM_BSP_PREFIX) {
+        return BspType::from_custom_name(rule_name);
+    }
+    match name {
+        "1" => Some(BspType::T1),
+        "1p" => Some(BspType::T1P),
+        "2" => Some(BspType::T2),
+        "2s" => Some(BspType::T2S),
+        "3a" => Some(BspType::T3A),
+        "3b" => Some(BspType::T3B),
+        _ => None,
+    }
+}
+
+fn macd_algo_from_name(name: &str) -> Option<MacdAlgo> {
+    match name {
+        "area" => Some(MacdAlgo::Area),
+        "peak" => Some(MacdAlgo::Peak),
+        "full_area" => Some(MacdAlgo::FullArea),
+        "diff" => Some(MacdAlgo::Diff),
+        "slope" => Some(MacdAlgo::Slope),
+        "amp" => Some(MacdAlgo::Amp),
+        "amount" => Some(MacdAlgo::Amount),
+        "volumn" => Some(MacdAlgo::Volumn),
+        "amount_avg" => Some(MacdAlgo::AmountAvg),
+        "volumn_avg" => Some(MacdAlgo::VolumnAvg),
+        "turnrate_avg" => Some(MacdAlgo::TurnrateAvg),
+        "rsi" => Some(MacdAlgo::Rsi),
+        "half_obverse" => Some(MacdAlgo::HalfObverse),
+        "half_reverse" => Some(MacdAlgo::HalfReverse),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(v: &[(&str, Value)]) -> HashMap<String, Value> {
+        v.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+    }
+
+    #[test]
+    fn test_default_args() {
+        let conf = CPointConfig::new(&args(&[
+            ("divergence_rate", Value::Null),
+            ("bs_type", Value::from("1,2,3a")),
+            ("macd_algo", Value::from("area")),
+        ]))
+        .unwrap();
+        assert!(conf.divergence_rate.is_infinite());
+        assert_eq!(
+            conf.target_types,
+            vec![BspType::T1, BspType::T2, BspType::T3A]
+        );
+        assert_eq!(conf.macd_algo, MacdAlgo::Area);
+    }
+
+    #[test]
+    fn test_report_all_errors() {
+        let err = CPointConfig::new(&args(&[
+            ("divergance_rate", Value::from(0.9)),
+            ("max_bs2_rate", Value::from(1.5)),
+            ("bs_type", Value::from("1,3c")),
+            ("bs1_peak", Value::from("yes")),
+        ]))
+        .err()
+        .unwrap();
+        assert_eq!(err.errcode as i32, ErrCode::ConfigError as i32);
+        assert!(err.msg.contains("did you mean divergence_rate"));
+        assert!(err.msg.contains("max_bs2_rate=1.5"));
+        assert!(err.msg.contains("unknown value 3c"));
+        assert!(err.msg.contains("bs1_peak=yes"));
+    }
+
+    #[test]
+    fn test_value_to_i32() {
+        assert_eq!(value_to_i32("k", &Value::from(3)), Ok(3));
+        assert_eq!(value_to_i32("k", &Value::from(" -2 ")), Ok(-2));
+        assert!(value_to_i32("k", &Value::from(1i64 << 40))
+            .unwrap_err()
+            .contains("out of i32 range"));
+        assert!(value_to_i32("k", &Value::from(1.5))
+            .unwrap_err()
+            .contains("not an integer"));
+        assert!(value_to_i32("k", &Value::from(u64::MAX))
+            .unwrap_err()
+            .contains("out of i32 range"));
+    }
+
+    #[test]
+    fn test_custom_bs_type() {
+        let mut conf = CPointConfig::default();
+        assert!(conf
+            .set("bs_type", Value::from("1,custom:zs_breakout_vol"))
+            .is_ok());
+        assert_eq!(
+            conf.target_types,
+            vec![BspType::T1, BspType::Custom("zs_breakout_vol".to_string())]
+        );
+        // 没有前缀的名字不会被当作自定义类型
+        assert!(conf
+            .set("bs_type", Value::from("1,zs_breakout_vol"))
+            .is_err());
+        // 规则名不能为空或与内置类型重名
+        assert!(conf.set("bs_type", Value::from("custom:")).is_err());
+        assert!(conf.set("bs_type", Value::from("custom:T1")).is_err());
+        assert!(conf.set("bs_type", Value::from("custom:2s")).is_err());
+        assert_eq!(conf.target_types.len(), 2);
+
+        conf.remove_custom_types();
+        assert_eq!(conf.target_types, vec![BspType::T1]);
+        assert_eq!(conf.tmp_target_types, vec!["1".to_string()]);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut conf = CPointConfig::default();
+        assert!(conf.set("macd_algo", "slope".into()).is_ok());
+        assert_eq!(conf.macd_algo, MacdAlgo::Slope);
+        assert!(conf.set("macd_algo", "slop".into()).is_err());
+        assert!(conf.set("min_zs_cnt", Value::from(-1)).is_err());
+        assert!(conf.set("macd_algo", "turnrate_avg".into()).is_ok());
+        assert_eq!(conf.macd_algo, MacdAlgo::TurnrateAvg);
+        assert!(conf.set("macd_algo", "half_reverse".into()).is_ok());
+        assert_eq!(conf.macd_algo, MacdAlgo::HalfReverse);
+        assert!(conf
+            .set("std_filter", serde_json::json!([20, 0.01]))
+            .is_ok());
+        assert_eq!(conf.std_filter, Some((20, 0.01)));
+        assert!(conf
+            .set("std_filter", serde_json::json!([1, 0.01]))
+            .is_err());
+        assert!(conf.set("std_filter", Value::from(20)).is_err());
+        assert!(conf.set("std_filter", Value::Null).is_ok());
+        assert_eq!(conf.std_filter, None);
     }
 }
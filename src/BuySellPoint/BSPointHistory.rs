@@ -0,0 +1,447 @@
+use crate::BuySellPoint::BS_Point::CBSPoint;
+use crate::Common::types::SharedCell;
+use crate::Common::CEnum::BspState;
+use crate::Common::CTime::CTime;
+use crate::KLine::KLine_Unit::CKLineUnit;
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+#[derive(Clone, Debug)]
+pub struct CBSPointEvent {
+    pub state: BspState,
+    pub bsp_type: String,
+    // 发生状态变化时最新一根K线，回测时只能使用 klu_idx 不晚于当前bar的事件
+    pub klu_idx: i32,
+    pub time: CTime,
+    pub reason: String,
+}
+
+pub struct CBSPointRecord {
+    pub id: String,
+    pub is_buy: bool,
+    pub klu_idx: i32,
+    pub klu_time: CTime,
+    pub bsp: SharedCell<CBSPoint>,
+    // 首次出现时的特征快照，之后买卖点重算带来的特征变化不会写回，保证没有未来函数
+    pub first_features: HashMap<String, f64>,
+    pub events: Vec<CBSPointEvent>,
+}
+
+impl CBSPointRecord {
+    pub fn state(&self) -> BspState {
+        // 记录创建时就带有 appear 事件，events 不会为空
+        self.events
+            .last()
+            .expect("CBSPointRecord always has an appear event")
+            .state
+    }
+
+    // cur_klu_idx 那根K线收盘时该买卖点的状态，还未出现时为 None
+    pub fn state_at(&self, cur_klu_idx: i32) -> Option<BspState> {
+        self.events
+            .iter()
+            .take_while(|e| e.klu_idx <= cur_klu_idx)
+            .last()
+            .map(|e| e.state)
+    }
+
+    pub fn first_seen_idx(&self) -> i32 {
+        self.events[0].klu_idx
+    }
+}
+
+// 记录每个买卖点从出现、确认到失效的完整过程
+// 同一根K线上同方向的买卖点视为同一个（id 相同），被重画后再次出现会重新记为 PROVISIONAL
+pub struct CBSPointHistory {
+    records: Vec<CBSPointRecord>,
+    id_dict: HashMap<String, usize>,
+    // 尚未失效的记录下标，失效判断只需要看这些记录
+    live_idx: BTreeSet<usize>,
+    // 最近一次 update 所在K线，以及这根K线上新增过事件的记录下标
+    cur_klu_idx: i32,
+    cur_event_idx: BTreeSet<usize>,
+}
+
+impl CBSPointHistory {
+    pub fn new() -> Self {
+        CBSPointHistory {
+            records: Vec::new(),
+            id_dict: HashMap::new(),
+            live_idx: BTreeSet::new(),
+            cur_klu_idx: -1,
+            cur_event_idx: BTreeSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CBSPointRecord> {
+        self.records.iter()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CBSPointRecord> {
+        self.id_dict.get(id).map(|&i| &self.records[i])
+    }
+
+    // cur_klu_idx 时刻可知且仍然有效的买卖点，用于严格无未来函数的回测
+    pub fn knowable_at(&self, cur_klu_idx: i32) -> Vec<&CBSPointRecord> {
+        self.records
+            .iter()
+            .filter(|r| {
+                matches!(
+                    r.state_at(cur_klu_idx),
+                    Some(BspState::PROVISIONAL) | Some(BspState::CONFIRMED)
+                )
+            })
+            .collect()
+    }
+
+    // 最近一次 update 所在K线上发生的事件，每个买卖点取这根K线上的最后一个事件，按记录先后排列
+    pub fn cur_events(&self) -> impl Iterator<Item = (&CBSPointRecord, &CBSPointEvent)> {
+        self.cur_event_idx.iter().map(|&i| {
+            let record = &self.records[i];
+            (record, record.events.last().unwrap())
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        bsp_lst: &[SharedCell<CBSPoint>],
+        last_sure_pos: i32,
+        cur_klu: &SharedCell<CKLineUnit>,
+    ) {
+        let cur_klu_idx = cur_klu.borrow().idx;
+        let cur_time = cur_klu.borrow().time.clone();
+        let mut live_idx = BTreeSet::new();
+        // 同一根K线可能 update 多次，换K线时才清空
+        if cur_klu_idx != self.cur_klu_idx {
+            self.cur_klu_idx = cur_klu_idx;
+            self.cur_event_idx.clear();
+        }
+
+        for bsp in bsp_lst {
+            let id = bsp.borrow().id.clone();
+            let klu_idx = bsp.borrow().klu.borrow().idx;
+            let bsp_type = bsp.borrow().type2str();
+            let state = if klu_idx <= last_sure_pos {
+                BspState::CONFIRMED
+            } else {
+                BspState::PROVISIONAL
+            };
+            bsp.borrow_mut().state = state;
+
+            let i = match self.id_dict.get(&id) {
+                Some(&i) => i,
+                None => {
+                    self.id_dict.insert(id.clone(), self.records.len());
+                    self.records.push(CBSPointRecord {
+                        id,
+                        is_buy: bsp.borrow().is_buy,
+                        klu_idx,
+                        klu_time: bsp.borrow().klu.borrow().time.clone(),
+                        bsp: Rc::clone(bsp),
+                        first_features: bsp
+                            .borrow()
+                            .features
+                            .items()
+                            .map(|(k, v)| (k.clone(), *v))
+                            .collect(),
+                        events: Vec::new(),
+                    });
+                    self.records.len() - 1
+                }
+            };
+            live_idx.insert(i);
+            let record = &mut self.records[i];
+            record.bsp = Rc::clone(bsp);
+            if let Some(reason) = alive_event_reason(record.events.last(), state, &bsp_type) {
+                record.events.push(CBSPointEvent {
+                    state,
+                    bsp_type,
+                    klu_idx: cur_klu_idx,
+                    time: cur_time.clone(),
+                    reason: reason.to_string(),
+                });
+                self.cur_event_idx.insert(i);
+            }
+        }
+
+        for &i in self.live_idx.difference(&live_idx) {
+            let record = &mut self.records[i];
+            record.bsp.borrow_mut().state = BspState::INVALIDATED;
+            let bsp_type = record
+                .events
+                .last()
+                .map_or_else(|| record.bsp.borrow().type2str(), |e| e.bsp_type.clone());
+            let reason =
+                invalid_event_reason(record.bsp.borrow().get_line_end_klu_idx(), record.klu_idx);
+            record.events.push(CBSPointEvent {
+                state: BspState::INVALIDATED,
+                bsp_type,
+                klu_idx: cur_klu_idx,
+                time: cur_time.clone(),
+                reason: reason.to_string(),
+            });
+            self.cur_event_idx.insert(i);
+        }
+        self.live_idx = live_idx;
+    }
+}
+
+// 买卖点仍在列表中时相对上一个事件的变化，None 表示状态和类型都没变
+fn alive_event_reason(
+    last_event: Option<&CBSPointEvent>,
+    state: BspState,
+    bsp_type: &str,
+) -> Option<&'static str> {
+    match last_event {
+        None => Some("appear"),
+        Some(e) if e.state == state && e.bsp_type == bsp_type => None,
+        Some(e) if e.state == BspState::INVALIDATED => Some("reappear"),
+        Some(e) if e.state != state => Some("seg sure"),
+        Some(_) => Some("type changed"),
+    }
+}
+
+// 所在笔的终点已经变了说明是虚笔被重画，否则是买卖点条件不再满足
+fn invalid_event_reason(line_end_klu_idx: i32, klu_idx: i32) -> &'static str {
+    if line_end_klu_idx != klu_idx {
+        "bi redrawn"
+    } else {
+        "condition not satisfied"
+    }
+}
+
+impl Default for CBSPointHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Bi::Bi::CBi;
+    use crate::BuySellPoint::BSPRisk::CBspRisk;
+    use crate::ChanModel::Features::CFeatures;
+    use crate::Common::types::LineType;
+    use crate::Common::CEnum::{BspType, FxType, KlineDir};
+    use crate::Common::TradeInfo::CTradeInfo;
+    use crate::KLine::KLine::CKLine;
+    use crate::Math::Demark::CDemarkIndex;
+    use std::cell::RefCell;
+
+    fn event(state: BspState, bsp_type: &str, reason: &str) -> CBSPointEvent {
+        CBSPointEvent {
+            state,
+            bsp_type: bsp_type.to_string(),
+            klu_idx: 0,
+            time: CTime::new(2024, 1, 2, 0, 0, 0, false),
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_alive_event_reason() {
+        // 首次出现
+        assert_eq!(
+            alive_event_reason(None, BspState::PROVISIONAL, "1"),
+            Some("appear")
+        );
+        let provisional = event(BspState::PROVISIONAL, "1", "appear");
+        // 没有变化不记事件
+        assert_eq!(
+            alive_event_reason(Some(&provisional), BspState::PROVISIONAL, "1"),
+            None
+        );
+        // 线段确认
+        assert_eq!(
+            alive_event_reason(Some(&provisional), BspState::CONFIRMED, "1"),
+            Some("seg sure")
+        );
+        assert_eq!(
+            alive_event_reason(Some(&provisional), BspState::PROVISIONAL, "1,2s"),
+            Some("type changed")
+        );
+        // 失效后重新出现
+        let invalidated = event(BspState::INVALIDATED, "1", "bi redrawn");
+        assert_eq!(
+            alive_event_reason(Some(&invalidated), BspState::PROVISIONAL, "1"),
+            Some("reappear")
+        );
+        assert_eq!(
+            alive_event_reason(Some(&invalidated), BspState::CONFIRMED, "1"),
+            Some("reappear")
+        );
+    }
+
+    #[test]
+    fn test_invalid_event_reason() {
+        assert_eq!(invalid_event_reason(25, 20), "bi redrawn");
+        assert_eq!(invalid_event_reason(20, 20), "condition not satisfied");
+    }
+
+    fn klu(idx: i32, high: f64, low: f64) -> SharedCell<CKLineUnit> {
+        Rc::new(RefCell::new(CKLineUnit {
+            kl_type: None,
+            time: CTime::new(2024, 1, 1 + idx as u32, 0, 0, 0, false),
+            close: (high + low) / 2.0,
+            open: (high + low) / 2.0,
+            high,
+            low,
+            trade_info: CTradeInfo::new(&HashMap::new()),
+            demark: CDemarkIndex::new(),
+            sub_kl_list: Vec::new(),
+            sup_kl: None,
+            klc: None,
+            trend: HashMap::new(),
+            limit_flag: 0,
+            pre: None,
+            next: None,
+            idx,
+            macd: None,
+            boll: None,
+            rsi: None,
+            kdj: None,
+            metric: HashMap::new(),
+        }))
+    }
+
+    fn klc(klu: SharedCell<CKLineUnit>, fx: FxType) -> SharedCell<CKLine> {
+        let idx = klu.borrow().idx;
+        let mut klc = CKLine::new(klu, idx, KlineDir::UP);
+        klc.fx = Some(fx);
+        Rc::new(RefCell::new(klc))
+    }
+
+    // 一根从 idx=0 到 idx=10 的向下笔，终点上的一买
+    fn buy_bsp(feat: f64) -> SharedCell<CBSPoint> {
+        let end_klu = klu(10, 9.0, 8.0);
+        let bi = CBi::new(
+            klc(klu(0, 12.0, 11.0), FxType::TOP),
+            klc(Rc::clone(&end_klu), FxType::BOTTOM),
+            0,
+            false,
+        );
+        Rc::new(RefCell::new(CBSPoint {
+            bi: LineType::Bi(Rc::new(RefCell::new(bi))),
+            klu: end_klu,
+            is_buy: true,
+            bsp_type: vec![BspType::T1],
+            relate_bsp1: None,
+            features: CFeatures::new(Some(HashMap::from([("feat".to_string(), feat)]))),
+            is_segbsp: false,
+            divergence_report: None,
+            id: "b10".to_string(),
+            state: BspState::PROVISIONAL,
+            score: None,
+            risk: CBspRisk::new(true, 8.5, Vec::new(), Vec::new()),
+        }))
+    }
+
+    #[test]
+    fn test_update_lifecycle() {
+        let mut history = CBSPointHistory::new();
+        let cur_events = |history: &CBSPointHistory| -> Vec<(String, BspState, String)> {
+            history
+                .cur_events()
+                .map(|(r, e)| (r.id.clone(), e.state, e.reason.clone()))
+                .collect()
+        };
+        let bsp = buy_bsp(1.0);
+
+        // idx=12 首次出现，所在线段还没确认
+        history.update(&[Rc::clone(&bsp)], 5, &klu(12, 9.5, 9.0));
+        assert_eq!(
+            cur_events(&history),
+            vec![(
+                "b10".to_string(),
+                BspState::PROVISIONAL,
+                "appear".to_string()
+            )]
+        );
+        assert_eq!(bsp.borrow().state, BspState::PROVISIONAL);
+
+        // idx=13 没有变化，不产生事件；之后特征被重算
+        history.update(&[Rc::clone(&bsp)], 5, &klu(13, 9.6, 9.1));
+        assert!(cur_events(&history).is_empty());
+        bsp.borrow_mut()
+            .features
+            .add_feat(("feat".to_string(), 2.0), None);
+
+        // idx=14 线段确认
+        history.update(&[Rc::clone(&bsp)], 10, &klu(14, 9.7, 9.2));
+        assert_eq!(
+            cur_events(&history),
+            vec![(
+                "b10".to_string(),
+                BspState::CONFIRMED,
+                "seg sure".to_string()
+            )]
+        );
+
+        // idx=15 从列表中消失，所在笔没变，是条件不再满足
+        history.update(&[], 10, &klu(15, 9.8, 9.3));
+        assert_eq!(
+            cur_events(&history),
+            vec![(
+                "b10".to_string(),
+                BspState::INVALIDATED,
+                "condition not satisfied".to_string()
+            )]
+        );
+        assert_eq!(bsp.borrow().state, BspState::INVALIDATED);
+
+        // idx=16 同一位置重新算出买卖点（新对象，id 相同），同一根K线 update 两次事件不丢
+        let new_bsp = buy_bsp(3.0);
+        history.update(&[Rc::clone(&new_bsp)], 5, &klu(16, 9.9, 9.4));
+        history.update(&[Rc::clone(&new_bsp)], 5, &klu(16, 9.9, 9.4));
+        assert_eq!(
+            cur_events(&history),
+            vec![(
+                "b10".to_string(),
+                BspState::PROVISIONAL,
+                "reappear".to_string()
+            )]
+        );
+
+        assert_eq!(history.len(), 1);
+        let record = history.get("b10").unwrap();
+        let states: Vec<(BspState, i32)> =
+            record.events.iter().map(|e| (e.state, e.klu_idx)).collect();
+        assert_eq!(
+            states,
+            vec![
+                (BspState::PROVISIONAL, 12),
+                (BspState::CONFIRMED, 14),
+                (BspState::INVALIDATED, 15),
+                (BspState::PROVISIONAL, 16),
+            ]
+        );
+        assert!(Rc::ptr_eq(&record.bsp, &new_bsp));
+        // 首次出现时的特征不被之后的重算覆盖
+        assert_eq!(record.first_features.get("feat"), Some(&1.0));
+        assert_eq!(record.first_seen_idx(), 12);
+
+        assert_eq!(record.state_at(11), None);
+        assert_eq!(record.state_at(13), Some(BspState::PROVISIONAL));
+        assert_eq!(record.state_at(14), Some(BspState::CONFIRMED));
+        assert_eq!(record.state_at(15), Some(BspState::INVALIDATED));
+        assert_eq!(record.state_at(16), Some(BspState::PROVISIONAL));
+
+        let knowable = |idx: i32| -> Vec<String> {
+            history
+                .knowable_at(idx)
+                .iter()
+                .map(|r| r.id.clone())
+                .collect()
+        };
+        assert!(knowable(11).is_empty());
+        assert_eq!(knowable(12), vec!["b10".to_string()]);
+        assert_eq!(knowable(14), vec!["b10".to_string()]);
+        assert!(knowable(15).is_empty());
+        assert_eq!(knowable(16), vec!["b10".to_string()]);
+    }
+}
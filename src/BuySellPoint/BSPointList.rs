@@ -1,34 +1,78 @@
+use crate::Bi::Bi::CBi;
 use crate::BuySellPoint::BSPointConfig::{CBSPointConfig, CPointConfig};
-use crate::Common::types::SharedCell;
+use crate::ChanModel::BspModel::BspModel;
+use crate::Common::types::{ChanLine, SharedCell};
 use crate::Common::CEnum::{BspType, MacdAlgo};
+use crate::KLine::KLine_Unit::CKLineUnit;
 use crate::Seg::Seg::CSeg;
 use crate::Seg::SegListComm::CSegListComm;
+use crate::ZS::ZSList::CZSList;
 use crate::ZS::ZS::CZS;
-use std::cell::RefCell;
 use std::collections::HashMap;
+use std::marker::PhantomData;
 use std::rc::Rc;
 
+use super::BSPRule::BspRule;
+use super::BSPointHistory::CBSPointHistory;
 use super::BS_Point::CBSPoint;
+use super::Divergence::CDivergenceReport;
 
-pub struct CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
+pub struct CBSPointList<LINE_TYPE> {
     lst: Vec<SharedCell<CBSPoint>>,
     bsp_dict: HashMap<i32, SharedCell<CBSPoint>>,
     bsp1_lst: Vec<SharedCell<CBSPoint>>,
     config: CBSPointConfig,
     last_sure_pos: i32,
+    pub history: CBSPointHistory,
+    model: Option<Rc<dyn BspModel>>,
+    _phantom: PhantomData<LINE_TYPE>,
 }
 
-impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
-    pub fn new(bs_point_config: CBSPointConfig) -> Self {
+impl<LINE_TYPE: ChanLine> CBSPointList<LINE_TYPE> {
+    pub fn new(bs_point_config: Option<CBSPointConfig>) -> Self {
         CBSPointList {
             lst: Vec::new(),
             bsp_dict: HashMap::new(),
             bsp1_lst: Vec::new(),
-            config: bs_point_config,
+            config: bs_point_config.unwrap_or_default(),
             last_sure_pos: -1,
+            history: CBSPointHistory::new(),
+            model: None,
+            _phantom: PhantomData,
         }
     }
 
+    pub fn set_model(&mut self, model: Option<Rc<dyn BspModel>>) {
+        self.model = model;
+    }
+
+    // 用打分模型更新买卖点分数，返回是否达到阈值（未配置模型或阈值时总是通过）
+    fn update_score(&self, bsp: &SharedCell<CBSPoint>) -> bool {
+        let model = match &self.model {
+            Some(model) => model,
+            None => return true,
+        };
+        let score = model.score(&bsp.borrow().features);
+        bsp.borrow_mut().score = Some(score);
+        let is_buy = bsp.borrow().is_buy;
+        self.config
+            .get_bs_config(is_buy)
+            .score_thred
+            .map_or(true, |thred| score >= thred)
+    }
+
+    // 波动过小（滚动标准差/收盘价 < 阈值）的买卖点不输出，K线上没有对应窗口的统计时不过滤
+    fn pass_std_filter(&self, bi: &SharedCell<LINE_TYPE>, is_buy: bool) -> bool {
+        let (t, min_rate) = match self.config.get_bs_config(is_buy).std_filter {
+            Some(std_filter) => std_filter,
+            None => return true,
+        };
+        let klu = bi.borrow().get_end_klu();
+        let klu = klu.borrow();
+        klu.trend_std(t)
+            .map_or(true, |std| std / klu.close >= min_rate)
+    }
+
     pub fn len(&self) -> usize {
         self.lst.len()
     }
@@ -37,18 +81,21 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
         self.lst.get(index).cloned()
     }
 
-    pub fn cal(&mut self, bi_list: &LINE_LIST_TYPE, seg_list: &CSegListComm<LINE_TYPE>) {
+    pub fn iter(&self) -> impl Iterator<Item = &SharedCell<CBSPoint>> {
+        self.lst.iter()
+    }
+
+    pub fn last(&self) -> Option<&SharedCell<CBSPoint>> {
+        self.lst.last()
+    }
+
+    pub fn cal(&mut self, bi_list: &[SharedCell<LINE_TYPE>], seg_list: &CSegListComm<LINE_TYPE>) {
         self.lst
             .retain(|bsp| bsp.borrow().klu.borrow().idx <= self.last_sure_pos);
         self.bsp_dict = self
             .lst
             .iter()
-            .map(|bsp| {
-                (
-                    bsp.borrow().bi.borrow().get_end_klu().borrow().idx,
-                    Rc::clone(bsp),
-                )
-            })
+            .map(|bsp| (bsp.borrow().bi.get_end_klu().borrow().idx, Rc::clone(bsp)))
             .collect();
         self.bsp1_lst
             .retain(|bsp| bsp.borrow().klu.borrow().idx <= self.last_sure_pos);
@@ -60,6 +107,11 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
         self.update_last_pos(seg_list);
     }
 
+    // 每次 cal 之后调用，cur_klu 为当前最新的K线，用来记录买卖点的出现/确认/失效
+    pub fn update_history(&mut self, cur_klu: &SharedCell<CKLineUnit>) {
+        self.history.update(&self.lst, self.last_sure_pos, cur_klu);
+    }
+
     pub fn update_last_pos(&mut self, seg_list: &CSegListComm<LINE_TYPE>) {
         self.last_sure_pos = -1;
         for seg in seg_list.iter().rev() {
@@ -81,7 +133,7 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
         relate_bsp1: Option<SharedCell<CBSPoint>>,
         is_target_bsp: bool,
         feature_dict: Option<HashMap<String, f64>>,
-    ) {
+    ) -> Option<SharedCell<CBSPoint>> {
         let is_buy = bi.borrow().is_down();
         if let Some(exist_bsp) = self.bsp_dict.get(&bi.borrow().get_end_klu().borrow().idx) {
             assert_eq!(exist_bsp.borrow().is_buy, is_buy);
@@ -90,10 +142,13 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
                 .add_another_bsp_prop(bs_type, relate_bsp1.clone());
             if let Some(feat_dict) = feature_dict {
                 exist_bsp.borrow_mut().add_feat(feat_dict);
+                // 已经加入列表的买卖点只更新分数，不再剔除
+                self.update_score(exist_bsp);
             }
-            return;
+            return Some(Rc::clone(exist_bsp));
         }
         let mut is_target_bsp = is_target_bsp;
+        // 自定义买卖点同样需要在 bs_type 中以 custom:规则名 配置才输出
         if !self
             .config
             .get_bs_config(is_buy)
@@ -104,13 +159,16 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
         }
 
         if is_target_bsp || bs_type == BspType::T1 || bs_type == BspType::T1P {
-            let bsp = Rc::new(RefCell::new(CBSPoint::new(
-                bi.clone(),
+            let bsp = CBSPoint::new(
+                LINE_TYPE::to_line_type(&bi),
                 is_buy,
-                bs_type,
+                bs_type.clone(),
                 relate_bsp1,
                 feature_dict,
-            )));
+            );
+            if !self.update_score(&bsp) || !self.pass_std_filter(&bi, is_buy) {
+                is_target_bsp = false;
+            }
             if is_target_bsp {
                 self.lst.push(Rc::clone(&bsp));
                 self.bsp_dict
@@ -119,13 +177,15 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
             if bs_type == BspType::T1 || bs_type == BspType::T1P {
                 self.bsp1_lst.push(Rc::clone(&bsp));
             }
+            return Some(bsp);
         }
+        None
     }
 
     pub fn cal_seg_bs1point(
         &mut self,
         seg_list: &CSegListComm<LINE_TYPE>,
-        bi_list: &LINE_LIST_TYPE,
+        bi_list: &[SharedCell<LINE_TYPE>],
     ) {
         for seg in seg_list.iter() {
             if !self.seg_need_cal(seg) {
@@ -138,47 +198,31 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
     pub fn cal_single_bs1point(
         &mut self,
         seg: &SharedCell<CSeg<LINE_TYPE>>,
-        bi_list: &LINE_LIST_TYPE,
+        bi_list: &[SharedCell<LINE_TYPE>],
     ) {
-        let bsp_conf = self.config.get_bs_config(seg.borrow().is_down());
+        let bsp_conf = self.config.get_bs_config(seg.borrow().is_down()).clone();
         let zs_cnt = if bsp_conf.bsp1_only_multibi_zs {
             seg.borrow().get_multi_bi_zs_cnt()
         } else {
             seg.borrow().zs_lst.len()
         };
-        let mut is_target_bsp = bsp_conf.min_zs_cnt <= 0 || zs_cnt >= bsp_conf.min_zs_cnt as usize;
-        if !seg.borrow().zs_lst.is_empty()
-            && !seg.borrow().zs_lst.last().unwrap().is_one_bi_zs()
-            && ((seg.borrow().zs_lst.last().unwrap().bi_out.is_some()
-                && seg
-                    .borrow()
-                    .zs_lst
-                    .last()
-                    .unwrap()
+        let is_target_bsp = bsp_conf.min_zs_cnt <= 0 || zs_cnt >= bsp_conf.min_zs_cnt as usize;
+        let end_bi_idx = seg.borrow().end_bi.borrow().idx();
+        let last_zs = seg.borrow().zs_lst.last().cloned();
+        let is_zs_bsp1 = last_zs.map_or(false, |last_zs| {
+            let last_zs = last_zs.borrow();
+            !last_zs.is_one_bi_zs()
+                && (last_zs
                     .bi_out
                     .as_ref()
-                    .unwrap()
-                    .borrow()
-                    .idx
-                    >= seg.borrow().end_bi.borrow().idx)
-                || seg
-                    .borrow()
-                    .zs_lst
-                    .last()
-                    .unwrap()
-                    .bi_lst
-                    .last()
-                    .unwrap()
-                    .borrow()
-                    .idx
-                    >= seg.borrow().end_bi.borrow().idx)
-            && seg.borrow().end_bi.borrow().idx
-                - seg.borrow().zs_lst.last().unwrap().get_bi_in().borrow().idx
-                > 2
-        {
-            self.treat_bsp1(seg, bsp_conf, is_target_bsp);
+                    .map_or(false, |bi_out| bi_out.idx() >= end_bi_idx)
+                    || last_zs.bi_lst.last().unwrap().idx() >= end_bi_idx)
+                && end_bi_idx - last_zs.get_bi_in().idx() > 2
+        });
+        if is_zs_bsp1 {
+            self.treat_bsp1(seg, &bsp_conf, is_target_bsp);
         } else {
-            self.treat_pz_bsp1(seg, bsp_conf, bi_list, is_target_bsp);
+            self.treat_pz_bsp1(seg, &bsp_conf, bi_list, is_target_bsp);
         }
     }
 
@@ -188,42 +232,46 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
         bsp_conf: &CPointConfig,
         mut is_target_bsp: bool,
     ) {
-        let last_zs = seg.borrow().zs_lst.last().unwrap();
-        let (break_peak, _) = last_zs.out_bi_is_peak(seg.borrow().end_bi.borrow().idx);
+        let end_bi = Rc::clone(&seg.borrow().end_bi);
+        let last_zs = Rc::clone(seg.borrow().zs_lst.last().unwrap());
+        let last_zs = last_zs.borrow();
+        let (break_peak, _) = last_zs.out_bi_is_peak(end_bi.borrow().idx());
         if bsp_conf.bs1_peak && !break_peak {
             is_target_bsp = false;
         }
-        let (is_diver, divergence_rate) =
-            last_zs.is_divergence(bsp_conf, Some(&seg.borrow().end_bi));
-        if !is_diver {
+        let diver_report =
+            last_zs.divergence_report(bsp_conf, Some(&LINE_TYPE::to_line_type(&end_bi)));
+        if !diver_report.is_diver {
             is_target_bsp = false;
         }
-        let feature_dict = HashMap::from([
-            ("divergence_rate".to_string(), divergence_rate),
+        let mut feature_dict = HashMap::from([
             ("zs_cnt".to_string(), seg.borrow().zs_lst.len() as f64),
+            ("zs_ext_cnt".to_string(), last_zs.ext_cnt as f64),
+            (
+                "zs_level_up".to_string(),
+                if last_zs.is_level_up() { 1.0 } else { 0.0 },
+            ),
         ]);
-        self.add_bs(
-            BspType::T1,
-            Rc::clone(&seg.borrow().end_bi),
-            None,
-            is_target_bsp,
-            Some(feature_dict),
-        );
+        add_diver_features(&mut feature_dict, &diver_report, bsp_conf);
+        if let Some(bsp) = self.add_bs(BspType::T1, end_bi, None, is_target_bsp, Some(feature_dict))
+        {
+            bsp.borrow_mut().divergence_report = Some(diver_report);
+        }
     }
 
     fn treat_pz_bsp1(
         &mut self,
         seg: &SharedCell<CSeg<LINE_TYPE>>,
         bsp_conf: &CPointConfig,
-        bi_list: &LINE_LIST_TYPE,
+        bi_list: &[SharedCell<LINE_TYPE>],
         mut is_target_bsp: bool,
     ) {
-        let last_bi = &seg.borrow().end_bi;
-        let pre_bi = &bi_list.get(last_bi.borrow().idx as usize - 2).unwrap();
-        if last_bi.borrow().seg_idx != pre_bi.borrow().seg_idx {
+        let last_bi = Rc::clone(&seg.borrow().end_bi);
+        let pre_bi = Rc::clone(&bi_list[last_bi.borrow().idx() as usize - 2]);
+        if last_bi.borrow().seg_idx() != pre_bi.borrow().seg_idx() {
             return;
         }
-        if last_bi.borrow().dir != seg.borrow().dir {
+        if last_bi.borrow().dir() != seg.borrow().dir {
             return;
         }
         if last_bi.borrow().is_down() && last_bi.borrow()._low() > pre_bi.borrow()._low() {
@@ -232,55 +280,51 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
         if last_bi.borrow().is_up() && last_bi.borrow()._high() < pre_bi.borrow()._high() {
             return;
         }
-        let in_metric = pre_bi.borrow().cal_macd_metric(bsp_conf.macd_algo, false);
-        let out_metric = last_bi.borrow().cal_macd_metric(bsp_conf.macd_algo, true);
-        let (is_diver, divergence_rate) = (
-            out_metric <= bsp_conf.divergence_rate * in_metric,
-            out_metric / (in_metric + 1e-7),
-        );
-        if !is_diver {
+        let diver_report =
+            CDivergenceReport::new(&*pre_bi.borrow(), &*last_bi.borrow(), bsp_conf, true);
+        if !diver_report.is_diver {
             is_target_bsp = false;
         }
-        let feature_dict = HashMap::from([
-            ("divergence_rate".to_string(), divergence_rate),
-            ("bsp1_bi_amp".to_string(), last_bi.borrow().amp()),
-        ]);
-        self.add_bs(
+        let mut feature_dict = HashMap::from([("bsp1_bi_amp".to_string(), last_bi.borrow().amp())]);
+        add_diver_features(&mut feature_dict, &diver_report, bsp_conf);
+        if let Some(bsp) = self.add_bs(
             BspType::T1P,
-            Rc::clone(last_bi),
+            last_bi,
             None,
             is_target_bsp,
             Some(feature_dict),
-        );
+        ) {
+            bsp.borrow_mut().divergence_report = Some(diver_report);
+        }
     }
 
     pub fn cal_seg_bs2point(
         &mut self,
         seg_list: &CSegListComm<LINE_TYPE>,
-        bi_list: &LINE_LIST_TYPE,
+        bi_list: &[SharedCell<LINE_TYPE>],
     ) {
         let bsp1_bi_idx_dict: HashMap<i32, SharedCell<CBSPoint>> = self
             .bsp1_lst
             .iter()
-            .map(|bsp| (bsp.borrow().bi.borrow().idx, Rc::clone(bsp)))
+            .map(|bsp| (bsp.borrow().bi.idx(), Rc::clone(bsp)))
             .collect();
 
         for seg in seg_list.iter() {
             if !self.seg_need_cal(seg) {
                 continue;
             }
-            let bsp_conf = self.config.get_bs_config(seg.borrow().is_down());
+            let bsp_conf = self.config.get_bs_config(seg.borrow().is_down()).clone();
             if !bsp_conf.target_types.contains(&BspType::T2) {
                 continue;
             }
-            let bsp1_bi = &seg.borrow().end_bi;
-            let real_bsp1 = bsp1_bi_idx_dict.get(&bsp1_bi.borrow().idx).cloned();
+            let bsp1_bi_idx = seg.borrow().end_bi.borrow().idx();
+            let real_bsp1 = bsp1_bi_idx_dict.get(&bsp1_bi_idx).cloned();
             if bsp_conf.bsp2_follow_1 && real_bsp1.is_none() {
                 continue;
             }
             let next_seg = seg.borrow().next.clone();
             if let Some(next_seg) = next_seg {
-                self.treat_bsp2(seg_list, &next_seg, bsp_conf, bi_list, real_bsp1);
+                self.treat_bsp2(seg_list, &next_seg, &bsp_conf, bi_list, real_bsp1);
             }
         }
     }
@@ -290,84 +334,52 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
         seg_list: &CSegListComm<LINE_TYPE>,
         next_seg: &SharedCell<CSeg<LINE_TYPE>>,
         bsp_conf: &CPointConfig,
-        bi_list: &LINE_LIST_TYPE,
+        bi_list: &[SharedCell<LINE_TYPE>],
         real_bsp1: Option<SharedCell<CBSPoint>>,
     ) {
-        let first_zs = next_seg.borrow().get_first_multi_bi_zs();
-        if first_zs.is_none() {
-            return;
-        }
-        let first_zs = first_zs.unwrap();
-        if first_zs.bi_out.is_none()
-            || first_zs.bi_out.as_ref().unwrap().borrow().idx + 1 >= bi_list.len() as i32
-        {
+        let first_zs = match next_seg.borrow().get_first_multi_bi_zs() {
+            Some(first_zs) => first_zs,
+            None => return,
+        };
+        let first_zs = first_zs.borrow();
+        let bsp2_break_bi = match &first_zs.bi_out {
+            Some(bi_out) if ((bi_out.idx() + 1) as usize) < bi_list.len() => bi_out,
+            _ => return,
+        };
+        let bsp2_bi = Rc::clone(&bi_list[(bsp2_break_bi.idx() + 1) as usize]);
+        if !bsp_in_next_seg(&bsp2_bi, next_seg, seg_list) {
             return;
         }
-        let bsp2_bi = bi_list
-            .get((first_zs.bi_out.as_ref().unwrap().borrow().idx + 1) as usize)
-            .unwrap();
-        if bsp2_bi.borrow().parent_seg.is_none() {
-            if next_seg.borrow().idx != seg_list.len() as i32 - 1 {
-                return;
-            }
-        } else if bsp2_bi.borrow().parent_seg.as_ref().unwrap().borrow().idx
-            != next_seg.borrow().idx
-        {
-            if bsp2_bi
-                .borrow()
-                .parent_seg
-                .as_ref()
-                .unwrap()
-                .borrow()
-                .bi_list
-                .len()
-                >= 3
-            {
-                return;
-            }
-        }
-        if bsp2_bi.borrow().dir == next_seg.borrow().dir {
+        if bsp2_bi.borrow().dir() == next_seg.borrow().dir {
             return;
         }
-        if bsp2_bi.borrow().seg_idx != next_seg.borrow().idx
+        if bsp2_bi.borrow().seg_idx() != Some(next_seg.borrow().idx)
             && next_seg.borrow().idx < seg_list.len() as i32 - 2
         {
             return;
         }
-        let bsp2_break_bi = first_zs.bi_out.as_ref().unwrap();
-        let retrace_rate = (bsp2_bi.borrow().get_end_val() - bsp2_break_bi.borrow().get_end_val())
-            .abs()
-            / (bsp2_break_bi.borrow().get_end_val() - first_zs.get_bi_in().borrow().get_end_val())
-                .abs();
+        let retrace_rate = (bsp2_bi.borrow().get_end_val() - bsp2_break_bi.get_end_val()).abs()
+            / (bsp2_break_bi.get_end_val() - first_zs.get_bi_in().get_end_val()).abs();
         if retrace_rate > bsp_conf.max_bs2_rate {
             return;
         }
         let feature_dict = HashMap::from([
             ("bsp2_retrace_rate".to_string(), retrace_rate),
-            (
-                "bsp2_break_bi_amp".to_string(),
-                bsp2_break_bi.borrow().amp(),
-            ),
+            ("bsp2_break_bi_amp".to_string(), bsp2_break_bi.amp()),
             ("bsp2_bi_amp".to_string(), bsp2_bi.borrow().amp()),
         ]);
-        self.add_bs(
-            BspType::T2,
-            Rc::clone(bsp2_bi),
-            real_bsp1,
-            true,
-            Some(feature_dict),
-        );
+        self.add_bs(BspType::T2, bsp2_bi, real_bsp1, true, Some(feature_dict));
     }
 
     pub fn cal_seg_bs3point(
         &mut self,
         seg_list: &CSegListComm<LINE_TYPE>,
-        bi_list: &LINE_LIST_TYPE,
+        bi_list: &[SharedCell<LINE_TYPE>],
     ) {
         let bsp1_bi_idx_dict: HashMap<i32, SharedCell<CBSPoint>> = self
             .bsp1_lst
             .iter()
-            .map(|bsp| (bsp.borrow().bi.borrow().idx, Rc::clone(bsp)))
+            .map(|bsp| (bsp.borrow().bi.idx(), Rc::clone(bsp)))
             .collect();
 
         for seg in seg_list.iter() {
@@ -383,9 +395,9 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
             let (bsp1_bi, bsp1_bi_idx, real_bsp1, next_seg_idx, next_seg, bsp_conf) =
                 if seg_list.len() > 1 {
                     let bsp1_bi = Rc::clone(&seg.borrow().end_bi);
-                    let bsp1_bi_idx = bsp1_bi.borrow().idx;
-                    let bsp_conf = self.config.get_bs_config(seg.borrow().is_down());
-                    let real_bsp1 = bsp1_bi_idx_dict.get(&bsp1_bi.borrow().idx).cloned();
+                    let bsp1_bi_idx = bsp1_bi.borrow().idx();
+                    let bsp_conf = self.config.get_bs_config(seg.borrow().is_down()).clone();
+                    let real_bsp1 = bsp1_bi_idx_dict.get(&bsp1_bi_idx).cloned();
                     let next_seg_idx = seg.borrow().idx + 1;
                     let next_seg = seg.borrow().next.clone();
                     (
@@ -399,21 +411,21 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
                 } else {
                     let next_seg = Rc::clone(seg);
                     let next_seg_idx = seg.borrow().idx;
-                    let bsp_conf = self.config.get_bs_config(seg.borrow().is_up());
+                    let bsp_conf = self.config.get_bs_config(seg.borrow().is_up()).clone();
                     (None, -1, None, next_seg_idx, Some(next_seg), bsp_conf)
                 };
             if bsp_conf.bsp3_follow_1
                 && !self
                     .bsp_dict
                     .values()
-                    .any(|bsp| bsp.borrow().bi.borrow().idx == bsp1_bi_idx)
+                    .any(|bsp| bsp.borrow().bi.idx() == bsp1_bi_idx)
             {
                 continue;
             }
-            if let Some(next_seg) = next_seg {
+            if let Some(next_seg) = &next_seg {
                 self.treat_bsp3_after(
                     seg_list,
-                    &next_seg,
+                    next_seg,
                     &bsp_conf,
                     bi_list,
                     real_bsp1.clone(),
@@ -439,57 +451,39 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
         seg_list: &CSegListComm<LINE_TYPE>,
         next_seg: &SharedCell<CSeg<LINE_TYPE>>,
         bsp_conf: &CPointConfig,
-        bi_list: &LINE_LIST_TYPE,
+        bi_list: &[SharedCell<LINE_TYPE>],
         real_bsp1: Option<SharedCell<CBSPoint>>,
         bsp1_bi_idx: i32,
         next_seg_idx: i32,
     ) {
-        let first_zs = next_seg.borrow().get_first_multi_bi_zs();
-        if first_zs.is_none() {
+        let first_zs = match next_seg.borrow().get_first_multi_bi_zs() {
+            Some(first_zs) => first_zs,
+            None => return,
+        };
+        let first_zs = first_zs.borrow();
+        if bsp_conf.strict_bsp3 && first_zs.get_bi_in().idx() != bsp1_bi_idx + 1 {
             return;
         }
-        let first_zs = first_zs.unwrap();
-        if bsp_conf.strict_bsp3 && first_zs.get_bi_in().borrow().idx != bsp1_bi_idx + 1 {
+        let bsp3_bi_idx = match &first_zs.bi_out {
+            Some(bi_out) if ((bi_out.idx() + 1) as usize) < bi_list.len() => bi_out.idx() + 1,
+            _ => return,
+        };
+        let bsp3_bi = Rc::clone(&bi_list[bsp3_bi_idx as usize]);
+        if !bsp_in_next_seg(&bsp3_bi, next_seg, seg_list) {
             return;
         }
-        if first_zs.bi_out.is_none()
-            || first_zs.bi_out.as_ref().unwrap().borrow().idx + 1 >= bi_list.len() as i32
-        {
+        if bsp3_bi.borrow().dir() == next_seg.borrow().dir {
             return;
         }
-        let bsp3_bi = bi_list
-            .get((first_zs.bi_out.as_ref().unwrap().borrow().idx + 1) as usize)
-            .unwrap();
-        if bsp3_bi.borrow().parent_seg.is_none() {
-            if next_seg.borrow().idx != seg_list.len() as i32 - 1 {
-                return;
-            }
-        } else if bsp3_bi.borrow().parent_seg.as_ref().unwrap().borrow().idx
-            != next_seg.borrow().idx
+        if bsp3_bi.borrow().seg_idx() != Some(next_seg_idx)
+            && next_seg_idx < seg_list.len() as i32 - 2
         {
-            if bsp3_bi
-                .borrow()
-                .parent_seg
-                .as_ref()
-                .unwrap()
-                .borrow()
-                .bi_list
-                .len()
-                >= 3
-            {
-                return;
-            }
-        }
-        if bsp3_bi.borrow().dir == next_seg.borrow().dir {
-            return;
-        }
-        if bsp3_bi.borrow().seg_idx != next_seg_idx && next_seg_idx < seg_list.len() as i32 - 2 {
             return;
         }
-        if bsp3_back2zs(bsp3_bi, &first_zs) {
+        if bsp3_back2zs(&bsp3_bi, &first_zs) {
             return;
         }
-        let bsp3_peak_zs = bsp3_break_zspeak(bsp3_bi, &first_zs);
+        let bsp3_peak_zs = bsp3_break_zspeak(&bsp3_bi, &first_zs);
         if bsp_conf.bsp3_peak && !bsp3_peak_zs {
             return;
         }
@@ -499,14 +493,12 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
                 (first_zs.high - first_zs.low) / first_zs.low,
             ),
             ("bsp3_bi_amp".to_string(), bsp3_bi.borrow().amp()),
+            (
+                "bsp3_zs_level_up".to_string(),
+                if first_zs.is_level_up() { 1.0 } else { 0.0 },
+            ),
         ]);
-        self.add_bs(
-            BspType::T3A,
-            Rc::clone(bsp3_bi),
-            real_bsp1,
-            true,
-            Some(feature_dict),
-        );
+        self.add_bs(BspType::T3A, bsp3_bi, real_bsp1, true, Some(feature_dict));
     }
 
     fn treat_bsp3_before(
@@ -516,38 +508,35 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
         next_seg: Option<&SharedCell<CSeg<LINE_TYPE>>>,
         bsp1_bi: Option<&SharedCell<LINE_TYPE>>,
         bsp_conf: &CPointConfig,
-        bi_list: &LINE_LIST_TYPE,
+        bi_list: &[SharedCell<LINE_TYPE>],
         real_bsp1: Option<SharedCell<CBSPoint>>,
         next_seg_idx: i32,
     ) {
-        let cmp_zs = seg.borrow().get_final_multi_bi_zs();
-        if cmp_zs.is_none() {
-            return;
-        }
-        let cmp_zs = cmp_zs.unwrap();
-        if bsp1_bi.is_none() {
-            return;
-        }
-        let bsp1_bi = bsp1_bi.unwrap();
+        let cmp_zs = match seg.borrow().get_final_multi_bi_zs() {
+            Some(cmp_zs) => cmp_zs,
+            None => return,
+        };
+        let cmp_zs = cmp_zs.borrow();
+        let bsp1_bi = match bsp1_bi {
+            Some(bsp1_bi) => bsp1_bi,
+            None => return,
+        };
+        let bsp1_bi_idx = bsp1_bi.borrow().idx();
         if bsp_conf.strict_bsp3
-            && (cmp_zs.bi_out.is_none()
-                || cmp_zs.bi_out.as_ref().unwrap().borrow().idx != bsp1_bi.borrow().idx)
+            && cmp_zs
+                .bi_out
+                .as_ref()
+                .map_or(true, |bi_out| bi_out.idx() != bsp1_bi_idx)
         {
             return;
         }
         let end_bi_idx = cal_bsp3_bi_end_idx(next_seg);
-        for bsp3_bi in bi_list
-            .iter()
-            .skip((bsp1_bi.borrow().idx + 2) as usize)
-            .step_by(2)
-        {
-            if bsp3_bi.borrow().idx > end_bi_idx {
+        for bsp3_bi in bi_list.iter().skip((bsp1_bi_idx + 2) as usize).step_by(2) {
+            if bsp3_bi.borrow().idx() > end_bi_idx {
                 break;
             }
-            assert!(bsp3_bi.borrow().seg_idx.is_some());
-            if bsp3_bi.borrow().seg_idx.unwrap() != next_seg_idx
-                && bsp3_bi.borrow().seg_idx.unwrap() < seg_list.len() as i32 - 1
-            {
+            let bsp3_seg_idx = bsp3_bi.borrow().seg_idx().unwrap();
+            if bsp3_seg_idx != next_seg_idx && bsp3_seg_idx < seg_list.len() as i32 - 1 {
                 break;
             }
             if bsp3_back2zs(bsp3_bi, &cmp_zs) {
@@ -559,6 +548,10 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
                     (cmp_zs.high - cmp_zs.low) / cmp_zs.low,
                 ),
                 ("bsp3_bi_amp".to_string(), bsp3_bi.borrow().amp()),
+                (
+                    "bsp3_zs_level_up".to_string(),
+                    if cmp_zs.is_level_up() { 1.0 } else { 0.0 },
+                ),
             ]);
             self.add_bs(
                 BspType::T3B,
@@ -576,12 +569,41 @@ impl<LINE_TYPE, LINE_LIST_TYPE> CBSPointList<LINE_TYPE, LINE_LIST_TYPE> {
             return Vec::new();
         }
         let mut result = self.lst.clone();
-        result.sort_by(|a, b| b.borrow().bi.borrow().idx.cmp(&a.borrow().bi.borrow().idx));
+        result.sort_by(|a, b| b.borrow().bi.idx().cmp(&a.borrow().bi.idx()));
         result
     }
 }
 
-fn bsp2s_break_bsp1<LINE_TYPE>(
+// 主指标算不出力度比时不加 divergence_rate；完整报告的各项指标只在配置开启时加入
+fn add_diver_features(
+    feature_dict: &mut HashMap<String, f64>,
+    diver_report: &CDivergenceReport,
+    bsp_conf: &CPointConfig,
+) {
+    if let Some(ratio) = diver_report.ratio {
+        feature_dict.insert("divergence_rate".to_string(), ratio);
+    }
+    if bsp_conf.divergence_report {
+        feature_dict.extend(diver_report.to_feature_dict());
+    }
+}
+
+// 二买/三买所在的笔应属于 next_seg，最后一根线段之后的笔或者只有不到3笔的线段也算
+fn bsp_in_next_seg<LINE_TYPE: ChanLine>(
+    bsp_bi: &SharedCell<LINE_TYPE>,
+    next_seg: &SharedCell<CSeg<LINE_TYPE>>,
+    seg_list: &CSegListComm<LINE_TYPE>,
+) -> bool {
+    match bsp_bi.borrow().parent_seg_idx() {
+        None => next_seg.borrow().idx == seg_list.len() as i32 - 1,
+        Some(parent_idx) if parent_idx != next_seg.borrow().idx => {
+            bsp_bi.borrow().parent_seg_bi_cnt().unwrap() < 3
+        }
+        Some(_) => true,
+    }
+}
+
+fn bsp2s_break_bsp1<LINE_TYPE: ChanLine>(
     bsp2s_bi: &SharedCell<LINE_TYPE>,
     bsp2_break_bi: &SharedCell<LINE_TYPE>,
 ) -> bool {
@@ -589,28 +611,29 @@ fn bsp2s_break_bsp1<LINE_TYPE>(
         || (bsp2s_bi.borrow().is_up() && bsp2s_bi.borrow()._high() > bsp2_break_bi.borrow()._high())
 }
 
-fn bsp3_back2zs<LINE_TYPE>(bsp3_bi: &SharedCell<LINE_TYPE>, zs: &CZS) -> bool {
+fn bsp3_back2zs<LINE_TYPE: ChanLine>(bsp3_bi: &SharedCell<LINE_TYPE>, zs: &CZS) -> bool {
     (bsp3_bi.borrow().is_down() && bsp3_bi.borrow()._low() < zs.high)
         || (bsp3_bi.borrow().is_up() && bsp3_bi.borrow()._high() > zs.low)
 }
 
-fn bsp3_break_zspeak<LINE_TYPE>(bsp3_bi: &SharedCell<LINE_TYPE>, zs: &CZS) -> bool {
+fn bsp3_break_zspeak<LINE_TYPE: ChanLine>(bsp3_bi: &SharedCell<LINE_TYPE>, zs: &CZS) -> bool {
     (bsp3_bi.borrow().is_down() && bsp3_bi.borrow()._high() >= zs.peak_high)
         || (bsp3_bi.borrow().is_up() && bsp3_bi.borrow()._low() <= zs.peak_low)
 }
 
-fn cal_bsp3_bi_end_idx<LINE_TYPE>(seg: Option<&SharedCell<CSeg<LINE_TYPE>>>) -> i32 {
+fn cal_bsp3_bi_end_idx<LINE_TYPE: ChanLine>(seg: Option<&SharedCell<CSeg<LINE_TYPE>>>) -> i32 {
     match seg {
         None => i32::MAX,
         Some(seg) => {
             if seg.borrow().get_multi_bi_zs_cnt() == 0 && seg.borrow().next.is_none() {
                 i32::MAX
             } else {
-                let mut end_bi_idx = seg.borrow().end_bi.borrow().idx - 1;
+                let mut end_bi_idx = seg.borrow().end_bi.borrow().idx() - 1;
                 for zs in &seg.borrow().zs_lst {
+                    let zs = zs.borrow();
                     if !zs.is_one_bi_zs() {
                         if let Some(bi_out) = &zs.bi_out {
-                            end_bi_idx = bi_out.borrow().idx;
+                            end_bi_idx = bi_out.idx();
                             break;
                         }
                     }
@@ -620,3 +643,37 @@ fn cal_bsp3_bi_end_idx<LINE_TYPE>(seg: Option<&SharedCell<CSeg<LINE_TYPE>>>) ->
         }
     }
 }
+
+impl CBSPointList<CBi> {
+    // 在内置买卖点算完之后调用，已确认的买卖点上同一规则不会重复添加
+    // 自定义规则只在笔级别计算，线段级别配置 custom 类型会在 CChanConfig 中报错
+    pub fn cal_custom_bsp(
+        &mut self,
+        rules: &[Rc<dyn BspRule>],
+        bi_list: &[SharedCell<CBi>],
+        seg_list: &CSegListComm<CBi>,
+        zs_list: &CZSList,
+    ) {
+        for rule in rules {
+            let bs_type = match BspType::from_custom_name(&rule.name()) {
+                Some(bs_type) => bs_type,
+                None => continue,
+            };
+            // bs_type 买卖两侧都没配置的规则不用计算
+            if !self.config.b_conf.target_types.contains(&bs_type)
+                && !self.config.s_conf.target_types.contains(&bs_type)
+            {
+                continue;
+            }
+            for hit in rule.cal(bi_list, seg_list, zs_list) {
+                let end_idx = hit.bi.borrow().get_end_klu().borrow().idx;
+                if let Some(exist_bsp) = self.bsp_dict.get(&end_idx) {
+                    if exist_bsp.borrow().bsp_type.contains(&bs_type) {
+                        continue;
+                    }
+                }
+                self.add_bs(bs_type.clone(), hit.bi, None, true, Some(hit.features));
+            }
+        }
+    }
+}
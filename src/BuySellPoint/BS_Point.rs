@@ -1,7 +1,10 @@
 use crate::Bi::Bi::CBi;
+use crate::BuySellPoint::BSPFeature::cal_bsp_std_features;
+use crate::BuySellPoint::BSPRisk::{cal_bsp_risk, CBspRisk};
+use crate::BuySellPoint::Divergence::CDivergenceReport;
 use crate::ChanModel::Features::CFeatures;
 use crate::Common::types::{LineType, SharedCell};
-use crate::Common::CEnum::BspType;
+use crate::Common::CEnum::{BspState, BspType};
 use crate::KLine::KLine_Unit::CKLineUnit;
 use crate::Seg::Seg::CSeg;
 use std::cell::RefCell;
@@ -16,6 +19,14 @@ pub struct CBSPoint {
     pub relate_bsp1: Option<SharedCell<CBSPoint>>,
     pub features: CFeatures,
     pub is_segbsp: bool,
+    pub divergence_report: Option<CDivergenceReport>,
+    // 稳定id：同一根K线上同方向的买卖点重算后id不变
+    pub id: String,
+    pub state: BspState,
+    // 打分模型给出的分数，未配置模型时为 None
+    pub score: Option<f64>,
+    // 结构止损/止盈位，创建时按当时的结构计算，补充类型后重算
+    pub risk: CBspRisk,
 }
 
 impl CBSPoint {
@@ -29,8 +40,12 @@ impl CBSPoint {
         let klu = match &bi {
             LineType::Bi(b) => b.borrow().get_end_klu(),
             LineType::Seg(s) => s.borrow().get_end_klu(),
+            LineType::Tier(s) => s.borrow().get_end_klu(),
         };
 
+        let id = format!("{}{}", if is_buy { "b" } else { "s" }, klu.borrow().idx);
+        let entry = klu.borrow().close;
+
         let bsp = Rc::new(RefCell::new(CBSPoint {
             bi,
             klu,
@@ -39,18 +54,34 @@ impl CBSPoint {
             relate_bsp1,
             features: CFeatures::new(feature_dict),
             is_segbsp: false,
+            divergence_report: None,
+            id,
+            state: BspState::PROVISIONAL,
+            score: None,
+            risk: CBspRisk::new(is_buy, entry, Vec::new(), Vec::new()),
         }));
 
         match &bsp.borrow().bi {
             LineType::Bi(b) => b.borrow_mut().bsp = Some(Rc::clone(&bsp)),
             LineType::Seg(s) => s.borrow_mut().bsp = Some(Rc::clone(&bsp)),
+            LineType::Tier(s) => s.borrow_mut().bsp = Some(Rc::clone(&bsp)),
         }
 
         bsp.borrow_mut().init_common_feature();
+        bsp.borrow_mut().update_risk();
 
         bsp
     }
 
+    // 所在笔/线段当前的终点，虚笔被重画后会与 klu 不一致
+    pub fn get_line_end_klu_idx(&self) -> i32 {
+        match &self.bi {
+            LineType::Bi(b) => b.borrow().get_end_klu().borrow().idx,
+            LineType::Seg(s) => s.borrow().get_end_klu().borrow().idx,
+            LineType::Tier(s) => s.borrow().get_end_klu().borrow().idx,
+        }
+    }
+
     pub fn add_type(&mut self, bs_type: BspType) {
         self.bsp_type.push(bs_type);
     }
@@ -77,22 +108,23 @@ impl CBSPoint {
                 new_relate_bsp1.borrow().klu.borrow().idx
             );
         }
+        // 可能变成一类买卖点或补上了 relate_bsp1
+        self.update_risk();
+    }
+
+    // 未确认的买卖点每次计算都会重建，已确认的结构不再变化，不需要重算
+    fn update_risk(&mut self) {
+        self.risk = cal_bsp_risk(self);
     }
 
     pub fn add_feat(&mut self, inp1: FeatureInput, inp2: Option<f64>) {
         self.features.add_feat(inp1, inp2);
     }
 
+    // 标准特征的名字和含义见 BSPFeature::cal_bsp_std_features
     fn init_common_feature(&mut self) {
-        let amp = match &self.bi {
-            LineType::Bi(b) => b.borrow().amp(),
-            LineType::Seg(s) => s.borrow().amp(),
-        };
-
-        self.add_feat(
-            FeatureInput::Dict(HashMap::from([("bsp_bi_amp".to_string(), amp)])),
-            None,
-        );
+        let features = cal_bsp_std_features(&self.bi, self.is_buy);
+        self.add_feat(FeatureInput::Dict(features), None);
     }
 }
 
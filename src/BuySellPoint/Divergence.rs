@@ -0,0 +1,299 @@
+use crate::Bi::Bi::CBi;
+use crate::BuySellPoint::BSPointConfig::CPointConfig;
+use crate::Common::types::{ChanLine, LineType};
+use crate::Common::CEnum::MacdAlgo;
+use crate::Common::ChanException::CChanException;
+use crate::Seg::Seg::CSeg;
+use std::collections::HashMap;
+
+// 背驰报告里逐一计算的力度指标
+pub const REPORT_MACD_ALGO: [MacdAlgo; 14] = [
+    MacdAlgo::Area,
+    MacdAlgo::Peak,
+    MacdAlgo::FullArea,
+    MacdAlgo::Diff,
+    MacdAlgo::Slope,
+    MacdAlgo::Amp,
+    MacdAlgo::Volumn,
+    MacdAlgo::Amount,
+    MacdAlgo::VolumnAvg,
+    MacdAlgo::AmountAvg,
+    MacdAlgo::TurnrateAvg,
+    MacdAlgo::Rsi,
+    MacdAlgo::HalfObverse,
+    MacdAlgo::HalfReverse,
+];
+
+pub trait MacdMetricLine {
+    fn macd_metric(&self, macd_algo: MacdAlgo, is_reverse: bool) -> Result<f64, CChanException>;
+}
+
+impl MacdMetricLine for CBi {
+    fn macd_metric(&self, macd_algo: MacdAlgo, is_reverse: bool) -> Result<f64, CChanException> {
+        self.cal_macd_metric(macd_algo, is_reverse)
+    }
+}
+
+impl<LINE_TYPE: ChanLine> MacdMetricLine for CSeg<LINE_TYPE> {
+    fn macd_metric(&self, macd_algo: MacdAlgo, is_reverse: bool) -> Result<f64, CChanException> {
+        self.cal_macd_metric(macd_algo, is_reverse)
+    }
+}
+
+impl MacdMetricLine for LineType {
+    fn macd_metric(&self, macd_algo: MacdAlgo, is_reverse: bool) -> Result<f64, CChanException> {
+        match self {
+            LineType::Bi(bi) => bi.borrow().cal_macd_metric(macd_algo, is_reverse),
+            LineType::Seg(seg) => seg.borrow().cal_macd_metric(macd_algo, is_reverse),
+            LineType::Tier(seg) => seg.borrow().cal_macd_metric(macd_algo, is_reverse),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CDivergenceMetric {
+    pub macd_algo: MacdAlgo,
+    pub in_metric: f64,
+    pub out_metric: f64,
+    pub ratio: f64,
+    pub is_diver: bool,
+}
+
+// 进入段/离开段在所有力度指标下的对比，用于解释一类买卖点的背驰判断
+// 配置 divergence_report 关闭时 metrics 中只有 macd_algo
+// 计算失败的指标（如未开启rsi）不会出现在 metrics 中
+#[derive(Clone, Debug)]
+pub struct CDivergenceReport {
+    pub macd_algo: MacdAlgo,
+    pub divergence_rate: f64,
+    pub end_bi_break: bool,
+    pub metrics: Vec<CDivergenceMetric>,
+    pub is_diver: bool,
+    pub ratio: Option<f64>,
+    pub score: f64,
+}
+
+impl CDivergenceReport {
+    pub fn new<IN: MacdMetricLine, OUT: MacdMetricLine>(
+        in_line: &IN,
+        out_line: &OUT,
+        config: &CPointConfig,
+        end_bi_break: bool,
+    ) -> Self {
+        // divergence_rate 为 inf 时不做背驰要求，单个指标按 1.0 评估
+        let metric_rate = if config.divergence_rate > 100.0 {
+            1.0
+        } else {
+            config.divergence_rate
+        };
+        // 不需要完整报告时只算判断用的 macd_algo
+        let macd_algo_lst = if config.divergence_report {
+            REPORT_MACD_ALGO.to_vec()
+        } else {
+            vec![config.macd_algo]
+        };
+        let metrics: Vec<CDivergenceMetric> = macd_algo_lst
+            .into_iter()
+            .filter_map(|macd_algo| {
+                let in_metric = in_line.macd_metric(macd_algo, false).ok()?;
+                let out_metric = out_line.macd_metric(macd_algo, true).ok()?;
+                Some(CDivergenceMetric {
+                    macd_algo,
+                    in_metric,
+                    out_metric,
+                    ratio: out_metric / in_metric,
+                    is_diver: out_metric <= metric_rate * in_metric,
+                })
+            })
+            .collect();
+
+        let main_metric = metrics.iter().find(|m| m.macd_algo == config.macd_algo);
+        let ratio = main_metric.map(|m| m.ratio);
+        let is_diver = end_bi_break
+            && (config.divergence_rate > 100.0 || main_metric.map_or(false, |m| m.is_diver));
+        let score = if metrics.is_empty() || !end_bi_break {
+            0.0
+        } else {
+            metrics.iter().filter(|m| m.is_diver).count() as f64 / metrics.len() as f64
+        };
+
+        CDivergenceReport {
+            macd_algo: config.macd_algo,
+            divergence_rate: config.divergence_rate,
+            end_bi_break,
+            metrics,
+            is_diver,
+            ratio,
+            score,
+        }
+    }
+
+    pub fn get_metric(&self, macd_algo: MacdAlgo) -> Option<&CDivergenceMetric> {
+        self.metrics.iter().find(|m| m.macd_algo == macd_algo)
+    }
+
+    pub fn to_feature_dict(&self) -> HashMap<String, f64> {
+        let mut feature_dict: HashMap<String, f64> = self
+            .metrics
+            .iter()
+            .map(|m| {
+                (
+                    format!("diver_{}_ratio", m.macd_algo.to_string().to_lowercase()),
+                    m.ratio,
+                )
+            })
+            .collect();
+        feature_dict.insert("diver_score".to_string(), self.score);
+        feature_dict
+    }
+}
+
+impl std::fmt::Display for CDivergenceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let detail = self
+            .metrics
+            .iter()
+            .map(|m| {
+                format!(
+                    "{}:{:.4}/{:.4}={:.4}{}",
+                    m.macd_algo,
+                    m.out_metric,
+                    m.in_metric,
+                    m.ratio,
+                    if m.is_diver { "(Y)" } else { "(N)" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        write!(
+            f,
+            "diver={} break={} main={}@{} score={:.2} [{}]",
+            self.is_diver,
+            self.end_bi_break,
+            self.macd_algo,
+            self.divergence_rate,
+            self.score,
+            detail
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Common::ChanException::ErrCode;
+
+    struct MockLine {
+        metric: f64,
+        only_slope: bool,
+    }
+
+    impl MacdMetricLine for MockLine {
+        fn macd_metric(
+            &self,
+            macd_algo: MacdAlgo,
+            _is_reverse: bool,
+        ) -> Result<f64, CChanException> {
+            if self.only_slope && macd_algo != MacdAlgo::Slope {
+                return Err(CChanException::new(
+                    "unsupport".to_string(),
+                    ErrCode::ParaError,
+                ));
+            }
+            Ok(self.metric)
+        }
+    }
+
+    fn point_config(divergence_rate: f64, macd_algo: MacdAlgo) -> CPointConfig {
+        CPointConfig {
+            divergence_rate,
+            macd_algo,
+            divergence_report: true,
+            ..CPointConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_divergence_report() {
+        let in_line = MockLine {
+            metric: 10.0,
+            only_slope: false,
+        };
+        let out_line = MockLine {
+            metric: 5.0,
+            only_slope: false,
+        };
+        let report = CDivergenceReport::new(
+            &in_line,
+            &out_line,
+            &point_config(0.8, MacdAlgo::Peak),
+            true,
+        );
+        assert!(report.is_diver);
+        assert_eq!(report.metrics.len(), REPORT_MACD_ALGO.len());
+        assert_eq!(report.ratio, Some(0.5));
+        assert_eq!(report.score, 1.0);
+
+        let report = CDivergenceReport::new(
+            &in_line,
+            &out_line,
+            &point_config(0.3, MacdAlgo::Peak),
+            true,
+        );
+        assert!(!report.is_diver);
+        assert_eq!(report.score, 0.0);
+
+        let report = CDivergenceReport::new(
+            &in_line,
+            &out_line,
+            &point_config(0.8, MacdAlgo::Peak),
+            false,
+        );
+        assert!(!report.is_diver);
+    }
+
+    #[test]
+    fn test_divergence_report_unsupported_metric() {
+        let in_line = MockLine {
+            metric: 10.0,
+            only_slope: true,
+        };
+        let out_line = MockLine {
+            metric: 12.0,
+            only_slope: true,
+        };
+        let report = CDivergenceReport::new(
+            &in_line,
+            &out_line,
+            &point_config(f64::INFINITY, MacdAlgo::Slope),
+            true,
+        );
+        assert_eq!(report.metrics.len(), 1);
+        assert!(report.is_diver);
+        assert!(!report.get_metric(MacdAlgo::Slope).unwrap().is_diver);
+        assert!(report.get_metric(MacdAlgo::Peak).is_none());
+    }
+
+    #[test]
+    fn test_divergence_report_main_metric_only() {
+        let in_line = MockLine {
+            metric: 10.0,
+            only_slope: false,
+        };
+        let out_line = MockLine {
+            metric: 7.0,
+            only_slope: false,
+        };
+        let config = CPointConfig {
+            divergence_report: false,
+            ..point_config(0.8, MacdAlgo::Area)
+        };
+        let report = CDivergenceReport::new(&in_line, &out_line, &config, true);
+        assert_eq!(report.metrics.len(), 1);
+        assert_eq!(report.metrics[0].macd_algo, MacdAlgo::Area);
+        // 判断和比值都不带平滑项
+        assert!(report.is_diver);
+        assert_eq!(report.ratio, Some(0.7));
+        assert_eq!(report.score, 1.0);
+    }
+}
@@ -0,0 +1,436 @@
+use crate::Bi::Bi::CBi;
+use crate::BuySellPoint::BS_Point::CBSPoint;
+use crate::Common::types::{LineType, SharedCell};
+use crate::Common::CEnum::BspType;
+use crate::Common::CTime::CTime;
+use crate::KLine::KLine_List::CKLineList;
+use crate::KLine::KLine_Unit::CKLineUnit;
+use std::rc::Rc;
+
+// 区间套：用次级别在本级别买卖点最后一笔内部的结构来确认本级别买卖点
+pub struct CIntervalNest {
+    pub bsp: SharedCell<CBSPoint>,
+    // 本级别最后一笔对应到次级别的K线区间（次级别klu的idx与时间）
+    pub sub_begin_idx: i32,
+    pub sub_end_idx: i32,
+    pub begin_time: CTime,
+    pub end_time: CTime,
+    // 区间内同向的次级别买卖点，按时间先后
+    pub sub_bsp_lst: Vec<SharedCell<CBSPoint>>,
+    // 区间内最后两根与本级别最后一笔同向的次级别笔的力度比，<1 代表次级别背驰
+    pub sub_divergence_rate: Option<f64>,
+    // 取区间内次级别买卖点类型的最高权重（1类1.0/2类0.8/3类0.6），次级别背驰时再加分，上限1
+    pub confidence: f64,
+}
+
+impl CIntervalNest {
+    pub fn has_sub_bsp(&self, bsp_type: BspType) -> bool {
+        self.sub_bsp_lst
+            .iter()
+            .any(|bsp| bsp.borrow().bsp_type.contains(&bsp_type))
+    }
+
+    pub fn has_sub_divergence(&self) -> bool {
+        self.sub_divergence_rate.map_or(false, |rate| rate < 1.0)
+    }
+
+    pub fn sub_bsp_type2str(&self) -> String {
+        self.sub_bsp_lst
+            .iter()
+            .map(|bsp| bsp.borrow().type2str())
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+// bsp 所在的最后一笔（线段买卖点取线段的最后一笔）的首尾klu
+fn bsp_last_bi_klu(bsp: &CBSPoint) -> (SharedCell<CKLineUnit>, SharedCell<CKLineUnit>) {
+    match &bsp.bi {
+        LineType::Bi(bi) => (bi.borrow().get_begin_klu(), bi.borrow().get_end_klu()),
+        LineType::Seg(seg) => {
+            let end_bi = Rc::clone(&seg.borrow().end_bi);
+            let end_bi = end_bi.borrow();
+            (end_bi.get_begin_klu(), end_bi.get_end_klu())
+        }
+        // 更高递归级别的线段直接取整段
+        LineType::Tier(seg) => (seg.borrow().get_begin_klu(), seg.borrow().get_end_klu()),
+    }
+}
+
+fn first_sub_klu(klu: &SharedCell<CKLineUnit>) -> Option<SharedCell<CKLineUnit>> {
+    klu.borrow().sub_kl_list.first().cloned()
+}
+
+fn last_sub_klu(klu: &SharedCell<CKLineUnit>) -> Option<SharedCell<CKLineUnit>> {
+    klu.borrow().sub_kl_list.last().cloned()
+}
+
+fn bsp_type_weight(bsp_type: &BspType) -> f64 {
+    match bsp_type.main_type().as_str() {
+        "1" => 1.0,
+        "2" => 0.8,
+        "3" => 0.6,
+        _ => 0.0,
+    }
+}
+
+// 次级别买卖点类型的最高权重，次级别背驰（rate<1）时按背驰程度加分，上限1
+fn cal_confidence(sub_bsp_types: &[BspType], sub_divergence_rate: Option<f64>) -> f64 {
+    let mut confidence = sub_bsp_types
+        .iter()
+        .map(bsp_type_weight)
+        .fold(0.0, f64::max);
+    if let Some(rate) = sub_divergence_rate {
+        if rate < 1.0 {
+            confidence = (confidence + 0.5 * (1.0 - rate)).min(1.0);
+        }
+    }
+    confidence
+}
+
+// 次级别区间内与 is_buy 对应方向（买点看向下笔）的最后两笔力度比
+fn cal_sub_divergence(
+    sub_kl_list: &CKLineList,
+    is_buy: bool,
+    begin_idx: i32,
+    end_idx: i32,
+) -> Option<f64> {
+    let macd_algo = sub_kl_list
+        .config
+        .bs_point_conf
+        .get_bs_config(is_buy)
+        .macd_algo;
+    let same_dir_bi: Vec<&SharedCell<CBi>> = sub_kl_list
+        .bi_list
+        .bi_list
+        .iter()
+        .filter(|bi| {
+            let bi = bi.borrow();
+            bi.is_down() == is_buy
+                && bi.get_begin_klu().borrow().idx >= begin_idx
+                && bi.get_end_klu().borrow().idx <= end_idx
+        })
+        .collect();
+    if same_dir_bi.len() < 2 {
+        return None;
+    }
+    let in_bi = same_dir_bi[same_dir_bi.len() - 2].borrow();
+    let out_bi = same_dir_bi[same_dir_bi.len() - 1].borrow();
+    // 出笔需要创新低/新高才谈得上背驰
+    if (is_buy && out_bi.low() > in_bi.low()) || (!is_buy && out_bi.high() < in_bi.high()) {
+        return None;
+    }
+    let in_metric = in_bi.cal_macd_metric(macd_algo, false).ok()?;
+    let out_metric = out_bi.cal_macd_metric(macd_algo, true).ok()?;
+    Some(out_metric / (in_metric + 1e-7))
+}
+
+pub fn cal_interval_nest(
+    bsp: &SharedCell<CBSPoint>,
+    sub_kl_list: &CKLineList,
+) -> Option<CIntervalNest> {
+    let (begin_klu, end_klu) = bsp_last_bi_klu(&bsp.borrow());
+    let sub_begin = first_sub_klu(&begin_klu)?;
+    let sub_end = last_sub_klu(&end_klu)?;
+    let sub_begin_idx = sub_begin.borrow().idx;
+    let sub_end_idx = sub_end.borrow().idx;
+    let is_buy = bsp.borrow().is_buy;
+
+    let sub_bsp_lst: Vec<SharedCell<CBSPoint>> = sub_kl_list
+        .bs_point_lst
+        .iter()
+        .filter(|sub_bsp| {
+            let sub_bsp = sub_bsp.borrow();
+            let idx = sub_bsp.klu.borrow().idx;
+            sub_bsp.is_buy == is_buy && idx >= sub_begin_idx && idx <= sub_end_idx
+        })
+        .cloned()
+        .collect();
+
+    let sub_divergence_rate = cal_sub_divergence(sub_kl_list, is_buy, sub_begin_idx, sub_end_idx);
+
+    let sub_bsp_types: Vec<BspType> = sub_bsp_lst
+        .iter()
+        .flat_map(|sub_bsp| sub_bsp.borrow().bsp_type.clone())
+        .collect();
+    let confidence = cal_confidence(&sub_bsp_types, sub_divergence_rate);
+
+    let begin_time = sub_begin.borrow().time.clone();
+    let end_time = sub_end.borrow().time.clone();
+    Some(CIntervalNest {
+        bsp: Rc::clone(bsp),
+        sub_begin_idx,
+        sub_end_idx,
+        begin_time,
+        end_time,
+        sub_bsp_lst,
+        sub_divergence_rate,
+        confidence,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BuySellPoint::BSPRule::{BspRule, CBspRuleHit};
+    use crate::ChanConfig::CChanConfig;
+    use crate::Common::CEnum::{FxType, KlineDir};
+    use crate::Common::TradeInfo::CTradeInfo;
+    use crate::KLine::KLine::CKLine;
+    use crate::Math::Demark::CDemarkIndex;
+    use crate::Seg::SegListComm::CSegListComm;
+    use crate::ZS::ZSList::CZSList;
+    use serde_json::json;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_bsp_type_weight() {
+        assert_eq!(bsp_type_weight(&BspType::T1), 1.0);
+        assert_eq!(bsp_type_weight(&BspType::T1P), 1.0);
+        assert_eq!(bsp_type_weight(&BspType::T2), 0.8);
+        assert_eq!(bsp_type_weight(&BspType::T2S), 0.8);
+        assert_eq!(bsp_type_weight(&BspType::T3A), 0.6);
+        assert_eq!(bsp_type_weight(&BspType::T3B), 0.6);
+        assert_eq!(bsp_type_weight(&BspType::Custom("vol".to_string())), 0.0);
+    }
+
+    #[test]
+    fn test_cal_confidence() {
+        // 没有次级别买卖点也没有背驰
+        assert_eq!(cal_confidence(&[], None), 0.0);
+        // 取最高权重
+        assert_eq!(cal_confidence(&[BspType::T3A, BspType::T2], None), 0.8);
+        assert_eq!(
+            cal_confidence(&[BspType::T3B, BspType::T1P, BspType::T2S], None),
+            1.0
+        );
+        // 没有背驰不加分
+        assert_eq!(cal_confidence(&[BspType::T3A], Some(1.2)), 0.6);
+        // 背驰按 0.5*(1-rate) 加分
+        assert!((cal_confidence(&[BspType::T3A], Some(0.6)) - 0.8).abs() < 1e-12);
+        assert!((cal_confidence(&[], Some(0.5)) - 0.25).abs() < 1e-12);
+        // 上限为1
+        assert_eq!(cal_confidence(&[BspType::T2], Some(0.1)), 1.0);
+    }
+
+    // 每一笔的终点都出一个买卖点，让次级别买卖点只取决于笔的划分
+    struct BiEndRule;
+
+    impl BspRule for BiEndRule {
+        fn name(&self) -> String {
+            "bi_end".to_string()
+        }
+
+        fn cal(
+            &self,
+            bi_list: &[SharedCell<CBi>],
+            _seg_list: &CSegListComm<CBi>,
+            _zs_list: &CZSList,
+        ) -> Vec<CBspRuleHit> {
+            bi_list
+                .iter()
+                .map(|bi| CBspRuleHit {
+                    bi: Rc::clone(bi),
+                    features: HashMap::new(),
+                })
+                .collect()
+        }
+    }
+
+    fn klu(idx: i32, time: CTime, high: f64, low: f64, close: f64) -> CKLineUnit {
+        CKLineUnit {
+            kl_type: None,
+            time,
+            close,
+            open: close,
+            high,
+            low,
+            trade_info: CTradeInfo::new(&HashMap::new()),
+            demark: CDemarkIndex::new(),
+            sub_kl_list: Vec::new(),
+            sup_kl: None,
+            klc: None,
+            trend: HashMap::new(),
+            limit_flag: 0,
+            pre: None,
+            next: None,
+            idx,
+            macd: None,
+            boll: None,
+            rsi: None,
+            kdj: None,
+            metric: HashMap::new(),
+        }
+    }
+
+    // 次级别每4根K线对应一根本级别K线
+    fn sub_time(idx: i32) -> CTime {
+        CTime::new(
+            2024,
+            1,
+            1 + (idx / 4) as u32,
+            10 + (idx % 4) as u32,
+            0,
+            0,
+            false,
+        )
+    }
+
+    // 次级别收盘价：3 顶(16) -> 9 底(10) -> 14 顶(12.5) -> 22 底(9.5) -> 28 顶(11.5) -> 31
+    // 第二个向下笔创新低但更平缓，macd 峰值更小，构成次级别背驰
+    fn sub_closes() -> Vec<f64> {
+        let mut closes = vec![13.0, 14.0, 15.0, 16.0, 15.0, 14.0, 13.0, 12.0, 11.0, 10.0];
+        closes.extend([10.5, 11.0, 11.5, 12.0, 12.5]);
+        closes.extend((1..=8).map(|i| 12.5 - 0.375 * i as f64));
+        closes.extend((1..=6).map(|i| 9.5 + i as f64 / 3.0));
+        closes.extend([11.0, 10.7, 10.4]);
+        closes
+    }
+
+    fn sub_kl_list() -> CKLineList {
+        let mut conf = CChanConfig::new(Some(HashMap::from([(
+            "bs_type".to_string(),
+            json!("custom:bi_end"),
+        )])))
+        .unwrap();
+        conf.add_bsp_rule(Rc::new(BiEndRule)).unwrap();
+        let mut kl_list = CKLineList::new("K_30M".to_string(), conf).unwrap();
+        for (idx, close) in sub_closes().into_iter().enumerate() {
+            let idx = idx as i32;
+            kl_list
+                .add_single_klu(klu(idx, sub_time(idx), close + 0.2, close - 0.2, close))
+                .unwrap();
+        }
+        kl_list.cal_seg_and_zs().unwrap();
+        kl_list
+    }
+
+    // 用次级别已经入库的K线合成本级别K线，并像 CChan 加载时一样建立父子关系
+    fn parent_klu_lst(sub_kl_list: &CKLineList) -> Vec<SharedCell<CKLineUnit>> {
+        let sub_klu_lst: Vec<SharedCell<CKLineUnit>> = sub_kl_list
+            .lst
+            .iter()
+            .flat_map(|klc| klc.borrow().lst.clone())
+            .collect();
+        sub_klu_lst
+            .chunks(4)
+            .enumerate()
+            .map(|(idx, children)| {
+                let high = children
+                    .iter()
+                    .map(|c| c.borrow().high)
+                    .fold(f64::MIN, f64::max);
+                let low = children
+                    .iter()
+                    .map(|c| c.borrow().low)
+                    .fold(f64::MAX, f64::min);
+                let close = children.last().unwrap().borrow().close;
+                let time = CTime::new(2024, 1, 1 + idx as u32, 0, 0, 0, false);
+                let parent = Rc::new(RefCell::new(klu(idx as i32, time, high, low, close)));
+                for child in children {
+                    parent.borrow_mut().add_children(Rc::clone(child));
+                    child.borrow_mut().set_parent(Rc::clone(&parent));
+                }
+                parent
+            })
+            .collect()
+    }
+
+    fn klc(klu: &SharedCell<CKLineUnit>, fx: FxType) -> SharedCell<CKLine> {
+        let idx = klu.borrow().idx;
+        let mut klc = CKLine::new(Rc::clone(klu), idx, KlineDir::UP);
+        klc.fx = Some(fx);
+        Rc::new(RefCell::new(klc))
+    }
+
+    // 本级别从 begin 顶到 end 底的向下笔，终点上的一买
+    fn parent_buy_bsp(
+        parent_klu_lst: &[SharedCell<CKLineUnit>],
+        begin: usize,
+        end: usize,
+    ) -> SharedCell<CBSPoint> {
+        let bi = CBi::new(
+            klc(&parent_klu_lst[begin], FxType::TOP),
+            klc(&parent_klu_lst[end], FxType::BOTTOM),
+            0,
+            false,
+        );
+        CBSPoint::new(
+            LineType::Bi(Rc::new(RefCell::new(bi))),
+            true,
+            BspType::T1,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_cal_interval_nest() {
+        let sub_kl_list = sub_kl_list();
+        let bi_ends: Vec<(i32, i32)> = sub_kl_list
+            .bi_list
+            .bi_list
+            .iter()
+            .map(|bi| {
+                let bi = bi.borrow();
+                (
+                    bi.get_begin_klu().borrow().idx,
+                    bi.get_end_klu().borrow().idx,
+                )
+            })
+            .collect();
+        assert_eq!(bi_ends, vec![(3, 9), (9, 14), (14, 22), (22, 28)]);
+        let parent_klu_lst = parent_klu_lst(&sub_kl_list);
+        assert_eq!(parent_klu_lst.len(), 8);
+
+        // 本级别笔 0 -> 5 对应次级别 0..=23，包含两根向下笔
+        let bsp = parent_buy_bsp(&parent_klu_lst, 0, 5);
+        let nest = cal_interval_nest(&bsp, &sub_kl_list).unwrap();
+        assert!(Rc::ptr_eq(&nest.bsp, &bsp));
+        assert_eq!((nest.sub_begin_idx, nest.sub_end_idx), (0, 23));
+        assert_eq!(nest.begin_time, sub_time(0));
+        assert_eq!(nest.end_time, sub_time(23));
+        // 区间内的卖点 s14 和区间外的卖点 s28 都不算
+        let sub_bsp_idx: Vec<i32> = nest
+            .sub_bsp_lst
+            .iter()
+            .map(|sub_bsp| sub_bsp.borrow().klu.borrow().idx)
+            .collect();
+        assert_eq!(sub_bsp_idx, vec![9, 22]);
+        assert_eq!(nest.sub_bsp_type2str(), "bi_end;bi_end");
+        assert!(nest.has_sub_bsp(BspType::Custom("bi_end".to_string())));
+        // 默认 macd_algo=peak：出笔 14->22 的 macd 峰值 0.427591，进笔 3->9 的峰值 0.785457
+        let rate = nest.sub_divergence_rate.unwrap();
+        assert!((rate - 0.544384).abs() < 1e-5);
+        assert!(nest.has_sub_divergence());
+        assert_eq!(nest.confidence, cal_confidence(&[], Some(rate)));
+
+        // 本级别笔 3 -> 5 对应次级别 12..=23，只有一根向下笔，算不了背驰
+        let bsp = parent_buy_bsp(&parent_klu_lst, 3, 5);
+        let nest = cal_interval_nest(&bsp, &sub_kl_list).unwrap();
+        assert_eq!((nest.sub_begin_idx, nest.sub_end_idx), (12, 23));
+        let sub_bsp_idx: Vec<i32> = nest
+            .sub_bsp_lst
+            .iter()
+            .map(|sub_bsp| sub_bsp.borrow().klu.borrow().idx)
+            .collect();
+        assert_eq!(sub_bsp_idx, vec![22]);
+        assert_eq!(nest.sub_divergence_rate, None);
+        assert!(!nest.has_sub_divergence());
+        assert_eq!(nest.confidence, 0.0);
+
+        // 没有次级别K线的本级别K线无法做区间套
+        let orphan = Rc::new(RefCell::new(klu(
+            8,
+            CTime::new(2024, 1, 9, 0, 0, 0, false),
+            12.0,
+            11.0,
+            11.5,
+        )));
+        let mut lst = parent_klu_lst.clone();
+        lst.push(orphan);
+        let bsp = parent_buy_bsp(&lst, 7, 8);
+        assert!(cal_interval_nest(&bsp, &sub_kl_list).is_none());
+    }
+}
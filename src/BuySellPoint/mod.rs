@@ -1,3 +1,9 @@
+pub mod BSPFeature;
+pub mod BSPRisk;
+pub mod BSPRule;
 pub mod BSPointConfig;
+pub mod BSPointHistory;
 pub mod BSPointList;
 pub mod BS_Point;
+pub mod Divergence;
+pub mod IntervalNest;
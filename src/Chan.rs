@@ -2,8 +2,11 @@ use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
+use crate::BuySellPoint::BS_Point::CBSPoint;
+use crate::BuySellPoint::IntervalNest::{cal_interval_nest, CIntervalNest};
 use crate::ChanConfig::CChanConfig;
 use crate::Common::func_util::check_kltype_order;
+use crate::Common::types::SharedCell;
 use crate::Common::CEnum::{KlType, AUTYPE, DATA_SRC};
 use crate::Common::CTime::CTime;
 use crate::Common::ChanException::CChanException;
@@ -58,7 +61,7 @@ impl CChan {
             klu_last_t: Vec::new(),
         };
 
-        chan.do_init();
+        chan.do_init()?;
 
         if !chan.conf.trigger_step {
             chan.load()?;
@@ -67,12 +70,13 @@ impl CChan {
         Ok(chan)
     }
 
-    fn do_init(&mut self) {
+    fn do_init(&mut self) -> Result<(), CChanException> {
         self.kl_datas.clear();
         for lv in &self.lv_list {
             self.kl_datas
-                .insert(*lv, CKLineList::new(*lv, self.conf.clone()));
+                .insert(*lv, CKLineList::new(*lv, self.conf.clone())?);
         }
+        Ok(())
     }
 
     fn load_stock_data(
@@ -121,11 +125,11 @@ impl CChan {
         None
     }
 
-    pub fn step_load(&mut self) -> impl Iterator<Item = &Self> {
+    pub fn step_load(&mut self) -> Result<impl Iterator<Item = &Self>, CChanException> {
         assert!(self.conf.trigger_step);
-        self.do_init();
+        self.do_init()?;
         let skip_step = self.conf.skip_step;
-        self.load().enumerate().filter_map(
+        Ok(self.load().enumerate().filter_map(
             move |(idx, result)| {
                 if idx < skip_step {
                     None
@@ -133,7 +137,7 @@ impl CChan {
                     Some(self)
                 }
             },
-        )
+        ))
     }
 
     pub fn trigger_load(
@@ -412,6 +416,40 @@ impl CChan {
         self.kl_datas.get(&n)
     }
 
+    // 区间套：在 lv_idx 的次级别里确认 bsp，最低级别没有次级别时返回 None
+    pub fn get_interval_nest(
+        &self,
+        lv_idx: usize,
+        bsp: &SharedCell<CBSPoint>,
+    ) -> Option<CIntervalNest> {
+        let sub_lv = self.lv_list.get(lv_idx + 1)?;
+        let sub_kl_list = self.kl_datas.get(sub_lv)?;
+        cal_interval_nest(bsp, sub_kl_list)
+    }
+
+    // 对 lv_idx 级别当前所有买卖点做区间套确认
+    // lv_idx 超出级别列表时报错
+    pub fn get_interval_nest_list(
+        &self,
+        lv_idx: usize,
+    ) -> Result<Vec<CIntervalNest>, CChanException> {
+        let lv = self.lv_list.get(lv_idx).ok_or_else(|| {
+            CChanException::new(
+                &format!("lv_idx={} 超出级别数{}", lv_idx, self.lv_list.len()),
+                ErrCode::ParaError,
+            )
+        })?;
+        let kl_data = match self.kl_datas.get(lv) {
+            Some(kl_data) => kl_data,
+            None => return Ok(Vec::new()),
+        };
+        Ok(kl_data
+            .bs_point_lst
+            .iter()
+            .filter_map(|bsp| self.get_interval_nest(lv_idx, bsp))
+            .collect())
+    }
+
     pub fn get_bsp(&self, idx: Option<usize>) -> Vec<CBSPoint> {
         if let Some(idx) = idx {
             if let Some(kl_data) = self.kl_datas.get(&self.lv_list[idx]) {
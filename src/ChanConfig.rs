@@ -1,7 +1,27 @@
 use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::BuySellPoint::BSPRule::BspRule;
+use crate::ChanModel::BspModel::{load_bsp_model, BspModel};
+use crate::Common::CEnum::BspType;
+use crate::Math::MetricModel::MetricModel;
+use crate::Math::{
+    Demark::DemarkConfig,
+    Donchian::CDonchian,
+    Keltner::CKeltner,
+    RollingStats::CRollingStats,
+    ADX::CADX,
+    ATR::CATR,
+    CCI::CCCI,
+    MA::{CEMA, CSMA},
+    OBV::COBV,
+    VWAP::CVWAP,
+};
 
 use crate::{
-    Bi::BiConfig::CBiConfig, BuySellPoint::BSPointConfig::CBSPointConfig, ZS::ZSConfig::CZSConfig,
+    Bi::BiConfig::CBiConfig,
+    BuySellPoint::BSPointConfig::{unknown_key_msg, CBSPointConfig, BSP_CONF_KEYS},
+    ZS::ZSConfig::CZSConfig,
 };
 
 pub struct CChanConfig {
@@ -18,16 +38,43 @@ pub struct CChanConfig {
     pub print_err_time: bool,
     pub mean_metrics: Vec<i32>,
     pub trend_metrics: Vec<i32>,
+    pub std_metrics: Vec<i32>,
     pub macd_config: HashMap<String, i32>,
     pub cal_demark: bool,
     pub cal_rsi: bool,
     pub cal_kdj: bool,
     pub rsi_cycle: i32,
     pub kdj_cycle: i32,
-    pub demark_config: HashMap<String, bool>,
+    pub demark_config: DemarkConfig,
     pub boll_n: i32,
+    pub ema_metrics: Vec<i32>,
+    pub sma_metrics: Vec<i32>,
+    pub cal_atr: bool,
+    pub atr_cycle: i32,
+    pub cal_obv: bool,
+    pub cal_vwap: bool,
+    pub vwap_cycle: i32,
+    pub cal_adx: bool,
+    pub adx_cycle: i32,
+    pub cal_cci: bool,
+    pub cci_cycle: i32,
+    pub cal_donchian: bool,
+    pub donchian_cycle: i32,
+    pub cal_keltner: bool,
+    pub keltner_cycle: i32,
+    pub keltner_atr_cycle: i32,
+    pub keltner_mult: f64,
     pub bs_point_conf: CBSPointConfig,
     pub seg_bs_point_conf: CBSPointConfig,
+    pub max_seg_level: usize,
+    pub tier_bs_point_conf: HashMap<usize, CBSPointConfig>,
+    // 买卖点打分模型，bsp_model 用于笔的买卖点，seg_bsp_model 用于各级线段的买卖点
+    pub bsp_model: Option<Rc<dyn BspModel>>,
+    pub seg_bsp_model: Option<Rc<dyn BspModel>>,
+    // 自定义买卖点规则，只作用于笔的买卖点
+    pub bsp_rules: Vec<Rc<dyn BspRule>>,
+    // 自定义指标，每个 CKLineList 调用一次生成独立的指标实例
+    pub metric_model_builders: Vec<Rc<dyn Fn() -> Box<dyn MetricModel>>>,
 }
 
 impl CChanConfig {
@@ -69,6 +116,7 @@ impl CChanConfig {
             print_err_time: conf.get("print_err_time").unwrap_or(false),
             mean_metrics: conf.get("mean_metrics").unwrap_or_else(Vec::new),
             trend_metrics: conf.get("trend_metrics").unwrap_or_else(Vec::new),
+            std_metrics: conf.get("std_metrics").unwrap_or_else(Vec::new),
             macd_config: conf.get("macd").unwrap_or_else(|| {
                 let mut map = HashMap::new();
                 map.insert("fast".to_string(), 12);
@@ -81,23 +129,101 @@ impl CChanConfig {
             cal_kdj: conf.get("cal_kdj").unwrap_or(false),
             rsi_cycle: conf.get("rsi_cycle").unwrap_or(14),
             kdj_cycle: conf.get("kdj_cycle").unwrap_or(9),
-            demark_config: conf.get("demark").unwrap_or_else(|| {
-                let mut map = HashMap::new();
-                map.insert("demark_len".to_string(), 9);
-                map.insert("setup_bias".to_string(), 4);
-                map.insert("countdown_bias".to_string(), 2);
-                map.insert("max_countdown".to_string(), 13);
-                map.insert("tiaokong_st".to_string(), true);
-                map.insert("setup_cmp2close".to_string(), true);
-                map.insert("countdown_cmp2close".to_string(), true);
-                map
-            }),
+            demark_config: DemarkConfig::default(),
             boll_n: conf.get("boll_n").unwrap_or(20),
+            ema_metrics: conf.get("ema_metrics").unwrap_or_else(Vec::new),
+            sma_metrics: conf.get("sma_metrics").unwrap_or_else(Vec::new),
+            cal_atr: conf.get("cal_atr").unwrap_or(false),
+            atr_cycle: conf.get("atr_cycle").unwrap_or(14),
+            cal_obv: conf.get("cal_obv").unwrap_or(false),
+            cal_vwap: conf.get("cal_vwap").unwrap_or(false),
+            vwap_cycle: conf.get("vwap_cycle").unwrap_or(20),
+            cal_adx: conf.get("cal_adx").unwrap_or(false),
+            adx_cycle: conf.get("adx_cycle").unwrap_or(14),
+            cal_cci: conf.get("cal_cci").unwrap_or(false),
+            cci_cycle: conf.get("cci_cycle").unwrap_or(20),
+            cal_donchian: conf.get("cal_donchian").unwrap_or(false),
+            donchian_cycle: conf.get("donchian_cycle").unwrap_or(20),
+            cal_keltner: conf.get("cal_keltner").unwrap_or(false),
+            keltner_cycle: conf.get("keltner_cycle").unwrap_or(20),
+            keltner_atr_cycle: conf.get("keltner_atr_cycle").unwrap_or(10),
+            keltner_mult: conf.get("keltner_mult").unwrap_or(2.0),
             bs_point_conf: CBSPointConfig::default(),
             seg_bs_point_conf: CBSPointConfig::default(),
+            max_seg_level: conf.get("max_seg_level").unwrap_or(2),
+            tier_bs_point_conf: HashMap::new(),
+            bsp_model: None,
+            seg_bsp_model: None,
+            bsp_rules: Vec::new(),
+            metric_model_builders: Vec::new(),
         };
 
-        config.set_bsp_config(&mut conf)?;
+        // 收集所有配置错误一起返回，避免改一个报一个
+        let mut errors: Vec<String> = Vec::new();
+        if let Some(demark) = conf.get("demark") {
+            match DemarkConfig::from_value(&demark) {
+                Ok(demark_config) => config.demark_config = demark_config,
+                Err(e) => errors.push(e.msg),
+            }
+        }
+
+        for (k, is_seg) in [("bsp_model", false), ("seg_bsp_model", true)] {
+            let Some(v) = conf.get(k) else {
+                continue;
+            };
+            let Some(path) = v.as_str() else {
+                errors.push(format!("{}={} must be a model file path", k, v));
+                continue;
+            };
+            match load_bsp_model(path) {
+                Ok(model) if is_seg => config.seg_bsp_model = Some(model),
+                Ok(model) => config.bsp_model = Some(model),
+                Err(e) => errors.push(format!("{}: {}", k, e.msg)),
+            }
+        }
+
+        // 指标周期在 get_metric_model 中会转成 usize，这里先检查
+        for (k, cycle) in [
+            ("rsi_cycle", config.rsi_cycle),
+            ("kdj_cycle", config.kdj_cycle),
+            ("atr_cycle", config.atr_cycle),
+            ("vwap_cycle", config.vwap_cycle),
+            ("adx_cycle", config.adx_cycle),
+            ("cci_cycle", config.cci_cycle),
+            ("donchian_cycle", config.donchian_cycle),
+            ("keltner_cycle", config.keltner_cycle),
+            ("keltner_atr_cycle", config.keltner_atr_cycle),
+        ] {
+            if cycle <= 0 {
+                errors.push(format!("{}={} must be > 0", k, cycle));
+            }
+        }
+        for (k, metrics) in [
+            ("mean_metrics", &config.mean_metrics),
+            ("trend_metrics", &config.trend_metrics),
+            ("std_metrics", &config.std_metrics),
+            ("ema_metrics", &config.ema_metrics),
+            ("sma_metrics", &config.sma_metrics),
+        ] {
+            if let Some(t) = metrics.iter().find(|t| **t <= 0) {
+                errors.push(format!("{} contains {}, every window must be > 0", k, t));
+            }
+        }
+
+        if config.max_seg_level < 1 {
+            errors.push(format!(
+                "max_seg_level={} must be >= 1",
+                config.max_seg_level
+            ));
+        }
+
+        config.set_bsp_config(&mut conf, &mut errors)?;
+        if !errors.is_empty() {
+            return Err(CChanException::new(
+                format!("invalid CChanConfig: {}", errors.join("; ")),
+                ErrCode::ConfigError,
+            ));
+        }
 
         conf.check()?;
 
@@ -113,27 +239,18 @@ impl CChanConfig {
             *self.macd_config.get("signal").unwrap(),
         )));
 
-        for &mean_t in &self.mean_metrics {
-            res.push(Box::new(CTrendModel::new(TrendType::Mean, mean_t)));
-        }
-
-        for &trend_t in &self.trend_metrics {
-            res.push(Box::new(CTrendModel::new(TrendType::Max, trend_t)));
-            res.push(Box::new(CTrendModel::new(TrendType::Min, trend_t)));
+        // 同一窗口只建一个滚动统计，均值/最大/最小/标准差一起算
+        // get_rolling_windows 已去掉 0 窗口
+        for t in self.get_rolling_windows() {
+            if let Ok(stats) = CRollingStats::new(t) {
+                res.push(Box::new(stats));
+            }
         }
 
         res.push(Box::new(BollModel::new(self.boll_n)));
 
         if self.cal_demark {
-            res.push(Box::new(CDemarkEngine::new(
-                *self.demark_config.get("demark_len").unwrap() as i32,
-                *self.demark_config.get("setup_bias").unwrap() as i32,
-                *self.demark_config.get("countdown_bias").unwrap() as i32,
-                *self.demark_config.get("max_countdown").unwrap() as i32,
-                *self.demark_config.get("tiaokong_st").unwrap(),
-                *self.demark_config.get("setup_cmp2close").unwrap(),
-                *self.demark_config.get("countdown_cmp2close").unwrap(),
-            )));
+            res.push(Box::new(CDemarkEngine::new(self.demark_config)));
         }
 
         if self.cal_rsi {
@@ -144,10 +261,100 @@ impl CChanConfig {
             res.push(Box::new(KDJ::new(self.kdj_cycle)));
         }
 
+        for &ema_t in &self.ema_metrics {
+            res.push(Box::new(CEMA::new(ema_t as usize)));
+        }
+
+        for &sma_t in &self.sma_metrics {
+            res.push(Box::new(CSMA::new(sma_t as usize)));
+        }
+
+        if self.cal_atr {
+            res.push(Box::new(CATR::new(self.atr_cycle as usize)));
+        }
+
+        if self.cal_obv {
+            res.push(Box::new(COBV::new()));
+        }
+
+        if self.cal_vwap {
+            res.push(Box::new(CVWAP::new(self.vwap_cycle as usize)));
+        }
+
+        if self.cal_adx {
+            res.push(Box::new(CADX::new(self.adx_cycle as usize)));
+        }
+
+        if self.cal_cci {
+            res.push(Box::new(CCCI::new(self.cci_cycle as usize)));
+        }
+
+        if self.cal_donchian {
+            res.push(Box::new(CDonchian::new(self.donchian_cycle as usize)));
+        }
+
+        if self.cal_keltner {
+            res.push(Box::new(CKeltner::new(
+                self.keltner_cycle as usize,
+                self.keltner_atr_cycle as usize,
+                self.keltner_mult,
+            )));
+        }
+
+        for builder in &self.metric_model_builders {
+            res.push(builder());
+        }
+
         res
     }
 
-    fn set_bsp_config(&mut self, conf: &mut ConfigWithCheck) -> Result<(), CChanException> {
+    // mean_metrics/trend_metrics/std_metrics 以及买卖点 std_filter 用到的所有窗口
+    pub fn get_rolling_windows(&self) -> Vec<usize> {
+        let mut windows: Vec<usize> = self
+            .mean_metrics
+            .iter()
+            .chain(self.trend_metrics.iter())
+            .chain(self.std_metrics.iter())
+            .map(|&t| t as usize)
+            .collect();
+        let bsp_confs = [&self.bs_point_conf, &self.seg_bs_point_conf]
+            .into_iter()
+            .chain(self.tier_bs_point_conf.values());
+        for bsp_conf in bsp_confs {
+            for point_conf in [&bsp_conf.b_conf, &bsp_conf.s_conf] {
+                if let Some((t, _)) = point_conf.std_filter {
+                    windows.push(t);
+                }
+            }
+        }
+        windows.retain(|&t| t > 0);
+        windows.sort_unstable();
+        windows.dedup();
+        windows
+    }
+
+    // 注册自定义指标，name 不能和已有指标重复
+    pub fn add_metric_model(
+        &mut self,
+        builder: Rc<dyn Fn() -> Box<dyn MetricModel>>,
+    ) -> Result<(), CChanException> {
+        let name = builder().name();
+        if self.get_metric_model().iter().any(|m| m.name() == name) {
+            return Err(CChanException::new(
+                format!("metric model {} already registered", name),
+                ErrCode::ParaError,
+            ));
+        }
+        self.metric_model_builders.push(builder);
+        Ok(())
+    }
+
+    // 参数错误追加到 errors 中，由调用方统一返回
+    fn set_bsp_config(
+        &mut self,
+        conf: &mut ConfigWithCheck,
+        errors: &mut Vec<String>,
+    ) -> Result<(), CChanException> {
         let para_dict = [
             ("divergence_rate", serde_json::Value::from(f64::INFINITY)),
             ("min_zs_cnt", serde_json::Value::from(1)),
@@ -162,6 +369,9 @@ impl CChanConfig {
             ("bsp2s_follow_2", serde_json::Value::from(false)),
             ("max_bsp2s_lv", serde_json::Value::Null),
             ("strict_bsp3", serde_json::Value::from(false)),
+            ("score_thred", serde_json::Value::Null),
+            ("std_filter", serde_json::Value::Null),
+            ("divergence_report", serde_json::Value::from(false)),
         ]
         .iter()
         .cloned()
@@ -172,79 +382,164 @@ impl CChanConfig {
             .map(|(k, v)| (k.to_string(), conf.get(k).unwrap_or(v)))
             .collect();
 
-        self.bs_point_conf = CBSPointConfig::new(&args)?;
-        self.seg_bs_point_conf = CBSPointConfig::new(&args)?;
-
-        self.seg_bs_point_conf
-            .b_conf
-            .set("macd_algo", "slope".into())?;
-        self.seg_bs_point_conf
-            .s_conf
-            .set("macd_algo", "slope".into())?;
-        self.seg_bs_point_conf
-            .b_conf
-            .set("bsp1_only_multibi_zs", false)?;
-        self.seg_bs_point_conf
-            .s_conf
-            .set("bsp1_only_multibi_zs", false)?;
+        self.bs_point_conf = CBSPointConfig::new(&args).unwrap_or_else(|e| {
+            errors.push(e.msg);
+            CBSPointConfig::default()
+        });
+        // 自定义规则只在笔级别计算，线段级别不继承自定义类型
+        self.seg_bs_point_conf = self.bs_point_conf.clone();
+        self.seg_bs_point_conf.remove_custom_types();
+        for seg_conf in [
+            &mut self.seg_bs_point_conf.b_conf,
+            &mut self.seg_bs_point_conf.s_conf,
+        ] {
+            seg_conf.set("macd_algo", "slope".into())?;
+            seg_conf.set("bsp1_only_multibi_zs", false.into())?;
+        }
 
+        // -segN 参数在所有 -seg/-segbuy/-segsell 参数生效之后再覆盖到各级别，结果与参数顺序无关
+        let mut tier_items = Vec::new();
         for (k, v) in conf.items() {
-            let v = if let serde_json::Value::String(s) = v {
-                serde_json::Value::String(format!("\"{}\"", s))
+            let res = if let Some(prop) = k.strip_suffix("-buy") {
+                self.bs_point_conf.b_conf.set(prop, v)
+            } else if let Some(prop) = k.strip_suffix("-sell") {
+                self.bs_point_conf.s_conf.set(prop, v)
+            } else if let Some(prop) = k.strip_suffix("-segbuy") {
+                self.seg_bs_point_conf.b_conf.set(prop, v)
+            } else if let Some(prop) = k.strip_suffix("-segsell") {
+                self.seg_bs_point_conf.s_conf.set(prop, v)
+            } else if let Some(level) = parse_tier_suffix(&k) {
+                if level < 3 || level > self.max_seg_level {
+                    errors.push(format!(
+                        "{} out of range, max_seg_level={}",
+                        k, self.max_seg_level
+                    ));
+                } else {
+                    tier_items.push((level, k, v));
+                }
+                continue;
+            } else if let Some(prop) = k.strip_suffix("-seg") {
+                self.seg_bs_point_conf
+                    .b_conf
+                    .set(prop, v.clone())
+                    .and_then(|_| self.seg_bs_point_conf.s_conf.set(prop, v))
             } else {
-                v
+                let mut candidates = conf.visited_keys();
+                candidates.extend(BSP_CONF_KEYS.iter());
+                errors.push(unknown_key_msg(&k, &candidates));
+                continue;
             };
-            let v = parse_inf(v);
-
-            if k.ends_with("-buy") {
-                let prop = k.trim_end_matches("-buy");
-                self.bs_point_conf.b_conf.set(prop, v)?;
-            } else if k.ends_with("-sell") {
-                let prop = k.trim_end_matches("-sell");
-                self.bs_point_conf.s_conf.set(prop, v)?;
-            } else if k.ends_with("-segbuy") {
-                let prop = k.trim_end_matches("-segbuy");
-                self.seg_bs_point_conf.b_conf.set(prop, v)?;
-            } else if k.ends_with("-segsell") {
-                let prop = k.trim_end_matches("-segsell");
-                self.seg_bs_point_conf.s_conf.set(prop, v)?;
-            } else if k.ends_with("-seg") {
-                let prop = k.trim_end_matches("-seg");
-                self.seg_bs_point_conf.b_conf.set(prop, v.clone())?;
-                self.seg_bs_point_conf.s_conf.set(prop, v)?;
-            } else if args.contains_key(k) {
-                self.bs_point_conf.b_conf.set(k, v.clone())?;
-                self.bs_point_conf.s_conf.set(k, v)?;
-            } else {
-                return Err(CChanException::new(
-                    &format!("unknown para = {}", k),
-                    ErrCode::ParaError,
+            if let Err(e) = res {
+                errors.push(format!("{}: {}", k, e.msg));
+            }
+        }
+
+        for (level, k, v) in tier_items {
+            let prop = &k[..k.rfind("-seg").unwrap()];
+            let tier_conf = self
+                .tier_bs_point_conf
+                .entry(level)
+                .or_insert_with(|| self.seg_bs_point_conf.clone());
+            let res = tier_conf
+                .b_conf
+                .set(prop, v.clone())
+                .and_then(|_| tier_conf.s_conf.set(prop, v));
+            if let Err(e) = res {
+                errors.push(format!("{}: {}", k, e.msg));
+            }
+        }
+
+        let seg_confs = std::iter::once((2, &self.seg_bs_point_conf))
+            .chain(self.tier_bs_point_conf.iter().map(|(lv, c)| (*lv, c)));
+        for (level, seg_conf) in seg_confs {
+            for t in seg_conf.custom_types() {
+                errors.push(format!(
+                    "bs_type: custom bsp {} only supported at bi level, got it at seg level {}",
+                    t, level
                 ));
             }
         }
 
-        self.bs_point_conf.b_conf.parse_target_type()?;
-        self.bs_point_conf.s_conf.parse_target_type()?;
-        self.seg_bs_point_conf.b_conf.parse_target_type()?;
-        self.seg_bs_point_conf.s_conf.parse_target_type()?;
+        Ok(())
+    }
 
+    // bs_type 里配置的自定义买卖点必须有对应的规则，避免拼错名字后静默不出点
+    pub fn check_bsp_rules(&self) -> Result<(), CChanException> {
+        let missing: Vec<String> = self
+            .bs_point_conf
+            .custom_types()
+            .iter()
+            .map(|t| t.to_string())
+            .filter(|name| !self.bsp_rules.iter().any(|r| &r.name() == name))
+            .collect();
+        if !missing.is_empty() {
+            return Err(CChanException::new(
+                format!("bs_type: no bsp rule registered for {}", missing.join(",")),
+                ErrCode::ConfigError,
+            ));
+        }
         Ok(())
     }
+
+    pub fn add_bsp_rule(&mut self, rule: Rc<dyn BspRule>) -> Result<(), CChanException> {
+        let name = rule.name();
+        if BspType::from_custom_name(&name).is_none() {
+            return Err(CChanException::new(
+                format!(
+                    "bsp rule name {} is empty or conflicts with builtin bsp type",
+                    name
+                ),
+                ErrCode::ParaError,
+            ));
+        }
+        if self.bsp_rules.iter().any(|r| r.name() == name) {
+            return Err(CChanException::new(
+                format!("bsp rule {} already registered", name),
+                ErrCode::ParaError,
+            ));
+        }
+        self.bsp_rules.push(rule);
+        Ok(())
+    }
+
+    // level=2 为线段的线段，使用 seg_bs_point_conf；更高级别可用 xxx-seg3 之类的参数单独覆盖
+    pub fn get_tier_bs_point_conf(&self, level: usize) -> CBSPointConfig {
+        self.tier_bs_point_conf
+            .get(&level)
+            .cloned()
+            .unwrap_or_else(|| self.seg_bs_point_conf.clone())
+    }
+}
+
+// 解析 divergence_rate-seg3 这类参数的级别后缀
+fn parse_tier_suffix(k: &str) -> Option<usize> {
+    let pos = k.rfind("-seg")?;
+    k[pos + 4..].parse::<usize>().ok()
 }
 
 struct ConfigWithCheck {
     conf: HashMap<String, serde_json::Value>,
+    // 所有被读取过的参数名，用于拼写错误时给出提示
+    visited: Vec<&'static str>,
 }
 
 impl ConfigWithCheck {
     fn new(conf: HashMap<String, serde_json::Value>) -> Self {
-        ConfigWithCheck { conf }
+        ConfigWithCheck {
+            conf,
+            visited: Vec::new(),
+        }
     }
 
-    fn get(&mut self, k: &str) -> Option<serde_json::Value> {
+    fn get(&mut self, k: &'static str) -> Option<serde_json::Value> {
+        self.visited.push(k);
         self.conf.remove(k)
     }
 
+    fn visited_keys(&self) -> Vec<&'static str> {
+        self.visited.clone()
+    }
+
     fn items(&mut self) -> Vec<(String, serde_json::Value)> {
         let keys: Vec<String> = self.conf.keys().cloned().collect();
         keys.into_iter()
@@ -265,11 +560,83 @@ impl ConfigWithCheck {
     }
 }
 
-pub trait MetricModel {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tier_bsp_config_ignore_key_order() {
+        // HashMap 每次遍历顺序不同，多建几次覆盖 -seg 与 -seg3 的两种先后顺序
+        for _ in 0..16 {
+            let conf = CChanConfig::new(Some(HashMap::from([
+                ("max_seg_level".to_string(), json!(3)),
+                ("min_zs_cnt-seg".to_string(), json!(2)),
+                ("divergence_rate-seg3".to_string(), json!(0.8)),
+            ])))
+            .unwrap();
+            let tier3 = conf.get_tier_bs_point_conf(3);
+            assert_eq!(tier3.b_conf.min_zs_cnt, 2);
+            assert_eq!(tier3.s_conf.min_zs_cnt, 2);
+            assert_eq!(tier3.b_conf.divergence_rate, 0.8);
+            let tier2 = conf.get_tier_bs_point_conf(2);
+            assert_eq!(tier2.b_conf.min_zs_cnt, 2);
+            assert!(tier2.b_conf.divergence_rate.is_infinite());
+        }
+    }
 
-impl MetricModel for CMACD {}
-impl MetricModel for CTrendModel {}
-impl MetricModel for BollModel {}
-impl MetricModel for CDemarkEngine {}
-impl MetricModel for RSI {}
-impl MetricModel for KDJ {}
+    #[test]
+    fn test_report_all_config_errors() {
+        let err = CChanConfig::new(Some(HashMap::from([
+            ("max_seg_level".to_string(), json!(0)),
+            ("demark".to_string(), json!({"demark_len": -1})),
+            ("max_bs2_rate".to_string(), json!(1.5)),
+        ])))
+        .err()
+        .unwrap();
+        assert_eq!(err.errcode as i32, ErrCode::ConfigError as i32);
+        assert!(err.msg.contains("max_seg_level=0"));
+        assert!(err.msg.contains("demark_len=-1"));
+        assert!(err.msg.contains("max_bs2_rate=1.5"));
+    }
+
+    #[test]
+    fn test_bsp_model_errors_collected() {
+        let err = CChanConfig::new(Some(HashMap::from([
+            ("bsp_model".to_string(), json!(5)),
+            (
+                "seg_bsp_model".to_string(),
+                json!("/nonexistent/seg_bsp_model.json"),
+            ),
+            ("atr_cycle".to_string(), json!(0)),
+        ])))
+        .err()
+        .unwrap();
+        assert_eq!(err.errcode as i32, ErrCode::ConfigError as i32);
+        assert!(err.msg.contains("bsp_model=5 must be a model file path"));
+        assert!(err.msg.contains("seg_bsp_model:"));
+        assert!(err.msg.contains("atr_cycle=0"));
+    }
+
+    #[test]
+    fn test_metric_cycle_check() {
+        let err = CChanConfig::new(Some(HashMap::from([
+            ("atr_cycle".to_string(), json!(0)),
+            ("keltner_atr_cycle".to_string(), json!(-3)),
+            ("ema_metrics".to_string(), json!([5, -1])),
+            ("sma_metrics".to_string(), json!([0])),
+        ])))
+        .err()
+        .unwrap();
+        assert_eq!(err.errcode as i32, ErrCode::ConfigError as i32);
+        assert!(err.msg.contains("atr_cycle=0"));
+        assert!(err.msg.contains("keltner_atr_cycle=-3"));
+        assert!(err.msg.contains("ema_metrics contains -1"));
+        assert!(err.msg.contains("sma_metrics contains 0"));
+        assert!(CChanConfig::new(Some(HashMap::from([(
+            "ema_metrics".to_string(),
+            json!([5, 10])
+        )])))
+        .is_ok());
+    }
+}
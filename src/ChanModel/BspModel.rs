@@ -0,0 +1,53 @@
+use crate::ChanModel::Features::CFeatures;
+use crate::ChanModel::GbdtModel::CGbdtModel;
+use crate::ChanModel::LinearModel::CLinearModel;
+use crate::Common::ChanException::{CChanException, ErrCode};
+use std::rc::Rc;
+
+// 买卖点打分模型，输入买卖点的特征，分数越高代表买卖点越可靠
+pub trait BspModel {
+    fn score(&self, features: &CFeatures) -> f64;
+}
+
+// 根据json的结构自动判断模型类型：
+// {"weights": {...}} 为线性/逻辑回归，{"tree_info": [...]} 为 lightgbm dump，
+// {"learner": {...}} 为 xgboost save_model 的模型，{"objective", "base_score", "trees"} 为带参数的 xgboost dump
+// 单独的 xgboost dump 数组里没有 objective 和 base_score，无法正确还原分数，直接报错
+pub fn load_bsp_model(path: &str) -> Result<Rc<dyn BspModel>, CChanException> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        CChanException::new(
+            format!("read bsp model {} fail: {}", path, e),
+            ErrCode::ModelError,
+        )
+    })?;
+    let value: serde_json::Value = serde_json::from_str(&content).map_err(|e| {
+        CChanException::new(
+            format!("bsp model {} is not a valid json: {}", path, e),
+            ErrCode::ModelError,
+        )
+    })?;
+    if value.is_array() {
+        Err(CChanException::new(
+            format!(
+                "xgboost dump {} has no objective/base_score, use save_model json or wrap it as {{\"objective\", \"base_score\", \"trees\"}}",
+                path
+            ),
+            ErrCode::ModelError,
+        ))
+    } else if value.get("learner").is_some() || value.get("trees").is_some() {
+        Ok(Rc::new(CGbdtModel::from_xgboost_model(&value)?))
+    } else if value.get("tree_info").is_some() {
+        Ok(Rc::new(CGbdtModel::from_lightgbm_value(&value)?))
+    } else if value.get("weights").is_some() {
+        Ok(Rc::new(CLinearModel::from_value(&value)?))
+    } else {
+        Err(CChanException::new(
+            format!("unknown bsp model format: {}", path),
+            ErrCode::ModelError,
+        ))
+    }
+}
+
+pub fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
@@ -0,0 +1,555 @@
+use crate::ChanModel::BspModel::{sigmoid, BspModel};
+use crate::ChanModel::Features::CFeatures;
+use crate::Common::ChanException::{CChanException, ErrCode};
+use serde_json::Value;
+
+// 哪些值算缺失：NAN 只有 NaN/没有该特征，ZERO 时 0 也算缺失，NONE 时缺失按 0 参与比较
+// 与 lightgbm 的 missing_type 一致，xgboost 均为 NAN
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingType {
+    NONE,
+    ZERO,
+    NAN,
+}
+
+// lightgbm 的 kZeroThreshold
+const ZERO_THRESHOLD: f64 = 1e-35;
+
+pub enum CTreeNode {
+    Leaf(f64),
+    Split {
+        feature: String,
+        threshold: f64,
+        // xgboost 为 value < threshold 走左边，lightgbm 为 value <= threshold
+        include_equal: bool,
+        missing_type: MissingType,
+        // 特征缺失时走左边
+        missing_left: bool,
+        left: Box<CTreeNode>,
+        right: Box<CTreeNode>,
+    },
+}
+
+impl CTreeNode {
+    pub fn predict(&self, features: &CFeatures) -> f64 {
+        match self {
+            CTreeNode::Leaf(v) => *v,
+            CTreeNode::Split {
+                feature,
+                threshold,
+                include_equal,
+                missing_type,
+                missing_left,
+                left,
+                right,
+            } => {
+                let v = features.get(feature).copied().unwrap_or(f64::NAN);
+                let is_missing = match missing_type {
+                    MissingType::NAN => v.is_nan(),
+                    MissingType::ZERO => v.is_nan() || v.abs() <= ZERO_THRESHOLD,
+                    MissingType::NONE => false,
+                };
+                let go_left = if is_missing {
+                    *missing_left
+                } else {
+                    let v = if v.is_nan() { 0.0 } else { v };
+                    if *include_equal {
+                        v <= *threshold
+                    } else {
+                        v < *threshold
+                    }
+                };
+                if go_left {
+                    left.predict(features)
+                } else {
+                    right.predict(features)
+                }
+            }
+        }
+    }
+}
+
+// 梯度提升树，由 xgboost 模型文件/dump_model(dump_format="json") 或 lightgbm dump_model() 的结果加载
+pub struct CGbdtModel {
+    pub trees: Vec<CTreeNode>,
+    pub base_margin: f64,
+    pub logistic: bool,
+}
+
+fn model_err(msg: String) -> CChanException {
+    CChanException::new(msg, ErrCode::ModelError)
+}
+
+fn get_f64(node: &Value, key: &str) -> Result<f64, CChanException> {
+    node.get(key)
+        .and_then(|v| v.as_f64())
+        .ok_or_else(|| model_err(format!("tree node miss number field {}: {}", key, node)))
+}
+
+impl CGbdtModel {
+    pub fn from_xgboost_json(
+        s: &str,
+        base_margin: f64,
+        logistic: bool,
+    ) -> Result<Self, CChanException> {
+        let value: Value = serde_json::from_str(s)
+            .map_err(|e| model_err(format!("xgboost model is not a valid json: {}", e)))?;
+        Self::from_xgboost_value(&value, base_margin, logistic)
+    }
+
+    // xgboost 的 dump 里没有 base_score 和 objective，需要外部传入；从模型文件加载用 from_xgboost_model
+    pub fn from_xgboost_value(
+        value: &Value,
+        base_margin: f64,
+        logistic: bool,
+    ) -> Result<Self, CChanException> {
+        let trees = value
+            .as_array()
+            .ok_or_else(|| model_err("xgboost dump should be a list of trees".to_string()))?
+            .iter()
+            .map(parse_xgboost_node)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CGbdtModel {
+            trees,
+            base_margin,
+            logistic,
+        })
+    }
+
+    // 支持两种带 objective/base_score 的格式：
+    // 1. Booster.save_model("xxx.json") 保存的原生模型，{"learner": {...}}
+    // 2. {"objective": "binary:logistic", "base_score": 0.5, "trees": [dump_model 的结果]}
+    pub fn from_xgboost_model(value: &Value) -> Result<Self, CChanException> {
+        if let Some(learner) = value.get("learner") {
+            let objective = learner
+                .pointer("/objective/name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| model_err("xgboost model has no objective".to_string()))?;
+            let base_score = learner
+                .pointer("/learner_model_param/base_score")
+                .ok_or_else(|| model_err("xgboost model has no base_score".to_string()))
+                .and_then(parse_base_score)?;
+            let (base_margin, logistic) = xgboost_objective(objective, base_score)?;
+            let feature_names: Vec<String> = learner
+                .get("feature_names")
+                .and_then(|v| v.as_array())
+                .map(|lst| {
+                    lst.iter()
+                        .map(|v| v.as_str().unwrap_or_default().to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let trees = learner
+                .pointer("/gradient_booster/model/trees")
+                .and_then(|v| v.as_array())
+                .ok_or_else(|| model_err("xgboost model has no gbtree trees".to_string()))?
+                .iter()
+                .map(|tree| parse_xgboost_tree(tree, &feature_names))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(CGbdtModel {
+                trees,
+                base_margin,
+                logistic,
+            });
+        }
+        let objective = value
+            .get("objective")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| model_err("xgboost model has no objective".to_string()))?;
+        let base_score = value
+            .get("base_score")
+            .ok_or_else(|| model_err("xgboost model has no base_score".to_string()))
+            .and_then(parse_base_score)?;
+        let (base_margin, logistic) = xgboost_objective(objective, base_score)?;
+        let trees = value
+            .get("trees")
+            .ok_or_else(|| model_err("xgboost model has no trees".to_string()))?;
+        Self::from_xgboost_value(trees, base_margin, logistic)
+    }
+
+    pub fn from_lightgbm_json(s: &str) -> Result<Self, CChanException> {
+        let value: Value = serde_json::from_str(s)
+            .map_err(|e| model_err(format!("lightgbm model is not a valid json: {}", e)))?;
+        Self::from_lightgbm_value(&value)
+    }
+
+    pub fn from_lightgbm_value(value: &Value) -> Result<Self, CChanException> {
+        let feature_names: Vec<String> = value
+            .get("feature_names")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| model_err("lightgbm dump has no feature_names".to_string()))?
+            .iter()
+            .map(|v| v.as_str().unwrap_or_default().to_string())
+            .collect();
+        let trees = value
+            .get("tree_info")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| model_err("lightgbm dump has no tree_info".to_string()))?
+            .iter()
+            .map(|tree| {
+                let root = tree
+                    .get("tree_structure")
+                    .ok_or_else(|| model_err("lightgbm tree has no tree_structure".to_string()))?;
+                parse_lightgbm_node(root, &feature_names)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let logistic = value
+            .get("objective")
+            .and_then(|v| v.as_str())
+            .map_or(false, |obj| obj.starts_with("binary"));
+        Ok(CGbdtModel {
+            trees,
+            base_margin: 0.0,
+            logistic,
+        })
+    }
+}
+
+// base_score 在新版 xgboost 里保存为字符串，如 "5E-1" 或 "[5E-1]"
+fn parse_base_score(v: &Value) -> Result<f64, CChanException> {
+    if let Some(f) = v.as_f64() {
+        return Ok(f);
+    }
+    v.as_str()
+        .and_then(|s| s.trim_matches(|c| c == '[' || c == ']').parse::<f64>().ok())
+        .ok_or_else(|| model_err(format!("invalid xgboost base_score: {}", v)))
+}
+
+// 返回 (base_margin, 是否需要 sigmoid)，逻辑回归类目标的 base_score 是概率，需要转成 margin
+fn xgboost_objective(objective: &str, base_score: f64) -> Result<(f64, bool), CChanException> {
+    let logit = || {
+        if base_score <= 0.0 || base_score >= 1.0 {
+            return Err(model_err(format!(
+                "base_score={} should be in (0, 1) for {}",
+                base_score, objective
+            )));
+        }
+        Ok((base_score / (1.0 - base_score)).ln())
+    };
+    match objective {
+        "binary:logistic" | "reg:logistic" => Ok((logit()?, true)),
+        "binary:logitraw" => Ok((logit()?, false)),
+        "reg:squarederror"
+        | "reg:pseudohubererror"
+        | "reg:absoluteerror"
+        | "rank:pairwise"
+        | "rank:ndcg"
+        | "rank:map" => Ok((base_score, false)),
+        _ => Err(model_err(format!(
+            "unsupport xgboost objective {}",
+            objective
+        ))),
+    }
+}
+
+// 原生模型里每棵树是按节点下标展开的数组，叶子节点的 left_children 为 -1，值存在 split_conditions
+struct XgbTreeArrays {
+    left_children: Vec<f64>,
+    right_children: Vec<f64>,
+    split_indices: Vec<f64>,
+    split_conditions: Vec<f64>,
+    default_left: Vec<f64>,
+}
+
+fn parse_xgboost_tree(tree: &Value, feature_names: &[String]) -> Result<CTreeNode, CChanException> {
+    let array = |key: &str| -> Result<Vec<f64>, CChanException> {
+        tree.get(key)
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| model_err(format!("xgboost tree has no {}", key)))?
+            .iter()
+            .map(|v| {
+                v.as_f64()
+                    .or_else(|| v.as_bool().map(|b| if b { 1.0 } else { 0.0 }))
+                    .ok_or_else(|| model_err(format!("xgboost tree {} has non number {}", key, v)))
+            })
+            .collect()
+    };
+    if let Some(split_type) = tree.get("split_type").and_then(|v| v.as_array()) {
+        if split_type.iter().any(|t| t.as_i64() != Some(0)) {
+            return Err(model_err("unsupport xgboost categorical split".to_string()));
+        }
+    }
+    let arrays = XgbTreeArrays {
+        left_children: array("left_children")?,
+        right_children: array("right_children")?,
+        split_indices: array("split_indices")?,
+        split_conditions: array("split_conditions")?,
+        default_left: array("default_left")?,
+    };
+    build_xgboost_node(&arrays, 0, 0, feature_names)
+}
+
+fn build_xgboost_node(
+    arrays: &XgbTreeArrays,
+    idx: usize,
+    depth: usize,
+    feature_names: &[String],
+) -> Result<CTreeNode, CChanException> {
+    let get = |lst: &[f64]| {
+        lst.get(idx)
+            .copied()
+            .ok_or_else(|| model_err(format!("xgboost tree node {} out of range", idx)))
+    };
+    // 错误的模型文件里可能有环
+    if depth > arrays.left_children.len() {
+        return Err(model_err("xgboost tree has a cycle".to_string()));
+    }
+    let left = get(&arrays.left_children)?;
+    if left < 0.0 {
+        return Ok(CTreeNode::Leaf(get(&arrays.split_conditions)?));
+    }
+    let right = get(&arrays.right_children)?;
+    let feature_idx = get(&arrays.split_indices)? as usize;
+    let feature = feature_names
+        .get(feature_idx)
+        .cloned()
+        .unwrap_or_else(|| format!("f{}", feature_idx));
+    Ok(CTreeNode::Split {
+        feature,
+        threshold: get(&arrays.split_conditions)?,
+        include_equal: false,
+        missing_type: MissingType::NAN,
+        missing_left: get(&arrays.default_left)? != 0.0,
+        left: Box::new(build_xgboost_node(
+            arrays,
+            left as usize,
+            depth + 1,
+            feature_names,
+        )?),
+        right: Box::new(build_xgboost_node(
+            arrays,
+            right as usize,
+            depth + 1,
+            feature_names,
+        )?),
+    })
+}
+
+fn parse_xgboost_node(node: &Value) -> Result<CTreeNode, CChanException> {
+    if node.get("leaf").is_some() {
+        return Ok(CTreeNode::Leaf(get_f64(node, "leaf")?));
+    }
+    let feature = node
+        .get("split")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| model_err(format!("xgboost node has no split: {}", node)))?
+        .to_string();
+    let children = node
+        .get("children")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| model_err(format!("xgboost node has no children: {}", node)))?;
+    let find_child = |key: &str| -> Result<&Value, CChanException> {
+        let nodeid = get_f64(node, key)?;
+        children
+            .iter()
+            .find(|c| c.get("nodeid").and_then(|v| v.as_f64()) == Some(nodeid))
+            .ok_or_else(|| model_err(format!("xgboost node {} not found", nodeid)))
+    };
+    let yes = get_f64(node, "yes")?;
+    let missing = node.get("missing").and_then(|v| v.as_f64()).unwrap_or(yes);
+    Ok(CTreeNode::Split {
+        feature,
+        threshold: get_f64(node, "split_condition")?,
+        include_equal: false,
+        missing_type: MissingType::NAN,
+        missing_left: missing == yes,
+        left: Box::new(parse_xgboost_node(find_child("yes")?)?),
+        right: Box::new(parse_xgboost_node(find_child("no")?)?),
+    })
+}
+
+fn parse_lightgbm_node(
+    node: &Value,
+    feature_names: &[String],
+) -> Result<CTreeNode, CChanException> {
+    if node.get("leaf_value").is_some() {
+        return Ok(CTreeNode::Leaf(get_f64(node, "leaf_value")?));
+    }
+    let feature_idx = get_f64(node, "split_feature")? as usize;
+    let feature = feature_names.get(feature_idx).cloned().ok_or_else(|| {
+        model_err(format!(
+            "lightgbm split_feature {} out of range",
+            feature_idx
+        ))
+    })?;
+    let decision_type = node
+        .get("decision_type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("<=");
+    if decision_type != "<=" {
+        return Err(model_err(format!(
+            "unsupport lightgbm decision_type {}",
+            decision_type
+        )));
+    }
+    // dump_model 的结果都带有 missing_type，没有时按 NaN 处理，缺失走 default_left
+    let missing_type = match node
+        .get("missing_type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("NaN")
+    {
+        "None" => MissingType::NONE,
+        "Zero" => MissingType::ZERO,
+        "NaN" => MissingType::NAN,
+        other => {
+            return Err(model_err(format!(
+                "unsupport lightgbm missing_type {}",
+                other
+            )))
+        }
+    };
+    let child = |key: &str| -> Result<Box<CTreeNode>, CChanException> {
+        let c = node
+            .get(key)
+            .ok_or_else(|| model_err(format!("lightgbm node has no {}", key)))?;
+        Ok(Box::new(parse_lightgbm_node(c, feature_names)?))
+    };
+    Ok(CTreeNode::Split {
+        feature,
+        threshold: get_f64(node, "threshold")?,
+        include_equal: true,
+        missing_type,
+        missing_left: node
+            .get("default_left")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true),
+        left: child("left_child")?,
+        right: child("right_child")?,
+    })
+}
+
+impl BspModel for CGbdtModel {
+    fn score(&self, features: &CFeatures) -> f64 {
+        let margin = self.base_margin
+            + self
+                .trees
+                .iter()
+                .map(|tree| tree.predict(features))
+                .sum::<f64>();
+        if self.logistic {
+            sigmoid(margin)
+        } else {
+            margin
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn features(v: &[(&str, f64)]) -> CFeatures {
+        CFeatures::new(Some(
+            v.iter()
+                .map(|(k, v)| (k.to_string(), *v))
+                .collect::<HashMap<_, _>>(),
+        ))
+    }
+
+    #[test]
+    fn test_xgboost_dump() {
+        let dump = r#"[
+            {"nodeid": 0, "depth": 0, "split": "a", "split_condition": 1.0, "yes": 1, "no": 2, "missing": 2,
+             "children": [{"nodeid": 1, "leaf": 0.5}, {"nodeid": 2, "leaf": -0.5}]},
+            {"nodeid": 0, "leaf": 0.25}
+        ]"#;
+        let model = CGbdtModel::from_xgboost_json(dump, 0.0, false).unwrap();
+        assert!((model.score(&features(&[("a", 0.5)])) - 0.75).abs() < 1e-9);
+        // 等于阈值走右边
+        assert!((model.score(&features(&[("a", 1.0)])) + 0.25).abs() < 1e-9);
+        // 缺失按 missing 走右边
+        assert!((model.score(&features(&[])) + 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_lightgbm_dump() {
+        let dump = r#"{
+            "objective": "binary sigmoid:1",
+            "feature_names": ["a", "b"],
+            "tree_info": [{"tree_structure": {
+                "split_feature": 1, "threshold": 2.0, "decision_type": "<=", "default_left": false,
+                "left_child": {"leaf_value": 1.0},
+                "right_child": {"leaf_value": -1.0}
+            }}]
+        }"#;
+        let model = CGbdtModel::from_lightgbm_json(dump).unwrap();
+        assert!(model.logistic);
+        assert!((model.score(&features(&[("b", 2.0)])) - sigmoid(1.0)).abs() < 1e-9);
+        assert!((model.score(&features(&[])) - sigmoid(-1.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_xgboost_model_objective_and_base_score() {
+        // save_model 保存的原生格式，base_score 为字符串
+        let model = r#"{"learner": {
+            "feature_names": ["a"],
+            "learner_model_param": {"base_score": "8E-1"},
+            "objective": {"name": "binary:logistic"},
+            "gradient_booster": {"model": {"trees": [{
+                "left_children": [1, -1, -1],
+                "right_children": [2, -1, -1],
+                "split_indices": [0, 0, 0],
+                "split_conditions": [1.0, 0.5, -0.5],
+                "default_left": [0, 0, 0],
+                "split_type": [0, 0, 0]
+            }]}}
+        }}"#;
+        let model = CGbdtModel::from_xgboost_model(&serde_json::from_str(model).unwrap()).unwrap();
+        let base_margin = (0.8f64 / 0.2).ln();
+        assert!(model.logistic);
+        assert!((model.base_margin - base_margin).abs() < 1e-9);
+        assert!((model.score(&features(&[("a", 0.0)])) - sigmoid(base_margin + 0.5)).abs() < 1e-9);
+        // 缺失按 default_left 走右边
+        assert!((model.score(&features(&[])) - sigmoid(base_margin - 0.5)).abs() < 1e-9);
+
+        // 带参数的 dump
+        let model = r#"{"objective": "reg:squarederror", "base_score": 2.0, "trees": [
+            {"nodeid": 0, "leaf": 0.25}
+        ]}"#;
+        let model = CGbdtModel::from_xgboost_model(&serde_json::from_str(model).unwrap()).unwrap();
+        assert!(!model.logistic);
+        assert!((model.score(&features(&[])) - 2.25).abs() < 1e-9);
+
+        let bad = r#"{"objective": "multi:softprob", "base_score": 0.5, "trees": []}"#;
+        assert!(CGbdtModel::from_xgboost_model(&serde_json::from_str(bad).unwrap()).is_err());
+        let bad = r#"{"objective": "binary:logistic", "trees": []}"#;
+        assert!(CGbdtModel::from_xgboost_model(&serde_json::from_str(bad).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_lightgbm_missing_type() {
+        let dump = |missing_type: &str| {
+            format!(
+                r#"{{
+                "objective": "regression",
+                "feature_names": ["a"],
+                "tree_info": [{{"tree_structure": {{
+                    "split_feature": 0, "threshold": -1.0, "decision_type": "<=",
+                    "default_left": true, "missing_type": "{}",
+                    "left_child": {{"leaf_value": 1.0}},
+                    "right_child": {{"leaf_value": -1.0}}
+                }}}}]
+            }}"#,
+                missing_type
+            )
+        };
+        // None：缺失按 0 比较，0 > -1 走右边
+        let model = CGbdtModel::from_lightgbm_json(&dump("None")).unwrap();
+        assert_eq!(model.score(&features(&[])), -1.0);
+        assert_eq!(model.score(&features(&[("a", f64::NAN)])), -1.0);
+        assert_eq!(model.score(&features(&[("a", 0.0)])), -1.0);
+        // Zero：0 和缺失都走 default_left
+        let model = CGbdtModel::from_lightgbm_json(&dump("Zero")).unwrap();
+        assert_eq!(model.score(&features(&[])), 1.0);
+        assert_eq!(model.score(&features(&[("a", 0.0)])), 1.0);
+        assert_eq!(model.score(&features(&[("a", 0.5)])), -1.0);
+        // NaN：只有缺失走 default_left，0 正常比较
+        let model = CGbdtModel::from_lightgbm_json(&dump("NaN")).unwrap();
+        assert_eq!(model.score(&features(&[("a", f64::NAN)])), 1.0);
+        assert_eq!(model.score(&features(&[("a", 0.0)])), -1.0);
+        assert_eq!(model.score(&features(&[("a", -2.0)])), 1.0);
+
+        assert!(CGbdtModel::from_lightgbm_json(&dump("Other")).is_err());
+    }
+}
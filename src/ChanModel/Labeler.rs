@@ -0,0 +1,551 @@
+use crate::BuySellPoint::BSPointHistory::{CBSPointHistory, CBSPointRecord};
+use crate::Common::types::SharedCell;
+use crate::Common::CEnum::BspState;
+use crate::Common::ChanException::{CChanException, ErrCode};
+use crate::KLine::KLine_List::CKLineList;
+use crate::KLine::KLine_Unit::CKLineUnit;
+#[cfg(feature = "parquet")]
+use parquet::basic::{LogicalType, Repetition, Type as PhysicalType};
+#[cfg(feature = "parquet")]
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int32Type};
+#[cfg(feature = "parquet")]
+use parquet::errors::ParquetError;
+#[cfg(feature = "parquet")]
+use parquet::file::properties::WriterProperties;
+#[cfg(feature = "parquet")]
+use parquet::file::writer::SerializedFileWriter;
+#[cfg(feature = "parquet")]
+use parquet::schema::types::Type;
+use std::collections::{BTreeSet, HashMap};
+#[cfg(feature = "parquet")]
+use std::fs::File;
+#[cfg(feature = "parquet")]
+use std::sync::Arc;
+
+pub struct CLabelConfig {
+    // 向后看多少根K线
+    pub horizon: usize,
+    // 止盈/止损幅度，按入场价的比例
+    pub target_rate: f64,
+    pub stop_rate: f64,
+}
+
+impl Default for CLabelConfig {
+    fn default() -> Self {
+        CLabelConfig {
+            horizon: 20,
+            target_rate: 0.05,
+            stop_rate: 0.03,
+        }
+    }
+}
+
+// 一个买卖点的训练样本
+// 入场点为买卖点首次出现时那根K线的收盘价（买卖点所在K线本身要之后才能确认），
+// 特征取首次出现时的快照，标签只用入场之后的K线
+pub struct CBspLabel {
+    pub id: String,
+    pub is_buy: bool,
+    pub bsp_type: String,
+    pub bsp_time: String,
+    pub entry_idx: i32,
+    pub entry_time: String,
+    pub entry_price: f64,
+    pub features: HashMap<String, f64>,
+    // horizon 根K线内最大有利/不利幅度（比例，>=0）
+    pub mfe: f64,
+    pub mae: f64,
+    // horizon 结束时的收益率，已按方向调整，后续K线不足时为 None
+    pub horizon_ret: Option<f64>,
+    // 先到止盈为 Some(true)，先到止损为 Some(false)，同一根K线同时触及按止损算，都没到为 None
+    pub hit_target_first: Option<bool>,
+    // 下一个反向买卖点是否在止盈之前出现，没有反向买卖点为 None
+    pub opposite_bsp_first: Option<bool>,
+    // 该买卖点最终是否失效
+    pub invalidated: bool,
+}
+
+pub struct CBspLabeler {
+    pub config: CLabelConfig,
+}
+
+impl CBspLabeler {
+    pub fn new(config: CLabelConfig) -> Self {
+        CBspLabeler { config }
+    }
+
+    // 买卖点历史只在逐K线计算（trigger_step）时才有，一次性计算只能看到最终结果，不能保证无未来函数
+    pub fn label(&self, kl_list: &CKLineList) -> Result<Vec<CBspLabel>, CChanException> {
+        if !kl_list.step_calculation {
+            return Err(CChanException::new(
+                "CBspLabeler needs bsp history, please set trigger_step=true".to_string(),
+                ErrCode::ParaError,
+            ));
+        }
+        let klu_lst: Vec<_> = kl_list
+            .lst
+            .iter()
+            .flat_map(|klc| klc.borrow().lst.clone())
+            .collect();
+        Ok(self.label_history(&kl_list.bs_point_lst.history, &klu_lst))
+    }
+
+    pub fn label_history(
+        &self,
+        history: &CBSPointHistory,
+        klu_lst: &[SharedCell<CKLineUnit>],
+    ) -> Vec<CBspLabel> {
+        let klu_pos: HashMap<i32, usize> = klu_lst
+            .iter()
+            .enumerate()
+            .map(|(pos, klu)| (klu.borrow().idx, pos))
+            .collect();
+        history
+            .iter()
+            .filter_map(|record| {
+                let pos = *klu_pos.get(&record.first_seen_idx())?;
+                Some(self.label_record(record, history, klu_lst, pos))
+            })
+            .collect()
+    }
+
+    fn label_record(
+        &self,
+        record: &CBSPointRecord,
+        history: &CBSPointHistory,
+        klu_lst: &[SharedCell<CKLineUnit>],
+        entry_pos: usize,
+    ) -> CBspLabel {
+        let entry_klu = klu_lst[entry_pos].borrow();
+        let entry_price = entry_klu.close;
+        // 只取入场之后 horizon 根K线
+        let end_pos = (entry_pos + self.config.horizon).min(klu_lst.len() - 1);
+        let future: Vec<CBar> = klu_lst[entry_pos + 1..=end_pos]
+            .iter()
+            .map(|klu| {
+                let klu = klu.borrow();
+                CBar {
+                    idx: klu.idx,
+                    high: klu.high,
+                    low: klu.low,
+                    close: klu.close,
+                }
+            })
+            .collect();
+        let outcome = cal_outcome(&self.config, record.is_buy, entry_price, &future);
+
+        let entry_idx = entry_klu.idx;
+        let opposite_idx = history
+            .iter()
+            .filter(|r| r.is_buy != record.is_buy && r.first_seen_idx() > entry_idx)
+            .map(|r| r.first_seen_idx())
+            .min();
+        let opposite_bsp_first =
+            opposite_idx.map(|opp_idx| outcome.target_idx.map_or(true, |t_idx| opp_idx < t_idx));
+
+        CBspLabel {
+            id: record.id.clone(),
+            is_buy: record.is_buy,
+            bsp_type: record.events[0].bsp_type.clone(),
+            bsp_time: record.klu_time.to_string(),
+            entry_idx,
+            entry_time: entry_klu.time.to_string(),
+            entry_price,
+            features: record.first_features.clone(),
+            mfe: outcome.mfe,
+            mae: outcome.mae,
+            horizon_ret: outcome.horizon_ret,
+            hit_target_first: outcome.hit_target_first,
+            opposite_bsp_first,
+            invalidated: record.state() == BspState::INVALIDATED,
+        }
+    }
+}
+
+struct CBar {
+    idx: i32,
+    high: f64,
+    low: f64,
+    close: f64,
+}
+
+struct CLabelOutcome {
+    mfe: f64,
+    mae: f64,
+    horizon_ret: Option<f64>,
+    hit_target_first: Option<bool>,
+    target_idx: Option<i32>,
+}
+
+// future 为入场之后的K线（不含入场K线），最多 horizon 根
+fn cal_outcome(
+    config: &CLabelConfig,
+    is_buy: bool,
+    entry_price: f64,
+    future: &[CBar],
+) -> CLabelOutcome {
+    let mut outcome = CLabelOutcome {
+        mfe: 0.0,
+        mae: 0.0,
+        horizon_ret: None,
+        hit_target_first: None,
+        target_idx: None,
+    };
+    for bar in future.iter().take(config.horizon) {
+        let (favorable, adverse) = if is_buy {
+            (bar.high / entry_price - 1.0, 1.0 - bar.low / entry_price)
+        } else {
+            (1.0 - bar.low / entry_price, bar.high / entry_price - 1.0)
+        };
+        outcome.mfe = outcome.mfe.max(favorable);
+        outcome.mae = outcome.mae.max(adverse);
+        if outcome.hit_target_first.is_none() {
+            if adverse >= config.stop_rate {
+                outcome.hit_target_first = Some(false);
+            } else if favorable >= config.target_rate {
+                outcome.hit_target_first = Some(true);
+                outcome.target_idx = Some(bar.idx);
+            }
+        }
+    }
+    if config.horizon > 0 && future.len() >= config.horizon {
+        let sign = if is_buy { 1.0 } else { -1.0 };
+        outcome.horizon_ret = Some(sign * (future[config.horizon - 1].close / entry_price - 1.0));
+    }
+    outcome
+}
+
+fn opt2str<T: ToString>(v: &Option<T>) -> String {
+    v.as_ref().map_or(String::new(), |v| v.to_string())
+}
+
+// 特征列按名字排序，缺失的特征留空
+pub fn labels_to_csv(labels: &[CBspLabel], path: &str) -> Result<(), CChanException> {
+    let io_err = |e: csv::Error| {
+        CChanException::new(format!("write {} fail: {}", path, e), ErrCode::CommonError)
+    };
+    let feature_names: BTreeSet<&String> = labels.iter().flat_map(|l| l.features.keys()).collect();
+    let mut wtr = csv::Writer::from_path(path).map_err(io_err)?;
+
+    let mut headers: Vec<String> = [
+        "id",
+        "is_buy",
+        "bsp_type",
+        "bsp_time",
+        "entry_idx",
+        "entry_time",
+        "entry_price",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    headers.extend(feature_names.iter().map(|s| s.to_string()));
+    headers.extend(
+        [
+            "label_mfe",
+            "label_mae",
+            "label_horizon_ret",
+            "label_hit_target_first",
+            "label_opposite_bsp_first",
+            "label_invalidated",
+        ]
+        .iter()
+        .map(|s| s.to_string()),
+    );
+    wtr.write_record(&headers).map_err(io_err)?;
+
+    for label in labels {
+        let mut row = vec![
+            label.id.clone(),
+            label.is_buy.to_string(),
+            label.bsp_type.clone(),
+            label.bsp_time.clone(),
+            label.entry_idx.to_string(),
+            label.entry_time.clone(),
+            label.entry_price.to_string(),
+        ];
+        row.extend(
+            feature_names
+                .iter()
+                .map(|name| opt2str(&label.features.get(*name))),
+        );
+        row.extend([
+            label.mfe.to_string(),
+            label.mae.to_string(),
+            opt2str(&label.horizon_ret),
+            opt2str(&label.hit_target_first.map(|b| b as i32)),
+            opt2str(&label.opposite_bsp_first.map(|b| b as i32)),
+            (label.invalidated as i32).to_string(),
+        ]);
+        wtr.write_record(&row).map_err(io_err)?;
+    }
+    wtr.flush().map_err(|e| {
+        CChanException::new(format!("write {} fail: {}", path, e), ErrCode::CommonError)
+    })?;
+    Ok(())
+}
+
+// parquet 的一列，Option 为 None 时写 null
+#[cfg(feature = "parquet")]
+enum CLabelColumn {
+    Str(Vec<String>),
+    Int(Vec<i32>),
+    Bool(Vec<Option<bool>>),
+    Double(Vec<Option<f64>>),
+}
+
+// 列的顺序和 csv 一致，布尔值保持 BOOLEAN 类型
+#[cfg(feature = "parquet")]
+fn label_columns(labels: &[CBspLabel]) -> Vec<(String, CLabelColumn)> {
+    let str_col =
+        |f: &dyn Fn(&CBspLabel) -> String| CLabelColumn::Str(labels.iter().map(f).collect());
+    let bool_col =
+        |f: &dyn Fn(&CBspLabel) -> Option<bool>| CLabelColumn::Bool(labels.iter().map(f).collect());
+    let double_col = |f: &dyn Fn(&CBspLabel) -> Option<f64>| {
+        CLabelColumn::Double(labels.iter().map(f).collect())
+    };
+    let feature_names: BTreeSet<&String> = labels.iter().flat_map(|l| l.features.keys()).collect();
+
+    let mut columns = vec![
+        ("id".to_string(), str_col(&|l| l.id.clone())),
+        ("is_buy".to_string(), bool_col(&|l| Some(l.is_buy))),
+        ("bsp_type".to_string(), str_col(&|l| l.bsp_type.clone())),
+        ("bsp_time".to_string(), str_col(&|l| l.bsp_time.clone())),
+        (
+            "entry_idx".to_string(),
+            CLabelColumn::Int(labels.iter().map(|l| l.entry_idx).collect()),
+        ),
+        ("entry_time".to_string(), str_col(&|l| l.entry_time.clone())),
+        (
+            "entry_price".to_string(),
+            double_col(&|l| Some(l.entry_price)),
+        ),
+    ];
+    for name in feature_names {
+        columns.push((name.clone(), double_col(&|l| l.features.get(name).copied())));
+    }
+    columns.extend([
+        ("label_mfe".to_string(), double_col(&|l| Some(l.mfe))),
+        ("label_mae".to_string(), double_col(&|l| Some(l.mae))),
+        (
+            "label_horizon_ret".to_string(),
+            double_col(&|l| l.horizon_ret),
+        ),
+        (
+            "label_hit_target_first".to_string(),
+            bool_col(&|l| l.hit_target_first),
+        ),
+        (
+            "label_opposite_bsp_first".to_string(),
+            bool_col(&|l| l.opposite_bsp_first),
+        ),
+        (
+            "label_invalidated".to_string(),
+            bool_col(&|l| Some(l.invalidated)),
+        ),
+    ]);
+    columns
+}
+
+// 可为空的列：非空值和 definition level（1 有值，0 为 null）
+#[cfg(feature = "parquet")]
+fn split_nulls<T: Clone>(lst: &[Option<T>]) -> (Vec<T>, Vec<i16>) {
+    let values = lst.iter().flatten().cloned().collect();
+    let def_levels = lst.iter().map(|v| v.is_some() as i16).collect();
+    (values, def_levels)
+}
+
+// 与 labels_to_csv 同样的列，写成一个 row group，需要开启 parquet feature
+#[cfg(feature = "parquet")]
+pub fn labels_to_parquet(labels: &[CBspLabel], path: &str) -> Result<(), CChanException> {
+    let pq_err = |e: ParquetError| {
+        CChanException::new(format!("write {} fail: {}", path, e), ErrCode::CommonError)
+    };
+    let columns = label_columns(labels);
+    let fields = columns
+        .iter()
+        .map(|(name, col)| {
+            let (physical_type, logical_type, repetition) = match col {
+                CLabelColumn::Str(_) => (
+                    PhysicalType::BYTE_ARRAY,
+                    Some(LogicalType::String),
+                    Repetition::REQUIRED,
+                ),
+                CLabelColumn::Int(_) => (PhysicalType::INT32, None, Repetition::REQUIRED),
+                CLabelColumn::Bool(_) => (PhysicalType::BOOLEAN, None, Repetition::OPTIONAL),
+                CLabelColumn::Double(_) => (PhysicalType::DOUBLE, None, Repetition::OPTIONAL),
+            };
+            Type::primitive_type_builder(name, physical_type)
+                .with_logical_type(logical_type)
+                .with_repetition(repetition)
+                .build()
+                .map(Arc::new)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(pq_err)?;
+    let schema = Type::group_type_builder("bsp_label")
+        .with_fields(fields)
+        .build()
+        .map_err(pq_err)?;
+
+    let file = File::create(path).map_err(|e| {
+        CChanException::new(format!("write {} fail: {}", path, e), ErrCode::CommonError)
+    })?;
+    let mut writer = SerializedFileWriter::new(
+        file,
+        Arc::new(schema),
+        Arc::new(WriterProperties::builder().build()),
+    )
+    .map_err(pq_err)?;
+    let mut row_group = writer.next_row_group().map_err(pq_err)?;
+    for (name, col) in &columns {
+        let mut col_writer = row_group
+            .next_column()
+            .map_err(pq_err)?
+            .ok_or_else(|| pq_err(ParquetError::General(format!("no column {}", name))))?;
+        match col {
+            CLabelColumn::Str(lst) => {
+                let values: Vec<ByteArray> =
+                    lst.iter().map(|v| ByteArray::from(v.as_str())).collect();
+                col_writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, None, None)
+            }
+            CLabelColumn::Int(lst) => col_writer.typed::<Int32Type>().write_batch(lst, None, None),
+            CLabelColumn::Bool(lst) => {
+                let (values, def_levels) = split_nulls(lst);
+                col_writer
+                    .typed::<BoolType>()
+                    .write_batch(&values, Some(&def_levels), None)
+            }
+            CLabelColumn::Double(lst) => {
+                let (values, def_levels) = split_nulls(lst);
+                col_writer
+                    .typed::<DoubleType>()
+                    .write_batch(&values, Some(&def_levels), None)
+            }
+        }
+        .map_err(pq_err)?;
+        col_writer.close().map_err(pq_err)?;
+    }
+    row_group.close().map_err(pq_err)?;
+    writer.close().map_err(pq_err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "parquet")]
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    fn bar(idx: i32, high: f64, low: f64, close: f64) -> CBar {
+        CBar {
+            idx,
+            high,
+            low,
+            close,
+        }
+    }
+
+    fn config(horizon: usize) -> CLabelConfig {
+        CLabelConfig {
+            horizon,
+            target_rate: 0.05,
+            stop_rate: 0.03,
+        }
+    }
+
+    #[test]
+    fn test_cal_outcome() {
+        let future = vec![
+            bar(11, 101.0, 99.0, 100.0),
+            bar(12, 106.0, 100.0, 105.0),
+            bar(13, 104.0, 96.0, 97.0),
+        ];
+        let buy = cal_outcome(&config(3), true, 100.0, &future);
+        assert!((buy.mfe - 0.06).abs() < 1e-9);
+        assert!((buy.mae - 0.04).abs() < 1e-9);
+        assert_eq!(buy.hit_target_first, Some(true));
+        assert_eq!(buy.target_idx, Some(12));
+        assert!((buy.horizon_ret.unwrap() + 0.03).abs() < 1e-9);
+
+        // 卖点方向相反：第三根K线的低点 96 之前第二根已经到了 106 止损
+        let sell = cal_outcome(&config(3), false, 100.0, &future);
+        assert!((sell.mfe - 0.04).abs() < 1e-9);
+        assert!((sell.mae - 0.06).abs() < 1e-9);
+        assert_eq!(sell.hit_target_first, Some(false));
+        assert_eq!(sell.target_idx, None);
+        assert!((sell.horizon_ret.unwrap() - 0.03).abs() < 1e-9);
+
+        // 同一根K线同时触及止盈止损按止损算
+        let both = cal_outcome(&config(3), true, 100.0, &[bar(11, 106.0, 96.0, 100.0)]);
+        assert_eq!(both.hit_target_first, Some(false));
+        // 后续K线不足 horizon 根时没有 horizon_ret
+        assert_eq!(both.horizon_ret, None);
+    }
+
+    #[test]
+    fn test_cal_outcome_no_lookahead() {
+        // horizon 之外的K线不影响任何标签
+        let mut future = vec![bar(11, 101.0, 99.0, 100.5), bar(12, 102.0, 99.5, 101.0)];
+        let base = cal_outcome(&config(2), true, 100.0, &future);
+        future.push(bar(13, 150.0, 50.0, 60.0));
+        let extended = cal_outcome(&config(2), true, 100.0, &future);
+        assert_eq!(base.mfe, extended.mfe);
+        assert_eq!(base.mae, extended.mae);
+        assert_eq!(base.hit_target_first, None);
+        assert_eq!(extended.hit_target_first, None);
+        assert_eq!(base.horizon_ret, extended.horizon_ret);
+        assert!((extended.horizon_ret.unwrap() - 0.01).abs() < 1e-9);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_labels_to_parquet() {
+        let label = |id: &str, features: &[(&str, f64)], hit: Option<bool>| CBspLabel {
+            id: id.to_string(),
+            is_buy: true,
+            bsp_type: "1".to_string(),
+            bsp_time: "2024/01/02".to_string(),
+            entry_idx: 10,
+            entry_time: "2024/01/03".to_string(),
+            entry_price: 100.0,
+            features: features.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+            mfe: 0.06,
+            mae: 0.01,
+            horizon_ret: None,
+            hit_target_first: hit,
+            opposite_bsp_first: None,
+            invalidated: false,
+        };
+        let labels = vec![
+            label("b10", &[("bsp_rsi", 30.0)], Some(true)),
+            label("b20", &[("bsp_bi_amp", 1.5)], None),
+        ];
+        let path = std::env::temp_dir().join(format!(
+            "chan_labels_{}_{:?}.parquet",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        let path = path.to_str().unwrap();
+        labels_to_parquet(&labels, path).unwrap();
+
+        let reader = SerializedFileReader::new(File::open(path).unwrap()).unwrap();
+        let meta = reader.metadata();
+        assert_eq!(meta.file_metadata().num_rows(), 2);
+        let names: Vec<&str> = meta
+            .file_metadata()
+            .schema_descr()
+            .columns()
+            .iter()
+            .map(|c| c.name())
+            .collect();
+        assert_eq!(names.len(), 15);
+        assert_eq!(&names[..3], &["id", "is_buy", "bsp_type"]);
+        // 特征列按名字排序
+        assert_eq!(&names[7..9], &["bsp_bi_amp", "bsp_rsi"]);
+        assert_eq!(names[14], "label_invalidated");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,107 @@
+use crate::ChanModel::BspModel::{sigmoid, BspModel};
+use crate::ChanModel::Features::CFeatures;
+use crate::Common::ChanException::{CChanException, ErrCode};
+use std::collections::HashMap;
+
+// 线性模型，json格式：{"bias": 0.1, "weights": {"bsp_bi_amp_rate": 1.2, ...}, "logistic": true}
+// 缺失的特征按0处理，logistic=true 时输出经过 sigmoid
+pub struct CLinearModel {
+    pub bias: f64,
+    pub weights: HashMap<String, f64>,
+    pub logistic: bool,
+}
+
+impl CLinearModel {
+    pub fn new(bias: f64, weights: HashMap<String, f64>, logistic: bool) -> Self {
+        CLinearModel {
+            bias,
+            weights,
+            logistic,
+        }
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, CChanException> {
+        let value: serde_json::Value = serde_json::from_str(s).map_err(|e| {
+            CChanException::new(
+                format!("linear model is not a valid json: {}", e),
+                ErrCode::ModelError,
+            )
+        })?;
+        Self::from_value(&value)
+    }
+
+    pub fn from_value(value: &serde_json::Value) -> Result<Self, CChanException> {
+        let weights = value
+            .get("weights")
+            .and_then(|w| w.as_object())
+            .ok_or_else(|| {
+                CChanException::new(
+                    "linear model has no weights".to_string(),
+                    ErrCode::ModelError,
+                )
+            })?
+            .iter()
+            .map(|(k, v)| {
+                v.as_f64().map(|w| (k.clone(), w)).ok_or_else(|| {
+                    CChanException::new(
+                        format!("weight of {} is not a number", k),
+                        ErrCode::ModelError,
+                    )
+                })
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        Ok(CLinearModel {
+            bias: value.get("bias").and_then(|b| b.as_f64()).unwrap_or(0.0),
+            weights,
+            logistic: value
+                .get("logistic")
+                .and_then(|l| l.as_bool())
+                .unwrap_or(false),
+        })
+    }
+}
+
+impl BspModel for CLinearModel {
+    fn score(&self, features: &CFeatures) -> f64 {
+        let margin = self.bias
+            + self
+                .weights
+                .iter()
+                .map(|(k, w)| w * features.get(k).copied().unwrap_or(0.0))
+                .sum::<f64>();
+        if self.logistic {
+            sigmoid(margin)
+        } else {
+            margin
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear_score() {
+        let model =
+            CLinearModel::from_json(r#"{"bias": 1.0, "weights": {"a": 2.0, "b": -1.0}}"#).unwrap();
+        let features = CFeatures::new(Some(HashMap::from([
+            ("a".to_string(), 3.0),
+            ("b".to_string(), 1.0),
+        ])));
+        assert!((model.score(&features) - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_logistic_missing_feature() {
+        let model =
+            CLinearModel::from_json(r#"{"weights": {"a": 2.0}, "logistic": true}"#).unwrap();
+        let features = CFeatures::new(None);
+        assert!((model.score(&features) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_invalid_weight() {
+        assert!(CLinearModel::from_json(r#"{"weights": {"a": "x"}}"#).is_err());
+    }
+}
@@ -1 +1,5 @@
+pub mod BspModel;
 pub mod Features;
+pub mod GbdtModel;
+pub mod Labeler;
+pub mod LinearModel;
@@ -1,5 +1,6 @@
 // File: chan/src/Common/CEnum.rs
 
+use std::str::FromStr;
 use strum_macros::{Display, EnumString};
 
 #[derive(Debug, EnumString, Display)]
@@ -9,7 +10,7 @@ pub enum DATA_SRC {
     CSV,
 }
 
-#[derive(Debug, EnumString, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, Display)]
 pub enum KlType {
     K_1S = 1,
     K_3S = 2,
@@ -47,7 +48,7 @@ pub enum FxType {
     UNKNOWN,
 }
 
-#[derive(Debug, EnumString, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
 pub enum BiDir {
     UP,
     DOWN,
@@ -67,7 +68,7 @@ pub enum BiType {
 
 pub type BSP_MAIN_TYPE = String;
 
-#[derive(Debug, EnumString, Display)]
+#[derive(Debug, Clone, PartialEq, EnumString)]
 pub enum BspType {
     T1,
     T1P,
@@ -75,6 +76,9 @@ pub enum BspType {
     T2S,
     T3A,
     T3B,
+    // 由 BspRule 产生的自定义买卖点，名字即规则名；不参与字符串解析，只能通过 from_custom_name 构造
+    #[strum(disabled)]
+    Custom(String),
 }
 
 impl BspType {
@@ -83,10 +87,60 @@ impl BspType {
             BspType::T1 | BspType::T1P => "1".to_string(),
             BspType::T2 | BspType::T2S => "2".to_string(),
             BspType::T3A | BspType::T3B => "3".to_string(),
+            BspType::Custom(name) => name.clone(),
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, BspType::Custom(_))
+    }
+
+    // 规则名不能为空，也不能和内置类型（含 1/1p/2/2s/3a/3b 简写与 main_type）同名
+    pub fn from_custom_name(name: &str) -> Option<BspType> {
+        if name.is_empty()
+            || BspType::from_str(&name.to_uppercase()).is_ok()
+            || matches!(name, "1" | "1p" | "2" | "2s" | "3" | "3a" | "3b")
+        {
+            return None;
         }
+        Some(BspType::Custom(name.to_string()))
     }
 }
 
+impl std::fmt::Display for BspType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BspType::Custom(name) => write!(f, "{}", name),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+// 买卖点生命周期：未确认（所在笔/线段还可能变化）、确认、失效
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
+pub enum BspState {
+    PROVISIONAL,
+    CONFIRMED,
+    INVALIDATED,
+}
+
+// 止损/止盈位的结构依据
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
+pub enum RiskReason {
+    // 一类买卖点所在笔的端点
+    BSP1_BI,
+    ZS_LOW,
+    ZS_HIGH,
+    ZS_PEAK_LOW,
+    ZS_PEAK_HIGH,
+    // 上一线段的极值
+    PRE_SEG,
+    // 之前线段中最近一个中枢的边界
+    NEXT_ZS,
+    // 趋势线在买卖点K线处的值
+    TREND_LINE,
+}
+
 #[derive(Debug, EnumString, Display)]
 pub enum AUTYPE {
     QFQ,
@@ -94,14 +148,15 @@ pub enum AUTYPE {
     NONE,
 }
 
-#[derive(Debug, EnumString, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, Display)]
 pub enum TrendType {
     MEAN,
     MAX,
     MIN,
+    STD,
 }
 
-#[derive(Debug, EnumString, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
 pub enum TrendLineSide {
     INSIDE,
     OUTSIDE,
@@ -127,7 +182,31 @@ pub enum SegType {
     SEG,
 }
 
-#[derive(Debug, EnumString, Display)]
+// 走势类型：只有一个中枢为盘整，两个及以上同向不重叠中枢为趋势
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
+pub enum SegTrendType {
+    NO_ZS,
+    CONSOLIDATION,
+    UP_TREND,
+    DOWN_TREND,
+}
+
+// 相邻两个中枢的关系：上移/下移/扩展
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
+pub enum ZsRelation {
+    UP,
+    DOWN,
+    EXTEND,
+}
+
+// 中枢级别升级的原因：延伸到9笔，或两个中枢波动区间重叠而扩展
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
+pub enum ZsLevelUpReason {
+    NINE_BI,
+    EXPANSION,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
 pub enum MacdAlgo {
     AREA,
     PEAK,
@@ -141,6 +220,9 @@ pub enum MacdAlgo {
     AMOUNT_AVG,
     TURNRATE_AVG,
     RSI,
+    // 只算从起点开始/从终点往回的同号 macd 柱面积，AREA 按进入段/离开段自动选择其一
+    HALF_OBVERSE,
+    HALF_REVERSE,
 }
 
 pub struct DataField;
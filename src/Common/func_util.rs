@@ -52,6 +52,33 @@ pub fn parse_inf(v: f64) -> String {
     }
 }
 
+// 编辑距离
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut pre: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut cur = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            cur[j] = (pre[j] + 1).min(cur[j - 1] + 1).min(pre[j - 1] + cost);
+        }
+        pre = cur;
+    }
+    pre[b.len()]
+}
+
+// 从候选中找出与 key 最接近的一个，用于配置拼写错误时给出提示，差别太大时返回 None
+pub fn nearest_key<'a>(key: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max_dis = (key.chars().count() / 3).max(2);
+    candidates
+        .iter()
+        .map(|c| (edit_distance(key, c), *c))
+        .filter(|(dis, _)| *dis <= max_dis)
+        .min_by_key(|(dis, _)| *dis)
+        .map(|(_, c)| c)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,4 +134,16 @@ mod tests {
         assert_eq!(parse_inf(f64::NEG_INFINITY), "f64::NEG_INFINITY");
         assert_eq!(parse_inf(3.14), "3.14");
     }
+
+    #[test]
+    fn test_nearest_key() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        let keys = ["divergence_rate", "min_zs_cnt", "macd_algo"];
+        assert_eq!(
+            nearest_key("divergance_rate", &keys),
+            Some("divergence_rate")
+        );
+        assert_eq!(nearest_key("macd_alog", &keys), Some("macd_algo"));
+        assert_eq!(nearest_key("foo", &keys), None);
+    }
 }
@@ -1,13 +1,296 @@
 use maybe_atomic_refcell::MaybeAtomicRefCell;
 use std::{cell::RefCell, rc::Rc};
 
+use crate::BuySellPoint::Divergence::MacdMetricLine;
+use crate::Common::CEnum::BiDir;
+use crate::KLine::KLine_Unit::CKLineUnit;
+use crate::Math::TrendLine::TrendLineElement;
 use crate::{Bi::Bi::CBi, Seg::Seg::CSeg};
 
 pub type SharedCell<T> = Rc<RefCell<T>>;
 
+#[derive(Clone)]
 pub enum LineType {
     Bi(SharedCell<CBi>),
     Seg(SharedCell<CSeg<CBi>>),
+    // 第3级及以上的递归线段（线段的线段的线段...），其子线为下一级的LineType
+    Tier(SharedCell<CSeg<LineType>>),
+}
+
+// 线段/中枢/买卖点计算中的“笔”：第1层是CBi，递归层是下一层线段包成的LineType
+pub trait ChanLine: TrendLineElement + MacdMetricLine + Sized {
+    fn idx(&self) -> i32;
+    fn dir(&self) -> BiDir;
+    fn is_sure(&self) -> bool;
+    fn seg_idx(&self) -> Option<i32>;
+    fn set_seg_idx(&mut self, idx: i32);
+    fn get_begin_klu(&self) -> SharedCell<CKLineUnit>;
+    fn get_end_klu(&self) -> SharedCell<CKLineUnit>;
+    fn get_begin_val(&self) -> f64;
+    fn get_end_val(&self) -> f64;
+    fn _high(&self) -> f64;
+    fn _low(&self) -> f64;
+    fn pre_line(&self) -> Option<SharedCell<Self>>;
+    fn next_line(&self) -> Option<SharedCell<Self>>;
+    fn parent_seg_idx(&self) -> Option<i32>;
+    fn parent_seg_dir(&self) -> Option<BiDir>;
+    fn parent_seg_bi_cnt(&self) -> Option<usize>;
+    fn set_parent_seg(&mut self, seg: SharedCell<CSeg<Self>>);
+    fn clear_parent_seg(&mut self);
+    fn to_line_type(line: &SharedCell<Self>) -> LineType;
+
+    fn amp(&self) -> f64 {
+        (self.get_end_val() - self.get_begin_val()).abs()
+    }
+
+    fn is_up(&self) -> bool {
+        self.dir() == BiDir::Up
+    }
+
+    fn is_down(&self) -> bool {
+        self.dir() == BiDir::Down
+    }
+}
+
+impl ChanLine for CBi {
+    fn idx(&self) -> i32 {
+        self.idx
+    }
+
+    fn dir(&self) -> BiDir {
+        self.dir
+    }
+
+    fn is_sure(&self) -> bool {
+        self.is_sure
+    }
+
+    fn seg_idx(&self) -> Option<i32> {
+        self.seg_idx
+    }
+
+    fn set_seg_idx(&mut self, idx: i32) {
+        self.seg_idx = Some(idx);
+    }
+
+    fn get_begin_klu(&self) -> SharedCell<CKLineUnit> {
+        CBi::get_begin_klu(self)
+    }
+
+    fn get_end_klu(&self) -> SharedCell<CKLineUnit> {
+        CBi::get_end_klu(self)
+    }
+
+    fn get_begin_val(&self) -> f64 {
+        CBi::get_begin_val(self)
+    }
+
+    fn get_end_val(&self) -> f64 {
+        CBi::get_end_val(self)
+    }
+
+    fn _high(&self) -> f64 {
+        self.high()
+    }
+
+    fn _low(&self) -> f64 {
+        self.low()
+    }
+
+    fn pre_line(&self) -> Option<SharedCell<Self>> {
+        self.pre.clone()
+    }
+
+    fn next_line(&self) -> Option<SharedCell<Self>> {
+        self.next.clone()
+    }
+
+    fn parent_seg_idx(&self) -> Option<i32> {
+        self.parent_seg.as_ref().map(|seg| seg.borrow().idx)
+    }
+
+    fn parent_seg_dir(&self) -> Option<BiDir> {
+        self.parent_seg.as_ref().map(|seg| seg.borrow().dir)
+    }
+
+    fn parent_seg_bi_cnt(&self) -> Option<usize> {
+        self.parent_seg
+            .as_ref()
+            .map(|seg| seg.borrow().bi_list.len())
+    }
+
+    fn set_parent_seg(&mut self, seg: SharedCell<CSeg<Self>>) {
+        self.parent_seg = Some(seg);
+    }
+
+    fn clear_parent_seg(&mut self) {
+        self.parent_seg = None;
+    }
+
+    fn to_line_type(line: &SharedCell<Self>) -> LineType {
+        LineType::Bi(Rc::clone(line))
+    }
+}
+
+impl ChanLine for LineType {
+    fn idx(&self) -> i32 {
+        match self {
+            LineType::Bi(bi) => bi.borrow().idx,
+            LineType::Seg(seg) => seg.borrow().idx,
+            LineType::Tier(seg) => seg.borrow().idx,
+        }
+    }
+
+    fn dir(&self) -> BiDir {
+        match self {
+            LineType::Bi(bi) => bi.borrow().dir,
+            LineType::Seg(seg) => seg.borrow().dir,
+            LineType::Tier(seg) => seg.borrow().dir,
+        }
+    }
+
+    fn is_sure(&self) -> bool {
+        match self {
+            LineType::Bi(bi) => bi.borrow().is_sure,
+            LineType::Seg(seg) => seg.borrow().is_sure,
+            LineType::Tier(seg) => seg.borrow().is_sure,
+        }
+    }
+
+    fn seg_idx(&self) -> Option<i32> {
+        match self {
+            LineType::Bi(bi) => bi.borrow().seg_idx,
+            LineType::Seg(seg) => seg.borrow().seg_idx,
+            LineType::Tier(seg) => seg.borrow().seg_idx,
+        }
+    }
+
+    fn set_seg_idx(&mut self, idx: i32) {
+        match self {
+            LineType::Bi(bi) => bi.borrow_mut().set_seg_idx(idx),
+            LineType::Seg(seg) => seg.borrow_mut().set_seg_idx(idx),
+            LineType::Tier(seg) => seg.borrow_mut().set_seg_idx(idx),
+        }
+    }
+
+    fn get_begin_klu(&self) -> SharedCell<CKLineUnit> {
+        match self {
+            LineType::Bi(bi) => bi.borrow().get_begin_klu(),
+            LineType::Seg(seg) => seg.borrow().get_begin_klu(),
+            LineType::Tier(seg) => seg.borrow().get_begin_klu(),
+        }
+    }
+
+    fn get_end_klu(&self) -> SharedCell<CKLineUnit> {
+        match self {
+            LineType::Bi(bi) => bi.borrow().get_end_klu(),
+            LineType::Seg(seg) => seg.borrow().get_end_klu(),
+            LineType::Tier(seg) => seg.borrow().get_end_klu(),
+        }
+    }
+
+    fn get_begin_val(&self) -> f64 {
+        match self {
+            LineType::Bi(bi) => bi.borrow().get_begin_val(),
+            LineType::Seg(seg) => seg.borrow().get_begin_val(),
+            LineType::Tier(seg) => seg.borrow().get_begin_val(),
+        }
+    }
+
+    fn get_end_val(&self) -> f64 {
+        match self {
+            LineType::Bi(bi) => bi.borrow().get_end_val(),
+            LineType::Seg(seg) => seg.borrow().get_end_val(),
+            LineType::Tier(seg) => seg.borrow().get_end_val(),
+        }
+    }
+
+    fn _high(&self) -> f64 {
+        match self {
+            LineType::Bi(bi) => bi.borrow().high(),
+            LineType::Seg(seg) => seg.borrow()._high(),
+            LineType::Tier(seg) => seg.borrow()._high(),
+        }
+    }
+
+    fn _low(&self) -> f64 {
+        match self {
+            LineType::Bi(bi) => bi.borrow().low(),
+            LineType::Seg(seg) => seg.borrow()._low(),
+            LineType::Tier(seg) => seg.borrow()._low(),
+        }
+    }
+
+    fn pre_line(&self) -> Option<SharedCell<Self>> {
+        let pre = match self {
+            LineType::Bi(bi) => bi.borrow().pre.clone().map(LineType::Bi),
+            LineType::Seg(seg) => seg.borrow().pre.clone().map(LineType::Seg),
+            LineType::Tier(seg) => seg.borrow().pre.clone().map(LineType::Tier),
+        };
+        pre.map(|line| Rc::new(RefCell::new(line)))
+    }
+
+    fn next_line(&self) -> Option<SharedCell<Self>> {
+        let next = match self {
+            LineType::Bi(bi) => bi.borrow().next.clone().map(LineType::Bi),
+            LineType::Seg(seg) => seg.borrow().next.clone().map(LineType::Seg),
+            LineType::Tier(seg) => seg.borrow().next.clone().map(LineType::Tier),
+        };
+        next.map(|line| Rc::new(RefCell::new(line)))
+    }
+
+    fn parent_seg_idx(&self) -> Option<i32> {
+        match self {
+            LineType::Bi(bi) => bi.borrow().parent_seg_idx(),
+            LineType::Seg(seg) => seg.borrow().parent_seg.as_ref().map(|p| p.borrow().idx),
+            LineType::Tier(seg) => seg.borrow().parent_seg.as_ref().map(|p| p.borrow().idx),
+        }
+    }
+
+    fn parent_seg_dir(&self) -> Option<BiDir> {
+        match self {
+            LineType::Bi(bi) => bi.borrow().parent_seg_dir(),
+            LineType::Seg(seg) => seg.borrow().parent_seg.as_ref().map(|p| p.borrow().dir),
+            LineType::Tier(seg) => seg.borrow().parent_seg.as_ref().map(|p| p.borrow().dir),
+        }
+    }
+
+    fn parent_seg_bi_cnt(&self) -> Option<usize> {
+        match self {
+            LineType::Bi(bi) => bi.borrow().parent_seg_bi_cnt(),
+            LineType::Seg(seg) => seg
+                .borrow()
+                .parent_seg
+                .as_ref()
+                .map(|p| p.borrow().bi_list.len()),
+            LineType::Tier(seg) => seg
+                .borrow()
+                .parent_seg
+                .as_ref()
+                .map(|p| p.borrow().bi_list.len()),
+        }
+    }
+
+    fn set_parent_seg(&mut self, seg: SharedCell<CSeg<Self>>) {
+        match self {
+            LineType::Seg(s) => s.borrow_mut().parent_seg = Some(seg),
+            LineType::Tier(s) => s.borrow_mut().parent_seg = Some(seg),
+            // 递归层的子线都是线段，笔的父线段由第1层的 CSegListChan<CBi> 设置
+            LineType::Bi(_) => unreachable!("bi can not be the sub line of a seg tier"),
+        }
+    }
+
+    fn clear_parent_seg(&mut self) {
+        match self {
+            LineType::Bi(bi) => bi.borrow_mut().parent_seg = None,
+            LineType::Seg(seg) => seg.borrow_mut().parent_seg = None,
+            LineType::Tier(seg) => seg.borrow_mut().parent_seg = None,
+        }
+    }
+
+    fn to_line_type(line: &SharedCell<Self>) -> LineType {
+        line.borrow().clone()
+    }
 }
 
 // 创建一个辅助函数来简化 SharedCell 的创建
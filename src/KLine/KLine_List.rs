@@ -1,97 +1,120 @@
 use crate::Bi::Bi::CBi;
 use crate::Bi::BiList::CBiList;
+use crate::BuySellPoint::BSPointHistory::CBSPointHistory;
 use crate::BuySellPoint::BSPointList::CBSPointList;
 use crate::ChanConfig::CChanConfig;
-use crate::Common::types::SharedCell;
-use crate::Common::CEnum::{BiDir, KlType, KlineDir, SegType};
+use crate::Common::func_util::revert_BiDir;
+use crate::Common::types::{ChanLine, LineType, SharedCell};
+use crate::Common::CEnum::{BiDir, KlType, KlineDir, SegTrendType, SegType, ZsRelation};
 use crate::Common::ChanException::{CChanException, ErrCode};
 use crate::KLine::KLine::CKLine;
 use crate::KLine::KLine_Unit::CKLineUnit;
-use crate::Seg::Seg::CSeg;
+use crate::Seg::Seg::{cal_trend_type, cal_zs_relations, zs_relations2str, CSeg};
 use crate::Seg::SegListChan::CSegListChan;
 use crate::Seg::SegListComm::CSegListComm;
+use crate::Seg::SegTier::{seg_lines_from_bi_seg, CSegTier};
+use crate::Seg::SegTrendLine::CSegTrendLine;
 use crate::ZS::ZSList::CZSList;
+use crate::ZS::ZS::CZS;
 
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use super::KLine_Unit::MetricModel;
+use crate::Math::MetricModel::MetricModel;
 
 pub struct CKLineList {
     pub kl_type: String,
     pub config: CChanConfig,
     pub lst: Vec<SharedCell<CKLine>>,
     pub bi_list: CBiList,
-    pub seg_list: SharedCell<CSegListComm<CBi>>,
-    pub segseg_list: SharedCell<CSegListComm<CSeg<CBi>>>,
+    pub seg_list: SharedCell<CSegListChan<CBi>>,
     pub zs_list: CZSList,
-    pub segzs_list: CZSList,
-    pub bs_point_lst: CBSPointList<CBi, CBiList>,
-    pub seg_bs_point_lst: CBSPointList<CSeg<CBi>, CSegListComm<CBi>>,
+    pub bs_point_lst: CBSPointList<CBi>,
+    pub seg_tiers: Vec<CSegTier>,
     pub metric_model_lst: Vec<Box<dyn MetricModel>>,
     pub step_calculation: bool,
     pub bs_point_history: Vec<HashMap<String, String>>,
-    pub seg_bs_point_history: Vec<HashMap<String, String>>,
+    pub trend_line: CSegTrendLine,
 }
 
 impl CKLineList {
-    pub fn new(kl_type: String, conf: CChanConfig) -> Self {
-        let seg_list = CSegListChan::new(Some(conf.seg_conf.clone()), SegType::Bi);
-        let segseg_list = CSegListChan::new(Some(conf.seg_conf.clone()), SegType::Seg);
-
-        CKLineList {
+    pub fn new(kl_type: String, conf: CChanConfig) -> Result<Self, CChanException> {
+        conf.check_bsp_rules()?;
+        let seg_list = Rc::new(RefCell::new(CSegListChan::new(
+            Some(conf.seg_conf.clone()),
+            SegType::BI,
+        )));
+        let seg_tiers = (2..=conf.max_seg_level)
+            .map(|level| {
+                let mut tier = CSegTier::new(
+                    level,
+                    conf.seg_conf.clone(),
+                    conf.zs_conf.clone(),
+                    conf.get_tier_bs_point_conf(level),
+                )?;
+                tier.bs_point_lst.set_model(conf.seg_bsp_model.clone());
+                Ok(tier)
+            })
+            .collect::<Result<Vec<_>, CChanException>>()?;
+
+        let mut bs_point_lst = CBSPointList::<CBi>::new(Some(conf.bs_point_conf.clone()));
+        bs_point_lst.set_model(conf.bsp_model.clone());
+
+        Ok(CKLineList {
             kl_type,
             config: conf.clone(),
             lst: Vec::new(),
             bi_list: CBiList::new(Some(conf.bi_conf.clone())),
             seg_list,
-            segseg_list,
             zs_list: CZSList::new(Some(conf.zs_conf.clone())),
-            segzs_list: CZSList::new(Some(conf.zs_conf.clone())),
-            bs_point_lst: CBSPointList::new(Some(conf.bs_point_conf.clone())),
-            seg_bs_point_lst: CBSPointList::new(Some(conf.seg_bs_point_conf.clone())),
+            bs_point_lst,
+            seg_tiers,
             metric_model_lst: conf.get_metric_model(),
             step_calculation: conf.trigger_step,
             bs_point_history: Vec::new(),
-            seg_bs_point_history: Vec::new(),
-        }
+            trend_line: CSegTrendLine::new(),
+        })
     }
 
     pub fn cal_seg_and_zs(&mut self) -> Result<(), CChanException> {
         if !self.step_calculation {
             self.bi_list
-                .try_add_virtual_bi(self.lst.last().unwrap().clone(), false)?;
+                .try_add_virtual_bi(self.lst.last().unwrap().clone(), false);
         }
-        cal_seg(&self.bi_list, &mut self.seg_list.borrow_mut())?;
+        cal_seg(&self.bi_list.bi_list, &mut self.seg_list.borrow_mut())?;
         self.zs_list
-            .cal_bi_zs(&self.bi_list, &self.seg_list.borrow())?;
+            .cal_bi_zs(&self.bi_list.bi_list, &self.seg_list.borrow());
         update_zs_in_seg(
-            &self.bi_list,
+            &self.bi_list.bi_list,
             &mut self.seg_list.borrow_mut(),
             &mut self.zs_list,
         )?;
+        self.trend_line
+            .update(&self.seg_list.borrow(), &self.bi_list.bi_list);
+
+        // 每一层的线段作为上一层的笔，逐层向上递归
+        let mut sub_lines = seg_lines_from_bi_seg(&self.seg_list.borrow());
+        for tier in self.seg_tiers.iter_mut() {
+            tier.cal(sub_lines)?;
+            sub_lines = tier.lines_for_next_tier();
+        }
 
-        cal_seg(&self.seg_list.borrow(), &mut self.segseg_list.borrow_mut())?;
-        self.segzs_list
-            .cal_bi_zs(&self.seg_list.borrow(), &self.segseg_list.borrow())?;
-        update_zs_in_seg(
-            &self.seg_list.borrow(),
-            &mut self.segseg_list.borrow_mut(),
-            &mut self.segzs_list,
-        )?;
-
-        self.seg_bs_point_lst
-            .cal(&self.seg_list.borrow(), &self.segseg_list.borrow())?;
         self.bs_point_lst
-            .cal(&self.bi_list, &self.seg_list.borrow())?;
+            .cal(&self.bi_list.bi_list, &self.seg_list.borrow());
+        self.bs_point_lst.cal_custom_bsp(
+            &self.config.bsp_rules,
+            &self.bi_list.bi_list,
+            &self.seg_list.borrow(),
+            &self.zs_list,
+        );
         self.record_current_bs_points();
 
         Ok(())
     }
 
-    pub fn add_single_klu(&mut self, klu: CKLineUnit) -> Result<(), CChanException> {
-        klu.set_metric(&self.metric_model_lst);
+    pub fn add_single_klu(&mut self, mut klu: CKLineUnit) -> Result<(), CChanException> {
+        klu.set_metric(&mut self.metric_model_lst);
         if self.lst.is_empty() {
             self.lst
                 .push(Rc::new(RefCell::new(CKLine::new(klu, 0, None))));
@@ -121,7 +144,7 @@ impl CKLineList {
             } else if self.step_calculation
                 && self
                     .bi_list
-                    .try_add_virtual_bi(self.lst.last().unwrap().clone(), true)?
+                    .try_add_virtual_bi(self.lst.last().unwrap().clone(), true)
             {
                 self.cal_seg_and_zs()?;
             }
@@ -129,6 +152,62 @@ impl CKLineList {
         Ok(())
     }
 
+    // level=2 为线段的线段，level=3 为线段的线段的线段...
+    pub fn get_seg_tier(&self, level: usize) -> Option<&CSegTier> {
+        self.seg_tiers.iter().find(|tier| tier.level == level)
+    }
+
+    // 以下为递归级别之前的线段的线段接口，转发到 seg_tiers[0]，max_seg_level=1 时为 None
+    #[deprecated(note = "use seg_tiers[0].seg_list or get_seg_tier(2)")]
+    pub fn segseg_list(&self) -> Option<&SharedCell<CSegListChan<LineType>>> {
+        self.seg_tiers.first().map(|tier| &tier.seg_list)
+    }
+
+    #[deprecated(note = "use seg_tiers[0].zs_list or get_seg_tier(2)")]
+    pub fn segzs_list(&self) -> Option<&CZSList> {
+        self.seg_tiers.first().map(|tier| &tier.zs_list)
+    }
+
+    #[deprecated(note = "use seg_tiers[0].bs_point_lst or get_seg_tier(2)")]
+    pub fn seg_bs_point_lst(&self) -> Option<&CBSPointList<LineType>> {
+        self.seg_tiers.first().map(|tier| &tier.bs_point_lst)
+    }
+
+    #[deprecated(note = "use seg_tiers[0].bs_point_history or get_seg_tier(2)")]
+    pub fn seg_bs_point_history(&self) -> Option<&Vec<HashMap<String, String>>> {
+        self.seg_tiers.first().map(|tier| &tier.bs_point_history)
+    }
+
+    // 最后一根线段之后尚未完成的走势：只看其后新生成的中枢，方向与最后一根线段相反
+    pub fn get_cur_move_trend(&self) -> (SegTrendType, Vec<ZsRelation>) {
+        let seg_list = self.seg_list.borrow();
+        let (last_end_idx, dir) = match seg_list.last() {
+            Some(seg) => (
+                seg.borrow().end_bi.borrow().idx,
+                revert_BiDir(&seg.borrow().dir),
+            ),
+            None => match self.bi_list.bi_list.last() {
+                Some(bi) => (-1, bi.borrow().dir),
+                None => return (SegTrendType::NO_ZS, Vec::new()),
+            },
+        };
+        let cur_zs_lst: Vec<SharedCell<CZS>> = self
+            .zs_list
+            .iter()
+            .filter(|zs| {
+                zs.borrow()
+                    .begin_bi
+                    .as_ref()
+                    .map_or(false, |bi| bi.borrow().idx > last_end_idx)
+            })
+            .cloned()
+            .collect();
+        (
+            cal_trend_type(&cur_zs_lst, dir),
+            cal_zs_relations(&cur_zs_lst),
+        )
+    }
+
     pub fn klu_iter(&self, klc_begin_idx: usize) -> impl Iterator<Item = &CKLineUnit> {
         self.lst[klc_begin_idx..]
             .iter()
@@ -138,50 +217,82 @@ impl CKLineList {
     pub fn to_dataframes(&self) -> HashMap<String, Vec<HashMap<String, String>>> {
         let mut dataframes = HashMap::new();
 
-        // Convert segseg_list to DataFrame
+        // Convert seg_list to DataFrame
         dataframes.insert(
-            "segseg_list".to_string(),
-            self.segseg_list
+            "seg_list".to_string(),
+            self.seg_list
                 .borrow()
                 .iter()
-                .map(|segseg| {
-                    let segseg = segseg.borrow();
+                .map(|seg| {
+                    let seg = seg.borrow();
                     HashMap::from([
                         (
                             "begin_time".to_string(),
-                            segseg.get_begin_klu().time.to_string(),
+                            seg.get_begin_klu().borrow().time.to_string(),
                         ),
                         (
                             "end_time".to_string(),
-                            segseg.get_end_klu().time.to_string(),
+                            seg.get_end_klu().borrow().time.to_string(),
                         ),
-                        ("idx".to_string(), segseg.idx.to_string()),
-                        ("dir".to_string(), format!("{:?}", segseg.dir)),
-                        ("high".to_string(), segseg._high().to_string()),
-                        ("low".to_string(), segseg._low().to_string()),
-                        ("is_sure".to_string(), segseg.is_sure.to_string()),
+                        ("idx".to_string(), seg.idx.to_string()),
+                        ("dir".to_string(), format!("{:?}", seg.dir)),
+                        ("high".to_string(), seg._high().to_string()),
+                        ("low".to_string(), seg._low().to_string()),
+                        ("is_sure".to_string(), seg.is_sure.to_string()),
                         (
-                            "start_seg_idx".to_string(),
-                            segseg
-                                .start_bi
-                                .as_ref()
-                                .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            "start_bi_idx".to_string(),
+                            seg.start_bi.borrow().idx.to_string(),
                         ),
                         (
-                            "end_seg_idx".to_string(),
-                            segseg
-                                .end_bi
-                                .as_ref()
-                                .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            "end_bi_idx".to_string(),
+                            seg.end_bi.borrow().idx.to_string(),
+                        ),
+                        ("zs_count".to_string(), seg.zs_lst.len().to_string()),
+                        (
+                            "multi_bi_zs_count".to_string(),
+                            seg.get_multi_bi_zs_cnt().to_string(),
                         ),
-                        ("zs_count".to_string(), segseg.zs_lst.len().to_string()),
-                        ("bi_count".to_string(), segseg.bi_list.len().to_string()),
-                        ("reason".to_string(), segseg.reason.clone()),
+                        ("trend_type".to_string(), seg.get_trend_type().to_string()),
+                        (
+                            "zs_relation".to_string(),
+                            zs_relations2str(&seg.get_zs_relations()),
+                        ),
+                        ("reason".to_string(), seg.reason.clone()),
                     ])
                 })
                 .collect(),
         );
 
+        // 每根K线的指标值，包括自定义指标
+        dataframes.insert(
+            "klu_metric".to_string(),
+            self.klu_iter(0)
+                .map(|klu| {
+                    let mut row: HashMap<String, String> = klu
+                        .metric_dict()
+                        .into_iter()
+                        .map(|(k, v)| (k, v.to_string()))
+                        .collect();
+                    row.insert("idx".to_string(), klu.idx.to_string());
+                    row.insert("time".to_string(), klu.time.to_string());
+                    row
+                })
+                .collect(),
+        );
+
+        // Convert the current unfinished move to DataFrame
+        let (cur_trend_type, cur_zs_relations) = self.get_cur_move_trend();
+        dataframes.insert(
+            "cur_move".to_string(),
+            vec![HashMap::from([
+                ("trend_type".to_string(), cur_trend_type.to_string()),
+                (
+                    "zs_relation".to_string(),
+                    zs_relations2str(&cur_zs_relations),
+                ),
+            ])],
+        );
+
         // Convert zs_list to DataFrame
         dataframes.insert(
             "zs_list".to_string(),
@@ -207,6 +318,9 @@ impl CKLineList {
                         ("peak_high".to_string(), zs.peak_high.to_string()),
                         ("peak_low".to_string(), zs.peak_low.to_string()),
                         ("is_sure".to_string(), zs.is_sure.to_string()),
+                        ("ext_cnt".to_string(), zs.ext_cnt.to_string()),
+                        ("level_up".to_string(), zs.level_up2str()),
+                        ("sub_zs_cnt".to_string(), zs.sub_zs_lst.len().to_string()),
                         (
                             "begin_bi_idx".to_string(),
                             zs.begin_bi
@@ -260,88 +374,6 @@ impl CKLineList {
                 .collect(),
         );
 
-        // Convert segzs_list to DataFrame
-        dataframes.insert(
-            "segzs_list".to_string(),
-            self.segzs_list
-                .iter()
-                .map(|segzs| {
-                    let segzs = segzs.borrow();
-                    HashMap::from([
-                        (
-                            "begin_time".to_string(),
-                            segzs.begin_bi.as_ref().map_or("None".to_string(), |bi| {
-                                bi.borrow().get_begin_klu().time.to_string()
-                            }),
-                        ),
-                        (
-                            "end_time".to_string(),
-                            segzs.end_bi.as_ref().map_or("None".to_string(), |bi| {
-                                bi.borrow().get_end_klu().time.to_string()
-                            }),
-                        ),
-                        ("high".to_string(), segzs.high.to_string()),
-                        ("low".to_string(), segzs.low.to_string()),
-                        ("peak_high".to_string(), segzs.peak_high.to_string()),
-                        ("peak_low".to_string(), segzs.peak_low.to_string()),
-                        ("is_sure".to_string(), segzs.is_sure.to_string()),
-                        (
-                            "begin_seg_idx".to_string(),
-                            segzs
-                                .begin_bi
-                                .as_ref()
-                                .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
-                        ),
-                        (
-                            "end_seg_idx".to_string(),
-                            segzs
-                                .end_bi
-                                .as_ref()
-                                .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
-                        ),
-                        (
-                            "bi_in".to_string(),
-                            segzs
-                                .bi_in
-                                .as_ref()
-                                .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
-                        ),
-                        (
-                            "bi_out".to_string(),
-                            segzs
-                                .bi_out
-                                .as_ref()
-                                .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
-                        ),
-                        (
-                            "begin_bi_time".to_string(),
-                            segzs.begin_bi.as_ref().map_or("None".to_string(), |bi| {
-                                bi.borrow().get_begin_klu().time.to_string()
-                            }),
-                        ),
-                        (
-                            "end_bi_time".to_string(),
-                            segzs.end_bi.as_ref().map_or("None".to_string(), |bi| {
-                                bi.borrow().get_begin_klu().time.to_string()
-                            }),
-                        ),
-                        (
-                            "bi_in_time".to_string(),
-                            segzs.bi_in.as_ref().map_or("None".to_string(), |bi| {
-                                bi.borrow().get_begin_klu().time.to_string()
-                            }),
-                        ),
-                        (
-                            "bi_out_time".to_string(),
-                            segzs.bi_out.as_ref().map_or("None".to_string(), |bi| {
-                                bi.borrow().get_begin_klu().time.to_string()
-                            }),
-                        ),
-                    ])
-                })
-                .collect(),
-        );
-
         // Convert bs_point_lst to DataFrame
         dataframes.insert(
             "bs_point_lst".to_string(),
@@ -349,62 +381,41 @@ impl CKLineList {
                 .iter()
                 .map(|bsp| {
                     let bsp = bsp.borrow();
-                    HashMap::from([
+                    let mut row = HashMap::from([
                         ("begin_time".to_string(), bsp.klu.time.to_string()),
                         ("bsp_type".to_string(), bsp.type2str()),
                         (
-                            "bi_idx".to_string(),
-                            bsp.bi
-                                .as_ref()
-                                .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            "score".to_string(),
+                            bsp.score.map_or("None".to_string(), |s| s.to_string()),
                         ),
                         (
-                            "bi_begin_time".to_string(),
-                            bsp.bi.as_ref().map_or("None".to_string(), |bi| {
-                                bi.borrow().get_begin_klu().time.to_string()
-                            }),
-                        ),
-                        (
-                            "bi_end_time".to_string(),
-                            bsp.bi.as_ref().map_or("None".to_string(), |bi| {
-                                bi.borrow().get_end_klu().time.to_string()
-                            }),
+                            "divergence_report".to_string(),
+                            bsp.divergence_report
+                                .as_ref()
+                                .map_or("None".to_string(), |r| r.to_string()),
                         ),
-                    ])
-                })
-                .collect(),
-        );
-
-        // Convert seg_bs_point_lst to DataFrame
-        dataframes.insert(
-            "seg_bs_point_lst".to_string(),
-            self.seg_bs_point_lst
-                .iter()
-                .map(|seg_bsp| {
-                    let seg_bsp = seg_bsp.borrow();
-                    HashMap::from([
-                        ("begin_time".to_string(), seg_bsp.klu.time.to_string()),
-                        ("bsp_type".to_string(), seg_bsp.type2str()),
                         (
-                            "seg_idx".to_string(),
-                            seg_bsp
-                                .bi
+                            "bi_idx".to_string(),
+                            bsp.bi
                                 .as_ref()
                                 .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
                         ),
                         (
                             "bi_begin_time".to_string(),
-                            seg_bsp.bi.as_ref().map_or("None".to_string(), |bi| {
+                            bsp.bi.as_ref().map_or("None".to_string(), |bi| {
                                 bi.borrow().get_begin_klu().time.to_string()
                             }),
                         ),
                         (
                             "bi_end_time".to_string(),
-                            seg_bsp.bi.as_ref().map_or("None".to_string(), |bi| {
+                            bsp.bi.as_ref().map_or("None".to_string(), |bi| {
                                 bi.borrow().get_end_klu().time.to_string()
                             }),
                         ),
-                    ])
+                    ]);
+                    // 结构止损/止盈位
+                    row.extend(bsp.risk.to_columns());
+                    row
                 })
                 .collect(),
         );
@@ -415,12 +426,210 @@ impl CKLineList {
             self.bs_point_history.clone(),
         );
 
-        // Add historical seg_bs_points
+        // 每个买卖点的每次状态变化一行
+        dataframes.insert(
+            "bs_point_lifecycle".to_string(),
+            bsp_lifecycle_rows(&self.bs_point_lst.history),
+        );
+
         dataframes.insert(
-            "seg_bs_point_history".to_string(),
-            self.seg_bs_point_history.clone(),
+            "trend_line_break".to_string(),
+            trend_line_break_rows(&self.trend_line),
         );
 
+        // Convert every recursive seg tier (segseg_list, seg2seg_list, ...) to DataFrame
+        for tier in &self.seg_tiers {
+            let prefix = tier.name_prefix();
+            dataframes.insert(
+                format!("{}seg_list", prefix),
+                tier.seg_list
+                    .borrow()
+                    .iter()
+                    .map(|segseg| {
+                        let segseg = segseg.borrow();
+                        HashMap::from([
+                            (
+                                "begin_time".to_string(),
+                                segseg.get_begin_klu().time.to_string(),
+                            ),
+                            (
+                                "end_time".to_string(),
+                                segseg.get_end_klu().time.to_string(),
+                            ),
+                            ("idx".to_string(), segseg.idx.to_string()),
+                            ("dir".to_string(), format!("{:?}", segseg.dir)),
+                            ("high".to_string(), segseg._high().to_string()),
+                            ("low".to_string(), segseg._low().to_string()),
+                            ("is_sure".to_string(), segseg.is_sure.to_string()),
+                            (
+                                "start_seg_idx".to_string(),
+                                segseg
+                                    .start_bi
+                                    .as_ref()
+                                    .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            ),
+                            (
+                                "end_seg_idx".to_string(),
+                                segseg
+                                    .end_bi
+                                    .as_ref()
+                                    .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            ),
+                            ("zs_count".to_string(), segseg.zs_lst.len().to_string()),
+                            ("bi_count".to_string(), segseg.bi_list.len().to_string()),
+                            (
+                                "trend_type".to_string(),
+                                segseg.get_trend_type().to_string(),
+                            ),
+                            (
+                                "zs_relation".to_string(),
+                                zs_relations2str(&segseg.get_zs_relations()),
+                            ),
+                            ("reason".to_string(), segseg.reason.clone()),
+                        ])
+                    })
+                    .collect(),
+            );
+
+            dataframes.insert(
+                format!("{}zs_list", prefix),
+                tier.zs_list
+                    .iter()
+                    .map(|segzs| {
+                        let segzs = segzs.borrow();
+                        HashMap::from([
+                            (
+                                "begin_time".to_string(),
+                                segzs.begin_bi.as_ref().map_or("None".to_string(), |bi| {
+                                    bi.borrow().get_begin_klu().time.to_string()
+                                }),
+                            ),
+                            (
+                                "end_time".to_string(),
+                                segzs.end_bi.as_ref().map_or("None".to_string(), |bi| {
+                                    bi.borrow().get_end_klu().time.to_string()
+                                }),
+                            ),
+                            ("high".to_string(), segzs.high.to_string()),
+                            ("low".to_string(), segzs.low.to_string()),
+                            ("peak_high".to_string(), segzs.peak_high.to_string()),
+                            ("peak_low".to_string(), segzs.peak_low.to_string()),
+                            ("is_sure".to_string(), segzs.is_sure.to_string()),
+                            ("ext_cnt".to_string(), segzs.ext_cnt.to_string()),
+                            ("level_up".to_string(), segzs.level_up2str()),
+                            ("sub_zs_cnt".to_string(), segzs.sub_zs_lst.len().to_string()),
+                            (
+                                "begin_seg_idx".to_string(),
+                                segzs
+                                    .begin_bi
+                                    .as_ref()
+                                    .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            ),
+                            (
+                                "end_seg_idx".to_string(),
+                                segzs
+                                    .end_bi
+                                    .as_ref()
+                                    .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            ),
+                            (
+                                "bi_in".to_string(),
+                                segzs
+                                    .bi_in
+                                    .as_ref()
+                                    .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            ),
+                            (
+                                "bi_out".to_string(),
+                                segzs
+                                    .bi_out
+                                    .as_ref()
+                                    .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            ),
+                            (
+                                "begin_bi_time".to_string(),
+                                segzs.begin_bi.as_ref().map_or("None".to_string(), |bi| {
+                                    bi.borrow().get_begin_klu().time.to_string()
+                                }),
+                            ),
+                            (
+                                "end_bi_time".to_string(),
+                                segzs.end_bi.as_ref().map_or("None".to_string(), |bi| {
+                                    bi.borrow().get_begin_klu().time.to_string()
+                                }),
+                            ),
+                            (
+                                "bi_in_time".to_string(),
+                                segzs.bi_in.as_ref().map_or("None".to_string(), |bi| {
+                                    bi.borrow().get_begin_klu().time.to_string()
+                                }),
+                            ),
+                            (
+                                "bi_out_time".to_string(),
+                                segzs.bi_out.as_ref().map_or("None".to_string(), |bi| {
+                                    bi.borrow().get_begin_klu().time.to_string()
+                                }),
+                            ),
+                        ])
+                    })
+                    .collect(),
+            );
+
+            dataframes.insert(
+                format!("{}_bs_point_lst", prefix),
+                tier.bs_point_lst
+                    .iter()
+                    .map(|seg_bsp| {
+                        let seg_bsp = seg_bsp.borrow();
+                        let mut row = HashMap::from([
+                            ("begin_time".to_string(), seg_bsp.klu.time.to_string()),
+                            ("bsp_type".to_string(), seg_bsp.type2str()),
+                            (
+                                "score".to_string(),
+                                seg_bsp.score.map_or("None".to_string(), |s| s.to_string()),
+                            ),
+                            (
+                                "seg_idx".to_string(),
+                                seg_bsp
+                                    .bi
+                                    .as_ref()
+                                    .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                            ),
+                            (
+                                "bi_begin_time".to_string(),
+                                seg_bsp.bi.as_ref().map_or("None".to_string(), |bi| {
+                                    bi.borrow().get_begin_klu().time.to_string()
+                                }),
+                            ),
+                            (
+                                "bi_end_time".to_string(),
+                                seg_bsp.bi.as_ref().map_or("None".to_string(), |bi| {
+                                    bi.borrow().get_end_klu().time.to_string()
+                                }),
+                            ),
+                        ]);
+                        row.extend(seg_bsp.risk.to_columns());
+                        row
+                    })
+                    .collect(),
+            );
+
+            dataframes.insert(
+                format!("{}_bs_point_history", prefix),
+                tier.bs_point_history.clone(),
+            );
+
+            dataframes.insert(
+                format!("{}_bs_point_lifecycle", prefix),
+                bsp_lifecycle_rows(&tier.bs_point_lst.history),
+            );
+
+            dataframes.insert(
+                format!("{}_trend_line_break", prefix),
+                trend_line_break_rows(&tier.trend_line),
+            );
+        }
+
         dataframes
     }
 
@@ -454,6 +663,20 @@ impl CKLineList {
     }
 
     fn record_current_bs_points(&mut self) {
+        let cur_klu = self
+            .lst
+            .last()
+            .unwrap()
+            .borrow()
+            .lst
+            .last()
+            .unwrap()
+            .clone();
+        self.bs_point_lst.update_history(&cur_klu);
+        for tier in self.seg_tiers.iter_mut() {
+            tier.bs_point_lst.update_history(&cur_klu);
+        }
+
         if let Some(latest_bsp) = self.bs_point_lst.last() {
             let latest_bsp = latest_bsp.borrow();
             self.bs_point_history.push(HashMap::from([
@@ -489,47 +712,52 @@ impl CKLineList {
             ]));
         }
 
-        if let Some(latest_seg_bsp) = self.seg_bs_point_lst.last() {
-            let latest_seg_bsp = latest_seg_bsp.borrow();
-            self.seg_bs_point_history.push(HashMap::from([
-                (
-                    "begin_time".to_string(),
-                    latest_seg_bsp.klu.time.to_string(),
-                ),
-                ("bsp_type".to_string(), latest_seg_bsp.type2str()),
-                ("is_buy".to_string(), latest_seg_bsp.is_buy.to_string()),
-                (
-                    "relate_bsp1".to_string(),
-                    latest_seg_bsp
-                        .relate_bsp1
-                        .as_ref()
-                        .map_or("None".to_string(), |bsp| bsp.borrow().klu.time.to_string()),
-                ),
-                (
-                    "seg_idx".to_string(),
-                    latest_seg_bsp
-                        .bi
-                        .as_ref()
-                        .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
-                ),
-                (
-                    "bi_begin_time".to_string(),
-                    latest_seg_bsp.bi.as_ref().map_or("None".to_string(), |bi| {
-                        bi.borrow().get_begin_klu().time.to_string()
-                    }),
-                ),
-                (
-                    "bi_end_time".to_string(),
-                    latest_seg_bsp.bi.as_ref().map_or("None".to_string(), |bi| {
-                        bi.borrow().get_end_klu().time.to_string()
-                    }),
-                ),
-            ]));
+        for tier in self.seg_tiers.iter_mut() {
+            if let Some(latest_seg_bsp) = tier.bs_point_lst.last() {
+                let latest_seg_bsp = latest_seg_bsp.borrow();
+                tier.bs_point_history.push(HashMap::from([
+                    (
+                        "begin_time".to_string(),
+                        latest_seg_bsp.klu.time.to_string(),
+                    ),
+                    ("bsp_type".to_string(), latest_seg_bsp.type2str()),
+                    ("is_buy".to_string(), latest_seg_bsp.is_buy.to_string()),
+                    (
+                        "relate_bsp1".to_string(),
+                        latest_seg_bsp
+                            .relate_bsp1
+                            .as_ref()
+                            .map_or("None".to_string(), |bsp| bsp.borrow().klu.time.to_string()),
+                    ),
+                    (
+                        "seg_idx".to_string(),
+                        latest_seg_bsp
+                            .bi
+                            .as_ref()
+                            .map_or("None".to_string(), |bi| bi.borrow().idx.to_string()),
+                    ),
+                    (
+                        "bi_begin_time".to_string(),
+                        latest_seg_bsp.bi.as_ref().map_or("None".to_string(), |bi| {
+                            bi.borrow().get_begin_klu().time.to_string()
+                        }),
+                    ),
+                    (
+                        "bi_end_time".to_string(),
+                        latest_seg_bsp.bi.as_ref().map_or("None".to_string(), |bi| {
+                            bi.borrow().get_end_klu().time.to_string()
+                        }),
+                    ),
+                ]));
+            }
         }
     }
 }
 
-pub fn cal_seg(bi_list: &CBiList, seg_list: &mut CSegListComm<CBi>) -> Result<(), CChanException> {
+pub fn cal_seg<L: ChanLine>(
+    bi_list: &[SharedCell<L>],
+    seg_list: &mut CSegListChan<L>,
+) -> Result<(), CChanException> {
     seg_list.update(bi_list)?;
 
     let mut sure_seg_cnt = 0;
@@ -554,18 +782,19 @@ pub fn cal_seg(bi_list: &CBiList, seg_list: &mut CSegListComm<CBi>) -> Result<()
 
     let mut cur_seg = seg_list.last().unwrap().clone();
     for bi in bi_list.iter().rev() {
-        if bi.borrow().seg_idx.is_some()
-            && bi.borrow().idx < begin_seg.borrow().start_bi.borrow().idx
+        if bi.borrow().seg_idx().is_some()
+            && bi.borrow().idx() < begin_seg.borrow().start_bi.borrow().idx()
         {
             break;
         }
-        if bi.borrow().idx > cur_seg.borrow().end_bi.borrow().idx {
+        if bi.borrow().idx() > cur_seg.borrow().end_bi.borrow().idx() {
             bi.borrow_mut().set_seg_idx(cur_seg.borrow().idx + 1);
             continue;
         }
-        if bi.borrow().idx < cur_seg.borrow().start_bi.borrow().idx {
+        if bi.borrow().idx() < cur_seg.borrow().start_bi.borrow().idx() {
             assert!(cur_seg.borrow().pre.is_some());
-            cur_seg = cur_seg.borrow().pre.as_ref().unwrap().clone();
+            let pre_seg = cur_seg.borrow().pre.as_ref().unwrap().clone();
+            cur_seg = pre_seg;
         }
         bi.borrow_mut().set_seg_idx(cur_seg.borrow().idx);
     }
@@ -573,9 +802,9 @@ pub fn cal_seg(bi_list: &CBiList, seg_list: &mut CSegListComm<CBi>) -> Result<()
     Ok(())
 }
 
-pub fn update_zs_in_seg(
-    bi_list: &CBiList,
-    seg_list: &mut CSegListComm<CBi>,
+pub fn update_zs_in_seg<L: ChanLine>(
+    bi_list: &[SharedCell<L>],
+    seg_list: &mut CSegListComm<L>,
     zs_list: &mut CZSList,
 ) -> Result<(), CChanException> {
     let mut sure_seg_cnt = 0;
@@ -589,24 +818,27 @@ pub fn update_zs_in_seg(
         }
         seg.clear_zs_lst();
         for zs in zs_list.iter().rev() {
-            let mut zs = zs.borrow_mut();
-            if zs.end.borrow().idx < seg.start_bi.borrow().get_begin_klu().idx {
+            if zs.borrow().end.as_ref().unwrap().borrow().idx
+                < seg.start_bi.borrow().get_begin_klu().borrow().idx
+            {
                 break;
             }
-            if zs.is_inside(&seg) {
-                seg.add_zs(zs.clone());
+            if zs.borrow().is_inside(&seg) {
+                seg.add_zs(Rc::clone(zs));
             }
-            assert!(zs.begin_bi.as_ref().unwrap().borrow().idx > 0);
-            zs.set_bi_in(bi_list[zs.begin_bi.as_ref().unwrap().borrow().idx as usize - 1].clone());
-            if zs.end_bi.as_ref().unwrap().borrow().idx + 1 < bi_list.len() as i32 {
-                zs.set_bi_out(
-                    bi_list[zs.end_bi.as_ref().unwrap().borrow().idx as usize + 1].clone(),
-                );
+            let mut zs = zs.borrow_mut();
+            let begin_bi_idx = zs.begin_bi.as_ref().unwrap().idx() as usize;
+            let end_bi_idx = zs.end_bi.as_ref().unwrap().idx() as usize;
+            assert!(begin_bi_idx > 0);
+            zs.set_bi_in(L::to_line_type(&bi_list[begin_bi_idx - 1]));
+            if end_bi_idx + 1 < bi_list.len() {
+                zs.set_bi_out(L::to_line_type(&bi_list[end_bi_idx + 1]));
             }
             zs.set_bi_lst(
-                bi_list[zs.begin_bi.as_ref().unwrap().borrow().idx as usize
-                    ..=zs.end_bi.as_ref().unwrap().borrow().idx as usize]
-                    .to_vec(),
+                bi_list[begin_bi_idx..=end_bi_idx]
+                    .iter()
+                    .map(L::to_line_type)
+                    .collect(),
             );
         }
 
@@ -617,3 +849,48 @@ pub fn update_zs_in_seg(
 
     Ok(())
 }
+
+fn bsp_lifecycle_rows(history: &CBSPointHistory) -> Vec<HashMap<String, String>> {
+    history
+        .iter()
+        .flat_map(|record| {
+            record.events.iter().map(move |event| {
+                HashMap::from([
+                    ("id".to_string(), record.id.clone()),
+                    ("is_buy".to_string(), record.is_buy.to_string()),
+                    ("klu_time".to_string(), record.klu_time.to_string()),
+                    ("bsp_type".to_string(), event.bsp_type.clone()),
+                    ("state".to_string(), event.state.to_string()),
+                    ("step_idx".to_string(), event.klu_idx.to_string()),
+                    ("step_time".to_string(), event.time.to_string()),
+                    ("reason".to_string(), event.reason.clone()),
+                ])
+            })
+        })
+        .collect()
+}
+
+fn trend_line_break_rows(trend_line: &CSegTrendLine) -> Vec<HashMap<String, String>> {
+    trend_line
+        .break_events
+        .iter()
+        .map(|event| {
+            HashMap::from([
+                ("seg_idx".to_string(), event.seg_idx.to_string()),
+                (
+                    "line".to_string(),
+                    if event.is_support {
+                        "support".to_string()
+                    } else {
+                        "resistance".to_string()
+                    },
+                ),
+                ("break_dir".to_string(), format!("{:?}", event.break_dir)),
+                ("klu_idx".to_string(), event.klu_idx.to_string()),
+                ("time".to_string(), event.time.to_string()),
+                ("close".to_string(), event.close.to_string()),
+                ("line_val".to_string(), event.line_val.to_string()),
+            ])
+        })
+        .collect()
+}
@@ -9,12 +9,11 @@ use crate::{
         TradeInfo::CTradeInfo,
     },
     Math::{
-        Demark::{CDemarkEngine, CDemarkIndex},
-        TrendModel::CTrendModel,
-        BOLL::{BOLLMetric, BollModel},
-        KDJ::KDJ,
-        MACD::{CMACDItem, CMACD},
-        RSI::RSI,
+        Demark::CDemarkIndex,
+        MetricModel::{MetricModel, MetricValue},
+        BOLL::BOLLMetric,
+        KDJ::KDJItem,
+        MACD::CMACDItem,
     },
 };
 
@@ -32,7 +31,8 @@ pub struct CKLineUnit {
     pub sub_kl_list: Vec<SharedCell<CKLineUnit>>,
     pub sup_kl: Option<SharedCell<CKLineUnit>>,
     pub klc: Option<SharedCell<CKLine>>,
-    pub trend: HashMap<TrendType, HashMap<i32, f64>>,
+    // (类型, 窗口) -> 收盘价的滚动统计值，通过 get_trend/trend_mean 等读取
+    pub trend: HashMap<(TrendType, usize), f64>,
     pub limit_flag: i32,
     pub pre: Option<SharedCell<CKLineUnit>>,
     pub next: Option<SharedCell<CKLineUnit>>,
@@ -40,7 +40,9 @@ pub struct CKLineUnit {
     pub macd: Option<CMACDItem>,
     pub boll: Option<BOLLMetric>,
     pub rsi: Option<f64>,
-    pub kdj: Option<KDJ>,
+    pub kdj: Option<KDJItem>,
+    // 所有指标（含自定义指标）的结果，key 为 MetricModel::name
+    pub metric: HashMap<String, MetricValue>,
 }
 
 impl CKLineUnit {
@@ -66,6 +68,7 @@ impl CKLineUnit {
             boll: None,
             rsi: None,
             kdj: None,
+            metric: HashMap::new(),
         };
 
         unit.check(autofix)?;
@@ -126,29 +129,63 @@ impl CKLineUnit {
         self.high
     }
 
-    pub fn set_metric(&mut self, metric_model_lst: &[Box<dyn MetricModel>]) {
-        for metric_model in metric_model_lst {
-            if let Some(macd) = metric_model.as_any().downcast_ref::<CMACD>() {
-                self.macd = Some(macd.add(self.close));
-            } else if let Some(trend_model) = metric_model.as_any().downcast_ref::<CTrendModel>() {
-                self.trend
-                    .entry(trend_model.get_type())
-                    .or_insert_with(HashMap::new)
-                    .insert(trend_model.get_t(), trend_model.add(self.close));
-            } else if let Some(boll_model) = metric_model.as_any().downcast_ref::<BollModel>() {
-                self.boll = Some(boll_model.add(self.close));
-            } else if let Some(demark_engine) =
-                metric_model.as_any().downcast_ref::<CDemarkEngine>()
-            {
-                self.demark = demark_engine.update(self.idx, self.close, self.high, self.low);
-            } else if let Some(rsi) = metric_model.as_any().downcast_ref::<RSI>() {
-                self.rsi = Some(rsi.add(self.close));
-            } else if let Some(kdj) = metric_model.as_any().downcast_ref::<KDJ>() {
-                self.kdj = Some(kdj.add(self.high, self.low, self.close));
+    // 依次更新各指标，结果按 name 存入 metric；内置指标同时写入对应字段
+    pub fn set_metric(&mut self, metric_model_lst: &mut [Box<dyn MetricModel>]) {
+        for metric_model in metric_model_lst.iter_mut() {
+            let value = metric_model.update(self);
+            match &value {
+                MetricValue::Macd(macd) => self.macd = Some(macd.clone()),
+                MetricValue::Boll(boll) => self.boll = Some(boll.clone()),
+                MetricValue::Kdj(kdj) => self.kdj = Some(*kdj),
+                MetricValue::Demark(demark) => self.demark = demark.clone(),
+                MetricValue::Rsi(rsi) => self.rsi = Some(*rsi),
+                MetricValue::Trend(trend_type, t, v) => {
+                    self.trend.insert((*trend_type, *t), *v);
+                }
+                MetricValue::Rolling(t, item) => {
+                    self.trend.insert((TrendType::MEAN, *t), item.mean);
+                    self.trend.insert((TrendType::MAX, *t), item.max);
+                    self.trend.insert((TrendType::MIN, *t), item.min);
+                    self.trend.insert((TrendType::STD, *t), item.std);
+                }
+                MetricValue::Value(_) | MetricValue::Dict(_) | MetricValue::Empty => {}
             }
+            self.metric.insert(metric_model.name(), value);
         }
     }
 
+    pub fn get_trend(&self, trend_type: TrendType, t: usize) -> Option<f64> {
+        self.trend.get(&(trend_type, t)).copied()
+    }
+
+    pub fn trend_mean(&self, t: usize) -> Option<f64> {
+        self.get_trend(TrendType::MEAN, t)
+    }
+
+    pub fn trend_max(&self, t: usize) -> Option<f64> {
+        self.get_trend(TrendType::MAX, t)
+    }
+
+    pub fn trend_min(&self, t: usize) -> Option<f64> {
+        self.get_trend(TrendType::MIN, t)
+    }
+
+    pub fn trend_std(&self, t: usize) -> Option<f64> {
+        self.get_trend(TrendType::STD, t)
+    }
+
+    pub fn get_metric(&self, name: &str) -> Option<&MetricValue> {
+        self.metric.get(name)
+    }
+
+    // 所有指标展开后的数值，key 形如 {name}_{field}
+    pub fn metric_dict(&self) -> HashMap<String, f64> {
+        self.metric
+            .iter()
+            .flat_map(|(name, value)| value.to_dict(name))
+            .collect()
+    }
+
     pub fn get_parent_klc(&self) -> Option<SharedCell<CKLine>> {
         self.sup_kl
             .as_ref()
@@ -209,17 +246,6 @@ impl std::fmt::Display for CKLineUnit {
     }
 }
 
-pub trait MetricModel {
-    fn as_any(&self) -> &dyn std::any::Any;
-}
-
-impl MetricModel for CMACD {}
-impl MetricModel for CTrendModel {}
-impl MetricModel for BollModel {}
-impl MetricModel for CDemarkEngine {}
-impl MetricModel for RSI {}
-impl MetricModel for KDJ {}
-
 impl Clone for CKLineUnit {
     fn clone(&self) -> Self {
         let mut kl_dict = HashMap::new();
@@ -243,6 +269,7 @@ impl Clone for CKLineUnit {
         obj.boll = self.boll.clone();
         obj.rsi = self.rsi;
         obj.kdj = self.kdj.clone();
+        obj.metric = self.metric.clone();
         obj.set_idx(self.idx);
         obj
     }
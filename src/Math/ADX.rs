@@ -0,0 +1,132 @@
+use crate::Math::ATR::{true_range, CWilderAvg};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ADXItem {
+    pub plus_di: f64,
+    pub minus_di: f64,
+    pub adx: f64,
+}
+
+// TA-Lib 对 +DM/-DM/TR 的平滑：前 n-1 个值直接累加，之后 sum - sum/n + x
+// 只用于求 DI 这种比值，不需要除以 n
+struct CWilderSum {
+    period: usize,
+    cnt: usize,
+    sum: f64,
+}
+
+impl CWilderSum {
+    fn new(period: usize) -> Self {
+        CWilderSum {
+            period,
+            cnt: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn add(&mut self, value: f64) -> f64 {
+        self.cnt += 1;
+        if self.cnt >= self.period {
+            self.sum -= self.sum / self.period as f64;
+        }
+        self.sum += value;
+        self.sum
+    }
+
+    fn is_ready(&self) -> bool {
+        self.cnt >= self.period
+    }
+}
+
+// 平均趋向指数，算法与 TA-Lib 的 ADX/PLUS_DI/MINUS_DI 一致：
+// 第一根K线没有昨收，不计入；TR/+DM/-DM 用 CWilderSum 平滑；
+// 从第 n 个 DX 起取前 n 个 DX 的均值作为 ADX 初值，之后 Wilder 平滑
+// TA-Lib 在前 2n-1 根K线输出 NaN，这里输出的是预热中的值（没有 DX 时 adx 为0）
+pub struct CADX {
+    period: usize,
+    pre_kl: Option<(f64, f64, f64)>,
+    tr_sum: CWilderSum,
+    plus_dm_sum: CWilderSum,
+    minus_dm_sum: CWilderSum,
+    adx_avg: CWilderAvg,
+    dx_cnt: usize,
+    adx: f64,
+}
+
+impl CADX {
+    pub fn new(period: usize) -> Self {
+        CADX {
+            period,
+            pre_kl: None,
+            tr_sum: CWilderSum::new(period),
+            plus_dm_sum: CWilderSum::new(period),
+            minus_dm_sum: CWilderSum::new(period),
+            adx_avg: CWilderAvg::new(period),
+            dx_cnt: 0,
+            adx: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, high: f64, low: f64, close: f64) -> ADXItem {
+        let Some((pre_high, pre_low, pre_close)) = self.pre_kl.replace((high, low, close)) else {
+            return ADXItem {
+                plus_di: 0.0,
+                minus_di: 0.0,
+                adx: 0.0,
+            };
+        };
+        let up = high - pre_high;
+        let down = pre_low - low;
+        let plus_dm = if up > down && up > 0.0 { up } else { 0.0 };
+        let minus_dm = if down > up && down > 0.0 { down } else { 0.0 };
+
+        let tr = self.tr_sum.add(true_range(high, low, Some(pre_close)));
+        let plus_dm = self.plus_dm_sum.add(plus_dm);
+        let minus_dm = self.minus_dm_sum.add(minus_dm);
+        let (plus_di, minus_di) = if tr > 0.0 {
+            (100.0 * plus_dm / tr, 100.0 * minus_dm / tr)
+        } else {
+            (0.0, 0.0)
+        };
+        if self.tr_sum.is_ready() {
+            // 和 TA-Lib 一样，DI 全为0时初值阶段按 DX=0 计，之后沿用上一个 ADX
+            if plus_di + minus_di > 0.0 {
+                let dx = 100.0 * (plus_di - minus_di).abs() / (plus_di + minus_di);
+                self.adx = self.adx_avg.add(dx);
+                self.dx_cnt += 1;
+            } else if self.dx_cnt < self.period {
+                self.adx = self.adx_avg.add(0.0);
+                self.dx_cnt += 1;
+            }
+        }
+        ADXItem {
+            plus_di,
+            minus_di,
+            adx: self.adx,
+        }
+    }
+
+    pub fn get_period(&self) -> usize {
+        self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Math::test_data::{CLOSE, HIGH, LOW};
+
+    #[test]
+    fn test_adx() {
+        let mut adx = CADX::new(5);
+        let mut res = None;
+        for i in 0..CLOSE.len() {
+            res = Some(adx.add(HIGH[i], LOW[i], CLOSE[i]));
+        }
+        // TA-Lib ADX(5)/PLUS_DI(5)/MINUS_DI(5) 在最后一根K线的输出
+        let res = res.unwrap();
+        assert!((res.plus_di - 28.923817).abs() < 1e-4);
+        assert!((res.minus_di - 31.986883).abs() < 1e-4);
+        assert!((res.adx - 27.555120).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,86 @@
+// 真实波幅，第一根K线没有昨收，取 high-low
+pub fn true_range(high: f64, low: f64, pre_close: Option<f64>) -> f64 {
+    match pre_close {
+        Some(pre_close) => (high - low)
+            .max((high - pre_close).abs())
+            .max((low - pre_close).abs()),
+        None => high - low,
+    }
+}
+
+// Wilder 平滑：前 n 根取算术平均，之后 (pre*(n-1)+x)/n
+pub struct CWilderAvg {
+    period: usize,
+    cnt: usize,
+    avg: f64,
+}
+
+impl CWilderAvg {
+    pub fn new(period: usize) -> Self {
+        CWilderAvg {
+            period,
+            cnt: 0,
+            avg: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, value: f64) -> f64 {
+        self.cnt += 1;
+        let n = self.cnt.min(self.period) as f64;
+        self.avg = (self.avg * (n - 1.0) + value) / n;
+        self.avg
+    }
+}
+
+pub struct CATR {
+    period: usize,
+    pre_close: Option<f64>,
+    avg: CWilderAvg,
+}
+
+impl CATR {
+    pub fn new(period: usize) -> Self {
+        CATR {
+            period,
+            pre_close: None,
+            avg: CWilderAvg::new(period),
+        }
+    }
+
+    // 与 TA-Lib 一致，第一根K线没有昨收，不计入平均，直接返回 high-low
+    pub fn add(&mut self, high: f64, low: f64, close: f64) -> f64 {
+        let pre_close = self.pre_close.replace(close);
+        match pre_close {
+            Some(_) => self.avg.add(true_range(high, low, pre_close)),
+            None => high - low,
+        }
+    }
+
+    pub fn get_period(&self) -> usize {
+        self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Math::test_data::{CLOSE, HIGH, LOW};
+
+    #[test]
+    fn test_true_range() {
+        assert_eq!(true_range(10.0, 8.0, None), 2.0);
+        assert_eq!(true_range(10.0, 8.0, Some(12.0)), 4.0);
+        assert_eq!(true_range(10.0, 8.0, Some(5.0)), 5.0);
+    }
+
+    #[test]
+    fn test_atr() {
+        let mut atr = CATR::new(14);
+        let mut res = 0.0;
+        for i in 0..CLOSE.len() {
+            res = atr.add(HIGH[i], LOW[i], CLOSE[i]);
+        }
+        // TA-Lib ATR(14) 的结果
+        assert!((res - 0.624356).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::VecDeque;
+
+// 顺势指标 (tp - ma(tp)) / (0.015 * 平均绝对偏差)，tp 为典型价
+pub struct CCCI {
+    period: usize,
+    arr: VecDeque<f64>,
+}
+
+impl CCCI {
+    pub fn new(period: usize) -> Self {
+        CCCI {
+            period,
+            arr: VecDeque::with_capacity(period),
+        }
+    }
+
+    pub fn add(&mut self, high: f64, low: f64, close: f64) -> f64 {
+        let tp = (high + low + close) / 3.0;
+        self.arr.push_back(tp);
+        if self.arr.len() > self.period {
+            self.arr.pop_front();
+        }
+        let n = self.arr.len() as f64;
+        let ma = self.arr.iter().sum::<f64>() / n;
+        let md = self.arr.iter().map(|x| (x - ma).abs()).sum::<f64>() / n;
+        if md > 0.0 {
+            (tp - ma) / (0.015 * md)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn get_period(&self) -> usize {
+        self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Math::test_data::{CLOSE, HIGH, LOW};
+
+    #[test]
+    fn test_cci() {
+        let mut cci = CCCI::new(5);
+        assert_eq!(cci.add(HIGH[0], LOW[0], CLOSE[0]), 0.0);
+        let mut res = 0.0;
+        for i in 1..CLOSE.len() {
+            res = cci.add(HIGH[i], LOW[i], CLOSE[i]);
+        }
+        assert!((res - 13.525906).abs() < 1e-4);
+    }
+}
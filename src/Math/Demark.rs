@@ -2,7 +2,90 @@ use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::rc::Rc;
 
+use crate::Common::func_util::nearest_key;
 use crate::Common::types::SharedCell;
+use crate::Common::ChanException::{CChanException, ErrCode};
+
+// TD Sequential 参数，对应 CChanConfig 的 demark 配置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DemarkConfig {
+    pub demark_len: i32,
+    pub setup_bias: i32,
+    pub countdown_bias: i32,
+    pub max_countdown: i32,
+    pub tiaokong_st: bool,
+    pub setup_cmp2close: bool,
+    pub countdown_cmp2close: bool,
+}
+
+pub const DEMARK_CONF_KEYS: [&str; 7] = [
+    "demark_len",
+    "setup_bias",
+    "countdown_bias",
+    "max_countdown",
+    "tiaokong_st",
+    "setup_cmp2close",
+    "countdown_cmp2close",
+];
+
+impl DemarkConfig {
+    // 解析 {"demark_len": 9, "tiaokong_st": true, ...}，未给出的 key 取默认值，所有错误一起报告
+    pub fn from_value(value: &serde_json::Value) -> Result<Self, CChanException> {
+        let dict = value.as_object().ok_or_else(|| {
+            CChanException::new(
+                format!("demark config should be a dict, got {}", value),
+                ErrCode::ConfigError,
+            )
+        })?;
+        let mut conf = DemarkConfig::default();
+        let mut errors = Vec::new();
+        for (k, v) in dict {
+            let int_v = v.as_i64().filter(|x| *x > 0).map(|x| x as i32);
+            let ok = match k.as_str() {
+                "demark_len" => int_v.map(|x| conf.demark_len = x),
+                "setup_bias" => int_v.map(|x| conf.setup_bias = x),
+                "countdown_bias" => int_v.map(|x| conf.countdown_bias = x),
+                "max_countdown" => int_v.map(|x| conf.max_countdown = x),
+                "tiaokong_st" => v.as_bool().map(|x| conf.tiaokong_st = x),
+                "setup_cmp2close" => v.as_bool().map(|x| conf.setup_cmp2close = x),
+                "countdown_cmp2close" => v.as_bool().map(|x| conf.countdown_cmp2close = x),
+                _ => {
+                    errors.push(match nearest_key(k, &DEMARK_CONF_KEYS) {
+                        Some(similar) => {
+                            format!("unknown demark para = {}, did you mean {}?", k, similar)
+                        }
+                        None => format!("unknown demark para = {}", k),
+                    });
+                    continue;
+                }
+            };
+            if ok.is_none() {
+                errors.push(format!("demark para {}={} is invalid", k, v));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(CChanException::new(
+                format!("invalid demark config: {}", errors.join("; ")),
+                ErrCode::ConfigError,
+            ));
+        }
+        Ok(conf)
+    }
+}
+
+impl Default for DemarkConfig {
+    fn default() -> Self {
+        DemarkConfig {
+            demark_len: 9,
+            setup_bias: 4,
+            countdown_bias: 2,
+            max_countdown: 13,
+            tiaokong_st: true,
+            setup_cmp2close: true,
+            countdown_cmp2close: true,
+        }
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum BiDir {
@@ -53,13 +136,19 @@ pub struct DemarkIndex {
     series: SharedCell<CDemarkSetup>,
 }
 
+#[derive(Clone, Debug)]
 pub struct CDemarkIndex {
     data: Vec<DemarkIndex>,
+    // 正在 countdown 的序列的 TDST 水平，下跌序列为压力位，上涨序列为支撑位
+    tdst: Vec<(BiDir, f64)>,
 }
 
 impl CDemarkIndex {
-    fn new() -> Self {
-        CDemarkIndex { data: Vec::new() }
+    pub fn new() -> Self {
+        CDemarkIndex {
+            data: Vec::new(),
+            tdst: Vec::new(),
+        }
     }
 
     fn add(
@@ -91,12 +180,37 @@ impl CDemarkIndex {
             .collect()
     }
 
+    // dir 方向上最大的 setup 计数，没有时为0
+    pub fn max_setup_idx(&self, dir: BiDir) -> i32 {
+        self.get_setup()
+            .iter()
+            .filter(|info| info.dir == dir)
+            .map(|info| info.idx)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn max_countdown_idx(&self, dir: BiDir) -> i32 {
+        self.get_countdown()
+            .iter()
+            .filter(|info| info.dir == dir)
+            .map(|info| info.idx)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn get_tdst_peak(&self, dir: BiDir) -> Option<f64> {
+        self.tdst.iter().find(|(d, _)| *d == dir).map(|(_, v)| *v)
+    }
+
     fn update(&mut self, demark_index: &CDemarkIndex) {
         self.data.extend(demark_index.data.clone());
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct CDemarkCountdown {
+    conf: DemarkConfig,
     dir: BiDir,
     kl_list: VecDeque<CKL>,
     idx: i32,
@@ -105,8 +219,9 @@ pub struct CDemarkCountdown {
 }
 
 impl CDemarkCountdown {
-    fn new(dir: BiDir, kl_list: &[CKL], tdst_peak: f64) -> Self {
+    fn new(conf: DemarkConfig, dir: BiDir, kl_list: &[CKL], tdst_peak: f64) -> Self {
         CDemarkCountdown {
+            conf,
             dir,
             kl_list: VecDeque::from(kl_list.to_vec()),
             idx: 0,
@@ -120,10 +235,10 @@ impl CDemarkCountdown {
             return false;
         }
         self.kl_list.push_back(kl);
-        if self.kl_list.len() <= CDemarkEngine::COUNTDOWN_BIAS as usize {
+        if self.kl_list.len() <= self.conf.countdown_bias as usize {
             return false;
         }
-        if self.idx == CDemarkEngine::MAX_COUNTDOWN {
+        if self.idx == self.conf.max_countdown {
             self.finish = true;
             return false;
         }
@@ -134,11 +249,11 @@ impl CDemarkCountdown {
             return false;
         }
         let last = self.kl_list.back().unwrap();
-        let compare = self.kl_list[self.kl_list.len() - 1 - CDemarkEngine::COUNTDOWN_BIAS as usize];
+        let compare = self.kl_list[self.kl_list.len() - 1 - self.conf.countdown_bias as usize];
         if (self.dir == BiDir::Down
-            && last.close < compare.v(CDemarkEngine::COUNTDOWN_CMP2CLOSE, self.dir))
+            && last.close < compare.v(self.conf.countdown_cmp2close, self.dir))
             || (self.dir == BiDir::Up
-                && last.close > compare.v(CDemarkEngine::COUNTDOWN_CMP2CLOSE, self.dir))
+                && last.close > compare.v(self.conf.countdown_cmp2close, self.dir))
         {
             self.idx += 1;
             return true;
@@ -147,9 +262,12 @@ impl CDemarkCountdown {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct CDemarkSetup {
+    conf: DemarkConfig,
     dir: BiDir,
     kl_list: VecDeque<CKL>,
+    // setup 第一根K线的前一根，用于判断跳空
     pre_kl: CKL,
     countdown: Option<CDemarkCountdown>,
     setup_finished: bool,
@@ -159,9 +277,10 @@ pub struct CDemarkSetup {
 }
 
 impl CDemarkSetup {
-    fn new(dir: BiDir, kl_list: &[CKL], pre_kl: CKL) -> Self {
-        assert_eq!(kl_list.len(), CDemarkEngine::SETUP_BIAS as usize);
+    fn new(conf: DemarkConfig, dir: BiDir, kl_list: &[CKL], pre_kl: CKL) -> Self {
+        assert_eq!(kl_list.len(), conf.setup_bias as usize);
         CDemarkSetup {
+            conf,
             dir,
             kl_list: VecDeque::from(kl_list.to_vec()),
             pre_kl,
@@ -178,21 +297,30 @@ impl CDemarkSetup {
         if !self.setup_finished {
             self.kl_list.push_back(kl);
             let last = self.kl_list.back().unwrap();
-            let compare = self.kl_list[self.kl_list.len() - 1 - CDemarkEngine::SETUP_BIAS as usize];
+            let compare = self.kl_list[self.kl_list.len() - 1 - self.conf.setup_bias as usize];
             if (self.dir == BiDir::Down
-                && last.close < compare.v(CDemarkEngine::SETUP_CMP2CLOSE, self.dir))
+                && last.close < compare.v(self.conf.setup_cmp2close, self.dir))
                 || (self.dir == BiDir::Up
-                    && last.close > compare.v(CDemarkEngine::SETUP_CMP2CLOSE, self.dir))
+                    && last.close > compare.v(self.conf.setup_cmp2close, self.dir))
             {
                 self.add_setup();
             } else {
                 self.setup_finished = true;
             }
         }
-        if self.idx == CDemarkEngine::DEMARK_LEN && !self.setup_finished && self.countdown.is_none()
-        {
+        // setup 第9根本身也参与 countdown，下面 update 时再加入，这里不含当前K线
+        if self.idx == self.conf.demark_len && !self.setup_finished && self.countdown.is_none() {
             let tdst_peak = self.cal_tdst_peak();
-            self.countdown = Some(CDemarkCountdown::new(self.dir, &self.kl_list, tdst_peak));
+            self.countdown = Some(CDemarkCountdown::new(
+                self.conf,
+                self.dir,
+                &self
+                    .kl_list
+                    .range(..self.kl_list.len() - 1)
+                    .cloned()
+                    .collect::<Vec<_>>(),
+                tdst_peak,
+            ));
         }
         if let Some(countdown) = &mut self.countdown {
             if countdown.update(kl) {
@@ -220,27 +348,27 @@ impl CDemarkSetup {
     fn cal_tdst_peak(&mut self) -> f64 {
         assert_eq!(
             self.kl_list.len(),
-            (CDemarkEngine::SETUP_BIAS + CDemarkEngine::DEMARK_LEN) as usize
+            (self.conf.setup_bias + self.conf.demark_len) as usize
         );
         let arr: Vec<_> = self
             .kl_list
             .iter()
-            .skip(CDemarkEngine::SETUP_BIAS as usize)
-            .take(CDemarkEngine::DEMARK_LEN as usize)
+            .skip(self.conf.setup_bias as usize)
+            .take(self.conf.demark_len as usize)
             .collect();
-        assert_eq!(arr.len(), CDemarkEngine::DEMARK_LEN as usize);
+        assert_eq!(arr.len(), self.conf.demark_len as usize);
         let res = if self.dir == BiDir::Down {
             let mut res = arr
                 .iter()
                 .map(|kl| kl.high)
                 .fold(f64::NEG_INFINITY, f64::max);
-            if CDemarkEngine::TIAOKONG_ST && arr[0].high < self.pre_kl.close {
+            if self.conf.tiaokong_st && arr[0].high < self.pre_kl.close {
                 res = res.max(self.pre_kl.close);
             }
             res
         } else {
             let mut res = arr.iter().map(|kl| kl.low).fold(f64::INFINITY, f64::min);
-            if CDemarkEngine::TIAOKONG_ST && arr[0].low > self.pre_kl.close {
+            if self.conf.tiaokong_st && arr[0].low > self.pre_kl.close {
                 res = res.min(self.pre_kl.close);
             }
             res
@@ -251,21 +379,15 @@ impl CDemarkSetup {
 }
 
 pub struct CDemarkEngine {
+    conf: DemarkConfig,
     kl_lst: Vec<CKL>,
     series: Vec<SharedCell<CDemarkSetup>>,
 }
 
 impl CDemarkEngine {
-    const DEMARK_LEN: i32 = 9;
-    const SETUP_BIAS: i32 = 4;
-    const COUNTDOWN_BIAS: i32 = 2;
-    const MAX_COUNTDOWN: i32 = 13;
-    const TIAOKONG_ST: bool = true;
-    const SETUP_CMP2CLOSE: bool = true;
-    const COUNTDOWN_CMP2CLOSE: bool = true;
-
-    pub fn new() -> Self {
+    pub fn new(conf: DemarkConfig) -> Self {
         CDemarkEngine {
+            conf,
             kl_lst: Vec::new(),
             series: Vec::new(),
         }
@@ -273,12 +395,12 @@ impl CDemarkEngine {
 
     pub fn update(&mut self, idx: i32, close: f64, high: f64, low: f64) -> CDemarkIndex {
         self.kl_lst.push(CKL::new(idx, close, high, low));
-        if self.kl_lst.len() <= (Self::SETUP_BIAS + 1) as usize {
+        if self.kl_lst.len() <= (self.conf.setup_bias + 1) as usize {
             return CDemarkIndex::new();
         }
 
         let last = self.kl_lst.last().unwrap();
-        let compare = self.kl_lst[self.kl_lst.len() - 1 - Self::SETUP_BIAS as usize];
+        let compare = self.kl_lst[self.kl_lst.len() - 1 - self.conf.setup_bias as usize];
         if last.close < compare.close {
             if !self
                 .series
@@ -286,10 +408,11 @@ impl CDemarkEngine {
                 .any(|s| s.borrow().dir == BiDir::Down && !s.borrow().setup_finished)
             {
                 let new_series = Rc::new(RefCell::new(CDemarkSetup::new(
+                    self.conf,
                     BiDir::Down,
-                    &self.kl_lst
-                        [self.kl_lst.len() - Self::SETUP_BIAS as usize - 1..self.kl_lst.len() - 1],
-                    self.kl_lst[self.kl_lst.len() - Self::SETUP_BIAS as usize - 2],
+                    &self.kl_lst[self.kl_lst.len() - self.conf.setup_bias as usize - 1
+                        ..self.kl_lst.len() - 1],
+                    self.kl_lst[self.kl_lst.len() - 2],
                 )));
                 self.series.push(new_series);
             }
@@ -306,10 +429,11 @@ impl CDemarkEngine {
                 .any(|s| s.borrow().dir == BiDir::Up && !s.borrow().setup_finished)
             {
                 let new_series = Rc::new(RefCell::new(CDemarkSetup::new(
+                    self.conf,
                     BiDir::Up,
-                    &self.kl_lst
-                        [self.kl_lst.len() - Self::SETUP_BIAS as usize - 1..self.kl_lst.len() - 1],
-                    self.kl_lst[self.kl_lst.len() - Self::SETUP_BIAS as usize - 2],
+                    &self.kl_lst[self.kl_lst.len() - self.conf.setup_bias as usize - 1
+                        ..self.kl_lst.len() - 1],
+                    self.kl_lst[self.kl_lst.len() - 2],
                 )));
                 self.series.push(new_series);
             }
@@ -332,7 +456,13 @@ impl CDemarkEngine {
     fn cal_result(&self) -> CDemarkIndex {
         let mut demark_index = CDemarkIndex::new();
         for series in &self.series {
-            demark_index.update(&series.borrow().last_demark_index);
+            let series = series.borrow();
+            demark_index.update(&series.last_demark_index);
+            if let (Some(countdown), Some(tdst_peak)) = (&series.countdown, series.tdst_peak) {
+                if !countdown.finish {
+                    demark_index.tdst.push((series.dir, tdst_peak));
+                }
+            }
         }
         demark_index
     }
@@ -351,7 +481,7 @@ impl CDemarkEngine {
             let mut s = series.borrow_mut();
             let demark_idx = s.update(*self.kl_lst.last().unwrap());
             for setup_idx in demark_idx.get_setup() {
-                if setup_idx.idx == Self::DEMARK_LEN {
+                if setup_idx.idx == self.conf.demark_len {
                     assert!(finished_setup.is_none());
                     finished_setup = Some(Rc::clone(series));
                 }
@@ -362,3 +492,177 @@ impl CDemarkEngine {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 连续下跌的K线，high/low 为收盘价 ±0.5
+    fn run(conf: DemarkConfig, closes: &[f64]) -> Vec<CDemarkIndex> {
+        let mut engine = CDemarkEngine::new(conf);
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| engine.update(i as i32, c, c + 0.5, c - 0.5))
+            .collect()
+    }
+
+    fn falling(n: usize) -> Vec<f64> {
+        (0..n).map(|i| 100.0 - i as f64).collect()
+    }
+
+    #[test]
+    fn test_buy_setup_and_countdown() {
+        let res = run(DemarkConfig::default(), &falling(30));
+        // 第6根开始与4根前比较，连续9根完成 buy setup
+        assert_eq!(res[4].max_setup_idx(BiDir::Down), 0);
+        assert_eq!(res[5].max_setup_idx(BiDir::Down), 1);
+        assert_eq!(res[13].max_setup_idx(BiDir::Down), 9);
+        assert_eq!(res[14].max_setup_idx(BiDir::Down), 10);
+        assert_eq!(res[12].max_countdown_idx(BiDir::Down), 0);
+        assert_eq!(res[13].max_countdown_idx(BiDir::Down), 1);
+        assert_eq!(res[25].max_countdown_idx(BiDir::Down), 13);
+        assert_eq!(res[26].max_countdown_idx(BiDir::Down), 0);
+        assert_eq!(res[13].max_setup_idx(BiDir::Up), 0);
+        // setup 第一根的最高价低于前一根收盘（跳空），TDST 取前收盘
+        assert_eq!(res[13].get_tdst_peak(BiDir::Down), Some(96.0));
+        assert_eq!(res[12].get_tdst_peak(BiDir::Down), None);
+    }
+
+    // TD Sequential 的原始规则，取自 Jason Perl, DeMark Indicators (Bloomberg Press, 2008) 第1章：
+    //   buy setup：价格翻转（前一根收盘高于其4根前、当前收盘低于4根前）后，连续9根收盘低于4根前
+    //   TDST：buy setup 第1~9根的最高真实高点（与前一根收盘取大）
+    //   buy countdown：从 setup 第9根起，收盘 <= 2根前最低价计1，不要求连续，
+    //   第13根还要求最低价 <= countdown 第8根的收盘
+    // 返回 (setup 第9根, TDST, countdown 13根的位置)，只用于核对本引擎
+    fn td_sequential_reference(
+        high: &[f64],
+        low: &[f64],
+        close: &[f64],
+    ) -> (usize, f64, Vec<usize>) {
+        let flip = (5..close.len())
+            .find(|&i| close[i - 1] > close[i - 5] && close[i] < close[i - 4])
+            .unwrap();
+        assert!((flip..flip + 9).all(|i| close[i] < close[i - 4]));
+        let setup9 = flip + 8;
+        let tdst = (flip..=setup9)
+            .map(|i| high[i].max(close[i - 1]))
+            .fold(f64::NEG_INFINITY, f64::max);
+        let mut countdown = Vec::new();
+        for i in setup9..close.len() {
+            if countdown.len() == 13 {
+                break;
+            }
+            if close[i] <= low[i - 2] && (countdown.len() < 12 || low[i] <= close[countdown[7]]) {
+                countdown.push(i);
+            }
+        }
+        (setup9, tdst, countdown)
+    }
+
+    #[test]
+    fn test_td_sequential_example() {
+        // 先上涨，第6根跳空低开完成价格翻转，之后每根跌0.1走完 buy setup，
+        // 再每8根反弹一次打断新的 setup，countdown 在反弹后的第一根不计数
+        let close = [
+            10.0, 10.2, 10.4, 10.6, 10.8, 11.0, 10.3, 10.2, 10.1, 10.0, 9.9, 9.8, 9.7, 9.6, 9.5,
+            9.85, 9.75, 9.65, 9.55, 9.45, 9.35, 9.25, 9.15, 9.5, 9.4, 9.3, 9.2, 9.1, 9.0, 8.9, 8.8,
+            9.15, 9.05, 8.95, 8.85,
+        ];
+        let high: Vec<f64> = close.iter().map(|c| c + 0.15).collect();
+        let low: Vec<f64> = close.iter().map(|c| c - 0.15).collect();
+
+        // 按原始规则逐根推出的结果：setup 第9根为第14根，TDST 为第5根收盘 11.0
+        //（第6根最高 10.45 低于前收），countdown 13 落在第30根
+        let (setup9, tdst, countdown_bars) = td_sequential_reference(&high, &low, &close);
+        assert_eq!(setup9, 14);
+        assert!((tdst - 11.0).abs() < 1e-9);
+        assert_eq!(
+            countdown_bars,
+            vec![14, 17, 18, 19, 20, 21, 22, 25, 26, 27, 28, 29, 30]
+        );
+
+        // 原始规则的 countdown 与2根前的最低价比较
+        let conf = DemarkConfig {
+            countdown_cmp2close: false,
+            ..DemarkConfig::default()
+        };
+        let mut engine = CDemarkEngine::new(conf);
+        let res: Vec<CDemarkIndex> = (0..close.len())
+            .map(|i| engine.update(i as i32, close[i], high[i], low[i]))
+            .collect();
+
+        assert_eq!(res[setup9 - 1].max_setup_idx(BiDir::Down), 8);
+        assert_eq!(res[setup9].max_setup_idx(BiDir::Down), 9);
+        let countdown: Vec<i32> = res
+            .iter()
+            .map(|r| r.max_countdown_idx(BiDir::Down))
+            .collect();
+        for (cnt, &i) in countdown_bars.iter().enumerate() {
+            assert_eq!(countdown[i], cnt as i32 + 1, "countdown at {}", i);
+        }
+        assert!((setup9..=30)
+            .filter(|i| !countdown_bars.contains(i))
+            .all(|i| countdown[i] == 0));
+        assert!((setup9..=30).all(|i| res[i].get_tdst_peak(BiDir::Down) == Some(tdst)));
+        // countdown 13 之后序列结束，不再输出 TDST
+        assert_eq!(res[31].get_tdst_peak(BiDir::Down), None);
+    }
+
+    #[test]
+    fn test_tdst_without_tiaokong() {
+        let conf = DemarkConfig {
+            tiaokong_st: false,
+            ..DemarkConfig::default()
+        };
+        let res = run(conf, &falling(20));
+        assert_eq!(res[13].get_tdst_peak(BiDir::Down), Some(95.5));
+    }
+
+    #[test]
+    fn test_setup_interrupted() {
+        let mut closes: Vec<f64> = (0..10).map(|i| 10.0 + i as f64).collect();
+        closes.extend([15.0, 15.0, 15.0, 15.0, 15.0]);
+        let res = run(DemarkConfig::default(), &closes);
+        assert_eq!(res[9].max_setup_idx(BiDir::Up), 5);
+        // 第11根起不再高于4根前，sell setup 在第6根被打断，不进入 countdown
+        assert!(res[10..].iter().all(|r| r.max_setup_idx(BiDir::Up) == 0));
+        assert!(res.iter().all(|r| r.max_countdown_idx(BiDir::Up) == 0));
+    }
+
+    #[test]
+    fn test_demark_config_from_value() {
+        let conf = DemarkConfig::from_value(&serde_json::json!({
+            "demark_len": 7,
+            "tiaokong_st": false,
+        }))
+        .unwrap();
+        assert_eq!(conf.demark_len, 7);
+        assert!(!conf.tiaokong_st);
+        assert_eq!(conf.max_countdown, 13);
+
+        let err = DemarkConfig::from_value(&serde_json::json!({
+            "demark_lne": 7,
+            "setup_bias": -1,
+            "countdown_cmp2close": 1,
+        }))
+        .unwrap_err();
+        assert!(err.msg.contains("did you mean demark_len"));
+        assert!(err.msg.contains("setup_bias=-1"));
+        assert!(err.msg.contains("countdown_cmp2close=1"));
+    }
+
+    #[test]
+    fn test_custom_config() {
+        let conf = DemarkConfig {
+            demark_len: 5,
+            max_countdown: 3,
+            ..DemarkConfig::default()
+        };
+        let res = run(conf, &falling(20));
+        assert_eq!(res[9].max_setup_idx(BiDir::Down), 5);
+        assert_eq!(res[9].max_countdown_idx(BiDir::Down), 1);
+        assert_eq!(res[11].max_countdown_idx(BiDir::Down), 3);
+        assert_eq!(res[12].max_countdown_idx(BiDir::Down), 0);
+    }
+}
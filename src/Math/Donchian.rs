@@ -0,0 +1,64 @@
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DonchianItem {
+    pub up: f64,
+    pub down: f64,
+    pub mid: f64,
+}
+
+// 唐奇安通道，最近 period 根K线的最高价/最低价
+pub struct CDonchian {
+    period: usize,
+    arr: VecDeque<(f64, f64)>,
+}
+
+impl CDonchian {
+    pub fn new(period: usize) -> Self {
+        CDonchian {
+            period,
+            arr: VecDeque::with_capacity(period),
+        }
+    }
+
+    pub fn add(&mut self, high: f64, low: f64) -> DonchianItem {
+        self.arr.push_back((high, low));
+        if self.arr.len() > self.period {
+            self.arr.pop_front();
+        }
+        let up = self
+            .arr
+            .iter()
+            .map(|x| x.0)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let down = self.arr.iter().map(|x| x.1).fold(f64::INFINITY, f64::min);
+        DonchianItem {
+            up,
+            down,
+            mid: (up + down) / 2.0,
+        }
+    }
+
+    pub fn get_period(&self) -> usize {
+        self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Math::test_data::{HIGH, LOW};
+
+    #[test]
+    fn test_donchian() {
+        let mut donchian = CDonchian::new(5);
+        let mut res = None;
+        for i in 0..HIGH.len() {
+            res = Some(donchian.add(HIGH[i], LOW[i]));
+        }
+        let res = res.unwrap();
+        assert_eq!(res.up, 50.65);
+        assert_eq!(res.down, 48.98);
+        assert!((res.mid - 49.815).abs() < 1e-9);
+    }
+}
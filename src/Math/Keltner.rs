@@ -0,0 +1,63 @@
+use crate::Math::ATR::CATR;
+use crate::Math::MA::CEMA;
+
+#[derive(Clone, Copy, Debug)]
+pub struct KeltnerItem {
+    pub up: f64,
+    pub mid: f64,
+    pub down: f64,
+}
+
+// 肯特纳通道，中轨为收盘价 ema，上下轨为中轨 ± mult * atr
+pub struct CKeltner {
+    ema: CEMA,
+    atr: CATR,
+    mult: f64,
+}
+
+impl CKeltner {
+    pub fn new(period: usize, atr_period: usize, mult: f64) -> Self {
+        CKeltner {
+            ema: CEMA::new(period),
+            atr: CATR::new(atr_period),
+            mult,
+        }
+    }
+
+    pub fn add(&mut self, high: f64, low: f64, close: f64) -> KeltnerItem {
+        let mid = self.ema.add(close);
+        let atr = self.atr.add(high, low, close);
+        KeltnerItem {
+            up: mid + self.mult * atr,
+            mid,
+            down: mid - self.mult * atr,
+        }
+    }
+
+    pub fn get_period(&self) -> usize {
+        self.ema.get_period()
+    }
+
+    pub fn get_atr_period(&self) -> usize {
+        self.atr.get_period()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Math::test_data::{CLOSE, HIGH, LOW};
+
+    #[test]
+    fn test_keltner() {
+        let mut keltner = CKeltner::new(5, 3, 2.0);
+        let mut res = None;
+        for i in 0..CLOSE.len() {
+            res = Some(keltner.add(HIGH[i], LOW[i], CLOSE[i]));
+        }
+        let res = res.unwrap();
+        assert!((res.up - 51.520218).abs() < 1e-5);
+        assert!((res.mid - 49.905388).abs() < 1e-5);
+        assert!((res.down - 48.290558).abs() < 1e-5);
+    }
+}
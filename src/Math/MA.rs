@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+
+// 指数均线，首根K线以收盘价作为初值，和 CMACD 中的 ema 算法一致
+// 与 TA-Lib 的 EMA 不同：TA-Lib 以前 n 根的 SMA 作为初值，前 n-1 根输出 NaN，
+// 两者的差按 (n-1)/(n+1) 每根衰减，前几个周期内数值不同
+pub struct CEMA {
+    period: f64,
+    ema: Option<f64>,
+}
+
+impl CEMA {
+    pub fn new(period: usize) -> Self {
+        CEMA {
+            period: period as f64,
+            ema: None,
+        }
+    }
+
+    pub fn add(&mut self, value: f64) -> f64 {
+        let ema = match self.ema {
+            Some(last) => (2.0 * value + (self.period - 1.0) * last) / (self.period + 1.0),
+            None => value,
+        };
+        self.ema = Some(ema);
+        ema
+    }
+
+    pub fn get_period(&self) -> usize {
+        self.period as usize
+    }
+}
+
+// 简单均线，不足 period 根时取已有K线的均值
+pub struct CSMA {
+    period: usize,
+    arr: VecDeque<f64>,
+    sum: f64,
+}
+
+impl CSMA {
+    pub fn new(period: usize) -> Self {
+        CSMA {
+            period,
+            arr: VecDeque::with_capacity(period),
+            sum: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, value: f64) -> f64 {
+        self.arr.push_back(value);
+        self.sum += value;
+        if self.arr.len() > self.period {
+            self.sum -= self.arr.pop_front().unwrap();
+        }
+        self.sum / self.arr.len() as f64
+    }
+
+    pub fn get_period(&self) -> usize {
+        self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Math::test_data::CLOSE;
+
+    #[test]
+    fn test_ema() {
+        let mut ema = CEMA::new(5);
+        let res: Vec<f64> = CLOSE.iter().map(|&c| ema.add(c)).collect();
+        assert_eq!(res[0], CLOSE[0]);
+        assert!((res.last().unwrap() - 49.905388).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_sma() {
+        let mut sma = CSMA::new(5);
+        assert_eq!(sma.add(1.0), 1.0);
+        assert_eq!(sma.add(2.0), 1.5);
+        let mut sma = CSMA::new(5);
+        let res: Vec<f64> = CLOSE.iter().map(|&c| sma.add(c)).collect();
+        assert!((res.last().unwrap() - 49.974).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::Common::types::SharedCell;
+use crate::Common::CEnum::{DataField, MacdAlgo};
+use crate::Common::ChanException::{CChanException, ErrCode};
+use crate::KLine::KLine_Unit::CKLineUnit;
+
+// 笔/线段力度指标的计算，输入为从起点到终点逐根K线的序列
+// CBi/CSeg 的 cal_macd_metric 都通过 line_macd_metric 计算，面积类指标带 1e-7 防止除0
+
+// 全部 macd 柱面积
+pub fn macd_full_area(macd: &[f64]) -> f64 {
+    1e-7 + macd.iter().map(|m| m.abs()).sum::<f64>()
+}
+
+// 与方向一致的 macd 柱的峰值，向下看绿柱，向上看红柱
+pub fn macd_peak(macd: &[f64], is_down: bool) -> f64 {
+    macd.iter()
+        .filter(|m| (is_down && **m < 0.0) || (!is_down && **m > 0.0))
+        .fold(1e-7, |peak, m| peak.max(m.abs()))
+}
+
+// 从起点开始与起点同号的连续 macd 柱面积
+pub fn macd_half_obverse(macd: &[f64]) -> f64 {
+    half_area(macd.iter())
+}
+
+// 从终点往回与终点同号的连续 macd 柱面积
+pub fn macd_half_reverse(macd: &[f64]) -> f64 {
+    half_area(macd.iter().rev())
+}
+
+fn half_area<'a>(mut iter: impl Iterator<Item = &'a f64>) -> f64 {
+    let first = match iter.next() {
+        Some(first) => *first,
+        None => return 1e-7,
+    };
+    let mut s = 1e-7 + first.abs();
+    for m in iter {
+        if m * first > 0.0 {
+            s += m.abs();
+        } else {
+            break;
+        }
+    }
+    s
+}
+
+pub fn macd_diff(macd: &[f64]) -> f64 {
+    let max = macd.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let min = macd.iter().cloned().fold(f64::INFINITY, f64::min);
+    max - min
+}
+
+// 成交量/成交额/换手率的总和或均值，任何一根缺数据时返回0
+pub fn trade_metric(values: &[Option<f64>], cal_avg: bool) -> f64 {
+    if values.iter().any(|v| v.is_none()) {
+        return 0.0;
+    }
+    let s: f64 = values.iter().map(|v| v.unwrap()).sum();
+    if cal_avg && !values.is_empty() {
+        s / values.len() as f64
+    } else {
+        s
+    }
+}
+
+// 向下时取 rsi 最低点的倒数（越低力度越大），向上时取 rsi 最高点
+pub fn rsi_metric(rsi: &[f64], is_down: bool) -> f64 {
+    if is_down {
+        10000.0 / (rsi.iter().cloned().fold(f64::INFINITY, f64::min) + 1e-7)
+    } else {
+        rsi.iter().cloned().fold(f64::NEG_INFINITY, f64::max)
+    }
+}
+
+// 向上为 (终点高-起点低)/终点高，向下为 (起点高-终点低)/起点高，再除以K线数
+pub fn slope_metric(begin_klu: &CKLineUnit, end_klu: &CKLineUnit, is_up: bool) -> f64 {
+    let klu_cnt = (end_klu.idx - begin_klu.idx + 1) as f64;
+    if is_up {
+        (end_klu.high - begin_klu.low) / end_klu.high / klu_cnt
+    } else {
+        (begin_klu.high - end_klu.low) / begin_klu.high / klu_cnt
+    }
+}
+
+pub fn amp_metric(begin_klu: &CKLineUnit, end_klu: &CKLineUnit, is_down: bool) -> f64 {
+    if is_down {
+        (begin_klu.high - end_klu.low) / begin_klu.high
+    } else {
+        (end_klu.high - begin_klu.low) / begin_klu.low
+    }
+}
+
+// 笔/线段共用的力度指标计算
+// klu_lst 为参与计算的全部K线：笔取起止合并K线内的所有K线，线段取起止K线之间
+// 半面积只从起点K线（或终点K线）开始，不受 klu_lst 首尾多出的K线影响
+pub fn line_macd_metric(
+    macd_algo: MacdAlgo,
+    is_reverse: bool,
+    is_down: bool,
+    klu_lst: &[SharedCell<CKLineUnit>],
+    begin_klu: &CKLineUnit,
+    end_klu: &CKLineUnit,
+) -> Result<f64, CChanException> {
+    let macd_lst = |min_idx: i32, max_idx: i32| -> Vec<f64> {
+        klu_lst
+            .iter()
+            .map(|klu| klu.borrow())
+            .filter(|klu| klu.idx >= min_idx && klu.idx <= max_idx)
+            .map(|klu| klu.macd.as_ref().map_or(0.0, |m| m.macd))
+            .collect()
+    };
+    let half_obverse = || macd_half_obverse(&macd_lst(begin_klu.idx, i32::MAX));
+    let half_reverse = || macd_half_reverse(&macd_lst(i32::MIN, end_klu.idx));
+    let trade = |metric: &str, cal_avg: bool| {
+        let values: Vec<Option<f64>> = klu_lst
+            .iter()
+            .map(|klu| {
+                klu.borrow()
+                    .trade_info
+                    .metric
+                    .get(metric)
+                    .copied()
+                    .flatten()
+            })
+            .collect();
+        trade_metric(&values, cal_avg)
+    };
+    match macd_algo {
+        MacdAlgo::Area => Ok(if is_reverse {
+            half_reverse()
+        } else {
+            half_obverse()
+        }),
+        MacdAlgo::HalfObverse => Ok(half_obverse()),
+        MacdAlgo::HalfReverse => Ok(half_reverse()),
+        MacdAlgo::Peak => Ok(macd_peak(&macd_lst(i32::MIN, i32::MAX), is_down)),
+        MacdAlgo::FullArea => Ok(macd_full_area(&macd_lst(i32::MIN, i32::MAX))),
+        MacdAlgo::Diff => Ok(macd_diff(&macd_lst(i32::MIN, i32::MAX))),
+        MacdAlgo::Slope => Ok(slope_metric(begin_klu, end_klu, !is_down)),
+        MacdAlgo::Amp => Ok(amp_metric(begin_klu, end_klu, is_down)),
+        MacdAlgo::Amount => Ok(trade(DataField::FIELD_TURNOVER, false)),
+        MacdAlgo::Volumn => Ok(trade(DataField::FIELD_VOLUME, false)),
+        MacdAlgo::AmountAvg => Ok(trade(DataField::FIELD_TURNOVER, true)),
+        MacdAlgo::VolumnAvg => Ok(trade(DataField::FIELD_VOLUME, true)),
+        MacdAlgo::TurnrateAvg => Ok(trade(DataField::FIELD_TURNRATE, true)),
+        MacdAlgo::Rsi => {
+            let rsi_lst: Vec<f64> = klu_lst.iter().filter_map(|klu| klu.borrow().rsi).collect();
+            if rsi_lst.is_empty() {
+                return Err(CChanException::new(
+                    "rsi not calculated, set cal_rsi=true".to_string(),
+                    ErrCode::ParaError,
+                ));
+            }
+            Ok(rsi_metric(&rsi_lst, is_down))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Common::CTime::CTime;
+    use crate::Common::TradeInfo::CTradeInfo;
+    use crate::Math::Demark::CDemarkIndex;
+    use crate::Math::MACD::CMACDItem;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    const MACD: [f64; 6] = [1.0, 2.0, -1.0, -3.0, 0.5, 1.5];
+
+    #[test]
+    fn test_area() {
+        assert!((macd_full_area(&MACD) - 9.0).abs() < 1e-6);
+        assert!((macd_half_obverse(&MACD) - 3.0).abs() < 1e-6);
+        assert!((macd_half_reverse(&MACD) - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_peak_diff() {
+        assert!((macd_peak(&MACD, true) - 3.0).abs() < 1e-6);
+        assert!((macd_peak(&MACD, false) - 2.0).abs() < 1e-6);
+        assert!((macd_diff(&MACD) - 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_trade_metric() {
+        let values = [Some(1.0), Some(2.0), Some(6.0)];
+        assert!((trade_metric(&values, false) - 9.0).abs() < 1e-9);
+        assert!((trade_metric(&values, true) - 3.0).abs() < 1e-9);
+        assert_eq!(trade_metric(&[Some(1.0), None], true), 0.0);
+    }
+
+    #[test]
+    fn test_rsi_metric() {
+        let rsi = [40.0, 20.0, 25.0];
+        assert!((rsi_metric(&rsi, true) - 500.0).abs() < 1e-3);
+        assert!((rsi_metric(&rsi, false) - 40.0).abs() < 1e-9);
+    }
+
+    fn klu(idx: i32, high: f64, low: f64, macd: f64, volume: f64) -> SharedCell<CKLineUnit> {
+        Rc::new(RefCell::new(CKLineUnit {
+            kl_type: None,
+            time: CTime::new(2024, 1, 2 + idx as u32, 0, 0, 0, false),
+            close: (high + low) / 2.0,
+            open: (high + low) / 2.0,
+            high,
+            low,
+            trade_info: CTradeInfo::new(&HashMap::from([(
+                DataField::FIELD_VOLUME.to_string(),
+                volume,
+            )])),
+            demark: CDemarkIndex::new(),
+            sub_kl_list: Vec::new(),
+            sup_kl: None,
+            klc: None,
+            trend: HashMap::new(),
+            limit_flag: 0,
+            pre: None,
+            next: None,
+            idx,
+            macd: Some(CMACDItem::new(0.0, 0.0, macd / 2.0, 0.0)),
+            boll: None,
+            rsi: None,
+            kdj: None,
+            metric: HashMap::new(),
+        }))
+    }
+
+    // 向下笔：idx0 与起点K线同在第一根合并K线里，idx5 与终点K线同在最后一根合并K线里
+    fn down_bi_klu_lst() -> Vec<SharedCell<CKLineUnit>> {
+        vec![
+            klu(0, 11.0, 10.5, 0.5, 100.0),
+            klu(1, 12.0, 11.0, -1.0, 200.0),
+            klu(2, 11.5, 10.0, 1.0, 300.0),
+            klu(3, 10.5, 9.0, -0.5, 400.0),
+            klu(4, 9.5, 8.0, -3.0, 500.0),
+            klu(5, 9.0, 8.2, -0.5, 600.0),
+        ]
+    }
+
+    fn try_metric(
+        klu_lst: &[SharedCell<CKLineUnit>],
+        macd_algo: MacdAlgo,
+        is_reverse: bool,
+    ) -> Result<f64, CChanException> {
+        let begin_klu = klu_lst.iter().find(|klu| klu.borrow().idx == 1).unwrap();
+        let end_klu = klu_lst.iter().find(|klu| klu.borrow().idx == 4).unwrap();
+        line_macd_metric(
+            macd_algo,
+            is_reverse,
+            true,
+            klu_lst,
+            &begin_klu.borrow(),
+            &end_klu.borrow(),
+        )
+    }
+
+    fn metric(klu_lst: &[SharedCell<CKLineUnit>], macd_algo: MacdAlgo, is_reverse: bool) -> f64 {
+        try_metric(klu_lst, macd_algo, is_reverse).unwrap()
+    }
+
+    fn assert_metric(v: f64, expect: f64) {
+        assert!((v - expect).abs() < 1e-5, "{} expect {}", v, expect);
+    }
+
+    #[test]
+    fn test_bi_macd_metric() {
+        let klu_lst = down_bi_klu_lst();
+        // 半面积从起点/终点K线开始，遇到反号柱停止
+        assert_metric(metric(&klu_lst, MacdAlgo::Area, false), 1.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Area, true), 3.5);
+        assert_metric(metric(&klu_lst, MacdAlgo::HalfObverse, true), 1.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::HalfReverse, false), 3.5);
+        // 其余指标按合并K线内的全部K线计算
+        assert_metric(metric(&klu_lst, MacdAlgo::FullArea, false), 6.5);
+        assert_metric(metric(&klu_lst, MacdAlgo::Peak, false), 3.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Diff, false), 4.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Volumn, false), 2100.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::VolumnAvg, false), 350.0);
+        // 没有换手率数据
+        assert_metric(metric(&klu_lst, MacdAlgo::TurnrateAvg, false), 0.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Slope, false), 4.0 / 12.0 / 4.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Amp, false), 4.0 / 12.0);
+    }
+
+    #[test]
+    fn test_seg_macd_metric() {
+        // 线段只取起止K线之间
+        let klu_lst: Vec<_> = down_bi_klu_lst().into_iter().skip(1).take(4).collect();
+        assert_metric(metric(&klu_lst, MacdAlgo::Area, false), 1.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Area, true), 3.5);
+        assert_metric(metric(&klu_lst, MacdAlgo::FullArea, false), 5.5);
+        assert_metric(metric(&klu_lst, MacdAlgo::Peak, false), 3.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Diff, false), 4.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Volumn, false), 1400.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::AmountAvg, false), 0.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Slope, false), 4.0 / 12.0 / 4.0);
+
+        // 没开 rsi 时报错
+        assert!(try_metric(&klu_lst, MacdAlgo::Rsi, false).is_err());
+        klu_lst[1].borrow_mut().rsi = Some(25.0);
+        klu_lst[2].borrow_mut().rsi = Some(20.0);
+        assert_metric(metric(&klu_lst, MacdAlgo::Rsi, false), 500.0);
+    }
+}
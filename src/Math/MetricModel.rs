@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+
+use crate::Common::CEnum::{DataField, TrendType};
+use crate::KLine::KLine_Unit::CKLineUnit;
+use crate::Math::Demark::{BiDir as DemarkDir, CDemarkEngine, CDemarkIndex};
+use crate::Math::Donchian::CDonchian;
+use crate::Math::Keltner::CKeltner;
+use crate::Math::RollingStats::{CRollingStats, RollingStatItem};
+use crate::Math::TrendModel::CTrendModel;
+use crate::Math::ADX::CADX;
+use crate::Math::ATR::CATR;
+use crate::Math::BOLL::{BOLLMetric, BollModel};
+use crate::Math::CCI::CCCI;
+use crate::Math::KDJ::{KDJItem, KDJ};
+use crate::Math::MA::{CEMA, CSMA};
+use crate::Math::MACD::{CMACDItem, CMACD};
+use crate::Math::OBV::COBV;
+use crate::Math::RSI::RSI;
+use crate::Math::VWAP::CVWAP;
+
+// 指标在单根K线上的计算结果
+// 内置指标使用各自的类型，自定义指标一般返回 Value 或 Dict
+#[derive(Clone, Debug)]
+pub enum MetricValue {
+    Macd(CMACDItem),
+    Boll(BOLLMetric),
+    Kdj(KDJItem),
+    Demark(CDemarkIndex),
+    Trend(TrendType, usize, f64),
+    Rolling(usize, RollingStatItem),
+    Rsi(f64),
+    Value(f64),
+    Dict(HashMap<String, f64>),
+    Empty,
+}
+
+impl MetricValue {
+    // 展开为 {name}_{key} -> value，用于特征和导出
+    pub fn to_dict(&self, name: &str) -> HashMap<String, f64> {
+        match self {
+            MetricValue::Macd(macd) => HashMap::from([
+                (format!("{}_dif", name), macd.dif),
+                (format!("{}_dea", name), macd.dea),
+                (format!("{}_macd", name), macd.macd),
+            ]),
+            MetricValue::Boll(boll) => HashMap::from([
+                (format!("{}_up", name), boll.up),
+                (format!("{}_mid", name), boll.mid),
+                (format!("{}_down", name), boll.down),
+            ]),
+            MetricValue::Kdj(kdj) => HashMap::from([
+                (format!("{}_k", name), kdj.k),
+                (format!("{}_d", name), kdj.d),
+                (format!("{}_j", name), kdj.j),
+            ]),
+            MetricValue::Rolling(_, item) => HashMap::from([
+                (format!("{}_mean", name), item.mean),
+                (format!("{}_max", name), item.max),
+                (format!("{}_min", name), item.min),
+                (format!("{}_std", name), item.std),
+            ]),
+            MetricValue::Trend(_, _, v) | MetricValue::Rsi(v) | MetricValue::Value(v) => {
+                HashMap::from([(name.to_string(), *v)])
+            }
+            MetricValue::Dict(dict) => dict
+                .iter()
+                .map(|(k, v)| (format!("{}_{}", name, k), *v))
+                .collect(),
+            MetricValue::Demark(demark) => {
+                let mut dict = HashMap::new();
+                for (dir, dir_name) in [(DemarkDir::Up, "up"), (DemarkDir::Down, "down")] {
+                    dict.insert(
+                        format!("{}_setup_{}", name, dir_name),
+                        demark.max_setup_idx(dir) as f64,
+                    );
+                    dict.insert(
+                        format!("{}_countdown_{}", name, dir_name),
+                        demark.max_countdown_idx(dir) as f64,
+                    );
+                    if let Some(tdst) = demark.get_tdst_peak(dir) {
+                        dict.insert(format!("{}_tdst_{}", name, dir_name), tdst);
+                    }
+                }
+                dict
+            }
+            MetricValue::Empty => HashMap::new(),
+        }
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        !matches!(
+            self,
+            MetricValue::Value(_) | MetricValue::Dict(_) | MetricValue::Empty
+        )
+    }
+}
+
+// 逐K线更新的指标，name 作为 CKLineUnit::metric 的 key，需唯一
+// 带周期的指标把周期写进 name（如 atr14），不同周期可以同时计算
+// 自定义指标通过 CChanConfig::add_metric_model 注册
+pub trait MetricModel {
+    fn name(&self) -> String;
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue;
+}
+
+impl MetricModel for CMACD {
+    fn name(&self) -> String {
+        "macd".to_string()
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Macd(self.add(klu.close))
+    }
+}
+
+impl MetricModel for CTrendModel {
+    fn name(&self) -> String {
+        format!(
+            "{}{}",
+            self.get_type().to_string().to_lowercase(),
+            self.get_t()
+        )
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Trend(self.get_type(), self.get_t(), self.add(klu.close))
+    }
+}
+
+// 同一窗口的均值/最大/最小/标准差一起计算
+impl MetricModel for CRollingStats {
+    fn name(&self) -> String {
+        format!("rolling{}", self.get_t())
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Rolling(self.get_t(), self.add(klu.close))
+    }
+}
+
+impl MetricModel for BollModel {
+    fn name(&self) -> String {
+        "boll".to_string()
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Boll(self.add(klu.close))
+    }
+}
+
+impl MetricModel for CDemarkEngine {
+    fn name(&self) -> String {
+        "demark".to_string()
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Demark(CDemarkEngine::update(
+            self, klu.idx, klu.close, klu.high, klu.low,
+        ))
+    }
+}
+
+impl MetricModel for RSI {
+    fn name(&self) -> String {
+        "rsi".to_string()
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Rsi(self.add(klu.close))
+    }
+}
+
+impl MetricModel for KDJ {
+    fn name(&self) -> String {
+        "kdj".to_string()
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Kdj(self.add(klu.high, klu.low, klu.close))
+    }
+}
+
+impl MetricModel for CEMA {
+    fn name(&self) -> String {
+        format!("ema{}", self.get_period())
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Value(self.add(klu.close))
+    }
+}
+
+impl MetricModel for CSMA {
+    fn name(&self) -> String {
+        format!("sma{}", self.get_period())
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Value(self.add(klu.close))
+    }
+}
+
+impl MetricModel for CATR {
+    fn name(&self) -> String {
+        format!("atr{}", self.get_period())
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Value(self.add(klu.high, klu.low, klu.close))
+    }
+}
+
+impl MetricModel for COBV {
+    fn name(&self) -> String {
+        "obv".to_string()
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Value(self.add(klu.close, klu_volume(klu)))
+    }
+}
+
+impl MetricModel for CVWAP {
+    fn name(&self) -> String {
+        "vwap".to_string()
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Value(self.add(klu.high, klu.low, klu.close, klu_volume(klu)))
+    }
+}
+
+impl MetricModel for CADX {
+    fn name(&self) -> String {
+        format!("adx{}", self.get_period())
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        let item = self.add(klu.high, klu.low, klu.close);
+        MetricValue::Dict(HashMap::from([
+            ("plus_di".to_string(), item.plus_di),
+            ("minus_di".to_string(), item.minus_di),
+            ("adx".to_string(), item.adx),
+        ]))
+    }
+}
+
+impl MetricModel for CCCI {
+    fn name(&self) -> String {
+        format!("cci{}", self.get_period())
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        MetricValue::Value(self.add(klu.high, klu.low, klu.close))
+    }
+}
+
+impl MetricModel for CDonchian {
+    fn name(&self) -> String {
+        format!("donchian{}", self.get_period())
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        let item = self.add(klu.high, klu.low);
+        MetricValue::Dict(HashMap::from([
+            ("up".to_string(), item.up),
+            ("mid".to_string(), item.mid),
+            ("down".to_string(), item.down),
+        ]))
+    }
+}
+
+impl MetricModel for CKeltner {
+    fn name(&self) -> String {
+        format!("keltner{}_{}", self.get_period(), self.get_atr_period())
+    }
+
+    fn update(&mut self, klu: &CKLineUnit) -> MetricValue {
+        let item = self.add(klu.high, klu.low, klu.close);
+        MetricValue::Dict(HashMap::from([
+            ("up".to_string(), item.up),
+            ("mid".to_string(), item.mid),
+            ("down".to_string(), item.down),
+        ]))
+    }
+}
+
+// 没有成交量数据时按0处理
+fn klu_volume(klu: &CKLineUnit) -> f64 {
+    klu.trade_info
+        .metric
+        .get(DataField::FIELD_VOLUME)
+        .copied()
+        .flatten()
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metric_value_to_dict() {
+        let value = MetricValue::Dict(HashMap::from([("a".to_string(), 1.0)]));
+        assert_eq!(value.to_dict("foo").get("foo_a"), Some(&1.0));
+        assert!(!value.is_builtin());
+
+        let value = MetricValue::Rsi(55.0);
+        assert_eq!(value.to_dict("rsi").get("rsi"), Some(&55.0));
+        assert!(value.is_builtin());
+
+        assert!(MetricValue::Empty.to_dict("x").is_empty());
+    }
+
+    #[test]
+    fn test_metric_name_with_period() {
+        assert_eq!(CATR::new(14).name(), "atr14");
+        assert_ne!(CATR::new(14).name(), CATR::new(20).name());
+        assert_eq!(CADX::new(14).name(), "adx14");
+        assert_eq!(CCCI::new(20).name(), "cci20");
+        assert_eq!(CDonchian::new(20).name(), "donchian20");
+        assert_eq!(CKeltner::new(20, 10, 2.0).name(), "keltner20_10");
+    }
+}
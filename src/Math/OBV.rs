@@ -0,0 +1,51 @@
+// 能量潮，收盘价上涨累加成交量，下跌累减，第一根为0
+pub struct COBV {
+    pre_close: Option<f64>,
+    obv: f64,
+}
+
+impl COBV {
+    pub fn new() -> Self {
+        COBV {
+            pre_close: None,
+            obv: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, close: f64, volume: f64) -> f64 {
+        if let Some(pre_close) = self.pre_close {
+            if close > pre_close {
+                self.obv += volume;
+            } else if close < pre_close {
+                self.obv -= volume;
+            }
+        }
+        self.pre_close = Some(close);
+        self.obv
+    }
+}
+
+impl Default for COBV {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Math::test_data::{CLOSE, VOLUME};
+
+    #[test]
+    fn test_obv() {
+        let mut obv = COBV::new();
+        let res: Vec<f64> = CLOSE
+            .iter()
+            .zip(VOLUME.iter())
+            .map(|(&c, &v)| obv.add(c, v))
+            .collect();
+        assert_eq!(res[0], 0.0);
+        assert_eq!(res[1], 1200.0);
+        assert_eq!(*res.last().unwrap(), 11650.0);
+    }
+}
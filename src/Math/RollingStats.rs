@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+
+use crate::Common::ChanException::{CChanException, ErrCode};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RollingStatItem {
+    pub mean: f64,
+    pub max: f64,
+    pub min: f64,
+    pub std: f64,
+}
+
+// 固定窗口的滚动均值/最大/最小/标准差，每次 add 均摊 O(1)
+// 最大最小用单调队列，均值和方差用滑动的 Welford 递推（避免平方和相减的精度损失），并定期重算；不足 t 根时按已有数据计算
+pub struct CRollingStats {
+    t: usize,
+    cnt: usize,
+    arr: VecDeque<f64>,
+    mean: f64,
+    // 窗口内离差平方和
+    m2: f64,
+    // (序号, 值)，max_dq 单调递减，min_dq 单调递增
+    max_dq: VecDeque<(usize, f64)>,
+    min_dq: VecDeque<(usize, f64)>,
+}
+
+impl CRollingStats {
+    pub fn new(t: usize) -> Result<Self, CChanException> {
+        if t == 0 {
+            return Err(CChanException::new(
+                "rolling window must be > 0".to_string(),
+                ErrCode::ParaError,
+            ));
+        }
+        Ok(CRollingStats {
+            t,
+            cnt: 0,
+            arr: VecDeque::with_capacity(t + 1),
+            mean: 0.0,
+            m2: 0.0,
+            max_dq: VecDeque::new(),
+            min_dq: VecDeque::new(),
+        })
+    }
+
+    pub fn get_t(&self) -> usize {
+        self.t
+    }
+
+    pub fn add(&mut self, value: f64) -> RollingStatItem {
+        let pos = self.cnt;
+        self.cnt += 1;
+
+        if self.arr.len() == self.t {
+            // 窗口已满，新值替换最旧的值
+            let old = self.arr.pop_front().unwrap();
+            let n = self.t as f64;
+            let old_mean = self.mean;
+            self.mean += (value - old) / n;
+            self.m2 += (value - old) * (value - self.mean + old - old_mean);
+        } else {
+            let n = (self.arr.len() + 1) as f64;
+            let delta = value - self.mean;
+            self.mean += delta / n;
+            self.m2 += delta * (value - self.mean);
+        }
+        self.arr.push_back(value);
+        if self.cnt % self.t == 0 {
+            // 每 t 根用两遍法重算一次，消除递推累积的舍入误差，均摊仍是 O(1)
+            let n = self.arr.len() as f64;
+            self.mean = self.arr.iter().sum::<f64>() / n;
+            self.m2 = self.arr.iter().map(|x| (x - self.mean).powi(2)).sum();
+        }
+
+        while self.max_dq.back().map_or(false, |&(_, v)| v <= value) {
+            self.max_dq.pop_back();
+        }
+        self.max_dq.push_back((pos, value));
+        while self.min_dq.back().map_or(false, |&(_, v)| v >= value) {
+            self.min_dq.pop_back();
+        }
+        self.min_dq.push_back((pos, value));
+        // 窗口内最早的序号为 cnt - len
+        let first_pos = self.cnt - self.arr.len();
+        while self.max_dq.front().map_or(false, |&(p, _)| p < first_pos) {
+            self.max_dq.pop_front();
+        }
+        while self.min_dq.front().map_or(false, |&(p, _)| p < first_pos) {
+            self.min_dq.pop_front();
+        }
+
+        self.get()
+    }
+
+    pub fn get(&self) -> RollingStatItem {
+        let n = self.arr.len() as f64;
+        if n == 0.0 {
+            return RollingStatItem {
+                mean: f64::NAN,
+                max: f64::NAN,
+                min: f64::NAN,
+                std: f64::NAN,
+            };
+        }
+        // 总体标准差，浮点误差可能让方差略小于0
+        let var = (self.m2 / n).max(0.0);
+        RollingStatItem {
+            mean: self.mean,
+            max: self.max_dq.front().unwrap().1,
+            min: self.min_dq.front().unwrap().1,
+            std: var.sqrt(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Math::test_data::CLOSE;
+
+    fn brute_force(arr: &[f64]) -> RollingStatItem {
+        let n = arr.len() as f64;
+        let mean = arr.iter().sum::<f64>() / n;
+        RollingStatItem {
+            mean,
+            max: arr.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            min: arr.iter().cloned().fold(f64::INFINITY, f64::min),
+            std: (arr.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt(),
+        }
+    }
+
+    #[test]
+    fn test_rolling_stats() {
+        for t in [1, 3, 5, 20, 30] {
+            let mut stats = CRollingStats::new(t).unwrap();
+            for i in 0..CLOSE.len() {
+                let res = stats.add(CLOSE[i]);
+                let expect = brute_force(&CLOSE[(i + 1).saturating_sub(t)..=i]);
+                assert!((res.mean - expect.mean).abs() < 1e-9);
+                assert_eq!(res.max, expect.max);
+                assert_eq!(res.min, expect.min);
+                assert!((res.std - expect.std).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rolling_stats_monotonic() {
+        let mut stats = CRollingStats::new(3).unwrap();
+        for v in [5.0, 4.0, 3.0, 2.0] {
+            stats.add(v);
+        }
+        let res = stats.get();
+        assert_eq!(res.max, 4.0);
+        assert_eq!(res.min, 2.0);
+        assert_eq!(res.mean, 3.0);
+    }
+
+    #[test]
+    fn test_rolling_stats_precision() {
+        // 高价位、小波动、长序列，平方和相减的写法在这里会丢掉大部分有效数字
+        let t = 20;
+        let prices: Vec<f64> = (0..100_000)
+            .map(|i| 100_000.0 + ((i * 7919) % 13) as f64 * 0.01)
+            .collect();
+        let mut stats = CRollingStats::new(t).unwrap();
+        for (i, &p) in prices.iter().enumerate() {
+            let res = stats.add(p);
+            if i % 997 == 0 || i == prices.len() - 1 {
+                let expect = brute_force(&prices[(i + 1).saturating_sub(t)..=i]);
+                assert!((res.mean - expect.mean).abs() < 1e-8);
+                assert!((res.std - expect.std).abs() < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rolling_stats_zero_window() {
+        assert!(CRollingStats::new(0).is_err());
+    }
+}
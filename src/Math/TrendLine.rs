@@ -1,8 +1,86 @@
 use crate::Bi::Bi::CBi;
-use crate::Common::types::SharedCell;
+use crate::Common::types::{ChanLine, LineType, SharedCell};
 use crate::Common::CEnum::{BiDir, TrendLineSide};
+use crate::Common::CTime::CTime;
+use crate::KLine::KLine_Unit::CKLineUnit;
+use crate::Seg::Seg::CSeg;
 use std::f64;
 
+// 可以画趋势线的元素（笔、线段、各级递归线段），点的 x 为K线 idx
+pub trait TrendLineElement {
+    fn begin_point(&self) -> Point;
+    fn end_point(&self) -> Point;
+    fn line_dir(&self) -> BiDir;
+}
+
+impl TrendLineElement for CBi {
+    fn begin_point(&self) -> Point {
+        Point::new(self.get_begin_klu().borrow().idx, self.get_begin_val())
+    }
+
+    fn end_point(&self) -> Point {
+        Point::new(self.get_end_klu().borrow().idx, self.get_end_val())
+    }
+
+    fn line_dir(&self) -> BiDir {
+        self.dir
+    }
+}
+
+impl<LINE_TYPE: ChanLine> TrendLineElement for CSeg<LINE_TYPE> {
+    fn begin_point(&self) -> Point {
+        Point::new(self.get_begin_klu().borrow().idx, self.get_begin_val())
+    }
+
+    fn end_point(&self) -> Point {
+        Point::new(self.get_end_klu().borrow().idx, self.get_end_val())
+    }
+
+    fn line_dir(&self) -> BiDir {
+        self.dir
+    }
+}
+
+impl TrendLineElement for LineType {
+    fn begin_point(&self) -> Point {
+        match self {
+            LineType::Bi(bi) => bi.borrow().begin_point(),
+            LineType::Seg(seg) => seg.borrow().begin_point(),
+            LineType::Tier(seg) => seg.borrow().begin_point(),
+        }
+    }
+
+    fn end_point(&self) -> Point {
+        match self {
+            LineType::Bi(bi) => bi.borrow().end_point(),
+            LineType::Seg(seg) => seg.borrow().end_point(),
+            LineType::Tier(seg) => seg.borrow().end_point(),
+        }
+    }
+
+    fn line_dir(&self) -> BiDir {
+        match self {
+            LineType::Bi(bi) => bi.borrow().dir,
+            LineType::Seg(seg) => seg.borrow().dir,
+            LineType::Tier(seg) => seg.borrow().dir,
+        }
+    }
+}
+
+impl<T: TrendLineElement> TrendLineElement for SharedCell<T> {
+    fn begin_point(&self) -> Point {
+        self.borrow().begin_point()
+    }
+
+    fn end_point(&self) -> Point {
+        self.borrow().end_point()
+    }
+
+    fn line_dir(&self) -> BiDir {
+        self.borrow().line_dir()
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Point {
     pub x: i32,
@@ -34,42 +112,52 @@ impl Line {
         Line { p, slope }
     }
 
+    // 直线在 x 处的值，竖直线返回 None
+    pub fn y_at(&self, x: i32) -> Option<f64> {
+        if self.slope.is_finite() {
+            Some(self.p.y + self.slope * (x - self.p.x) as f64)
+        } else {
+            None
+        }
+    }
+
     pub fn cal_dis(&self, p: &Point) -> f64 {
         (self.slope * p.x as f64 - p.y + self.p.y - self.slope * self.p.x as f64).abs()
             / (self.slope.powi(2) + 1.0).sqrt()
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct CTrendLine {
     pub line: Option<Line>,
     pub side: TrendLineSide,
 }
 
 impl CTrendLine {
-    pub fn new(lst: &[crate::Common::types::SharedCell<CBi>], side: TrendLineSide) -> Self {
+    pub fn new<T: TrendLineElement>(lst: &[T], side: TrendLineSide) -> Self {
         let mut trend_line = CTrendLine { line: None, side };
         trend_line.cal(lst);
         trend_line
     }
 
-    pub fn cal(&mut self, lst: &[SharedCell<CBi>]) {
+    pub fn cal<T: TrendLineElement>(&mut self, lst: &[T]) {
         let mut bench = f64::INFINITY;
-        let all_p = if self.side == TrendLineSide::Inside {
+        let all_p = if self.side == TrendLineSide::INSIDE {
             lst.iter()
                 .rev()
                 .step_by(2)
-                .map(|bi| Point::new(bi.borrow().get_begin_klu().idx, bi.borrow().get_begin_val()))
+                .map(|bi| bi.begin_point())
                 .collect::<Vec<_>>()
         } else {
             lst.iter()
                 .rev()
                 .step_by(2)
-                .map(|bi| Point::new(bi.borrow().get_end_klu().idx, bi.borrow().get_end_val()))
+                .map(|bi| bi.end_point())
                 .collect::<Vec<_>>()
         };
         let mut c_p = all_p.clone();
         while !c_p.is_empty() {
-            let (line, idx) = cal_tl(&c_p, lst.last().unwrap().borrow().dir, self.side);
+            let (line, idx) = cal_tl(&c_p, lst.last().unwrap().line_dir(), self.side);
             let dis: f64 = all_p.iter().map(|p| line.cal_dis(p)).sum();
             if dis < bench {
                 bench = dis;
@@ -83,11 +171,107 @@ impl CTrendLine {
     }
 }
 
+// (支撑线, 压力线) 分别取哪一侧：内侧线取各笔起点，最后一笔向上时连的是低点，
+// 所以上涨时内侧为支撑、外侧为压力，下跌时反过来
+pub fn support_resistance_side(dir: BiDir) -> (TrendLineSide, TrendLineSide) {
+    match dir {
+        BiDir::UP => (TrendLineSide::INSIDE, TrendLineSide::OUTSIDE),
+        BiDir::DOWN => (TrendLineSide::OUTSIDE, TrendLineSide::INSIDE),
+    }
+}
+
+// 通道：base 为内侧趋势线（上涨连低点，下跌连高点），parallel 为过另一侧最远点的平行线
+#[derive(Clone, Copy, Debug)]
+pub struct CTrendChannel {
+    pub base: Line,
+    pub parallel: Line,
+}
+
+impl CTrendChannel {
+    pub fn new<T: TrendLineElement>(lst: &[T]) -> Option<Self> {
+        let base = CTrendLine::new(lst, TrendLineSide::INSIDE).line?;
+        if !base.slope.is_finite() {
+            return None;
+        }
+        let is_up = lst.last()?.line_dir() == BiDir::UP;
+        // 与最后一笔同向的笔的终点，即另一侧的高点/低点
+        let far_p = lst
+            .iter()
+            .rev()
+            .step_by(2)
+            .map(|bi| bi.end_point())
+            .max_by(|a, b| {
+                let da = a.y - base.y_at(a.x).unwrap();
+                let db = b.y - base.y_at(b.x).unwrap();
+                if is_up {
+                    da.total_cmp(&db)
+                } else {
+                    db.total_cmp(&da)
+                }
+            })?;
+        Some(CTrendChannel {
+            base,
+            parallel: Line::new(far_p, base.slope),
+        })
+    }
+
+    // 价格在通道中的位置，0为 base，1为 parallel
+    pub fn position(&self, x: i32, price: f64) -> Option<f64> {
+        let base = self.base.y_at(x)?;
+        let parallel = self.parallel.y_at(x)?;
+        if parallel == base {
+            return None;
+        }
+        Some((price - base) / (parallel - base))
+    }
+}
+
+// K线收盘价突破趋势线：支撑线被跌破为向下突破，压力线被升破为向上突破
+#[derive(Clone, Debug)]
+pub struct CTrendLineBreak {
+    pub seg_idx: i32,
+    pub is_support: bool,
+    pub break_dir: BiDir,
+    pub klu_idx: i32,
+    pub time: CTime,
+    pub close: f64,
+    pub line_val: f64,
+}
+
+// 从 klu 开始往后找第一根收盘价越过 line 的K线，is_support 时找收盘低于线，否则找收盘高于线
+// 只检查 idx <= max_idx 的K线，返回 (突破K线, 最后检查的K线)，未突破时前者为 None
+pub fn find_line_break(
+    line: &Line,
+    is_support: bool,
+    klu: Option<SharedCell<CKLineUnit>>,
+    max_idx: i32,
+) -> (
+    Option<(SharedCell<CKLineUnit>, f64)>,
+    Option<SharedCell<CKLineUnit>>,
+) {
+    let mut last = None;
+    let mut cur = klu;
+    while let Some(klu) = cur {
+        let (idx, close) = (klu.borrow().idx, klu.borrow().close);
+        if idx > max_idx {
+            break;
+        }
+        if let Some(line_val) = line.y_at(idx) {
+            if (is_support && close < line_val) || (!is_support && close > line_val) {
+                return (Some((klu, line_val)), last);
+            }
+        }
+        cur = klu.borrow().next.clone();
+        last = Some(klu);
+    }
+    (None, last)
+}
+
 fn init_peak_slope(dir: BiDir, side: TrendLineSide) -> f64 {
     match (side, dir) {
-        (TrendLineSide::Inside, _) => 0.0,
-        (_, BiDir::Up) => f64::INFINITY,
-        (_, BiDir::Down) => f64::NEG_INFINITY,
+        (TrendLineSide::INSIDE, _) => 0.0,
+        (_, BiDir::UP) => f64::INFINITY,
+        (_, BiDir::DOWN) => f64::NEG_INFINITY,
     }
 }
 
@@ -97,23 +281,23 @@ fn cal_tl(c_p: &[Point], dir: BiDir, side: TrendLineSide) -> (Line, usize) {
     let mut idx = 1;
     for (point_idx, p2) in c_p[1..].iter().enumerate() {
         let slope = p.cal_slope(p2);
-        if (dir == BiDir::Up && slope < 0.0) || (dir == BiDir::Down && slope > 0.0) {
+        if (dir == BiDir::UP && slope < 0.0) || (dir == BiDir::DOWN && slope > 0.0) {
             continue;
         }
         match (side, dir) {
-            (TrendLineSide::Inside, BiDir::Up) if slope > peak_slope => {
+            (TrendLineSide::INSIDE, BiDir::UP) if slope > peak_slope => {
                 peak_slope = slope;
                 idx = point_idx + 1;
             }
-            (TrendLineSide::Inside, BiDir::Down) if slope < peak_slope => {
+            (TrendLineSide::INSIDE, BiDir::DOWN) if slope < peak_slope => {
                 peak_slope = slope;
                 idx = point_idx + 1;
             }
-            (TrendLineSide::Outside, BiDir::Up) if slope < peak_slope => {
+            (TrendLineSide::OUTSIDE, BiDir::UP) if slope < peak_slope => {
                 peak_slope = slope;
                 idx = point_idx + 1;
             }
-            (TrendLineSide::Outside, BiDir::Down) if slope > peak_slope => {
+            (TrendLineSide::OUTSIDE, BiDir::DOWN) if slope > peak_slope => {
                 peak_slope = slope;
                 idx = point_idx + 1;
             }
@@ -126,62 +310,170 @@ fn cal_tl(c_p: &[Point], dir: BiDir, side: TrendLineSide) -> (Line, usize) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Common::TradeInfo::CTradeInfo;
+    use crate::Math::Demark::CDemarkIndex;
     use std::cell::RefCell;
+    use std::collections::HashMap;
     use std::rc::Rc;
 
-    // You'll need to mock CBi for testing purposes
     struct MockBi {
-        idx: i32,
-        val: f64,
+        begin: Point,
+        end: Point,
         dir: BiDir,
     }
 
-    impl MockBi {
-        fn get_begin_klu(&self) -> Self {
-            self.clone()
+    impl TrendLineElement for MockBi {
+        fn begin_point(&self) -> Point {
+            self.begin
         }
-        fn get_end_klu(&self) -> Self {
-            self.clone()
-        }
-        fn get_begin_val(&self) -> f64 {
-            self.val
+
+        fn end_point(&self) -> Point {
+            self.end
         }
-        fn get_end_val(&self) -> f64 {
-            self.val
+
+        fn line_dir(&self) -> BiDir {
+            self.dir
         }
     }
 
+    // 上涨：低点 10/11/12，高点 13/15/14
+    fn up_bis() -> Vec<MockBi> {
+        let pts = [
+            (0, 10.0),
+            (5, 13.0),
+            (10, 11.0),
+            (15, 15.0),
+            (20, 12.0),
+            (25, 14.0),
+        ];
+        pts.windows(2)
+            .map(|w| MockBi {
+                begin: Point::new(w[0].0, w[0].1),
+                end: Point::new(w[1].0, w[1].1),
+                dir: if w[1].1 > w[0].1 {
+                    BiDir::UP
+                } else {
+                    BiDir::DOWN
+                },
+            })
+            .collect()
+    }
+
     #[test]
     fn test_trend_line() {
-        let bis = vec![
-            Rc::new(RefCell::new(MockBi {
-                idx: 0,
-                val: 10.0,
-                dir: BiDir::Up,
-            })),
-            Rc::new(RefCell::new(MockBi {
-                idx: 1,
-                val: 12.0,
-                dir: BiDir::Up,
-            })),
-            Rc::new(RefCell::new(MockBi {
-                idx: 2,
-                val: 11.0,
-                dir: BiDir::Up,
-            })),
-            Rc::new(RefCell::new(MockBi {
-                idx: 3,
-                val: 13.0,
-                dir: BiDir::Up,
-            })),
-        ];
+        let bis = up_bis();
+        let trend_line = CTrendLine::new(&bis, TrendLineSide::INSIDE);
+        let line = trend_line.line.unwrap();
+        assert!((line.slope - 0.1).abs() < 1e-9);
+        assert!((line.y_at(30).unwrap() - 13.0).abs() < 1e-9);
 
-        let trend_line = CTrendLine::new(&bis, TrendLineSide::Outside);
+        let trend_line = CTrendLine::new(&bis, TrendLineSide::OUTSIDE);
         assert!(trend_line.line.is_some());
-        let line = trend_line.line.unwrap();
-        println!(
-            "Trend line: p=({}, {}), slope={}",
-            line.p.x, line.p.y, line.slope
+    }
+
+    #[test]
+    fn test_support_resistance_side() {
+        // 下跌：把上涨的笔上下翻转，高点 17/16/15，低点 13/11/12
+        let down_bis: Vec<MockBi> = up_bis()
+            .iter()
+            .map(|bi| MockBi {
+                begin: Point::new(bi.begin.x, 26.0 - bi.begin.y),
+                end: Point::new(bi.end.x, 26.0 - bi.end.y),
+                dir: if bi.dir == BiDir::UP {
+                    BiDir::DOWN
+                } else {
+                    BiDir::UP
+                },
+            })
+            .collect();
+        for (bis, dir) in [(up_bis(), BiDir::UP), (down_bis, BiDir::DOWN)] {
+            let lows: Vec<f64> = bis.iter().map(|bi| bi.begin.y.min(bi.end.y)).collect();
+            let highs: Vec<f64> = bis.iter().map(|bi| bi.begin.y.max(bi.end.y)).collect();
+            let (support_side, resistance_side) = support_resistance_side(dir);
+            // 支撑线总是从低点画，压力线总是从高点画
+            let support = CTrendLine::new(&bis, support_side).line.unwrap();
+            assert!(lows.contains(&support.p.y), "{:?}", dir);
+            let resistance = CTrendLine::new(&bis, resistance_side).line.unwrap();
+            assert!(highs.contains(&resistance.p.y), "{:?}", dir);
+        }
+        // 下跌时内侧线连的是高点，因此作为压力线
+        assert_eq!(
+            support_resistance_side(BiDir::DOWN),
+            (TrendLineSide::OUTSIDE, TrendLineSide::INSIDE)
         );
     }
+
+    fn klu_chain(closes: &[f64]) -> Vec<SharedCell<CKLineUnit>> {
+        let klu_lst: Vec<SharedCell<CKLineUnit>> = closes
+            .iter()
+            .enumerate()
+            .map(|(idx, &close)| {
+                Rc::new(RefCell::new(CKLineUnit {
+                    kl_type: None,
+                    time: CTime::new(2024, 1, 1, 0, 0, 0, false),
+                    close,
+                    open: close,
+                    high: close,
+                    low: close,
+                    trade_info: CTradeInfo::new(&HashMap::new()),
+                    demark: CDemarkIndex::new(),
+                    sub_kl_list: Vec::new(),
+                    sup_kl: None,
+                    klc: None,
+                    trend: HashMap::new(),
+                    limit_flag: 0,
+                    pre: None,
+                    next: None,
+                    idx: idx as i32,
+                    macd: None,
+                    boll: None,
+                    rsi: None,
+                    kdj: None,
+                    metric: HashMap::new(),
+                }))
+            })
+            .collect();
+        for w in klu_lst.windows(2) {
+            w[0].borrow_mut().next = Some(Rc::clone(&w[1]));
+        }
+        klu_lst
+    }
+
+    #[test]
+    fn test_find_line_break() {
+        let klu_lst = klu_chain(&[11.0, 10.5, 9.8, 9.0]);
+        let line = Line::new(Point::new(0, 10.0), 0.0);
+        let idx = |klu: &Option<SharedCell<CKLineUnit>>| klu.as_ref().map(|k| k.borrow().idx);
+
+        // 支撑线看收盘跌破
+        let (hit, last) = find_line_break(&line, true, Some(Rc::clone(&klu_lst[0])), 100);
+        let (hit_klu, line_val) = hit.unwrap();
+        assert_eq!(hit_klu.borrow().idx, 2);
+        assert_eq!(line_val, 10.0);
+        assert_eq!(idx(&last), Some(1));
+
+        // 超出 max_idx 的K线不检查
+        let (hit, last) = find_line_break(&line, true, Some(Rc::clone(&klu_lst[0])), 1);
+        assert!(hit.is_none());
+        assert_eq!(idx(&last), Some(1));
+
+        // 压力线看收盘升破，斜线按 idx 取值
+        let line = Line::new(Point::new(0, 10.0), 0.25);
+        let (hit, last) = find_line_break(&line, false, Some(Rc::clone(&klu_lst[0])), 100);
+        assert_eq!(hit.unwrap().0.borrow().idx, 0);
+        assert!(last.is_none());
+        let line = Line::new(Point::new(0, 11.5), -0.5);
+        let (hit, last) = find_line_break(&line, false, Some(Rc::clone(&klu_lst[0])), 100);
+        assert!(hit.is_none());
+        assert_eq!(idx(&last), Some(3));
+    }
+
+    #[test]
+    fn test_trend_channel() {
+        let channel = CTrendChannel::new(&up_bis()).unwrap();
+        // 距离支撑线最远的高点为 (15, 15.0)
+        assert_eq!(channel.parallel.p.x, 15);
+        assert!((channel.parallel.y_at(25).unwrap() - 16.0).abs() < 1e-9);
+        assert!((channel.position(25, 14.25).unwrap() - 0.5).abs() < 1e-9);
+    }
 }
@@ -1,45 +1,35 @@
 use crate::Common::CEnum::TrendType;
-use crate::Common::ChanException::{CChanException, ErrCode};
+use crate::Common::ChanException::CChanException;
+use crate::Math::RollingStats::CRollingStats;
 
 pub struct CTrendModel {
-    t: usize,
-    arr: Vec<f64>,
     trend_type: TrendType,
+    stats: CRollingStats,
 }
 
 impl CTrendModel {
-    pub fn new(trend_type: TrendType, t: usize) -> Self {
-        CTrendModel {
-            t,
-            arr: Vec::with_capacity(t),
+    pub fn new(trend_type: TrendType, t: usize) -> Result<Self, CChanException> {
+        Ok(CTrendModel {
             trend_type,
-        }
+            stats: CRollingStats::new(t)?,
+        })
     }
 
-    pub fn add(&mut self, value: f64) -> Result<f64, CChanException> {
-        self.arr.push(value);
-        if self.arr.len() > self.t {
-            self.arr = self.arr.split_off(self.arr.len() - self.t);
-        }
+    pub fn get_type(&self) -> TrendType {
+        self.trend_type
+    }
+
+    pub fn get_t(&self) -> usize {
+        self.stats.get_t()
+    }
 
+    pub fn add(&mut self, value: f64) -> f64 {
+        let item = self.stats.add(value);
         match self.trend_type {
-            TrendType::Mean => Ok(self.arr.iter().sum::<f64>() / self.arr.len() as f64),
-            TrendType::Max => self
-                .arr
-                .iter()
-                .max_by(|a, b| a.partial_cmp(b).unwrap())
-                .map(|&x| x)
-                .ok_or_else(|| CChanException::new("Empty array".to_string(), ErrCode::ParaError)),
-            TrendType::Min => self
-                .arr
-                .iter()
-                .min_by(|a, b| a.partial_cmp(b).unwrap())
-                .map(|&x| x)
-                .ok_or_else(|| CChanException::new("Empty array".to_string(), ErrCode::ParaError)),
-            _ => Err(CChanException::new(
-                &format!("Unknown trendModel Type = {:?}", self.trend_type),
-                ErrCode::ParaError,
-            )),
+            TrendType::MEAN => item.mean,
+            TrendType::MAX => item.max,
+            TrendType::MIN => item.min,
+            TrendType::STD => item.std,
         }
     }
 }
@@ -50,34 +40,33 @@ mod tests {
 
     #[test]
     fn test_trend_model_mean() {
-        let mut model = CTrendModel::new(TrendType::Mean, 3);
-        assert_eq!(model.add(1.0).unwrap(), 1.0);
-        assert_eq!(model.add(2.0).unwrap(), 1.5);
-        assert_eq!(model.add(3.0).unwrap(), 2.0);
-        assert_eq!(model.add(4.0).unwrap(), 3.0);
+        let mut model = CTrendModel::new(TrendType::MEAN, 3).unwrap();
+        assert_eq!(model.add(1.0), 1.0);
+        assert_eq!(model.add(2.0), 1.5);
+        assert_eq!(model.add(3.0), 2.0);
+        assert_eq!(model.add(4.0), 3.0);
     }
 
     #[test]
     fn test_trend_model_max() {
-        let mut model = CTrendModel::new(TrendType::Max, 3);
-        assert_eq!(model.add(1.0).unwrap(), 1.0);
-        assert_eq!(model.add(3.0).unwrap(), 3.0);
-        assert_eq!(model.add(2.0).unwrap(), 3.0);
-        assert_eq!(model.add(4.0).unwrap(), 4.0);
+        let mut model = CTrendModel::new(TrendType::MAX, 3).unwrap();
+        assert_eq!(model.add(1.0), 1.0);
+        assert_eq!(model.add(3.0), 3.0);
+        assert_eq!(model.add(2.0), 3.0);
+        assert_eq!(model.add(4.0), 4.0);
     }
 
     #[test]
     fn test_trend_model_min() {
-        let mut model = CTrendModel::new(TrendType::Min, 3);
-        assert_eq!(model.add(3.0).unwrap(), 3.0);
-        assert_eq!(model.add(1.0).unwrap(), 1.0);
-        assert_eq!(model.add(2.0).unwrap(), 1.0);
-        assert_eq!(model.add(4.0).unwrap(), 1.0);
+        let mut model = CTrendModel::new(TrendType::MIN, 3).unwrap();
+        assert_eq!(model.add(3.0), 3.0);
+        assert_eq!(model.add(1.0), 1.0);
+        assert_eq!(model.add(2.0), 1.0);
+        assert_eq!(model.add(4.0), 1.0);
     }
 
     #[test]
-    fn test_trend_model_unknown_type() {
-        let mut model = CTrendModel::new(TrendType::Unknown, 3);
-        assert!(model.add(1.0).is_err());
+    fn test_trend_model_zero_window() {
+        assert!(CTrendModel::new(TrendType::STD, 0).is_err());
     }
 }
@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+
+// 滚动窗口的成交量加权均价，价格取典型价 (high+low+close)/3
+// 窗口内没有成交量时返回当根典型价
+pub struct CVWAP {
+    period: usize,
+    arr: VecDeque<(f64, f64)>,
+    pv_sum: f64,
+    v_sum: f64,
+}
+
+impl CVWAP {
+    pub fn new(period: usize) -> Self {
+        CVWAP {
+            period,
+            arr: VecDeque::with_capacity(period),
+            pv_sum: 0.0,
+            v_sum: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, high: f64, low: f64, close: f64, volume: f64) -> f64 {
+        let tp = (high + low + close) / 3.0;
+        self.arr.push_back((tp * volume, volume));
+        self.pv_sum += tp * volume;
+        self.v_sum += volume;
+        if self.arr.len() > self.period {
+            let (pv, v) = self.arr.pop_front().unwrap();
+            self.pv_sum -= pv;
+            self.v_sum -= v;
+        }
+        if self.v_sum > 0.0 {
+            self.pv_sum / self.v_sum
+        } else {
+            tp
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Math::test_data::{CLOSE, HIGH, LOW, VOLUME};
+
+    #[test]
+    fn test_vwap() {
+        let mut vwap = CVWAP::new(5);
+        let mut res = 0.0;
+        for i in 0..CLOSE.len() {
+            res = vwap.add(HIGH[i], LOW[i], CLOSE[i], VOLUME[i]);
+        }
+        assert!((res - 49.957119).abs() < 1e-5);
+
+        let mut vwap = CVWAP::new(5);
+        assert!((vwap.add(3.0, 1.0, 2.0, 0.0) - 2.0).abs() < 1e-9);
+    }
+}
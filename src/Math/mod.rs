@@ -1,7 +1,21 @@
+pub mod ADX;
+pub mod ATR;
 pub mod BOLL;
+pub mod CCI;
 pub mod Demark;
+pub mod Donchian;
 pub mod KDJ;
+pub mod Keltner;
+pub mod MA;
 pub mod MACD;
+pub mod MacdMetric;
+pub mod MetricModel;
+pub mod OBV;
 pub mod RSI;
+pub mod RollingStats;
 pub mod TrendLine;
 pub mod TrendModel;
+pub mod VWAP;
+
+#[cfg(test)]
+mod test_data;
@@ -0,0 +1,22 @@
+// 指标单测共用的K线序列（手工构造的20根K线）
+// 参考值来源：
+//   ADX/+DI/-DI：TA-Lib 的 ADX/PLUS_DI/MINUS_DI 输出
+//   ATR/SMA/CCI：与 TA-Lib 同一公式（ATR 不计第一根K线），用 Python 3.11 逐根计算
+//   EMA/OBV/VWAP/Donchian/Keltner：按各自文件注释中的公式用 Python 3.11 逐根计算，
+//   其中 EMA 以首根收盘价为初值、OBV 从0开始，与 TA-Lib 的约定不同
+pub const HIGH: [f64; 20] = [
+    48.70, 48.72, 48.90, 48.87, 48.82, 49.05, 49.20, 49.35, 49.92, 50.19, 50.12, 49.66, 49.88,
+    50.19, 50.36, 50.57, 50.65, 50.43, 49.63, 50.33,
+];
+pub const LOW: [f64; 20] = [
+    47.79, 48.14, 48.39, 48.37, 48.24, 48.64, 48.94, 48.86, 49.50, 49.87, 49.20, 48.90, 49.43,
+    49.73, 49.26, 50.09, 50.30, 49.21, 48.98, 49.61,
+];
+pub const CLOSE: [f64; 20] = [
+    48.16, 48.61, 48.75, 48.63, 48.74, 49.03, 49.07, 49.32, 49.91, 50.13, 49.53, 49.50, 49.75,
+    50.03, 50.31, 50.52, 50.41, 49.34, 49.37, 50.23,
+];
+pub const VOLUME: [f64; 20] = [
+    1000.0, 1200.0, 900.0, 1100.0, 1300.0, 800.0, 950.0, 1400.0, 1600.0, 1500.0, 1250.0, 1000.0,
+    900.0, 1050.0, 1700.0, 1800.0, 1200.0, 2000.0, 1500.0, 1600.0,
+];
@@ -0,0 +1,558 @@
+use crate::Common::ChanException::{CChanException, ErrCode};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// 查询表达式中的值，缺失的数据（如还没有买卖点）为 Null
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryValue {
+    Num(f64),
+    Str(String),
+    Bool(bool),
+    Null,
+}
+
+impl QueryValue {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            QueryValue::Num(v) => *v != 0.0,
+            QueryValue::Str(s) => !s.is_empty(),
+            QueryValue::Bool(b) => *b,
+            QueryValue::Null => false,
+        }
+    }
+
+    pub fn as_num(&self) -> Option<f64> {
+        match self {
+            QueryValue::Num(v) => Some(*v),
+            QueryValue::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            QueryValue::Num(v) => serde_json::Number::from_f64(*v)
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
+            QueryValue::Str(s) => serde_json::Value::String(s.clone()),
+            QueryValue::Bool(b) => serde_json::Value::Bool(*b),
+            QueryValue::Null => serde_json::Value::Null,
+        }
+    }
+}
+
+impl fmt::Display for QueryValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryValue::Num(v) => write!(f, "{}", v),
+            QueryValue::Str(s) => write!(f, "{}", s),
+            QueryValue::Bool(b) => write!(f, "{}", b),
+            QueryValue::Null => write!(f, "None"),
+        }
+    }
+}
+
+impl From<f64> for QueryValue {
+    fn from(v: f64) -> Self {
+        QueryValue::Num(v)
+    }
+}
+
+impl From<i32> for QueryValue {
+    fn from(v: i32) -> Self {
+        QueryValue::Num(v as f64)
+    }
+}
+
+impl From<usize> for QueryValue {
+    fn from(v: usize) -> Self {
+        QueryValue::Num(v as f64)
+    }
+}
+
+impl From<bool> for QueryValue {
+    fn from(v: bool) -> Self {
+        QueryValue::Bool(v)
+    }
+}
+
+impl From<String> for QueryValue {
+    fn from(v: String) -> Self {
+        QueryValue::Str(v)
+    }
+}
+
+impl From<&str> for QueryValue {
+    fn from(v: &str) -> Self {
+        QueryValue::Str(v.to_string())
+    }
+}
+
+impl<T: Into<QueryValue>> From<Option<T>> for QueryValue {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(QueryValue::Null, Into::into)
+    }
+}
+
+// 表达式求值时按字段名取值，返回 None 表示没有这个字段
+pub trait QueryContext {
+    fn field(&self, name: &str) -> Option<QueryValue>;
+
+    // level 级别最近 n 根K线内出现过的买/卖点类型，逗号分隔；level 为空表示第一个级别
+    // 返回 None 表示没有这个级别
+    fn bsp_types_within(&self, _level: &str, _is_buy: bool, _n: f64) -> Option<String> {
+        None
+    }
+}
+
+impl QueryContext for BTreeMap<String, QueryValue> {
+    fn field(&self, name: &str) -> Option<QueryValue> {
+        self.get(name).cloned()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryExpr {
+    Literal(QueryValue),
+    Field(String),
+    Not(Box<QueryExpr>),
+    Neg(Box<QueryExpr>),
+    And(Box<QueryExpr>, Box<QueryExpr>),
+    Or(Box<QueryExpr>, Box<QueryExpr>),
+    Cmp(CmpOp, Box<QueryExpr>, Box<QueryExpr>),
+    Arith(ArithOp, Box<QueryExpr>, Box<QueryExpr>),
+    Call(String, Vec<QueryExpr>),
+}
+
+// 支持的函数及参数个数
+// between(x, lo, hi): lo <= x <= hi
+// has(s, item): 逗号分隔的 s 中包含 item（不区分大小写），如 has(bsp.type, "T2")
+// abs(x)
+// bsp_types_within(level, is_buy, n): 最近 n 根K线内的买卖点类型，不只看最后一个，
+// 如 has(bsp_types_within("K_60M", true, 5), "T2")
+const FUNCTIONS: [(&str, usize); 4] = [
+    ("between", 3),
+    ("has", 2),
+    ("abs", 1),
+    ("bsp_types_within", 3),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Num(f64),
+    Str(String),
+    Op(&'static str),
+}
+
+fn parse_err(msg: String) -> CChanException {
+    CChanException::new(msg, ErrCode::ParaError)
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, CChanException> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let s: String = chars[start..i].iter().collect();
+            let v = s
+                .parse::<f64>()
+                .map_err(|_| parse_err(format!("无法解析数字 {}", s)))?;
+            tokens.push(Token::Num(v));
+        } else if c.is_alphabetic() || c == '_' {
+            // 字段名可以带级别和对象前缀，如 K_DAY.bsp.type
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '"' || c == '\'' {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err(parse_err(format!("字符串没有结束: {}", text)));
+            }
+            tokens.push(Token::Str(chars[start..i].iter().collect()));
+            i += 1;
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            let op = ["==", "!=", "<=", ">="]
+                .into_iter()
+                .find(|op| *op == two)
+                .or_else(|| {
+                    ["<", ">", "(", ")", ",", "+", "-", "*", "/"]
+                        .into_iter()
+                        .find(|op| op.starts_with(c))
+                })
+                .ok_or_else(|| parse_err(format!("无法识别的字符 {}", c)))?;
+            i += op.len();
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(x)) if *x == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, kw: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(x)) if x.eq_ignore_ascii_case(kw)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_op(&mut self, op: &str) -> Result<(), CChanException> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            Err(parse_err(format!("缺少 {}", op)))
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<QueryExpr, CChanException> {
+        let mut expr = self.parse_and()?;
+        while self.eat_keyword("or") {
+            expr = QueryExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<QueryExpr, CChanException> {
+        let mut expr = self.parse_not()?;
+        while self.eat_keyword("and") {
+            expr = QueryExpr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<QueryExpr, CChanException> {
+        if self.eat_keyword("not") {
+            return Ok(QueryExpr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_cmp()
+    }
+
+    fn parse_cmp(&mut self) -> Result<QueryExpr, CChanException> {
+        let lhs = self.parse_add()?;
+        let op = match self.peek() {
+            Some(Token::Op("==")) => CmpOp::Eq,
+            Some(Token::Op("!=")) => CmpOp::Ne,
+            Some(Token::Op("<")) => CmpOp::Lt,
+            Some(Token::Op("<=")) => CmpOp::Le,
+            Some(Token::Op(">")) => CmpOp::Gt,
+            Some(Token::Op(">=")) => CmpOp::Ge,
+            _ => return Ok(lhs),
+        };
+        self.pos += 1;
+        let rhs = self.parse_add()?;
+        Ok(QueryExpr::Cmp(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn parse_add(&mut self) -> Result<QueryExpr, CChanException> {
+        let mut expr = self.parse_mul()?;
+        loop {
+            let op = if self.eat_op("+") {
+                ArithOp::Add
+            } else if self.eat_op("-") {
+                ArithOp::Sub
+            } else {
+                return Ok(expr);
+            };
+            expr = QueryExpr::Arith(op, Box::new(expr), Box::new(self.parse_mul()?));
+        }
+    }
+
+    fn parse_mul(&mut self) -> Result<QueryExpr, CChanException> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            let op = if self.eat_op("*") {
+                ArithOp::Mul
+            } else if self.eat_op("/") {
+                ArithOp::Div
+            } else {
+                return Ok(expr);
+            };
+            expr = QueryExpr::Arith(op, Box::new(expr), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<QueryExpr, CChanException> {
+        if self.eat_op("-") {
+            return Ok(QueryExpr::Neg(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<QueryExpr, CChanException> {
+        match self.next() {
+            Some(Token::Num(v)) => Ok(QueryExpr::Literal(QueryValue::Num(v))),
+            Some(Token::Str(s)) => Ok(QueryExpr::Literal(QueryValue::Str(s))),
+            Some(Token::Op("(")) => {
+                let expr = self.parse_or()?;
+                self.expect_op(")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => match name.to_lowercase().as_str() {
+                "true" => Ok(QueryExpr::Literal(QueryValue::Bool(true))),
+                "false" => Ok(QueryExpr::Literal(QueryValue::Bool(false))),
+                "null" | "none" => Ok(QueryExpr::Literal(QueryValue::Null)),
+                _ if self.eat_op("(") => self.parse_call(name),
+                _ => Ok(QueryExpr::Field(name)),
+            },
+            Some(token) => Err(parse_err(format!("意外的符号 {:?}", token))),
+            None => Err(parse_err("表达式不完整".to_string())),
+        }
+    }
+
+    fn parse_call(&mut self, name: String) -> Result<QueryExpr, CChanException> {
+        let mut args = Vec::new();
+        if !self.eat_op(")") {
+            loop {
+                args.push(self.parse_or()?);
+                if self.eat_op(")") {
+                    break;
+                }
+                self.expect_op(",")?;
+            }
+        }
+        let name = name.to_lowercase();
+        match FUNCTIONS.iter().find(|(f, _)| *f == name) {
+            Some((_, arg_cnt)) if *arg_cnt == args.len() => Ok(QueryExpr::Call(name, args)),
+            Some((_, arg_cnt)) => Err(parse_err(format!(
+                "函数 {} 需要 {} 个参数，实际 {} 个",
+                name,
+                arg_cnt,
+                args.len()
+            ))),
+            None => Err(parse_err(format!("未知函数 {}", name))),
+        }
+    }
+}
+
+fn cmp_value(op: CmpOp, lhs: &QueryValue, rhs: &QueryValue) -> bool {
+    let ord = match (lhs, rhs) {
+        (QueryValue::Num(a), QueryValue::Num(b)) => a.partial_cmp(b),
+        (QueryValue::Str(a), QueryValue::Str(b)) => Some(a.cmp(b)),
+        (QueryValue::Bool(a), QueryValue::Bool(b)) => Some(a.cmp(b)),
+        (QueryValue::Null, QueryValue::Null) => Some(std::cmp::Ordering::Equal),
+        // 类型不同（含一侧为 Null）时只有 != 成立
+        _ => return op == CmpOp::Ne,
+    };
+    match ord {
+        Some(ord) => match op {
+            CmpOp::Eq => ord.is_eq(),
+            CmpOp::Ne => ord.is_ne(),
+            CmpOp::Lt => ord.is_lt(),
+            CmpOp::Le => ord.is_le(),
+            CmpOp::Gt => ord.is_gt(),
+            CmpOp::Ge => ord.is_ge(),
+        },
+        None => op == CmpOp::Ne,
+    }
+}
+
+pub fn eval_expr(expr: &QueryExpr, ctx: &dyn QueryContext) -> Result<QueryValue, CChanException> {
+    Ok(match expr {
+        QueryExpr::Literal(v) => v.clone(),
+        QueryExpr::Field(name) => ctx
+            .field(name)
+            .ok_or_else(|| parse_err(format!("未知字段 {}", name)))?,
+        QueryExpr::Not(e) => QueryValue::Bool(!eval_expr(e, ctx)?.is_truthy()),
+        QueryExpr::Neg(e) => eval_expr(e, ctx)?
+            .as_num()
+            .map_or(QueryValue::Null, |v| QueryValue::Num(-v)),
+        QueryExpr::And(a, b) => {
+            QueryValue::Bool(eval_expr(a, ctx)?.is_truthy() && eval_expr(b, ctx)?.is_truthy())
+        }
+        QueryExpr::Or(a, b) => {
+            QueryValue::Bool(eval_expr(a, ctx)?.is_truthy() || eval_expr(b, ctx)?.is_truthy())
+        }
+        QueryExpr::Cmp(op, a, b) => {
+            QueryValue::Bool(cmp_value(*op, &eval_expr(a, ctx)?, &eval_expr(b, ctx)?))
+        }
+        QueryExpr::Arith(op, a, b) => {
+            match (eval_expr(a, ctx)?.as_num(), eval_expr(b, ctx)?.as_num()) {
+                (Some(a), Some(b)) => match op {
+                    ArithOp::Add => QueryValue::Num(a + b),
+                    ArithOp::Sub => QueryValue::Num(a - b),
+                    ArithOp::Mul => QueryValue::Num(a * b),
+                    ArithOp::Div if b != 0.0 => QueryValue::Num(a / b),
+                    ArithOp::Div => QueryValue::Null,
+                },
+                _ => QueryValue::Null,
+            }
+        }
+        QueryExpr::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|e| eval_expr(e, ctx))
+                .collect::<Result<Vec<_>, _>>()?;
+            match name.as_str() {
+                "between" => match (args[0].as_num(), args[1].as_num(), args[2].as_num()) {
+                    (Some(x), Some(lo), Some(hi)) => QueryValue::Bool(lo <= x && x <= hi),
+                    _ => QueryValue::Bool(false),
+                },
+                "has" => match (&args[0], &args[1]) {
+                    (QueryValue::Str(s), QueryValue::Str(item)) => {
+                        QueryValue::Bool(s.split(',').any(|x| x.trim().eq_ignore_ascii_case(item)))
+                    }
+                    _ => QueryValue::Bool(false),
+                },
+                "abs" => args[0]
+                    .as_num()
+                    .map_or(QueryValue::Null, |v| QueryValue::Num(v.abs())),
+                "bsp_types_within" => match (&args[0], &args[1], args[2].as_num()) {
+                    (QueryValue::Str(level), QueryValue::Bool(is_buy), Some(n)) => ctx
+                        .bsp_types_within(level, *is_buy, n)
+                        .map(QueryValue::Str)
+                        .ok_or_else(|| parse_err(format!("未知级别 {}", level)))?,
+                    _ => {
+                        return Err(parse_err(
+                            "bsp_types_within 参数应为 (级别, 是否买点, K线数)".to_string(),
+                        ))
+                    }
+                },
+                _ => unreachable!(),
+            }
+        }
+    })
+}
+
+// 一条解析好的查询，如
+// K_DAY.bsp.type == "T1" and K_DAY.bsp.is_buy and K_DAY.bsp.bars_ago <= 3 and has(K_60M.bsp.type, "T2")
+#[derive(Clone, Debug)]
+pub struct CQuery {
+    pub text: String,
+    pub expr: QueryExpr,
+}
+
+impl CQuery {
+    pub fn parse(text: &str) -> Result<Self, CChanException> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+        };
+        let expr = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(parse_err(format!("{} 中多余的符号 {:?}", text, token)));
+        }
+        Ok(CQuery {
+            text: text.to_string(),
+            expr,
+        })
+    }
+
+    pub fn eval(&self, ctx: &dyn QueryContext) -> Result<QueryValue, CChanException> {
+        eval_expr(&self.expr, ctx)
+    }
+
+    pub fn matches(&self, ctx: &dyn QueryContext) -> Result<bool, CChanException> {
+        Ok(self.eval(ctx)?.is_truthy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> BTreeMap<String, QueryValue> {
+        BTreeMap::from([
+            ("price".to_string(), QueryValue::from(10.5)),
+            ("K_DAY.bsp.type".to_string(), QueryValue::from("T1,T2S")),
+            ("K_DAY.bsp.is_buy".to_string(), QueryValue::from(true)),
+            ("K_DAY.bsp.bars_ago".to_string(), QueryValue::from(2)),
+            ("K_60M.bsp.type".to_string(), QueryValue::from("T2")),
+            ("zs.low".to_string(), QueryValue::from(10.0)),
+            ("zs.high".to_string(), QueryValue::from(11.0)),
+            ("seg.score".to_string(), QueryValue::Null),
+        ])
+    }
+
+    fn check(text: &str) -> bool {
+        CQuery::parse(text).unwrap().matches(&ctx()).unwrap()
+    }
+
+    #[test]
+    fn test_query_eval() {
+        assert!(check(
+            r#"has(K_DAY.bsp.type, "T1") and K_DAY.bsp.is_buy and K_DAY.bsp.bars_ago <= 3 and has(K_60M.bsp.type, 't2')"#
+        ));
+        assert!(check("between(price, zs.low, zs.high)"));
+        assert!(check("not price > zs.high or price < zs.low"));
+        assert!(check("(price - zs.low) / (zs.high - zs.low) == 0.5"));
+        assert!(check("-price < -10 and abs(-price) >= 10.5"));
+        assert!(!check("has(K_60M.bsp.type, \"T2S\")"));
+        // 缺失值参与比较时只有 != 成立
+        assert!(!check("seg.score > 0 or seg.score <= 0"));
+        assert!(check("seg.score != 0 and seg.score == null"));
+    }
+
+    #[test]
+    fn test_query_error() {
+        assert!(CQuery::parse("price >").is_err());
+        assert!(CQuery::parse("price > 1 1").is_err());
+        assert!(CQuery::parse("between(price, 1)").is_err());
+        assert!(CQuery::parse("foo(price)").is_err());
+        assert!(CQuery::parse("bsp.type == \"T1").is_err());
+        let query = CQuery::parse("bi.dir == \"UP\"").unwrap();
+        assert_eq!(
+            query.matches(&ctx()).unwrap_err().errcode,
+            ErrCode::ParaError
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use crate::Chan::CChan;
+use crate::Common::CEnum::KlType;
+use crate::Common::ChanException::CChanException;
+use crate::Screener::Query::{CQuery, QueryValue};
+use crate::Screener::Snapshot::CSymbolSnapshot;
+
+// 选股器：filter 过滤，sort_by 排序（如 bsp.score、bsp.feat.xxx），columns 为输出列
+// 排序和输出列都是查询表达式
+pub struct CScreener {
+    pub filter: CQuery,
+    pub sort_by: Option<CQuery>,
+    pub descending: bool,
+    pub limit: Option<usize>,
+    pub columns: Vec<CQuery>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CScreenRow {
+    pub code: String,
+    pub sort_key: Option<f64>,
+    pub values: Vec<QueryValue>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CScreenResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<CScreenRow>,
+}
+
+impl CScreener {
+    pub fn new(filter: &str) -> Result<Self, CChanException> {
+        Ok(CScreener {
+            filter: CQuery::parse(filter)?,
+            sort_by: None,
+            descending: true,
+            limit: None,
+            columns: Vec::new(),
+        })
+    }
+
+    pub fn set_sort(&mut self, sort_by: &str, descending: bool) -> Result<(), CChanException> {
+        self.sort_by = Some(CQuery::parse(sort_by)?);
+        self.descending = descending;
+        Ok(())
+    }
+
+    pub fn set_columns(&mut self, columns: &[&str]) -> Result<(), CChanException> {
+        self.columns = columns
+            .iter()
+            .map(|col| CQuery::parse(col))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    pub fn screen(&self, snapshots: &[CSymbolSnapshot]) -> Result<CScreenResult, CChanException> {
+        let mut rows = Vec::new();
+        for snapshot in snapshots {
+            if !self.filter.matches(snapshot)? {
+                continue;
+            }
+            let sort_key = match &self.sort_by {
+                Some(sort_by) => sort_by.eval(snapshot)?.as_num(),
+                None => None,
+            };
+            let values = self
+                .columns
+                .iter()
+                .map(|col| col.eval(snapshot))
+                .collect::<Result<_, _>>()?;
+            rows.push(CScreenRow {
+                code: snapshot.code.clone(),
+                sort_key,
+                values,
+            });
+        }
+        if self.sort_by.is_some() {
+            // 排序值缺失的排在最后
+            rows.sort_by(|a, b| match (a.sort_key, b.sort_key) {
+                (Some(x), Some(y)) if self.descending => y.total_cmp(&x),
+                (Some(x), Some(y)) => x.total_cmp(&y),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            });
+        }
+        if let Some(limit) = self.limit {
+            rows.truncate(limit);
+        }
+        Ok(CScreenResult {
+            columns: self.columns.iter().map(|col| col.text.clone()).collect(),
+            rows,
+        })
+    }
+
+    // chans 为 (代码, CChan)，lv_list 为参与查询的级别，第一个级别可以不带前缀
+    pub fn screen_chans(
+        &self,
+        chans: &[(String, &CChan)],
+        lv_list: &[KlType],
+    ) -> Result<CScreenResult, CChanException> {
+        let snapshots: Vec<CSymbolSnapshot> = chans
+            .iter()
+            .map(|(code, chan)| CSymbolSnapshot::from_chan(code, chan, lv_list))
+            .collect();
+        self.screen(&snapshots)
+    }
+}
+
+impl CScreenResult {
+    fn header(&self) -> Vec<String> {
+        let mut header = vec!["code".to_string(), "sort_key".to_string()];
+        header.extend(self.columns.iter().cloned());
+        header
+    }
+
+    fn row_strs(&self) -> Vec<Vec<String>> {
+        self.rows
+            .iter()
+            .map(|row| {
+                let mut cells = vec![
+                    row.code.clone(),
+                    row.sort_key.map_or("None".to_string(), |v| v.to_string()),
+                ];
+                cells.extend(row.values.iter().map(|v| v.to_string()));
+                cells
+            })
+            .collect()
+    }
+
+    // 按列对齐的文本表格
+    pub fn to_table(&self) -> String {
+        let header = self.header();
+        let rows = self.row_strs();
+        let widths: Vec<usize> = (0..header.len())
+            .map(|i| {
+                rows.iter()
+                    .map(|r| r[i].chars().count())
+                    .chain([header[i].chars().count()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let fmt_line = |cells: &[String]| {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(c, w)| format!("{}{}", c, " ".repeat(w - c.chars().count())))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+        let mut lines = vec![fmt_line(&header)];
+        lines.push(
+            widths
+                .iter()
+                .map(|w| "-".repeat(*w))
+                .collect::<Vec<_>>()
+                .join("  "),
+        );
+        lines.extend(rows.iter().map(|r| fmt_line(r)));
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let rows: Vec<serde_json::Value> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let mut obj = serde_json::Map::new();
+                obj.insert("code".to_string(), row.code.clone().into());
+                obj.insert(
+                    "sort_key".to_string(),
+                    QueryValue::from(row.sort_key).to_json(),
+                );
+                for (col, v) in self.columns.iter().zip(&row.values) {
+                    obj.insert(col.clone(), v.to_json());
+                }
+                serde_json::Value::Object(obj)
+            })
+            .collect();
+        serde_json::to_string_pretty(&rows).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(code: &str, bsp_type: &str, bars_ago: i32, score: Option<f64>) -> CSymbolSnapshot {
+        let mut snapshot = CSymbolSnapshot::new(code);
+        snapshot.insert("K_DAY.bsp.type".to_string(), bsp_type);
+        snapshot.insert("K_DAY.bsp.is_buy".to_string(), true);
+        snapshot.insert("K_DAY.bsp.bars_ago".to_string(), bars_ago);
+        snapshot.insert("K_DAY.bsp.score".to_string(), score);
+        snapshot.insert("seg.dir".to_string(), "UP");
+        snapshot.insert("seg.zs_cnt".to_string(), 2usize);
+        snapshot
+    }
+
+    #[test]
+    fn test_screen() {
+        let snapshots = vec![
+            snapshot("sz.000001", "T1", 2, Some(0.3)),
+            snapshot("sz.000002", "T1,T2S", 1, None),
+            snapshot("sz.000003", "T2", 1, Some(0.9)),
+            snapshot("sz.000004", "T1P", 0, Some(0.8)),
+            snapshot("sz.000005", "T1", 5, Some(0.7)),
+        ];
+        let mut screener = CScreener::new(
+            "(has(K_DAY.bsp.type, \"T1\") or has(K_DAY.bsp.type, \"T1P\")) \
+             and K_DAY.bsp.bars_ago <= 3 and seg.dir == \"UP\" and seg.zs_cnt >= 2",
+        )
+        .unwrap();
+        screener.set_sort("K_DAY.bsp.score", true).unwrap();
+        screener
+            .set_columns(&["K_DAY.bsp.type", "K_DAY.bsp.bars_ago"])
+            .unwrap();
+        let res = screener.screen(&snapshots).unwrap();
+        let codes: Vec<&str> = res.rows.iter().map(|r| r.code.as_str()).collect();
+        assert_eq!(codes, vec!["sz.000004", "sz.000001", "sz.000002"]);
+        assert_eq!(
+            res.rows[0].values,
+            vec![QueryValue::from("T1P"), QueryValue::from(0)]
+        );
+
+        let table = res.to_table();
+        assert_eq!(
+            table.lines().next().unwrap(),
+            "code       sort_key  K_DAY.bsp.type  K_DAY.bsp.bars_ago"
+        );
+        assert_eq!(table.lines().count(), 5);
+        let json: serde_json::Value = serde_json::from_str(&res.to_json()).unwrap();
+        assert_eq!(json[2]["sort_key"], serde_json::Value::Null);
+        assert_eq!(json[0]["K_DAY.bsp.type"], "T1P");
+
+        screener.descending = false;
+        screener.limit = Some(1);
+        let res = screener.screen(&snapshots).unwrap();
+        assert_eq!(res.rows[0].code, "sz.000001");
+        assert_eq!(res.rows.len(), 1);
+
+        // 未知字段报错
+        screener.set_sort("K_DAY.bsp.feat.macd", true).unwrap();
+        assert!(screener.screen(&snapshots).is_ok());
+        screener.set_sort("K_DAY.bi.amp", true).unwrap();
+        assert!(screener.screen(&snapshots).is_err());
+    }
+
+    #[test]
+    fn test_screen_recent_bsp() {
+        // 最后一个买卖点是 T1，T2 出现在 4 根K线之前
+        let mut s1 = snapshot("sz.000001", "T1", 0, None);
+        s1.add_recent_bsp("K_60M", 0, true, "T1");
+        s1.add_recent_bsp("K_60M", 4, true, "T2,T3A");
+        s1.add_recent_bsp("K_60M", 9, true, "T2");
+        let mut s2 = snapshot("sz.000002", "T1", 0, None);
+        s2.add_recent_bsp("K_60M", 2, false, "T2");
+        s2.add_recent_bsp("K_60M", 8, true, "T2");
+        let snapshots = vec![s1, s2];
+
+        let screener = CScreener::new("has(bsp_types_within(\"K_60M\", true, 5), \"T2\")").unwrap();
+        let res = screener.screen(&snapshots).unwrap();
+        assert_eq!(res.rows.len(), 1);
+        assert_eq!(res.rows[0].code, "sz.000001");
+
+        let mut screener = CScreener::new("true").unwrap();
+        screener
+            .set_columns(&[
+                "bsp_types_within(\"K_60M\", true, 10)",
+                "bsp_types_within(\"K_60M\", false, 10)",
+            ])
+            .unwrap();
+        let res = screener.screen(&snapshots).unwrap();
+        assert_eq!(
+            res.rows[0].values,
+            vec![QueryValue::from("T1,T2,T3A"), QueryValue::from("")]
+        );
+        assert_eq!(
+            res.rows[1].values,
+            vec![QueryValue::from("T2"), QueryValue::from("T2")]
+        );
+
+        // 没有这个级别、参数类型不对都报错
+        let screener = CScreener::new("has(bsp_types_within(\"K_DAY\", true, 5), \"T2\")").unwrap();
+        assert!(screener.screen(&snapshots).is_err());
+        let screener = CScreener::new("has(bsp_types_within(\"K_60M\", 1, 5), \"T2\")").unwrap();
+        assert!(screener.screen(&snapshots).is_err());
+        assert!(CScreener::new("bsp_types_within(\"K_60M\", true)").is_err());
+    }
+}
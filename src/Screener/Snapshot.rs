@@ -0,0 +1,269 @@
+use crate::Bi::Bi::CBi;
+use crate::BuySellPoint::BS_Point::CBSPoint;
+use crate::Chan::CChan;
+use crate::Common::types::{ChanLine, SharedCell};
+use crate::Common::CEnum::KlType;
+use crate::KLine::KLine_List::CKLineList;
+use crate::Screener::Query::{QueryContext, QueryValue};
+use crate::Seg::Seg::CSeg;
+use crate::ZS::ZS::CZS;
+use std::collections::BTreeMap;
+
+const BSP_FIELDS: [&str; 10] = [
+    "type",
+    "is_buy",
+    "bars_ago",
+    "score",
+    "state",
+    "time",
+    "price",
+    "stop_loss",
+    "take_profit",
+    "risk_reward",
+];
+const BI_FIELDS: [&str; 8] = [
+    "dir",
+    "is_sure",
+    "high",
+    "low",
+    "amp",
+    "klu_cnt",
+    "begin_val",
+    "end_val",
+];
+const SEG_FIELDS: [&str; 9] = [
+    "dir",
+    "is_sure",
+    "trend",
+    "zs_cnt",
+    "multi_bi_zs_cnt",
+    "bi_cnt",
+    "high",
+    "low",
+    "amp",
+];
+const ZS_FIELDS: [&str; 7] = [
+    "low",
+    "high",
+    "mid",
+    "peak_low",
+    "peak_high",
+    "bi_cnt",
+    "is_sure",
+];
+
+// 一个品种在最新一根K线上的可查询属性，字段名形如 K_DAY.bsp.type
+// 每个级别有：price/time/bar_idx，最后一个买卖点 bsp.*（特征为 bsp.feat.<name>），
+// 线段级别最后一个买卖点 seg_bsp.*，最后一笔 bi.*，最后一个线段 seg.*，最后一个中枢 zs.*
+// 第一个级别的字段同时可以不带级别前缀使用
+// 各级别全部买卖点另存在 recent_bsps，供 bsp_types_within 查询最近 n 根K线内的买卖点
+#[derive(Clone, Debug, Default)]
+pub struct CSymbolSnapshot {
+    pub code: String,
+    pub attrs: BTreeMap<String, QueryValue>,
+    // 级别（第一个级别另存一份在 "" 下）-> (bars_ago, is_buy, 类型)，按时间从近到远
+    pub recent_bsps: BTreeMap<String, Vec<(i32, bool, String)>>,
+}
+
+impl QueryContext for CSymbolSnapshot {
+    fn field(&self, name: &str) -> Option<QueryValue> {
+        match self.attrs.get(name) {
+            Some(v) => Some(v.clone()),
+            // 不是每个买卖点都有全部特征，没有的特征当作缺失值
+            None if name.contains("bsp.feat.") => Some(QueryValue::Null),
+            None => None,
+        }
+    }
+
+    fn bsp_types_within(&self, level: &str, is_buy: bool, n: f64) -> Option<String> {
+        let mut types: Vec<&str> = Vec::new();
+        for (bars_ago, bsp_is_buy, bsp_type) in self.recent_bsps.get(level)? {
+            if *bars_ago as f64 > n {
+                break;
+            }
+            if *bsp_is_buy != is_buy {
+                continue;
+            }
+            for t in bsp_type.split(',') {
+                if !types.contains(&t) {
+                    types.push(t);
+                }
+            }
+        }
+        Some(types.join(","))
+    }
+}
+
+impl CSymbolSnapshot {
+    pub fn new(code: &str) -> Self {
+        CSymbolSnapshot {
+            code: code.to_string(),
+            attrs: BTreeMap::new(),
+            recent_bsps: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_chan(code: &str, chan: &CChan, lv_list: &[KlType]) -> Self {
+        let mut snapshot = CSymbolSnapshot::new(code);
+        for (idx, lv) in lv_list.iter().enumerate() {
+            if let Some(kl_list) = chan.get(*lv) {
+                snapshot.add_kl_list(&format!("{}.", lv), kl_list);
+                if idx == 0 {
+                    snapshot.add_kl_list("", kl_list);
+                }
+            }
+        }
+        snapshot
+    }
+
+    pub fn insert(&mut self, name: String, value: impl Into<QueryValue>) {
+        self.attrs.insert(name, value.into());
+    }
+
+    // 需按时间从近到远添加
+    pub fn add_recent_bsp(&mut self, level: &str, bars_ago: i32, is_buy: bool, bsp_type: &str) {
+        self.recent_bsps
+            .entry(level.to_string())
+            .or_default()
+            .push((bars_ago, is_buy, bsp_type.to_string()));
+    }
+
+    pub fn add_kl_list(&mut self, prefix: &str, kl_list: &CKLineList) {
+        let cur_klu = kl_list
+            .lst
+            .last()
+            .and_then(|klc| klc.borrow().lst.last().cloned());
+        let cur_idx = cur_klu.as_ref().map(|klu| klu.borrow().idx);
+        self.insert(
+            format!("{}price", prefix),
+            cur_klu.as_ref().map(|klu| klu.borrow().close),
+        );
+        self.insert(
+            format!("{}time", prefix),
+            cur_klu.as_ref().map(|klu| klu.borrow().time.to_string()),
+        );
+        self.insert(format!("{}bar_idx", prefix), cur_idx);
+
+        self.add_bsp(
+            &format!("{}bsp.", prefix),
+            kl_list.bs_point_lst.last(),
+            cur_idx,
+        );
+        if let Some(cur_idx) = cur_idx {
+            let level = prefix.trim_end_matches('.');
+            for bsp in kl_list.bs_point_lst.iter().rev() {
+                let bsp = bsp.borrow();
+                let bars_ago = cur_idx - bsp.klu.borrow().idx;
+                self.add_recent_bsp(level, bars_ago, bsp.is_buy, &bsp.type2str());
+            }
+        }
+        self.add_bsp(
+            &format!("{}seg_bsp.", prefix),
+            kl_list
+                .seg_tiers
+                .first()
+                .and_then(|tier| tier.bs_point_lst.last()),
+            cur_idx,
+        );
+        self.add_bi(&format!("{}bi.", prefix), kl_list.bi_list.bi_list.last());
+
+        let seg_list = kl_list.seg_list.borrow();
+        self.add_seg(&format!("{}seg.", prefix), seg_list.lst.last());
+        // 最后一个中枢可能在更早的线段里
+        let last_zs = seg_list
+            .lst
+            .iter()
+            .rev()
+            .find_map(|seg| seg.borrow().zs_lst.last().cloned());
+        self.add_zs(&format!("{}zs.", prefix), last_zs.as_ref());
+    }
+
+    fn add_fields<const N: usize>(
+        &mut self,
+        prefix: &str,
+        names: [&str; N],
+        values: Option<[QueryValue; N]>,
+    ) {
+        let values = values.unwrap_or_else(|| std::array::from_fn(|_| QueryValue::Null));
+        for (name, value) in names.into_iter().zip(values) {
+            self.attrs.insert(format!("{}{}", prefix, name), value);
+        }
+    }
+
+    fn add_bsp(&mut self, prefix: &str, bsp: Option<&SharedCell<CBSPoint>>, cur_idx: Option<i32>) {
+        let values = bsp.map(|bsp| {
+            let bsp = bsp.borrow();
+            let klu = bsp.klu.borrow();
+            let risk = &bsp.risk;
+            [
+                QueryValue::from(bsp.type2str()),
+                QueryValue::from(bsp.is_buy),
+                QueryValue::from(cur_idx.map(|idx| idx - klu.idx)),
+                QueryValue::from(bsp.score),
+                QueryValue::from(bsp.state.to_string()),
+                QueryValue::from(klu.time.to_string()),
+                QueryValue::from(klu.close),
+                QueryValue::from(risk.stop_loss().map(|l| l.price)),
+                QueryValue::from(risk.take_profit().map(|l| l.price)),
+                QueryValue::from(risk.risk_reward()),
+            ]
+        });
+        if let Some(bsp) = bsp {
+            for (name, value) in bsp.borrow().features.items() {
+                self.insert(format!("{}feat.{}", prefix, name), *value);
+            }
+        }
+        self.add_fields(prefix, BSP_FIELDS, values);
+    }
+
+    fn add_bi(&mut self, prefix: &str, bi: Option<&SharedCell<CBi>>) {
+        let values = bi.map(|bi| {
+            let bi = bi.borrow();
+            [
+                QueryValue::from(bi.dir.to_string()),
+                QueryValue::from(bi.is_sure),
+                QueryValue::from(bi.high()),
+                QueryValue::from(bi.low()),
+                QueryValue::from(bi.amp()),
+                QueryValue::from(bi.get_klu_cnt()),
+                QueryValue::from(bi.get_begin_val()),
+                QueryValue::from(bi.get_end_val()),
+            ]
+        });
+        self.add_fields(prefix, BI_FIELDS, values);
+    }
+
+    fn add_seg<T: ChanLine>(&mut self, prefix: &str, seg: Option<&SharedCell<CSeg<T>>>) {
+        let values = seg.map(|seg| {
+            let seg = seg.borrow();
+            [
+                QueryValue::from(seg.dir.to_string()),
+                QueryValue::from(seg.is_sure),
+                QueryValue::from(seg.get_trend_type().to_string()),
+                QueryValue::from(seg.zs_lst.len()),
+                QueryValue::from(seg.get_multi_bi_zs_cnt()),
+                QueryValue::from(seg.cal_bi_cnt()),
+                QueryValue::from(seg._high()),
+                QueryValue::from(seg._low()),
+                QueryValue::from(seg.amp()),
+            ]
+        });
+        self.add_fields(prefix, SEG_FIELDS, values);
+    }
+
+    fn add_zs(&mut self, prefix: &str, zs: Option<&SharedCell<CZS>>) {
+        let values = zs.map(|zs| {
+            let zs = zs.borrow();
+            [
+                QueryValue::from(zs.low),
+                QueryValue::from(zs.high),
+                QueryValue::from(zs.mid),
+                QueryValue::from(zs.peak_low),
+                QueryValue::from(zs.peak_high),
+                QueryValue::from(zs.get_bi_cnt()),
+                QueryValue::from(zs.is_sure),
+            ]
+        });
+        self.add_fields(prefix, ZS_FIELDS, values);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod Query;
+pub mod Screener;
+pub mod Snapshot;
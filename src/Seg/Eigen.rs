@@ -1,16 +1,14 @@
-use crate::Bi::Bi::CBi;
-
 use crate::Combiner::KLine_Combiner::CKLineCombiner;
-use crate::Common::types::SharedCell;
+use crate::Common::types::{ChanLine, SharedCell};
 use crate::Common::CEnum::{BiDir, FxType};
 
-pub struct CEigen {
-    pub inner: CKLineCombiner<CBi>,
+pub struct CEigen<LINE_TYPE> {
+    pub inner: CKLineCombiner<LINE_TYPE>,
     pub gap: bool,
 }
 
-impl CEigen {
-    pub fn new(bi: SharedCell<CBi>, dir: BiDir) -> Self {
+impl<LINE_TYPE: ChanLine> CEigen<LINE_TYPE> {
+    pub fn new(bi: SharedCell<LINE_TYPE>, dir: BiDir) -> Self {
         CEigen {
             inner: CKLineCombiner::new(bi, dir),
             gap: false,
@@ -19,8 +17,8 @@ impl CEigen {
 
     pub fn update_fx(
         &mut self,
-        _pre: &CEigen,
-        _next: &CEigen,
+        _pre: &CEigen<LINE_TYPE>,
+        _next: &CEigen<LINE_TYPE>,
         exclude_included: bool,
         allow_top_equal: Option<i32>,
     ) {
@@ -35,23 +33,23 @@ impl CEigen {
 
     pub fn get_peak_bi_idx(&self) -> i32 {
         assert!(self.inner.fx() != FxType::Unknown);
-        let BiDir = self.inner.lst()[0].borrow().dir;
+        let BiDir = self.inner.lst()[0].borrow().dir();
         if BiDir == BiDir::Up {
             // 下降线段
-            self.inner.get_peak_klu(false).borrow().idx - 1
+            self.inner.get_peak_klu(false).borrow().idx() - 1
         } else {
-            self.inner.get_peak_klu(true).borrow().idx - 1
+            self.inner.get_peak_klu(true).borrow().idx() - 1
         }
     }
 }
 
-impl std::fmt::Display for CEigen {
+impl<LINE_TYPE: ChanLine> std::fmt::Display for CEigen<LINE_TYPE> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "{}~{} gap={} fx={:?}",
-            self.inner.lst()[0].borrow().idx,
-            self.inner.lst().last().unwrap().borrow().idx,
+            self.inner.lst()[0].borrow().idx(),
+            self.inner.lst().last().unwrap().borrow().idx(),
             self.gap,
             self.inner.fx()
         )
@@ -59,15 +57,15 @@ impl std::fmt::Display for CEigen {
 }
 
 // Implement Deref and DerefMut to allow CEigen to be used like CKLineCombiner
-impl std::ops::Deref for CEigen {
-    type Target = CKLineCombiner<CBi>;
+impl<LINE_TYPE> std::ops::Deref for CEigen<LINE_TYPE> {
+    type Target = CKLineCombiner<LINE_TYPE>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner
     }
 }
 
-impl std::ops::DerefMut for CEigen {
+impl<LINE_TYPE> std::ops::DerefMut for CEigen<LINE_TYPE> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.inner
     }
@@ -1,24 +1,22 @@
-use crate::Bi::Bi::CBi;
-use crate::Bi::BiList::CBiList;
 use crate::Common::func_util::revert_BiDir;
-use crate::Common::types::SharedCell;
+use crate::Common::types::{ChanLine, SharedCell};
 use crate::Common::CEnum::{BiDir, FxType, KlineDir, SegType};
 use crate::Common::ChanException::{CChanException, ErrCode};
 use crate::Seg::Eigen::CEigen;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-pub struct CEigenFX {
+pub struct CEigenFX<LINE_TYPE> {
     pub lv: SegType,
     pub dir: BiDir,
-    pub ele: [Option<SharedCell<CEigen>>; 3],
-    pub lst: Vec<SharedCell<CBi>>,
+    pub ele: [Option<SharedCell<CEigen<LINE_TYPE>>>; 3],
+    pub lst: Vec<SharedCell<LINE_TYPE>>,
     pub exclude_included: bool,
     pub kl_dir: KlineDir,
-    pub last_evidence_bi: Option<SharedCell<CBi>>,
+    pub last_evidence_bi: Option<SharedCell<LINE_TYPE>>,
 }
 
-impl CEigenFX {
+impl<LINE_TYPE: ChanLine> CEigenFX<LINE_TYPE> {
     pub fn new(dir: BiDir, exclude_included: bool, lv: SegType) -> Self {
         CEigenFX {
             lv,
@@ -35,12 +33,12 @@ impl CEigenFX {
         }
     }
 
-    fn treat_first_ele(&mut self, bi: SharedCell<CBi>) -> bool {
+    fn treat_first_ele(&mut self, bi: SharedCell<LINE_TYPE>) -> bool {
         self.ele[0] = Some(Rc::new(RefCell::new(CEigen::new(bi, self.kl_dir))));
         false
     }
 
-    fn treat_second_ele(&mut self, bi: SharedCell<CBi>) -> bool {
+    fn treat_second_ele(&mut self, bi: SharedCell<LINE_TYPE>) -> bool {
         let ele0 = self.ele[0].as_ref().unwrap();
         let combine_dir = ele0
             .borrow_mut()
@@ -58,7 +56,7 @@ impl CEigenFX {
         false
     }
 
-    fn treat_third_ele(&mut self, bi: SharedCell<CBi>) -> bool {
+    fn treat_third_ele(&mut self, bi: SharedCell<LINE_TYPE>) -> bool {
         self.last_evidence_bi = Some(bi.clone());
         let allow_top_equal = if self.exclude_included {
             Some(if bi.borrow().is_down() { 1 } else { -1 })
@@ -93,8 +91,8 @@ impl CEigenFX {
         }
     }
 
-    pub fn add(&mut self, bi: SharedCell<CBi>) -> bool {
-        assert!(bi.borrow().dir != self.dir);
+    pub fn add(&mut self, bi: SharedCell<LINE_TYPE>) -> bool {
+        assert!(bi.borrow().dir() != self.dir);
         self.lst.push(bi.clone());
         if self.ele[0].is_none() {
             self.treat_first_ele(bi)
@@ -105,7 +103,7 @@ impl CEigenFX {
         } else {
             panic!(
                 "特征序列3个都找齐了还没处理!! 当前笔:{},当前:{}",
-                bi.borrow().idx,
+                bi.borrow().idx(),
                 self.to_string()
             );
         }
@@ -121,19 +119,21 @@ impl CEigenFX {
                 }
             }
         } else {
-            let ele2_begin_idx = self.ele[1].as_ref().unwrap().borrow().lst()[0].borrow().idx;
+            let ele2_begin_idx = self.ele[1].as_ref().unwrap().borrow().lst()[0]
+                .borrow()
+                .idx();
             self.ele[0] = self.ele[1].take();
             self.ele[1] = self.ele[2].take();
             self.ele[2] = None;
             self.lst = bi_tmp_list
                 .into_iter()
-                .filter(|bi| bi.borrow().idx >= ele2_begin_idx)
+                .filter(|bi| bi.borrow().idx() >= ele2_begin_idx)
                 .collect();
         }
         false
     }
 
-    pub fn can_be_end(&mut self, bi_lst: &CBiList) -> Option<bool> {
+    pub fn can_be_end(&mut self, bi_lst: &[SharedCell<LINE_TYPE>]) -> Option<bool> {
         if self.ele[1].as_ref().unwrap().borrow().gap {
             let end_bi_idx = self.get_peak_bi_idx();
             let thred_value = bi_lst[end_bi_idx].borrow().get_end_val();
@@ -161,8 +161,8 @@ impl CEigenFX {
     }
 
     pub fn all_bi_is_sure(&self) -> bool {
-        self.lst.iter().all(|bi| bi.borrow().is_sure)
-            && self.last_evidence_bi.as_ref().unwrap().borrow().is_sure
+        self.lst.iter().all(|bi| bi.borrow().is_sure())
+            && self.last_evidence_bi.as_ref().unwrap().borrow().is_sure()
     }
 
     pub fn clear(&mut self) {
@@ -183,16 +183,16 @@ impl CEigenFX {
         }
         assert_eq!(ele2.lst().len(), 1);
         let ele2_bi = &ele2.lst()[0];
-        if let Some(next) = &ele2_bi.borrow().next {
-            if let Some(next_next) = &next.borrow().next {
+        if let Some(next) = ele2_bi.borrow().next_line() {
+            if let Some(next_next) = next.borrow().next_line() {
                 if ele2_bi.borrow().is_down() && next_next.borrow()._low() < ele2_bi.borrow()._low()
                 {
-                    self.last_evidence_bi = Some(next_next.clone());
+                    self.last_evidence_bi = Some(Rc::clone(&next_next));
                     return true;
                 } else if ele2_bi.borrow().is_up()
                     && next_next.borrow()._high() > ele2_bi.borrow()._high()
                 {
-                    self.last_evidence_bi = Some(next_next.clone());
+                    self.last_evidence_bi = Some(Rc::clone(&next_next));
                     return true;
                 }
             }
@@ -202,13 +202,13 @@ impl CEigenFX {
 
     fn find_revert_fx(
         &mut self,
-        bi_list: &CBiList,
+        bi_list: &[SharedCell<LINE_TYPE>],
         begin_idx: i32,
         thred_value: f64,
         break_thred: f64,
     ) -> Option<bool> {
         const COMMON_COMBINE: bool = true;
-        let first_BiDir = bi_list[begin_idx as usize].borrow().dir;
+        let first_BiDir = bi_list[begin_idx as usize].borrow().dir();
         let mut eigen_fx = CEigenFX::new(revert_BiDir(first_BiDir), !COMMON_COMBINE, self.lv);
         for bi in bi_list.iter().skip(begin_idx as usize).step_by(2) {
             if eigen_fx.add(bi.clone()) {
@@ -243,7 +243,7 @@ impl CEigenFX {
     }
 }
 
-impl std::fmt::Display for CEigenFX {
+impl<LINE_TYPE: ChanLine> std::fmt::Display for CEigenFX<LINE_TYPE> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let t: Vec<String> = self
             .ele
@@ -253,7 +253,7 @@ impl std::fmt::Display for CEigenFX {
                     e.borrow()
                         .lst()
                         .iter()
-                        .map(|b| b.borrow().idx.to_string())
+                        .map(|b| b.borrow().idx().to_string())
                         .collect::<Vec<_>>()
                         .join(",")
                 } else {
@@ -1,14 +1,13 @@
 use crate::BuySellPoint::BS_Point::CBSPoint;
-use crate::Common::types::SharedCell;
-use crate::Common::CEnum::{BiDir, MacdAlgo, TrendLineSide};
+use crate::Common::types::{ChanLine, LineType, SharedCell};
+use crate::Common::CEnum::{BiDir, MacdAlgo, SegTrendType, ZsRelation};
 use crate::Common::ChanException::{CChanException, ErrCode};
 use crate::KLine::KLine_Unit::CKLineUnit;
-use crate::Math::TrendLine::CTrendLine;
+use crate::Math::MacdMetric::{amp_metric, line_macd_metric, slope_metric};
+use crate::Math::TrendLine::{support_resistance_side, CTrendChannel, CTrendLine};
 use crate::Seg::EigenFX::CEigenFX;
 use crate::ZS::ZS::CZS;
-use std::cell::RefCell;
 use std::marker::PhantomData;
-use std::rc::Rc;
 
 pub struct CSeg<LINE_TYPE> {
     pub idx: i32,
@@ -17,21 +16,25 @@ pub struct CSeg<LINE_TYPE> {
     pub is_sure: bool,
     pub dir: BiDir,
     pub zs_lst: Vec<SharedCell<CZS>>,
-    pub eigen_fx: Option<SharedCell<CEigenFX>>,
+    pub eigen_fx: Option<SharedCell<CEigenFX<LINE_TYPE>>>,
     pub seg_idx: Option<i32>,
-    pub parent_seg: Option<SharedCell<CSeg<LINE_TYPE>>>,
+    // 本线段作为上一层的“笔”时所属的线段，上一层的子线统一为LineType
+    pub parent_seg: Option<SharedCell<CSeg<LineType>>>,
     pub pre: Option<SharedCell<CSeg<LINE_TYPE>>>,
     pub next: Option<SharedCell<CSeg<LINE_TYPE>>>,
     pub bsp: Option<SharedCell<CBSPoint>>,
     pub bi_list: Vec<SharedCell<LINE_TYPE>>,
     pub reason: String,
+    // 支撑线总是连低点、压力线总是连高点，由 support_resistance_side 按线段方向取内外侧；
+    // 之前固定支撑线为内侧、压力线为外侧，下跌线段的两条线与现在正好互换
     pub support_trend_line: Option<CTrendLine>,
     pub resistance_trend_line: Option<CTrendLine>,
+    pub channel: Option<CTrendChannel>,
     pub ele_inside_is_sure: bool,
     _phantom: PhantomData<LINE_TYPE>,
 }
 
-impl<LINE_TYPE> CSeg<LINE_TYPE> {
+impl<LINE_TYPE: ChanLine> CSeg<LINE_TYPE> {
     pub fn new(
         idx: i32,
         start_bi: SharedCell<LINE_TYPE>,
@@ -40,7 +43,7 @@ impl<LINE_TYPE> CSeg<LINE_TYPE> {
         seg_dir: Option<BiDir>,
         reason: &str,
     ) -> Result<Self, CChanException> {
-        let dir = seg_dir.unwrap_or_else(|| end_bi.borrow().dir);
+        let dir = seg_dir.unwrap_or_else(|| end_bi.borrow().dir());
         let mut seg = CSeg {
             idx,
             start_bi: start_bi.clone(),
@@ -58,11 +61,12 @@ impl<LINE_TYPE> CSeg<LINE_TYPE> {
             reason: reason.to_string(),
             support_trend_line: None,
             resistance_trend_line: None,
+            channel: None,
             ele_inside_is_sure: false,
             _phantom: PhantomData,
         };
 
-        if end_bi.borrow().idx - start_bi.borrow().idx < 2 {
+        if end_bi.borrow().idx() - start_bi.borrow().idx() < 2 {
             seg.is_sure = false;
         }
         seg.check()?;
@@ -90,12 +94,12 @@ impl<LINE_TYPE> CSeg<LINE_TYPE> {
                 ErrCode::SegEndValueErr,
             ));
         }
-        if self.end_bi.borrow().idx - self.start_bi.borrow().idx < 2 {
+        if self.end_bi.borrow().idx() - self.start_bi.borrow().idx() < 2 {
             return Err(CChanException::new(
                 format!(
                     "线段({}-{})长度不能小于2! idx={}",
-                    self.start_bi.borrow().idx,
-                    self.end_bi.borrow().idx,
+                    self.start_bi.borrow().idx(),
+                    self.end_bi.borrow().idx(),
                     self.idx
                 ),
                 ErrCode::SegLenErr,
@@ -119,7 +123,7 @@ impl<LINE_TYPE> CSeg<LINE_TYPE> {
     }
 
     pub fn cal_bi_cnt(&self) -> i32 {
-        self.end_bi.borrow().idx - self.start_bi.borrow().idx + 1
+        self.end_bi.borrow().idx() - self.start_bi.borrow().idx() + 1
     }
 
     pub fn clear_zs_lst(&mut self) {
@@ -143,11 +147,11 @@ impl<LINE_TYPE> CSeg<LINE_TYPE> {
     }
 
     pub fn is_down(&self) -> bool {
-        self.dir == BiDir::Down
+        self.dir == BiDir::DOWN
     }
 
     pub fn is_up(&self) -> bool {
-        self.dir == BiDir::Up
+        self.dir == BiDir::UP
     }
 
     pub fn get_end_val(&self) -> f64 {
@@ -179,52 +183,56 @@ impl<LINE_TYPE> CSeg<LINE_TYPE> {
         macd_algo: MacdAlgo,
         is_reverse: bool,
     ) -> Result<f64, CChanException> {
-        match macd_algo {
-            MacdAlgo::Slope => Ok(self.cal_macd_slope()),
-            MacdAlgo::Amp => Ok(self.cal_macd_amp()),
-            _ => Err(CChanException::new(
-                format!(
-                    "unsupport macd_algo={:?} of Seg, should be one of slope/amp",
-                    macd_algo
-                ),
-                ErrCode::ParaError,
-            )),
+        line_macd_metric(
+            macd_algo,
+            is_reverse,
+            self.is_down(),
+            &self.klu_lst(),
+            &self.get_begin_klu().borrow(),
+            &self.get_end_klu().borrow(),
+        )
+    }
+
+    // 线段从起点到终点的所有K线
+    fn klu_lst(&self) -> Vec<SharedCell<CKLineUnit>> {
+        let end_idx = self.get_end_klu().borrow().idx;
+        let mut res = Vec::new();
+        let mut klu = Some(self.get_begin_klu());
+        while let Some(cur) = klu {
+            if cur.borrow().idx > end_idx {
+                break;
+            }
+            klu = cur.borrow().next.clone();
+            res.push(cur);
         }
+        res
     }
 
     pub fn cal_macd_slope(&self) -> f64 {
-        let begin_klu = self.get_begin_klu();
-        let end_klu = self.get_end_klu();
-        if self.is_up() {
-            (end_klu.borrow().high - begin_klu.borrow().low)
-                / end_klu.borrow().high
-                / (end_klu.borrow().idx - begin_klu.borrow().idx + 1) as f64
-        } else {
-            (begin_klu.borrow().high - end_klu.borrow().low)
-                / begin_klu.borrow().high
-                / (end_klu.borrow().idx - begin_klu.borrow().idx + 1) as f64
-        }
+        slope_metric(
+            &self.get_begin_klu().borrow(),
+            &self.get_end_klu().borrow(),
+            self.is_up(),
+        )
     }
 
     pub fn cal_macd_amp(&self) -> f64 {
-        let begin_klu = self.get_begin_klu();
-        let end_klu = self.get_end_klu();
-        if self.is_down() {
-            (begin_klu.borrow().high - end_klu.borrow().low) / begin_klu.borrow().high
-        } else {
-            (end_klu.borrow().high - begin_klu.borrow().low) / begin_klu.borrow().low
-        }
+        amp_metric(
+            &self.get_begin_klu().borrow(),
+            &self.get_end_klu().borrow(),
+            self.is_down(),
+        )
     }
 
     pub fn update_bi_list(&mut self, bi_lst: &[SharedCell<LINE_TYPE>], idx1: usize, idx2: usize) {
         for bi_idx in idx1..=idx2 {
-            bi_lst[bi_idx].borrow_mut().parent_seg = Some(Rc::new(RefCell::new(self.clone())));
             self.bi_list.push(bi_lst[bi_idx].clone());
         }
         if self.bi_list.len() >= 3 {
-            self.support_trend_line = Some(CTrendLine::new(&self.bi_list, TrendLineSide::Inside));
-            self.resistance_trend_line =
-                Some(CTrendLine::new(&self.bi_list, TrendLineSide::Outside));
+            let (support_side, resistance_side) = support_resistance_side(self.dir);
+            self.support_trend_line = Some(CTrendLine::new(&self.bi_list, support_side));
+            self.resistance_trend_line = Some(CTrendLine::new(&self.bi_list, resistance_side));
+            self.channel = CTrendChannel::new(&self.bi_list);
         }
     }
 
@@ -249,17 +257,157 @@ impl<LINE_TYPE> CSeg<LINE_TYPE> {
             .filter(|zs| !zs.borrow().is_one_bi_zs())
             .count()
     }
+
+    pub fn get_zs_relations(&self) -> Vec<ZsRelation> {
+        cal_zs_relations(&self.zs_lst)
+    }
+
+    pub fn get_trend_type(&self) -> SegTrendType {
+        cal_trend_type(&self.zs_lst, self.dir)
+    }
+}
+
+// 中枢关系用波动区间(peak_low/peak_high)判断，完全不重叠才算上移/下移
+pub fn cal_zs_relation(zs1: &CZS, zs2: &CZS) -> ZsRelation {
+    if zs2.peak_low > zs1.peak_high {
+        ZsRelation::UP
+    } else if zs2.peak_high < zs1.peak_low {
+        ZsRelation::DOWN
+    } else {
+        ZsRelation::EXTEND
+    }
 }
 
-impl<LINE_TYPE> std::fmt::Display for CSeg<LINE_TYPE> {
+pub fn cal_zs_relations(zs_lst: &[SharedCell<CZS>]) -> Vec<ZsRelation> {
+    let multi_bi_zs: Vec<&SharedCell<CZS>> = zs_lst
+        .iter()
+        .filter(|zs| !zs.borrow().is_one_bi_zs())
+        .collect();
+    multi_bi_zs
+        .windows(2)
+        .map(|pair| cal_zs_relation(&pair[0].borrow(), &pair[1].borrow()))
+        .collect()
+}
+
+pub fn cal_trend_type(zs_lst: &[SharedCell<CZS>], dir: BiDir) -> SegTrendType {
+    let relations = cal_zs_relations(zs_lst);
+    let zs_cnt = zs_lst
+        .iter()
+        .filter(|zs| !zs.borrow().is_one_bi_zs())
+        .count();
+    if zs_cnt == 0 {
+        return SegTrendType::NO_ZS;
+    }
+    if zs_cnt == 1 {
+        return SegTrendType::CONSOLIDATION;
+    }
+    if dir == BiDir::UP && relations.iter().all(|r| *r == ZsRelation::UP) {
+        SegTrendType::UP_TREND
+    } else if dir == BiDir::DOWN && relations.iter().all(|r| *r == ZsRelation::DOWN) {
+        SegTrendType::DOWN_TREND
+    } else {
+        SegTrendType::CONSOLIDATION
+    }
+}
+
+pub fn zs_relations2str(relations: &[ZsRelation]) -> String {
+    relations
+        .iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl<LINE_TYPE: ChanLine> std::fmt::Display for CSeg<LINE_TYPE> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "{}->{}:{:?} {}",
-            self.start_bi.borrow().idx,
-            self.end_bi.borrow().idx,
+            self.start_bi.borrow().idx(),
+            self.end_bi.borrow().idx(),
             self.dir,
             self.is_sure
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn zs(peak_low: f64, peak_high: f64) -> SharedCell<CZS> {
+        let mut zs = CZS::new(None, true);
+        zs.peak_low = peak_low;
+        zs.peak_high = peak_high;
+        zs.low = peak_low;
+        zs.high = peak_high;
+        Rc::new(RefCell::new(zs))
+    }
+
+    #[test]
+    fn test_cal_zs_relation() {
+        let base = zs(10.0, 12.0);
+        let base = base.borrow();
+        assert_eq!(
+            cal_zs_relation(&base, &zs(12.5, 14.0).borrow()),
+            ZsRelation::UP
+        );
+        assert_eq!(
+            cal_zs_relation(&base, &zs(8.0, 9.5).borrow()),
+            ZsRelation::DOWN
+        );
+        // 波动区间有重叠（包括刚好相接）算扩展
+        assert_eq!(
+            cal_zs_relation(&base, &zs(11.0, 13.0).borrow()),
+            ZsRelation::EXTEND
+        );
+        assert_eq!(
+            cal_zs_relation(&base, &zs(12.0, 13.0).borrow()),
+            ZsRelation::EXTEND
+        );
+        assert_eq!(
+            cal_zs_relation(&base, &zs(9.0, 10.0).borrow()),
+            ZsRelation::EXTEND
+        );
+    }
+
+    #[test]
+    fn test_cal_trend_type() {
+        assert_eq!(cal_trend_type(&[], BiDir::UP), SegTrendType::NO_ZS);
+        assert_eq!(
+            cal_trend_type(&[zs(10.0, 12.0)], BiDir::UP),
+            SegTrendType::CONSOLIDATION
+        );
+
+        let up_lst = vec![zs(10.0, 12.0), zs(13.0, 15.0), zs(16.0, 18.0)];
+        assert_eq!(
+            cal_zs_relations(&up_lst),
+            vec![ZsRelation::UP, ZsRelation::UP]
+        );
+        assert_eq!(cal_trend_type(&up_lst, BiDir::UP), SegTrendType::UP_TREND);
+        // 中枢上移但线段向下，不算趋势
+        assert_eq!(
+            cal_trend_type(&up_lst, BiDir::DOWN),
+            SegTrendType::CONSOLIDATION
+        );
+
+        let down_lst = vec![zs(16.0, 18.0), zs(13.0, 15.0)];
+        assert_eq!(
+            cal_trend_type(&down_lst, BiDir::DOWN),
+            SegTrendType::DOWN_TREND
+        );
+
+        // 任一对中枢有重叠就是盘整
+        let mixed_lst = vec![zs(10.0, 12.0), zs(13.0, 15.0), zs(14.0, 16.0)];
+        assert_eq!(
+            cal_zs_relations(&mixed_lst),
+            vec![ZsRelation::UP, ZsRelation::EXTEND]
+        );
+        assert_eq!(
+            cal_trend_type(&mixed_lst, BiDir::UP),
+            SegTrendType::CONSOLIDATION
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Bi::BiList::CBiList;
+use crate::Common::types::{ChanLine, SharedCell};
 use crate::Common::CEnum::{BiDir, SegType};
 use crate::Common::ChanException::CChanException;
 use crate::Seg::EigenFX::CEigenFX;
@@ -12,7 +12,7 @@ pub struct CSegListChan<SUB_LINE_TYPE> {
     inner: CSegListComm<SUB_LINE_TYPE>,
 }
 
-impl<SUB_LINE_TYPE> CSegListChan<SUB_LINE_TYPE> {
+impl<SUB_LINE_TYPE: ChanLine> CSegListChan<SUB_LINE_TYPE> {
     pub fn new(seg_config: Option<CSegConfig>, lv: SegType) -> Self {
         CSegListChan {
             inner: CSegListComm::new(seg_config, lv),
@@ -24,7 +24,7 @@ impl<SUB_LINE_TYPE> CSegListChan<SUB_LINE_TYPE> {
         while !self.inner.lst.is_empty() && !self.inner.lst.last().unwrap().borrow().is_sure {
             let _seg = self.inner.lst.pop().unwrap();
             for bi in &_seg.borrow().bi_list {
-                bi.borrow_mut().parent_seg = None;
+                bi.borrow_mut().clear_parent_seg();
             }
             if let Some(pre) = &_seg.borrow().pre {
                 pre.borrow_mut().next = None;
@@ -72,19 +72,31 @@ impl<SUB_LINE_TYPE> CSegListChan<SUB_LINE_TYPE> {
         }
     }
 
-    pub fn update(&mut self, bi_lst: &CBiList) -> Result<(), CChanException> {
+    pub fn update(&mut self, bi_lst: &[SharedCell<SUB_LINE_TYPE>]) -> Result<(), CChanException> {
         self.do_init();
         if self.inner.lst.is_empty() {
             self.cal_seg_sure(bi_lst, 0)?;
         } else {
-            let last_end_bi_idx = self.inner.lst.last().unwrap().borrow().end_bi.borrow().idx;
+            let last_end_bi_idx = self
+                .inner
+                .lst
+                .last()
+                .unwrap()
+                .borrow()
+                .end_bi
+                .borrow()
+                .idx();
             self.cal_seg_sure(bi_lst, last_end_bi_idx + 1)?;
         }
         self.inner.collect_left_seg(bi_lst)?;
         Ok(())
     }
 
-    pub fn cal_seg_sure(&mut self, bi_lst: &CBiList, begin_idx: i32) -> Result<(), CChanException> {
+    pub fn cal_seg_sure(
+        &mut self,
+        bi_lst: &[SharedCell<SUB_LINE_TYPE>],
+        begin_idx: i32,
+    ) -> Result<(), CChanException> {
         let mut up_eigen = CEigenFX::new(BiDir::Up, false, self.inner.lv);
         let mut down_eigen = CEigenFX::new(BiDir::Down, false, self.inner.lv);
         let mut last_seg_dir = if self.inner.lst.is_empty() {
@@ -114,12 +126,12 @@ impl<SUB_LINE_TYPE> CSegListChan<SUB_LINE_TYPE> {
                 }
                 if up_eigen.ele[1].is_none()
                     && last_seg_dir == Some(BiDir::Down)
-                    && bi.borrow().dir == BiDir::Down
+                    && bi.borrow().dir() == BiDir::Down
                 {
                     last_seg_dir = None;
                 } else if down_eigen.ele[1].is_none()
                     && last_seg_dir == Some(BiDir::Up)
-                    && bi.borrow().dir == BiDir::Up
+                    && bi.borrow().dir() == BiDir::Up
                 {
                     last_seg_dir = None;
                 }
@@ -135,8 +147,8 @@ impl<SUB_LINE_TYPE> CSegListChan<SUB_LINE_TYPE> {
 
     pub fn treat_fx_eigen(
         &mut self,
-        fx_eigen: &mut CEigenFX,
-        bi_lst: &CBiList,
+        fx_eigen: &mut CEigenFX<SUB_LINE_TYPE>,
+        bi_lst: &[SharedCell<SUB_LINE_TYPE>],
     ) -> Result<(), CChanException> {
         let _test = fx_eigen.can_be_end(bi_lst);
         let end_bi_idx = fx_eigen.get_peak_bi_idx();
@@ -161,7 +173,7 @@ impl<SUB_LINE_TYPE> CSegListChan<SUB_LINE_TYPE> {
                 }
             }
             Some(false) => {
-                self.cal_seg_sure(bi_lst, fx_eigen.lst[1].borrow().idx)?;
+                self.cal_seg_sure(bi_lst, fx_eigen.lst[1].borrow().idx())?;
             }
         }
         Ok(())
@@ -1,6 +1,4 @@
-use crate::Bi::Bi::CBi;
-use crate::Bi::BiList::CBiList;
-use crate::Common::types::SharedCell;
+use crate::Common::types::{ChanLine, SharedCell};
 use crate::Common::CEnum::{BiDir, LeftSegMethod, SegType};
 use crate::Common::ChanException::{CChanException, ErrCode};
 use crate::Seg::Seg::CSeg;
@@ -16,7 +14,7 @@ pub struct CSegListComm<SUB_LINE_TYPE> {
     _phantom: PhantomData<SUB_LINE_TYPE>,
 }
 
-impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
+impl<SUB_LINE_TYPE: ChanLine> CSegListComm<SUB_LINE_TYPE> {
     pub fn new(seg_config: Option<CSegConfig>, lv: SegType) -> Self {
         let mut seg_list = CSegListComm {
             lst: Vec::new(),
@@ -32,14 +30,14 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
         self.lst.clear();
     }
 
-    pub fn left_bi_break(&self, bi_lst: &CBiList) -> bool {
+    pub fn left_bi_break(&self, bi_lst: &[SharedCell<SUB_LINE_TYPE>]) -> bool {
         if self.lst.is_empty() {
             return false;
         }
         let last_seg_end_bi = &self.lst.last().unwrap().borrow().end_bi;
         for bi in bi_lst
             .iter()
-            .skip(last_seg_end_bi.borrow().idx as usize + 1)
+            .skip(last_seg_end_bi.borrow().idx() as usize + 1)
         {
             if last_seg_end_bi.borrow().is_up()
                 && bi.borrow()._high() > last_seg_end_bi.borrow()._high()
@@ -54,7 +52,10 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
         false
     }
 
-    pub fn collect_first_seg(&mut self, bi_lst: &CBiList) -> Result<(), CChanException> {
+    pub fn collect_first_seg(
+        &mut self,
+        bi_lst: &[SharedCell<SUB_LINE_TYPE>],
+    ) -> Result<(), CChanException> {
         if bi_lst.len() < 3 {
             return Ok(());
         }
@@ -75,7 +76,7 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
                     if let Some(peak_bi) = peak_bi {
                         self.add_new_seg(
                             bi_lst,
-                            peak_bi.borrow().idx,
+                            peak_bi.borrow().idx(),
                             false,
                             Some(BiDir::Up),
                             false,
@@ -87,7 +88,7 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
                     if let Some(peak_bi) = peak_bi {
                         self.add_new_seg(
                             bi_lst,
-                            peak_bi.borrow().idx,
+                            peak_bi.borrow().idx(),
                             false,
                             Some(BiDir::Down),
                             false,
@@ -107,7 +108,7 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
                 };
                 self.add_new_seg(
                     bi_lst,
-                    bi_lst.last().unwrap().borrow().idx,
+                    bi_lst.last().unwrap().borrow().idx(),
                     false,
                     Some(_dir),
                     false,
@@ -127,16 +128,16 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
     pub fn collect_left_seg_peak_method(
         &mut self,
         last_seg_end_bi: SharedCell<SUB_LINE_TYPE>,
-        bi_lst: &CBiList,
+        bi_lst: &[SharedCell<SUB_LINE_TYPE>],
     ) -> Result<(), CChanException> {
         if last_seg_end_bi.borrow().is_down() {
             if let Some(peak_bi) =
-                find_peak_bi(&bi_lst[last_seg_end_bi.borrow().idx as usize + 3..], true)
+                find_peak_bi(&bi_lst[last_seg_end_bi.borrow().idx() as usize + 3..], true)
             {
-                if peak_bi.borrow().idx - last_seg_end_bi.borrow().idx >= 3 {
+                if peak_bi.borrow().idx() - last_seg_end_bi.borrow().idx() >= 3 {
                     self.add_new_seg(
                         bi_lst,
-                        peak_bi.borrow().idx,
+                        peak_bi.borrow().idx(),
                         false,
                         Some(BiDir::Up),
                         true,
@@ -145,13 +146,14 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
                 }
             }
         } else {
-            if let Some(peak_bi) =
-                find_peak_bi(&bi_lst[last_seg_end_bi.borrow().idx as usize + 3..], false)
-            {
-                if peak_bi.borrow().idx - last_seg_end_bi.borrow().idx >= 3 {
+            if let Some(peak_bi) = find_peak_bi(
+                &bi_lst[last_seg_end_bi.borrow().idx() as usize + 3..],
+                false,
+            ) {
+                if peak_bi.borrow().idx() - last_seg_end_bi.borrow().idx() >= 3 {
                     self.add_new_seg(
                         bi_lst,
-                        peak_bi.borrow().idx,
+                        peak_bi.borrow().idx(),
                         false,
                         Some(BiDir::Down),
                         true,
@@ -165,21 +167,24 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
         Ok(())
     }
 
-    pub fn collect_segs(&mut self, bi_lst: &CBiList) -> Result<(), CChanException> {
+    pub fn collect_segs(
+        &mut self,
+        bi_lst: &[SharedCell<SUB_LINE_TYPE>],
+    ) -> Result<(), CChanException> {
         let last_bi = bi_lst.last().unwrap();
         let last_seg_end_bi = self.lst.last().unwrap().borrow().end_bi.clone();
-        if last_bi.borrow().idx - last_seg_end_bi.borrow().idx < 3 {
+        if last_bi.borrow().idx() - last_seg_end_bi.borrow().idx() < 3 {
             return Ok(());
         }
         if last_seg_end_bi.borrow().is_down()
             && last_bi.borrow().get_end_val() <= last_seg_end_bi.borrow().get_end_val()
         {
             if let Some(peak_bi) =
-                find_peak_bi(&bi_lst[last_seg_end_bi.borrow().idx as usize + 3..], true)
+                find_peak_bi(&bi_lst[last_seg_end_bi.borrow().idx() as usize + 3..], true)
             {
                 self.add_new_seg(
                     bi_lst,
-                    peak_bi.borrow().idx,
+                    peak_bi.borrow().idx(),
                     false,
                     Some(BiDir::Up),
                     true,
@@ -190,12 +195,13 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
         } else if last_seg_end_bi.borrow().is_up()
             && last_bi.borrow().get_end_val() >= last_seg_end_bi.borrow().get_end_val()
         {
-            if let Some(peak_bi) =
-                find_peak_bi(&bi_lst[last_seg_end_bi.borrow().idx as usize + 3..], false)
-            {
+            if let Some(peak_bi) = find_peak_bi(
+                &bi_lst[last_seg_end_bi.borrow().idx() as usize + 3..],
+                false,
+            ) {
                 self.add_new_seg(
                     bi_lst,
-                    peak_bi.borrow().idx,
+                    peak_bi.borrow().idx(),
                     false,
                     Some(BiDir::Down),
                     true,
@@ -216,7 +222,10 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
         Ok(())
     }
 
-    pub fn collect_left_seg(&mut self, bi_lst: &CBiList) -> Result<(), CChanException> {
+    pub fn collect_left_seg(
+        &mut self,
+        bi_lst: &[SharedCell<SUB_LINE_TYPE>],
+    ) -> Result<(), CChanException> {
         if self.lst.is_empty() {
             self.collect_first_seg(bi_lst)?;
         } else {
@@ -225,16 +234,19 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
         Ok(())
     }
 
-    pub fn collect_left_as_seg(&mut self, bi_lst: &CBiList) -> Result<(), CChanException> {
+    pub fn collect_left_as_seg(
+        &mut self,
+        bi_lst: &[SharedCell<SUB_LINE_TYPE>],
+    ) -> Result<(), CChanException> {
         let last_bi = bi_lst.last().unwrap();
         let last_seg_end_bi = self.lst.last().unwrap().borrow().end_bi.clone();
-        if last_seg_end_bi.borrow().idx + 1 >= bi_lst.len() as i32 {
+        if last_seg_end_bi.borrow().idx() + 1 >= bi_lst.len() as i32 {
             return Ok(());
         }
-        if last_seg_end_bi.borrow().dir == last_bi.borrow().dir {
+        if last_seg_end_bi.borrow().dir() == last_bi.borrow().dir() {
             self.add_new_seg(
                 bi_lst,
-                last_bi.borrow().idx - 1,
+                last_bi.borrow().idx() - 1,
                 false,
                 None,
                 true,
@@ -243,7 +255,7 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
         } else {
             self.add_new_seg(
                 bi_lst,
-                last_bi.borrow().idx,
+                last_bi.borrow().idx(),
                 false,
                 None,
                 true,
@@ -255,7 +267,7 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
 
     pub fn try_add_new_seg(
         &mut self,
-        bi_lst: &CBiList,
+        bi_lst: &[SharedCell<SUB_LINE_TYPE>],
         end_bi_idx: i32,
         is_sure: bool,
         seg_dir: Option<BiDir>,
@@ -273,16 +285,16 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
             ) {
                 if (peak_bi.borrow().is_down()
                     && (peak_bi.borrow()._low() < bi_lst[0].borrow()._low()
-                        || peak_bi.borrow().idx == 0))
+                        || peak_bi.borrow().idx() == 0))
                     || (peak_bi.borrow().is_up()
                         && (peak_bi.borrow()._high() > bi_lst[0].borrow()._high()
-                            || peak_bi.borrow().idx == 0))
+                            || peak_bi.borrow().idx() == 0))
                 {
                     self.add_new_seg(
                         bi_lst,
-                        peak_bi.borrow().idx,
+                        peak_bi.borrow().idx(),
                         false,
-                        Some(peak_bi.borrow().dir),
+                        Some(peak_bi.borrow().dir()),
                         true,
                         "split_first_1st",
                     )?;
@@ -294,7 +306,7 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
         let bi1_idx = if self.lst.is_empty() {
             0
         } else {
-            self.lst.last().unwrap().borrow().end_bi.borrow().idx + 1
+            self.lst.last().unwrap().borrow().end_bi.borrow().idx() + 1
         };
         let bi1 = bi_lst[bi1_idx as usize].clone();
         let bi2 = bi_lst[end_bi_idx as usize].clone();
@@ -307,21 +319,24 @@ impl<SUB_LINE_TYPE> CSegListComm<SUB_LINE_TYPE> {
             reason,
         )?));
 
-        if self.lst.len() >= 2 {
-            let last_seg = self.lst.last().unwrap().clone();
+        // 与前一根线段互相链接，第1根线段（idx=1）也要连到第0根
+        if let Some(last_seg) = self.lst.last().cloned() {
             last_seg.borrow_mut().next = Some(new_seg.clone());
             new_seg.borrow_mut().pre = Some(last_seg);
         }
         new_seg
             .borrow_mut()
             .update_bi_list(bi_lst, bi1_idx as usize, end_bi_idx as usize);
+        for bi in &bi_lst[bi1_idx as usize..=end_bi_idx as usize] {
+            bi.borrow_mut().set_parent_seg(Rc::clone(&new_seg));
+        }
         self.lst.push(new_seg);
         Ok(())
     }
 
     pub fn add_new_seg(
         &mut self,
-        bi_lst: &CBiList,
+        bi_lst: &[SharedCell<SUB_LINE_TYPE>],
         end_bi_idx: i32,
         is_sure: bool,
         seg_dir: Option<BiDir>,
@@ -380,7 +395,10 @@ impl<SUB_LINE_TYPE> std::ops::DerefMut for CSegListComm<SUB_LINE_TYPE> {
     }
 }
 
-pub fn find_peak_bi(bi_lst: &[SharedCell<CBi>], is_high: bool) -> Option<SharedCell<CBi>> {
+pub fn find_peak_bi<LINE_TYPE: ChanLine>(
+    bi_lst: &[SharedCell<LINE_TYPE>],
+    is_high: bool,
+) -> Option<SharedCell<LINE_TYPE>> {
     let mut peak_val = if is_high {
         f64::NEG_INFINITY
     } else {
@@ -392,8 +410,8 @@ pub fn find_peak_bi(bi_lst: &[SharedCell<CBi>], is_high: bool) -> Option<SharedC
         if (is_high && bi_ref.get_end_val() >= peak_val && bi_ref.is_up())
             || (!is_high && bi_ref.get_end_val() <= peak_val && bi_ref.is_down())
         {
-            if let Some(pre) = &bi_ref.pre {
-                if let Some(pre_pre) = &pre.borrow().pre {
+            if let Some(pre) = bi_ref.pre_line() {
+                if let Some(pre_pre) = pre.borrow().pre_line() {
                     if (is_high && pre_pre.borrow().get_end_val() > bi_ref.get_end_val())
                         || (!is_high && pre_pre.borrow().get_end_val() < bi_ref.get_end_val())
                     {
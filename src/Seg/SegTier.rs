@@ -0,0 +1,287 @@
+use crate::Bi::Bi::CBi;
+use crate::BuySellPoint::BSPointConfig::CBSPointConfig;
+use crate::BuySellPoint::BSPointList::CBSPointList;
+use crate::Common::types::{LineType, SharedCell};
+use crate::Common::CEnum::SegType;
+use crate::Common::ChanException::{CChanException, ErrCode};
+use crate::KLine::KLine_List::{cal_seg, update_zs_in_seg};
+use crate::Seg::SegConfig::CSegConfig;
+use crate::Seg::SegListChan::CSegListChan;
+use crate::Seg::SegTrendLine::CSegTrendLine;
+use crate::ZS::ZSConfig::CZSConfig;
+use crate::ZS::ZSList::CZSList;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// 递归级别中的一层：以下一层的线段作为本层的“笔”，计算本层的线段、中枢和买卖点
+// level=2 即线段的线段，level=3 为线段的线段的线段，以此类推
+pub struct CSegTier {
+    pub level: usize,
+    pub line_list: Vec<SharedCell<LineType>>,
+    pub seg_list: SharedCell<CSegListChan<LineType>>,
+    pub zs_list: CZSList,
+    pub bs_point_lst: CBSPointList<LineType>,
+    pub bs_point_history: Vec<HashMap<String, String>>,
+    pub trend_line: CSegTrendLine,
+}
+
+impl CSegTier {
+    pub fn new(
+        level: usize,
+        seg_conf: CSegConfig,
+        zs_conf: CZSConfig,
+        bs_point_conf: CBSPointConfig,
+    ) -> Result<Self, CChanException> {
+        if level < 2 {
+            return Err(CChanException::new(
+                format!("seg tier level={} must be >= 2", level),
+                ErrCode::ParaError,
+            ));
+        }
+        Ok(CSegTier {
+            level,
+            line_list: Vec::new(),
+            seg_list: Rc::new(RefCell::new(CSegListChan::new(
+                Some(seg_conf),
+                SegType::SEG,
+            ))),
+            zs_list: CZSList::new(Some(zs_conf)),
+            bs_point_lst: CBSPointList::<LineType>::new(Some(bs_point_conf)),
+            bs_point_history: Vec::new(),
+            trend_line: CSegTrendLine::new(),
+        })
+    }
+
+    // 导出时的名字前缀，level=2 沿用原来的 seg（segseg_list/segzs_list/seg_bs_point_lst）
+    pub fn name_prefix(&self) -> String {
+        if self.level == 2 {
+            "seg".to_string()
+        } else {
+            format!("seg{}", self.level - 1)
+        }
+    }
+
+    pub fn cal(&mut self, sub_lines: Vec<SharedCell<LineType>>) -> Result<(), CChanException> {
+        self.line_list = sub_lines;
+
+        cal_seg(&self.line_list, &mut self.seg_list.borrow_mut())?;
+        self.zs_list
+            .cal_bi_zs(&self.line_list, &self.seg_list.borrow());
+        update_zs_in_seg(
+            &self.line_list,
+            &mut self.seg_list.borrow_mut(),
+            &mut self.zs_list,
+        )?;
+        self.trend_line
+            .update(&self.seg_list.borrow(), &self.line_list);
+
+        self.bs_point_lst
+            .cal(&self.line_list, &self.seg_list.borrow());
+        Ok(())
+    }
+
+    // 本层的线段，作为上一层的“笔”
+    pub fn lines_for_next_tier(&self) -> Vec<SharedCell<LineType>> {
+        self.seg_list
+            .borrow()
+            .iter()
+            .map(|seg| Rc::new(RefCell::new(LineType::Tier(Rc::clone(seg)))))
+            .collect()
+    }
+}
+
+// 第1层线段（笔的线段）转成第2层的输入
+pub fn seg_lines_from_bi_seg(seg_list: &CSegListChan<CBi>) -> Vec<SharedCell<LineType>> {
+    seg_list
+        .iter()
+        .map(|seg| Rc::new(RefCell::new(LineType::Seg(Rc::clone(seg)))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChanConfig::CChanConfig;
+    use crate::Common::types::ChanLine;
+    use crate::Common::CEnum::BiDir;
+    use crate::Common::CTime::CTime;
+    use crate::Common::TradeInfo::CTradeInfo;
+    use crate::KLine::KLine_List::CKLineList;
+    use crate::KLine::KLine_Unit::CKLineUnit;
+    use crate::Math::Demark::CDemarkIndex;
+    use serde_json::json;
+
+    // 逐层展开成笔的幅度（单位0.01）：每根线由5根（最上层3根）次级别的线组成，回撤为0.6倍
+    fn expand_legs(amp: f64, dir: i64, level: usize, out: &mut Vec<i64>) {
+        if level == 0 {
+            out.push(dir * (amp * 100.0).round() as i64);
+            return;
+        }
+        let n = if level == 3 { 3 } else { 5 };
+        let m = (n / 2) as f64;
+        let with_amp = amp / ((m + 1.0) - m * 0.6);
+        for i in 0..n {
+            if i % 2 == 0 {
+                expand_legs(with_amp, dir, level - 1, out);
+            } else {
+                expand_legs(with_amp * 0.6, -dir, level - 1, out);
+            }
+        }
+    }
+
+    // 上涨一个seg2，完整回落一个seg2，再上涨一个seg-of-seg；每笔4根K线，拐点前后只差0.02
+    fn closes() -> Vec<i64> {
+        let mut legs = Vec::new();
+        expand_legs(30.0, 1, 3, &mut legs);
+        expand_legs(24.0, -1, 3, &mut legs);
+        let mut last_up = Vec::new();
+        expand_legs(18.0, 1, 3, &mut last_up);
+        legs.extend(&last_up[..25]);
+
+        let mut closes = vec![1060, 1030, 1000];
+        for amp in legs {
+            let p = *closes.last().unwrap();
+            let step = amp.signum() * 2;
+            closes.extend([p + step, p + amp / 2, p + amp - step, p + amp]);
+        }
+        closes
+    }
+
+    fn klu(idx: i32, close: i64) -> CKLineUnit {
+        CKLineUnit {
+            kl_type: None,
+            time: CTime::new(
+                2024,
+                1,
+                1 + (idx / 24) as u32,
+                (idx % 24) as u32,
+                0,
+                0,
+                false,
+            ),
+            close: close as f64 / 100.0,
+            open: close as f64 / 100.0,
+            high: (close + 1) as f64 / 100.0,
+            low: (close - 1) as f64 / 100.0,
+            trade_info: CTradeInfo::new(&HashMap::new()),
+            demark: CDemarkIndex::new(),
+            sub_kl_list: Vec::new(),
+            sup_kl: None,
+            klc: None,
+            trend: HashMap::new(),
+            limit_flag: 0,
+            pre: None,
+            next: None,
+            idx,
+            macd: None,
+            boll: None,
+            rsi: None,
+            kdj: None,
+            metric: HashMap::new(),
+        }
+    }
+
+    fn seg_summary<T: ChanLine>(seg_list: &CSegListChan<T>) -> Vec<(i32, i32, BiDir, bool)> {
+        seg_list
+            .iter()
+            .map(|seg| {
+                let seg = seg.borrow();
+                (
+                    seg.get_begin_klu().borrow().idx,
+                    seg.get_end_klu().borrow().idx,
+                    seg.dir,
+                    seg.is_sure,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_seg_tier_level() {
+        assert!(CSegTier::new(
+            1,
+            CSegConfig::default(),
+            CZSConfig::default(),
+            CBSPointConfig::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_seg_tier_on_bars() {
+        let conf = CChanConfig::new(Some(HashMap::from([(
+            "max_seg_level".to_string(),
+            json!(3),
+        )])))
+        .unwrap();
+        let mut kl_list = CKLineList::new("K_60M".to_string(), conf).unwrap();
+        for (idx, close) in closes().into_iter().enumerate() {
+            kl_list.add_single_klu(klu(idx as i32, close)).unwrap();
+        }
+        kl_list.cal_seg_and_zs().unwrap();
+
+        assert_eq!(kl_list.bi_list.bi_list.len(), 175);
+        // 每根线段5笔，共35根
+        let seg_list = kl_list.seg_list.borrow();
+        assert_eq!(seg_list.len(), 35);
+        assert!(seg_list[..34].iter().all(|seg| seg.borrow().is_sure));
+        assert_eq!(seg_list[1].borrow().pre.as_ref().unwrap().borrow().idx, 0);
+
+        let levels: Vec<usize> = kl_list.seg_tiers.iter().map(|t| t.level).collect();
+        assert_eq!(levels, vec![2, 3]);
+        let prefixes: Vec<String> = kl_list.seg_tiers.iter().map(|t| t.name_prefix()).collect();
+        assert_eq!(prefixes, vec!["seg", "seg2"]);
+
+        // 线段的线段：每5根线段一段，最后一段还在延伸
+        let seg_tier = &kl_list.seg_tiers[0];
+        assert_eq!(seg_tier.line_list.len(), 35);
+        assert_eq!(
+            seg_summary(&seg_tier.seg_list.borrow()),
+            vec![
+                (2, 102, BiDir::Up, true),
+                (102, 202, BiDir::Down, true),
+                (202, 302, BiDir::Up, true),
+                (302, 402, BiDir::Down, true),
+                (402, 502, BiDir::Up, true),
+                (502, 602, BiDir::Down, true),
+                (602, 702, BiDir::Up, false),
+            ]
+        );
+        // 下一层的线段和笔都指回所属的上一层线段
+        for (idx, seg) in seg_list.iter().enumerate() {
+            let parent = seg.borrow().parent_seg.clone().unwrap();
+            assert_eq!(parent.borrow().idx, idx as i32 / 5);
+        }
+        let bi_parent = kl_list.bi_list.bi_list[7]
+            .borrow()
+            .parent_seg
+            .clone()
+            .unwrap();
+        assert_eq!(bi_parent.borrow().idx, 1);
+
+        // seg2：线段的线段再按3根一段，只有第一段被确认
+        let seg2_tier = &kl_list.seg_tiers[1];
+        assert_eq!(seg2_tier.line_list.len(), 7);
+        assert_eq!(
+            seg_summary(&seg2_tier.seg_list.borrow()),
+            vec![
+                (2, 302, BiDir::Up, true),
+                (302, 602, BiDir::Down, false),
+                (602, 702, BiDir::Up, false),
+            ]
+        );
+
+        let dataframes = kl_list.to_dataframes();
+        for name in [
+            "segseg_list",
+            "segzs_list",
+            "seg_bs_point_lst",
+            "seg2seg_list",
+            "seg2zs_list",
+            "seg2_bs_point_lst",
+        ] {
+            assert!(dataframes.contains_key(name), "missing {}", name);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::Common::types::{ChanLine, SharedCell};
+use crate::Common::CEnum::BiDir;
+use crate::KLine::KLine_Unit::CKLineUnit;
+use crate::Math::TrendLine::{
+    find_line_break, support_resistance_side, CTrendChannel, CTrendLine, CTrendLineBreak, Line,
+    TrendLineElement,
+};
+use crate::Seg::SegListComm::CSegListComm;
+use std::collections::{HashMap, HashSet};
+
+// 某一级别线段的趋势线：
+//   当前线段（最后一根确定线段之后的所有笔）的支撑线/压力线/通道，随新笔更新
+//   已确定线段的支撑线/压力线（存在 CSeg 上），线段结束后第一根收盘越过趋势线的K线记为突破
+//   线段结束后最多观察 max_watch_klu 根K线，下一根线段确定后也不再观察
+pub struct CSegTrendLine {
+    pub cur_support_trend_line: Option<CTrendLine>,
+    pub cur_resistance_trend_line: Option<CTrendLine>,
+    pub cur_channel: Option<CTrendChannel>,
+    pub break_events: Vec<CTrendLineBreak>,
+    pub max_watch_klu: i32,
+    // (seg_idx, is_support) -> 已检查到的最后一根K线
+    checked: HashMap<(i32, bool), SharedCell<CKLineUnit>>,
+    // 已突破或超出观察窗口的趋势线
+    finished: HashSet<(i32, bool)>,
+    // idx 小于该值的线段两条趋势线都已结束观察，不再遍历
+    watch_from_seg_idx: i32,
+}
+
+impl CSegTrendLine {
+    pub fn new() -> Self {
+        CSegTrendLine {
+            cur_support_trend_line: None,
+            cur_resistance_trend_line: None,
+            cur_channel: None,
+            break_events: Vec::new(),
+            max_watch_klu: 100,
+            checked: HashMap::new(),
+            finished: HashSet::new(),
+            watch_from_seg_idx: 0,
+        }
+    }
+
+    pub fn update<T: ChanLine, E: TrendLineElement>(
+        &mut self,
+        seg_list: &CSegListComm<T>,
+        line_list: &[E],
+    ) {
+        let last_sure_seg = seg_list.iter().rev().find(|seg| seg.borrow().is_sure);
+        let start = match last_sure_seg {
+            Some(seg) => {
+                let end_x = seg.borrow().get_end_klu().borrow().idx;
+                line_list
+                    .iter()
+                    .position(|line| line.begin_point().x >= end_x)
+                    .unwrap_or(line_list.len())
+            }
+            None => 0,
+        };
+        self.update_cur_lines(&line_list[start..]);
+
+        let sure_segs: Vec<_> = seg_list
+            .iter()
+            .filter(|seg| {
+                let seg = seg.borrow();
+                seg.is_sure && seg.idx >= self.watch_from_seg_idx
+            })
+            .cloned()
+            .collect();
+        let mut prefix_finished = true;
+        for (i, seg) in sure_segs.iter().enumerate() {
+            let seg = seg.borrow();
+            let seg_end_klu = seg.get_end_klu();
+            let mut watch_end = seg_end_klu.borrow().idx + self.max_watch_klu;
+            if let Some(next_seg) = sure_segs.get(i + 1) {
+                watch_end = watch_end.min(next_seg.borrow().get_end_klu().borrow().idx);
+            }
+            let mut seg_finished = true;
+            for (is_support, trend_line) in [
+                (true, &seg.support_trend_line),
+                (false, &seg.resistance_trend_line),
+            ] {
+                if let Some(line) = trend_line.as_ref().and_then(|t| t.line) {
+                    seg_finished &=
+                        self.check_line(seg.idx, is_support, &line, &seg_end_klu, watch_end);
+                }
+            }
+            prefix_finished &= seg_finished;
+            if prefix_finished {
+                self.watch_from_seg_idx = seg.idx + 1;
+            }
+        }
+        let watch_from = self.watch_from_seg_idx;
+        self.checked
+            .retain(|(seg_idx, _), _| *seg_idx >= watch_from);
+        self.finished.retain(|(seg_idx, _)| *seg_idx >= watch_from);
+    }
+
+    // 检查已确定线段的一条趋势线，返回该线是否已结束观察（已突破或检查到 watch_end）
+    fn check_line(
+        &mut self,
+        seg_idx: i32,
+        is_support: bool,
+        line: &Line,
+        seg_end_klu: &SharedCell<CKLineUnit>,
+        watch_end: i32,
+    ) -> bool {
+        let key = (seg_idx, is_support);
+        if self.finished.contains(&key) {
+            return true;
+        }
+        let begin_klu = match self.checked.get(&key) {
+            Some(last) => last.borrow().next.clone(),
+            None => seg_end_klu.borrow().next.clone(),
+        };
+        let (hit, last) = find_line_break(line, is_support, begin_klu, watch_end);
+        if let Some((klu, line_val)) = hit {
+            let klu = klu.borrow();
+            self.break_events.push(CTrendLineBreak {
+                seg_idx,
+                is_support,
+                break_dir: if is_support { BiDir::DOWN } else { BiDir::UP },
+                klu_idx: klu.idx,
+                time: klu.time.clone(),
+                close: klu.close,
+                line_val,
+            });
+            self.finished.insert(key);
+            self.checked.remove(&key);
+            return true;
+        }
+        if let Some(last) = last {
+            self.checked.insert(key, last);
+        }
+        let checked_idx = self
+            .checked
+            .get(&key)
+            .map_or(seg_end_klu.borrow().idx, |klu| klu.borrow().idx);
+        if checked_idx >= watch_end {
+            self.finished.insert(key);
+            self.checked.remove(&key);
+            return true;
+        }
+        false
+    }
+
+    fn update_cur_lines<E: TrendLineElement>(&mut self, lines: &[E]) {
+        if lines.len() < 3 {
+            self.cur_support_trend_line = None;
+            self.cur_resistance_trend_line = None;
+            self.cur_channel = None;
+            return;
+        }
+        // 与 CSeg::update_bi_list 一致，按最后一笔的方向区分内外侧
+        let (support_side, resistance_side) =
+            support_resistance_side(lines.last().unwrap().line_dir());
+        self.cur_support_trend_line = Some(CTrendLine::new(lines, support_side));
+        self.cur_resistance_trend_line = Some(CTrendLine::new(lines, resistance_side));
+        self.cur_channel = CTrendChannel::new(lines);
+    }
+}
+
+impl Default for CSegTrendLine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Common::CTime::CTime;
+    use crate::Common::TradeInfo::CTradeInfo;
+    use crate::Math::Demark::CDemarkIndex;
+    use crate::Math::TrendLine::Point;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn push_klu(klu_lst: &mut Vec<SharedCell<CKLineUnit>>, close: f64) {
+        let idx = klu_lst.len() as i32;
+        let klu = Rc::new(RefCell::new(CKLineUnit {
+            kl_type: None,
+            time: CTime::new(2024, 1, 1, 0, 0, 0, false),
+            close,
+            open: close,
+            high: close,
+            low: close,
+            trade_info: CTradeInfo::new(&HashMap::new()),
+            demark: CDemarkIndex::new(),
+            sub_kl_list: Vec::new(),
+            sup_kl: None,
+            klc: None,
+            trend: HashMap::new(),
+            limit_flag: 0,
+            pre: klu_lst.last().cloned(),
+            next: None,
+            idx,
+            macd: None,
+            boll: None,
+            rsi: None,
+            kdj: None,
+            metric: HashMap::new(),
+        }));
+        if let Some(pre) = klu_lst.last() {
+            pre.borrow_mut().next = Some(Rc::clone(&klu));
+        }
+        klu_lst.push(klu);
+    }
+
+    #[test]
+    fn test_break_event() {
+        let support = Line::new(Point::new(0, 10.0), 0.0);
+        let mut klu_lst = Vec::new();
+        for close in [12.0, 11.0, 10.5, 10.8, 10.2] {
+            push_klu(&mut klu_lst, close);
+        }
+        let seg_end_klu = Rc::clone(&klu_lst[2]);
+        let mut trend_line = CSegTrendLine::new();
+        assert!(!trend_line.check_line(0, true, &support, &seg_end_klu, 100));
+        assert!(trend_line.break_events.is_empty());
+
+        // 新K线收盘跌破支撑线，只记一次
+        push_klu(&mut klu_lst, 9.5);
+        push_klu(&mut klu_lst, 9.0);
+        assert!(trend_line.check_line(0, true, &support, &seg_end_klu, 100));
+        assert!(trend_line.check_line(0, true, &support, &seg_end_klu, 100));
+        assert_eq!(trend_line.break_events.len(), 1);
+        let event = &trend_line.break_events[0];
+        assert_eq!(event.klu_idx, 5);
+        assert_eq!(event.break_dir, BiDir::DOWN);
+        assert_eq!(event.close, 9.5);
+        assert_eq!(event.line_val, 10.0);
+        assert!(trend_line.checked.is_empty());
+    }
+
+    #[test]
+    fn test_stop_watching() {
+        let resistance = Line::new(Point::new(0, 10.0), 0.0);
+        let mut klu_lst = Vec::new();
+        for close in [8.0, 9.0, 9.5, 9.2, 9.8] {
+            push_klu(&mut klu_lst, close);
+        }
+        let seg_end_klu = Rc::clone(&klu_lst[2]);
+        let mut trend_line = CSegTrendLine::new();
+        // 观察到 idx=4 为止，之后的突破不再记录
+        assert!(trend_line.check_line(0, false, &resistance, &seg_end_klu, 4));
+        push_klu(&mut klu_lst, 10.5);
+        assert!(trend_line.check_line(0, false, &resistance, &seg_end_klu, 4));
+        assert!(trend_line.break_events.is_empty());
+        assert!(trend_line.checked.is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::Common::ChanException::CChanException;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrokerOrderStatus {
+    Submitted,
+    PartFilled,
+    Filled,
+    Canceled,
+}
+
+impl BrokerOrderStatus {
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            BrokerOrderStatus::Filled | BrokerOrderStatus::Canceled
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CBrokerOrder {
+    pub order_id: String,
+    pub code: String,
+    pub is_buy: bool,
+    // None 为市价单
+    pub price: Option<f64>,
+    pub volume: f64,
+    pub filled_volume: f64,
+    pub avg_fill_price: f64,
+    pub status: BrokerOrderStatus,
+    pub submit_time: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CHolding {
+    pub code: String,
+    pub volume: f64,
+    pub avg_cost: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CQuote {
+    pub code: String,
+    pub time: String,
+    pub last_price: f64,
+}
+
+// 券商接口，出错时返回 ErrCode 中交易段对应的错误码：
+// 解锁 TradeUnlockFail、下单 PlaceOrderFail、撤单 CandelOrderFail、查委托 ListOrderFail、
+// 查持仓 GetHoldingQtyFail、每手股数 GetFutuLotSizeFail、报价 GetFutuPriceFail、交易日 RequestTradingDaysFail
+pub trait BrokerGateway {
+    fn unlock(&mut self, password: &str) -> Result<(), CChanException>;
+
+    // 返回委托编号
+    fn place_order(
+        &mut self,
+        code: &str,
+        is_buy: bool,
+        volume: f64,
+        price: Option<f64>,
+    ) -> Result<String, CChanException>;
+
+    fn cancel_order(&mut self, order_id: &str) -> Result<(), CChanException>;
+
+    // code 为 None 时返回所有品种的委托
+    fn list_orders(&self, code: Option<&str>) -> Result<Vec<CBrokerOrder>, CChanException>;
+
+    fn get_holdings(&self) -> Result<Vec<CHolding>, CChanException>;
+
+    fn get_holding_qty(&self, code: &str) -> Result<f64, CChanException>;
+
+    fn get_lot_size(&self, code: &str) -> Result<f64, CChanException>;
+
+    fn get_quote(&self, code: &str) -> Result<CQuote, CChanException>;
+
+    // [begin, end] 之间的交易日，日期格式 YYYY/MM/DD
+    fn get_trading_days(&self, begin: &str, end: &str) -> Result<Vec<String>, CChanException>;
+}
@@ -0,0 +1,823 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use rusqlite::{params, Connection};
+use serde_json::{json, Value};
+
+use crate::BuySellPoint::BS_Point::CBSPoint;
+use crate::Common::ChanException::{CChanException, ErrCode};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignalStatus {
+    // 等待开仓
+    Watching,
+    // 已据此开仓
+    Traded,
+    // 被撤销（如买卖点失效），不再开仓
+    Canceled,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordStatus {
+    Opened,
+    Closed,
+}
+
+// 落盘时状态按 Debug 名称保存
+fn signal_status_of(s: &str) -> Option<SignalStatus> {
+    match s {
+        "Watching" => Some(SignalStatus::Watching),
+        "Traded" => Some(SignalStatus::Traded),
+        "Canceled" => Some(SignalStatus::Canceled),
+        _ => None,
+    }
+}
+
+fn record_status_of(s: &str) -> Option<RecordStatus> {
+    match s {
+        "Opened" => Some(RecordStatus::Opened),
+        "Closed" => Some(RecordStatus::Closed),
+        _ => None,
+    }
+}
+
+const SQLITE_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS ledger_config (
+    total_quota REAL NOT NULL,
+    single_quota REAL NOT NULL,
+    max_position_cnt INTEGER NOT NULL,
+    lot_size REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS signals (
+    id TEXT PRIMARY KEY,
+    code TEXT NOT NULL,
+    is_buy INTEGER NOT NULL,
+    bsp_type TEXT NOT NULL,
+    time TEXT NOT NULL,
+    price REAL NOT NULL,
+    status TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS records (
+    id INTEGER PRIMARY KEY,
+    signal_id TEXT NOT NULL,
+    code TEXT NOT NULL,
+    volume REAL NOT NULL,
+    open_price REAL NOT NULL,
+    open_time TEXT NOT NULL,
+    close_price REAL,
+    close_time TEXT,
+    close_signal_id TEXT,
+    status TEXT NOT NULL
+);
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CSignal {
+    pub id: String,
+    pub code: String,
+    pub is_buy: bool,
+    pub bsp_type: String,
+    pub time: String,
+    pub price: f64,
+    pub status: SignalStatus,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CPositionRecord {
+    pub id: usize,
+    pub signal_id: String,
+    pub code: String,
+    pub volume: f64,
+    pub open_price: f64,
+    pub open_time: String,
+    pub close_price: Option<f64>,
+    pub close_time: Option<String>,
+    // 平仓所依据的卖出信号，按品种手动平仓时为 None
+    pub close_signal_id: Option<String>,
+    pub status: RecordStatus,
+}
+
+impl CPositionRecord {
+    pub fn cost(&self) -> f64 {
+        self.open_price * self.volume
+    }
+
+    pub fn pnl(&self) -> Option<f64> {
+        self.close_price
+            .map(|p| (p - self.open_price) * self.volume)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CLedgerConfig {
+    // 所有持仓占用资金的上限
+    pub total_quota: f64,
+    // 单笔开仓金额上限
+    pub single_quota: f64,
+    pub max_position_cnt: usize,
+    pub lot_size: f64,
+}
+
+impl Default for CLedgerConfig {
+    fn default() -> Self {
+        CLedgerConfig {
+            total_quota: 1_000_000.0,
+            single_quota: 200_000.0,
+            max_position_cnt: 5,
+            lot_size: 100.0,
+        }
+    }
+}
+
+// 模拟盘台账：记录买卖点信号、开平仓记录，只做多
+// 出错时返回 ErrCode 中交易段的错误码，实盘执行层可以复用同一套流程
+pub struct CPaperLedger {
+    pub config: CLedgerConfig,
+    pub signals: BTreeMap<String, CSignal>,
+    pub records: Vec<CPositionRecord>,
+}
+
+impl CPaperLedger {
+    pub fn new(config: CLedgerConfig) -> Self {
+        CPaperLedger {
+            config,
+            signals: BTreeMap::new(),
+            records: Vec::new(),
+        }
+    }
+
+    pub fn opened_records(&self) -> impl Iterator<Item = &CPositionRecord> {
+        self.records
+            .iter()
+            .filter(|r| r.status == RecordStatus::Opened)
+    }
+
+    pub fn used_quota(&self) -> f64 {
+        self.opened_records().map(|r| r.cost()).sum()
+    }
+
+    pub fn add_signal(&mut self, signal: CSignal) -> Result<(), CChanException> {
+        if self.signals.contains_key(&signal.id) {
+            return Err(CChanException::new(
+                format!("signal {} already existed", signal.id),
+                ErrCode::SignalExisted,
+            ));
+        }
+        self.signals.insert(signal.id.clone(), signal);
+        Ok(())
+    }
+
+    // 同一品种同一买卖点只记录一次，信号 id 为 {code}_{bsp.id}
+    pub fn add_bsp_signal(&mut self, code: &str, bsp: &CBSPoint) -> Result<String, CChanException> {
+        let klu = bsp.klu.borrow();
+        let signal = CSignal {
+            id: format!("{}_{}", code, bsp.id),
+            code: code.to_string(),
+            is_buy: bsp.is_buy,
+            bsp_type: bsp.type2str(),
+            time: klu.time.to_string(),
+            price: klu.close,
+            status: SignalStatus::Watching,
+        };
+        let id = signal.id.clone();
+        self.add_signal(signal)?;
+        Ok(id)
+    }
+
+    pub fn cancel_signal(&mut self, signal_id: &str) -> Result<(), CChanException> {
+        let signal = self.get_signal_mut(signal_id)?;
+        if signal.status == SignalStatus::Traded {
+            return Err(CChanException::new(
+                format!("signal {} already traded", signal_id),
+                ErrCode::SignalTraded,
+            ));
+        }
+        signal.status = SignalStatus::Canceled;
+        Ok(())
+    }
+
+    fn get_signal_mut(&mut self, signal_id: &str) -> Result<&mut CSignal, CChanException> {
+        self.signals.get_mut(signal_id).ok_or_else(|| {
+            CChanException::new(
+                format!("signal {} not exist", signal_id),
+                ErrCode::RecordNotExist,
+            )
+        })
+    }
+
+    // 只有等待中的信号可以用来开平仓
+    fn get_watching_signal(&mut self, signal_id: &str) -> Result<CSignal, CChanException> {
+        let signal = self.get_signal_mut(signal_id)?.clone();
+        match signal.status {
+            SignalStatus::Traded => Err(CChanException::new(
+                format!("signal {} already traded", signal_id),
+                ErrCode::SignalTraded,
+            )),
+            SignalStatus::Canceled => Err(CChanException::new(
+                format!("signal {} is not watching", signal_id),
+                ErrCode::OpenRecordNotWatching,
+            )),
+            SignalStatus::Watching => Ok(signal),
+        }
+    }
+
+    // 按买入信号开仓，返回持仓记录 id；volume 需为整手
+    pub fn open(
+        &mut self,
+        signal_id: &str,
+        price: f64,
+        volume: f64,
+        time: &str,
+    ) -> Result<usize, CChanException> {
+        let signal = self.get_watching_signal(signal_id)?;
+        if !signal.is_buy {
+            return Err(CChanException::new(
+                format!("signal {} is a sell signal, can't open", signal_id),
+                ErrCode::PlaceOrderFail,
+            ));
+        }
+        if price <= 0.0 || volume <= 0.0 || volume % self.config.lot_size != 0.0 {
+            return Err(CChanException::new(
+                format!(
+                    "invalid order of {}: price={}, volume={}, lot_size={}",
+                    signal.code, price, volume, self.config.lot_size
+                ),
+                ErrCode::PlaceOrderFail,
+            ));
+        }
+        if self.opened_records().any(|r| r.code == signal.code) {
+            return Err(CChanException::new(
+                format!("{} already has an opened record", signal.code),
+                ErrCode::RecordAlreadyOpened,
+            ));
+        }
+        let amount = price * volume;
+        if self.opened_records().count() >= self.config.max_position_cnt
+            || amount > self.config.single_quota
+            || self.used_quota() + amount > self.config.total_quota
+        {
+            return Err(CChanException::new(
+                format!(
+                    "quota not enough for {}: amount={}, used={}/{}, position_cnt={}/{}",
+                    signal.code,
+                    amount,
+                    self.used_quota(),
+                    self.config.total_quota,
+                    self.opened_records().count(),
+                    self.config.max_position_cnt
+                ),
+                ErrCode::QuotaNotEnough,
+            ));
+        }
+
+        self.get_signal_mut(signal_id)?.status = SignalStatus::Traded;
+        let id = self.records.len();
+        self.records.push(CPositionRecord {
+            id,
+            signal_id: signal_id.to_string(),
+            code: signal.code,
+            volume,
+            open_price: price,
+            open_time: time.to_string(),
+            close_price: None,
+            close_time: None,
+            close_signal_id: None,
+            status: RecordStatus::Opened,
+        });
+        Ok(id)
+    }
+
+    pub fn close(
+        &mut self,
+        record_id: usize,
+        price: f64,
+        time: &str,
+    ) -> Result<(), CChanException> {
+        let record = self.records.get_mut(record_id).ok_or_else(|| {
+            CChanException::new(
+                format!("record {} not exist", record_id),
+                ErrCode::RecordNotExist,
+            )
+        })?;
+        if record.status == RecordStatus::Closed {
+            return Err(CChanException::new(
+                format!("record {} already closed", record_id),
+                ErrCode::RecordClosed,
+            ));
+        }
+        if price <= 0.0 {
+            return Err(CChanException::new(
+                format!("invalid close price {} of record {}", price, record_id),
+                ErrCode::PlaceOrderFail,
+            ));
+        }
+        record.close_price = Some(price);
+        record.close_time = Some(time.to_string());
+        record.status = RecordStatus::Closed;
+        Ok(())
+    }
+
+    // 按品种平仓，不关联信号
+    pub fn close_by_code(
+        &mut self,
+        code: &str,
+        price: f64,
+        time: &str,
+    ) -> Result<usize, CChanException> {
+        let record_id = self
+            .opened_records()
+            .find(|r| r.code == code)
+            .map(|r| r.id)
+            .ok_or_else(|| {
+                CChanException::new(
+                    format!("{} has no opened record", code),
+                    ErrCode::RecordNotOpened,
+                )
+            })?;
+        self.close(record_id, price, time)?;
+        Ok(record_id)
+    }
+
+    // 按卖出信号平掉该品种的持仓，信号记为已交易并记录到持仓上，返回持仓记录 id
+    pub fn close_by_signal(
+        &mut self,
+        signal_id: &str,
+        price: f64,
+        time: &str,
+    ) -> Result<usize, CChanException> {
+        let signal = self.get_watching_signal(signal_id)?;
+        if signal.is_buy {
+            return Err(CChanException::new(
+                format!("signal {} is a buy signal, can't close", signal_id),
+                ErrCode::PlaceOrderFail,
+            ));
+        }
+        let record_id = self.close_by_code(&signal.code, price, time)?;
+        self.records[record_id].close_signal_id = Some(signal.id);
+        self.get_signal_mut(signal_id)?.status = SignalStatus::Traded;
+        Ok(record_id)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let signals: Vec<Value> = self
+            .signals
+            .values()
+            .map(|s| {
+                json!({
+                    "id": s.id,
+                    "code": s.code,
+                    "is_buy": s.is_buy,
+                    "bsp_type": s.bsp_type,
+                    "time": s.time,
+                    "price": s.price,
+                    "status": format!("{:?}", s.status),
+                })
+            })
+            .collect();
+        let records: Vec<Value> = self
+            .records
+            .iter()
+            .map(|r| {
+                json!({
+                    "id": r.id,
+                    "signal_id": r.signal_id,
+                    "code": r.code,
+                    "volume": r.volume,
+                    "open_price": r.open_price,
+                    "open_time": r.open_time,
+                    "close_price": r.close_price,
+                    "close_time": r.close_time,
+                    "close_signal_id": r.close_signal_id,
+                    "status": format!("{:?}", r.status),
+                })
+            })
+            .collect();
+        json!({
+            "config": {
+                "total_quota": self.config.total_quota,
+                "single_quota": self.config.single_quota,
+                "max_position_cnt": self.config.max_position_cnt,
+                "lot_size": self.config.lot_size,
+            },
+            "signals": signals,
+            "records": records,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, CChanException> {
+        let err = |field: &str| {
+            CChanException::new(
+                format!("ledger file field {} missing or invalid", field),
+                ErrCode::CommonError,
+            )
+        };
+        let f64_of = |v: &Value, k: &str| v[k].as_f64().ok_or_else(|| err(k));
+        let str_of =
+            |v: &Value, k: &str| v[k].as_str().map(|s| s.to_string()).ok_or_else(|| err(k));
+
+        let conf = &value["config"];
+        let mut ledger = CPaperLedger::new(CLedgerConfig {
+            total_quota: f64_of(conf, "total_quota")?,
+            single_quota: f64_of(conf, "single_quota")?,
+            max_position_cnt: conf["max_position_cnt"]
+                .as_u64()
+                .ok_or_else(|| err("max_position_cnt"))? as usize,
+            lot_size: f64_of(conf, "lot_size")?,
+        });
+        for s in value["signals"].as_array().ok_or_else(|| err("signals"))? {
+            let status = s["status"]
+                .as_str()
+                .and_then(signal_status_of)
+                .ok_or_else(|| err("status"))?;
+            ledger.add_signal(CSignal {
+                id: str_of(s, "id")?,
+                code: str_of(s, "code")?,
+                is_buy: s["is_buy"].as_bool().ok_or_else(|| err("is_buy"))?,
+                bsp_type: str_of(s, "bsp_type")?,
+                time: str_of(s, "time")?,
+                price: f64_of(s, "price")?,
+                status,
+            })?;
+        }
+        for r in value["records"].as_array().ok_or_else(|| err("records"))? {
+            let status = r["status"]
+                .as_str()
+                .and_then(record_status_of)
+                .ok_or_else(|| err("status"))?;
+            ledger.records.push(CPositionRecord {
+                id: ledger.records.len(),
+                signal_id: str_of(r, "signal_id")?,
+                code: str_of(r, "code")?,
+                volume: f64_of(r, "volume")?,
+                open_price: f64_of(r, "open_price")?,
+                open_time: str_of(r, "open_time")?,
+                close_price: r["close_price"].as_f64(),
+                close_time: r["close_time"].as_str().map(|s| s.to_string()),
+                close_signal_id: r["close_signal_id"].as_str().map(|s| s.to_string()),
+                status,
+            });
+        }
+        Ok(ledger)
+    }
+
+    // 先写同目录下的临时文件再改名覆盖，写到一半中断不会损坏原台账
+    pub fn save(&self, path: &str) -> Result<(), CChanException> {
+        let io_err = |e: std::io::Error| {
+            CChanException::new(format!("write {} fail: {}", path, e), ErrCode::CommonError)
+        };
+        let tmp_path = format!("{}.tmp", path);
+        let mut file = std::fs::File::create(&tmp_path).map_err(io_err)?;
+        file.write_all(self.to_json().to_string().as_bytes())
+            .map_err(io_err)?;
+        file.sync_all().map_err(io_err)?;
+        std::fs::rename(&tmp_path, path).map_err(io_err)
+    }
+
+    pub fn load(path: &str) -> Result<Self, CChanException> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            CChanException::new(format!("read {} fail: {}", path, e), ErrCode::CommonError)
+        })?;
+        let value: Value = serde_json::from_str(&content).map_err(|e| {
+            CChanException::new(format!("parse {} fail: {}", path, e), ErrCode::CommonError)
+        })?;
+        CPaperLedger::from_json(&value)
+    }
+
+    // 保存到 SQLite，整库在一个事务里重写，和 save 一样不会留下写了一半的台账
+    pub fn save_sqlite(&self, path: &str) -> Result<(), CChanException> {
+        let db_err = |e: rusqlite::Error| {
+            CChanException::new(format!("write {} fail: {}", path, e), ErrCode::CommonError)
+        };
+        let mut conn = Connection::open(path).map_err(db_err)?;
+        let tx = conn.transaction().map_err(db_err)?;
+        tx.execute_batch(SQLITE_SCHEMA).map_err(db_err)?;
+        tx.execute_batch("DELETE FROM ledger_config; DELETE FROM signals; DELETE FROM records;")
+            .map_err(db_err)?;
+        tx.execute(
+            "INSERT INTO ledger_config (total_quota, single_quota, max_position_cnt, lot_size) \
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                self.config.total_quota,
+                self.config.single_quota,
+                self.config.max_position_cnt as i64,
+                self.config.lot_size
+            ],
+        )
+        .map_err(db_err)?;
+        for s in self.signals.values() {
+            tx.execute(
+                "INSERT INTO signals (id, code, is_buy, bsp_type, time, price, status) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    s.id,
+                    s.code,
+                    s.is_buy,
+                    s.bsp_type,
+                    s.time,
+                    s.price,
+                    format!("{:?}", s.status)
+                ],
+            )
+            .map_err(db_err)?;
+        }
+        for r in &self.records {
+            tx.execute(
+                "INSERT INTO records (id, signal_id, code, volume, open_price, open_time, \
+                 close_price, close_time, close_signal_id, status) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    r.id as i64,
+                    r.signal_id,
+                    r.code,
+                    r.volume,
+                    r.open_price,
+                    r.open_time,
+                    r.close_price,
+                    r.close_time,
+                    r.close_signal_id,
+                    format!("{:?}", r.status)
+                ],
+            )
+            .map_err(db_err)?;
+        }
+        tx.commit().map_err(db_err)
+    }
+
+    pub fn load_sqlite(path: &str) -> Result<Self, CChanException> {
+        let db_err = |e: rusqlite::Error| {
+            CChanException::new(format!("read {} fail: {}", path, e), ErrCode::CommonError)
+        };
+        let status_err = |status: &str| {
+            CChanException::new(
+                format!("invalid status {} in {}", status, path),
+                ErrCode::CommonError,
+            )
+        };
+        let conn = Connection::open(path).map_err(db_err)?;
+        let config = conn
+            .query_row(
+                "SELECT total_quota, single_quota, max_position_cnt, lot_size FROM ledger_config",
+                [],
+                |row| {
+                    Ok(CLedgerConfig {
+                        total_quota: row.get(0)?,
+                        single_quota: row.get(1)?,
+                        max_position_cnt: row.get::<_, i64>(2)? as usize,
+                        lot_size: row.get(3)?,
+                    })
+                },
+            )
+            .map_err(db_err)?;
+        let mut ledger = CPaperLedger::new(config);
+
+        let mut stmt = conn
+            .prepare("SELECT id, code, is_buy, bsp_type, time, price, status FROM signals")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    CSignal {
+                        id: row.get(0)?,
+                        code: row.get(1)?,
+                        is_buy: row.get(2)?,
+                        bsp_type: row.get(3)?,
+                        time: row.get(4)?,
+                        price: row.get(5)?,
+                        status: SignalStatus::Watching,
+                    },
+                    row.get::<_, String>(6)?,
+                ))
+            })
+            .map_err(db_err)?;
+        for row in rows {
+            let (mut signal, status) = row.map_err(db_err)?;
+            signal.status = signal_status_of(&status).ok_or_else(|| status_err(&status))?;
+            ledger.add_signal(signal)?;
+        }
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT signal_id, code, volume, open_price, open_time, close_price, close_time, \
+                 close_signal_id, status FROM records ORDER BY id",
+            )
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    CPositionRecord {
+                        id: 0,
+                        signal_id: row.get(0)?,
+                        code: row.get(1)?,
+                        volume: row.get(2)?,
+                        open_price: row.get(3)?,
+                        open_time: row.get(4)?,
+                        close_price: row.get(5)?,
+                        close_time: row.get(6)?,
+                        close_signal_id: row.get(7)?,
+                        status: RecordStatus::Opened,
+                    },
+                    row.get::<_, String>(8)?,
+                ))
+            })
+            .map_err(db_err)?;
+        for row in rows {
+            let (mut record, status) = row.map_err(db_err)?;
+            record.id = ledger.records.len();
+            record.status = record_status_of(&status).ok_or_else(|| status_err(&status))?;
+            ledger.records.push(record);
+        }
+        Ok(ledger)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(id: &str, code: &str, is_buy: bool) -> CSignal {
+        CSignal {
+            id: id.to_string(),
+            code: code.to_string(),
+            is_buy,
+            bsp_type: "T1".to_string(),
+            time: "2024/01/02".to_string(),
+            price: 10.0,
+            status: SignalStatus::Watching,
+        }
+    }
+
+    fn ledger() -> CPaperLedger {
+        CPaperLedger::new(CLedgerConfig {
+            total_quota: 3000.0,
+            single_quota: 2000.0,
+            max_position_cnt: 2,
+            lot_size: 100.0,
+        })
+    }
+
+    #[test]
+    fn test_signal_rules() {
+        let mut ledger = ledger();
+        ledger.add_signal(signal("a", "000001", true)).unwrap();
+        let err = ledger.add_signal(signal("a", "000001", true)).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::SignalExisted);
+
+        let err = ledger.open("x", 10.0, 100.0, "t").unwrap_err();
+        assert_eq!(err.errcode, ErrCode::RecordNotExist);
+
+        ledger.open("a", 10.0, 100.0, "t").unwrap();
+        let err = ledger.open("a", 10.0, 100.0, "t").unwrap_err();
+        assert_eq!(err.errcode, ErrCode::SignalTraded);
+        assert_eq!(
+            ledger.cancel_signal("a").unwrap_err().errcode,
+            ErrCode::SignalTraded
+        );
+
+        ledger.add_signal(signal("b", "000002", true)).unwrap();
+        ledger.cancel_signal("b").unwrap();
+        let err = ledger.open("b", 10.0, 100.0, "t").unwrap_err();
+        assert_eq!(err.errcode, ErrCode::OpenRecordNotWatching);
+    }
+
+    #[test]
+    fn test_open_close_and_quota() {
+        let mut ledger = ledger();
+        ledger.add_signal(signal("a", "000001", true)).unwrap();
+        ledger.add_signal(signal("a2", "000001", true)).unwrap();
+        ledger.add_signal(signal("b", "000002", true)).unwrap();
+        ledger.add_signal(signal("c", "000003", true)).unwrap();
+
+        assert_eq!(
+            ledger.open("a", 10.0, 150.0, "t").unwrap_err().errcode,
+            ErrCode::PlaceOrderFail
+        );
+        assert_eq!(
+            ledger.open("a", 10.0, 300.0, "t").unwrap_err().errcode,
+            ErrCode::QuotaNotEnough
+        );
+        let id = ledger.open("a", 10.0, 200.0, "t").unwrap();
+        assert_eq!(
+            ledger.open("a2", 10.0, 100.0, "t").unwrap_err().errcode,
+            ErrCode::RecordAlreadyOpened
+        );
+        // 总额度 3000，已用 2000
+        assert_eq!(
+            ledger.open("b", 10.0, 200.0, "t").unwrap_err().errcode,
+            ErrCode::QuotaNotEnough
+        );
+        ledger.open("b", 10.0, 100.0, "t").unwrap();
+        assert_eq!(
+            ledger.open("c", 1.0, 100.0, "t").unwrap_err().errcode,
+            ErrCode::QuotaNotEnough
+        );
+
+        assert_eq!(ledger.close_by_code("000001", 11.0, "t2").unwrap(), id);
+        assert_eq!(ledger.records[id].pnl(), Some(200.0));
+        assert_eq!(
+            ledger.close(id, 11.0, "t2").unwrap_err().errcode,
+            ErrCode::RecordClosed
+        );
+        assert_eq!(
+            ledger.close(9, 11.0, "t2").unwrap_err().errcode,
+            ErrCode::RecordNotExist
+        );
+        assert_eq!(
+            ledger
+                .close_by_code("000001", 11.0, "t2")
+                .unwrap_err()
+                .errcode,
+            ErrCode::RecordNotOpened
+        );
+    }
+
+    #[test]
+    fn test_close_by_signal() {
+        let mut ledger = ledger();
+        ledger.add_signal(signal("a", "000001", true)).unwrap();
+        ledger.add_signal(signal("s", "000001", false)).unwrap();
+        ledger.add_signal(signal("s2", "000002", false)).unwrap();
+        assert_eq!(
+            ledger.close_by_signal("a", 11.0, "t2").unwrap_err().errcode,
+            ErrCode::PlaceOrderFail
+        );
+        assert_eq!(
+            ledger.close_by_signal("s", 11.0, "t2").unwrap_err().errcode,
+            ErrCode::RecordNotOpened
+        );
+        // 平仓失败时信号仍在等待
+        assert_eq!(ledger.signals["s"].status, SignalStatus::Watching);
+
+        let id = ledger.open("a", 10.0, 100.0, "t").unwrap();
+        assert_eq!(ledger.close_by_signal("s", 11.0, "t2").unwrap(), id);
+        assert_eq!(ledger.records[id].status, RecordStatus::Closed);
+        assert_eq!(ledger.records[id].close_signal_id.as_deref(), Some("s"));
+        assert_eq!(ledger.signals["s"].status, SignalStatus::Traded);
+        assert_eq!(
+            ledger.close_by_signal("s", 11.0, "t2").unwrap_err().errcode,
+            ErrCode::SignalTraded
+        );
+        assert_eq!(
+            ledger
+                .close_by_signal("s2", 11.0, "t2")
+                .unwrap_err()
+                .errcode,
+            ErrCode::RecordNotOpened
+        );
+    }
+
+    fn saved_ledger() -> CPaperLedger {
+        let mut ledger = ledger();
+        ledger.add_signal(signal("a", "000001", true)).unwrap();
+        ledger.add_signal(signal("b", "000001", false)).unwrap();
+        ledger.add_signal(signal("c", "000002", true)).unwrap();
+        let id = ledger.open("a", 10.0, 100.0, "t").unwrap();
+        ledger.close_by_signal("b", 9.5, "t2").unwrap();
+        assert_eq!(ledger.records[id].pnl(), Some(-50.0));
+        ledger.open("c", 10.0, 100.0, "t2").unwrap();
+        ledger
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!(
+                "chan_paper_ledger_{}_{:?}_{}",
+                std::process::id(),
+                std::thread::current().id(),
+                name
+            ))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_save_load() {
+        let ledger = saved_ledger();
+        let path = temp_path("ledger.json");
+        // 覆盖已有文件，且不留下临时文件
+        std::fs::write(&path, "broken").unwrap();
+        ledger.save(&path).unwrap();
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+        let loaded = CPaperLedger::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.config, ledger.config);
+        assert_eq!(loaded.signals, ledger.signals);
+        assert_eq!(loaded.records, ledger.records);
+    }
+
+    #[test]
+    fn test_save_load_sqlite() {
+        let mut ledger = saved_ledger();
+        let path = temp_path("ledger.db");
+        let _ = std::fs::remove_file(&path);
+        ledger.save_sqlite(&path).unwrap();
+        // 再次保存是整库重写，不会重复插入
+        ledger.close_by_code("000002", 10.5, "t3").unwrap();
+        ledger.save_sqlite(&path).unwrap();
+        let loaded = CPaperLedger::load_sqlite(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.config, ledger.config);
+        assert_eq!(loaded.signals, ledger.signals);
+        assert_eq!(loaded.records, ledger.records);
+    }
+}
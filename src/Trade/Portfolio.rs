@@ -0,0 +1,755 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::{json, Value};
+
+use crate::Chan::CChan;
+use crate::Common::CEnum::KlType;
+use crate::Common::ChanException::{CChanException, ErrCode};
+use crate::KLine::KLine_Unit::CKLineUnit;
+use crate::Trade::BrokerGateway::CHolding;
+
+// 没有出现在行业映射里的品种归到这一组
+pub const UNKNOWN_SECTOR: &str = "UNKNOWN";
+
+pub struct CPortfolioConfig {
+    // 参与统计的级别，第一个级别用于计算收益率、波动率和相关系数
+    pub lv_list: Vec<KlType>,
+    // 最后一个买卖点距今不超过多少根K线才计入广度，None 表示不限
+    pub signal_window: Option<i32>,
+    // 计算波动率和相关系数用的K线根数
+    pub corr_window: usize,
+    // 仓位建议的总资金
+    pub capital: f64,
+    // 单个品种的最大权重
+    pub max_weight: f64,
+    pub lot_size: f64,
+}
+
+impl Default for CPortfolioConfig {
+    fn default() -> Self {
+        CPortfolioConfig {
+            lv_list: vec![KlType::K_DAY],
+            signal_window: Some(5),
+            corr_window: 60,
+            capital: 1_000_000.0,
+            max_weight: 0.2,
+            lot_size: 100.0,
+        }
+    }
+}
+
+// 某个级别上最后一个买卖点
+#[derive(Clone, Debug, PartialEq)]
+pub struct CMemberSignal {
+    pub bsp_type: String,
+    pub is_buy: bool,
+    pub time: String,
+    pub bars_ago: i32,
+}
+
+// 成员的增量状态，每次推送K线后只更新对应成员
+#[derive(Clone, Debug, Default)]
+pub struct CMemberState {
+    pub code: String,
+    // 第一个级别的收盘价，key 为时间
+    pub closes: BTreeMap<String, f64>,
+    pub last_price: Option<f64>,
+    // 与 lv_list 一一对应
+    pub signals: Vec<Option<CMemberSignal>>,
+    pub volume: f64,
+}
+
+impl CMemberState {
+    pub fn new(code: &str, lv_cnt: usize) -> Self {
+        CMemberState {
+            code: code.to_string(),
+            signals: vec![None; lv_cnt],
+            ..Default::default()
+        }
+    }
+
+    // 在 lv_idx 级别上、signal_window 内的最后一个买卖点
+    pub fn recent_signal(
+        &self,
+        lv_idx: usize,
+        signal_window: Option<i32>,
+    ) -> Option<&CMemberSignal> {
+        self.signals
+            .get(lv_idx)?
+            .as_ref()
+            .filter(|s| signal_window.is_none_or(|w| s.bars_ago <= w))
+    }
+
+    // 只保留最近 keep 根收盘价
+    pub fn add_close(&mut self, time: String, close: f64, keep: usize) {
+        self.closes.insert(time, close);
+        self.last_price = Some(close);
+        while self.closes.len() > keep {
+            self.closes.pop_first();
+        }
+    }
+
+    pub fn market_value(&self) -> f64 {
+        self.volume * self.last_price.unwrap_or(0.0)
+    }
+
+    // 最近 window 个收益率
+    pub fn returns(&self, window: usize) -> Vec<f64> {
+        let closes: Vec<f64> = self.closes.values().cloned().collect();
+        let begin = closes.len().saturating_sub(window + 1);
+        closes[begin..]
+            .windows(2)
+            .map(|w| w[1] / w[0] - 1.0)
+            .collect()
+    }
+}
+
+pub struct CPortfolioMember {
+    pub chan: CChan,
+    pub state: CMemberState,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CBreadth {
+    pub lv: String,
+    pub buy_cnt: usize,
+    pub sell_cnt: usize,
+    // 窗口内没有买卖点的品种数
+    pub none_cnt: usize,
+}
+
+impl CBreadth {
+    // (买 - 卖) / (买 + 卖)，都没有时为 None
+    pub fn ratio(&self) -> Option<f64> {
+        let total = self.buy_cnt + self.sell_cnt;
+        if total == 0 {
+            return None;
+        }
+        Some((self.buy_cnt as f64 - self.sell_cnt as f64) / total as f64)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CSectorRow {
+    pub sector: String,
+    pub codes: Vec<String>,
+    // 第一个级别上的买/卖点数
+    pub buy_cnt: usize,
+    pub sell_cnt: usize,
+    pub market_value: f64,
+    pub weight: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CSizeSuggestion {
+    pub code: String,
+    pub volatility: Option<f64>,
+    // 与其他候选品种的平均正相关系数
+    pub avg_corr: f64,
+    pub weight: f64,
+    pub amount: f64,
+    pub volume: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CExposureRow {
+    pub code: String,
+    pub sector: String,
+    pub volume: f64,
+    pub price: Option<f64>,
+    pub market_value: f64,
+    pub weight: f64,
+    // 持仓品种各级别最后一个买卖点的方向
+    pub signals: Vec<Option<bool>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CExposureReport {
+    pub lv_list: Vec<String>,
+    pub total_value: f64,
+    pub rows: Vec<CExposureRow>,
+    pub sectors: Vec<CSectorRow>,
+    // 第一个级别最后一个买卖点为卖点的持仓市值
+    pub at_risk_value: f64,
+    pub breadth: Vec<CBreadth>,
+}
+
+impl CExposureReport {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "total_value": self.total_value,
+            "at_risk_value": self.at_risk_value,
+            "positions": self.rows.iter().map(|row| json!({
+                "code": row.code,
+                "sector": row.sector,
+                "volume": row.volume,
+                "price": row.price,
+                "market_value": row.market_value,
+                "weight": row.weight,
+                "signals": self.lv_list.iter().zip(&row.signals).map(|(lv, s)| {
+                    (lv.clone(), match s {
+                        Some(true) => json!("buy"),
+                        Some(false) => json!("sell"),
+                        None => Value::Null,
+                    })
+                }).collect::<serde_json::Map<String, Value>>(),
+            })).collect::<Vec<_>>(),
+            "sectors": self.sectors.iter().map(|row| json!({
+                "sector": row.sector,
+                "codes": row.codes,
+                "buy_cnt": row.buy_cnt,
+                "sell_cnt": row.sell_cnt,
+                "market_value": row.market_value,
+                "weight": row.weight,
+            })).collect::<Vec<_>>(),
+            "breadth": self.breadth.iter().map(|b| json!({
+                "lv": b.lv,
+                "buy_cnt": b.buy_cnt,
+                "sell_cnt": b.sell_cnt,
+                "none_cnt": b.none_cnt,
+                "ratio": b.ratio(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+pub fn cal_breadth(
+    states: &[&CMemberState],
+    lv_list: &[String],
+    signal_window: Option<i32>,
+) -> Vec<CBreadth> {
+    lv_list
+        .iter()
+        .enumerate()
+        .map(|(lv_idx, lv)| {
+            let mut breadth = CBreadth {
+                lv: lv.clone(),
+                buy_cnt: 0,
+                sell_cnt: 0,
+                none_cnt: 0,
+            };
+            for state in states {
+                match state.recent_signal(lv_idx, signal_window) {
+                    Some(s) if s.is_buy => breadth.buy_cnt += 1,
+                    Some(_) => breadth.sell_cnt += 1,
+                    None => breadth.none_cnt += 1,
+                }
+            }
+            breadth
+        })
+        .collect()
+}
+
+pub fn cal_sectors(
+    states: &[&CMemberState],
+    sector_map: &HashMap<String, String>,
+    signal_window: Option<i32>,
+) -> Vec<CSectorRow> {
+    let total_value: f64 = states.iter().map(|s| s.market_value()).sum();
+    let mut groups: BTreeMap<String, CSectorRow> = BTreeMap::new();
+    for state in states {
+        let sector = sector_map
+            .get(&state.code)
+            .cloned()
+            .unwrap_or_else(|| UNKNOWN_SECTOR.to_string());
+        let row = groups.entry(sector.clone()).or_insert_with(|| CSectorRow {
+            sector,
+            codes: Vec::new(),
+            buy_cnt: 0,
+            sell_cnt: 0,
+            market_value: 0.0,
+            weight: 0.0,
+        });
+        row.codes.push(state.code.clone());
+        match state.recent_signal(0, signal_window) {
+            Some(s) if s.is_buy => row.buy_cnt += 1,
+            Some(_) => row.sell_cnt += 1,
+            None => {}
+        }
+        row.market_value += state.market_value();
+    }
+    groups
+        .into_values()
+        .map(|mut row| {
+            if total_value > 0.0 {
+                row.weight = row.market_value / total_value;
+            }
+            row
+        })
+        .collect()
+}
+
+// 样本标准差，少于两个样本时为 None
+pub fn volatility(rets: &[f64]) -> Option<f64> {
+    if rets.len() < 2 {
+        return None;
+    }
+    let mean = rets.iter().sum::<f64>() / rets.len() as f64;
+    let var = rets.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (rets.len() - 1) as f64;
+    Some(var.sqrt())
+}
+
+// 两个品种在共同时间上的收益率相关系数，只取最近 window 个共同收益率
+pub fn correlation(
+    closes1: &BTreeMap<String, f64>,
+    closes2: &BTreeMap<String, f64>,
+    window: usize,
+) -> Option<f64> {
+    let common: Vec<(f64, f64)> = closes1
+        .iter()
+        .filter_map(|(t, c1)| closes2.get(t).map(|c2| (*c1, *c2)))
+        .collect();
+    let begin = common.len().saturating_sub(window + 1);
+    let (rets1, rets2): (Vec<f64>, Vec<f64>) = common[begin..]
+        .windows(2)
+        .map(|w| (w[1].0 / w[0].0 - 1.0, w[1].1 / w[0].1 - 1.0))
+        .unzip();
+    if rets1.len() < 3 {
+        return None;
+    }
+    let n = rets1.len() as f64;
+    let mean1 = rets1.iter().sum::<f64>() / n;
+    let mean2 = rets2.iter().sum::<f64>() / n;
+    let cov: f64 = rets1
+        .iter()
+        .zip(&rets2)
+        .map(|(a, b)| (a - mean1) * (b - mean2))
+        .sum();
+    let var1: f64 = rets1.iter().map(|a| (a - mean1).powi(2)).sum();
+    let var2: f64 = rets2.iter().map(|b| (b - mean2).powi(2)).sum();
+    if var1 <= 0.0 || var2 <= 0.0 {
+        return None;
+    }
+    Some(cov / (var1 * var2).sqrt())
+}
+
+// 波动率倒数加权，再按与其他品种的正相关程度打折：w_i ∝ (1/σ_i) / (1 + Σ_j max(ρ_ij, 0))
+// 单个权重不超过 max_weight，超出部分按比例分给其余品种，全部封顶时剩余部分留作现金
+pub fn suggest_weights(
+    vols: &[Option<f64>],
+    corr: &[Vec<Option<f64>>],
+    max_weight: f64,
+) -> Vec<f64> {
+    let raw: Vec<f64> = vols
+        .iter()
+        .enumerate()
+        .map(|(i, vol)| match vol {
+            Some(vol) if *vol > 0.0 => {
+                let corr_sum: f64 = (0..vols.len())
+                    .filter(|j| *j != i)
+                    .filter_map(|j| corr[i][j])
+                    .map(|c| c.max(0.0))
+                    .sum();
+                1.0 / vol / (1.0 + corr_sum)
+            }
+            _ => 0.0,
+        })
+        .collect();
+    let mut weights = vec![0.0; raw.len()];
+    let mut capped = vec![false; raw.len()];
+    let mut left = 1.0;
+    loop {
+        let free_sum: f64 = (0..raw.len()).filter(|i| !capped[*i]).map(|i| raw[i]).sum();
+        if free_sum <= 0.0 {
+            break;
+        }
+        let mut new_cap = false;
+        for i in 0..raw.len() {
+            if capped[i] {
+                continue;
+            }
+            weights[i] = left * raw[i] / free_sum;
+            if weights[i] > max_weight {
+                weights[i] = max_weight;
+                capped[i] = true;
+                new_cap = true;
+            }
+        }
+        if !new_cap {
+            break;
+        }
+        left = 1.0
+            - (0..raw.len())
+                .filter(|i| capped[*i])
+                .map(|i| weights[i])
+                .sum::<f64>();
+    }
+    weights
+}
+
+// 持有多个品种的 CChan，K线推送后增量更新各成员的买卖点和价格，汇总出广度、行业、仓位和敞口
+pub struct CPortfolio {
+    pub config: CPortfolioConfig,
+    pub members: BTreeMap<String, CPortfolioMember>,
+    // 代码 -> 行业
+    pub sector_map: HashMap<String, String>,
+}
+
+impl CPortfolio {
+    pub fn new(config: CPortfolioConfig) -> Result<Self, CChanException> {
+        if config.lv_list.is_empty() {
+            return Err(CChanException::new(
+                "CPortfolioConfig.lv_list 不能为空".to_string(),
+                ErrCode::ParaError,
+            ));
+        }
+        Ok(CPortfolio {
+            config,
+            members: BTreeMap::new(),
+            sector_map: HashMap::new(),
+        })
+    }
+
+    fn lv_names(&self) -> Vec<String> {
+        self.config
+            .lv_list
+            .iter()
+            .map(|lv| lv.to_string())
+            .collect()
+    }
+
+    fn states(&self) -> Vec<&CMemberState> {
+        self.members.values().map(|m| &m.state).collect()
+    }
+
+    // chan 需以 trigger_step=true 创建，且包含 config.lv_list 中的级别
+    pub fn add_member(&mut self, code: &str, chan: CChan) {
+        let state = CMemberState::new(code, self.config.lv_list.len());
+        self.members
+            .insert(code.to_string(), CPortfolioMember { chan, state });
+    }
+
+    pub fn remove_member(&mut self, code: &str) -> Option<CPortfolioMember> {
+        self.members.remove(code)
+    }
+
+    pub fn set_sector_map(&mut self, sector_map: HashMap<String, String>) {
+        self.sector_map = sector_map;
+    }
+
+    fn get_member(&mut self, code: &str) -> Result<&mut CPortfolioMember, CChanException> {
+        self.members
+            .get_mut(code)
+            .ok_or_else(|| CChanException::new(format!("{} 不在组合中", code), ErrCode::ParaError))
+    }
+
+    pub fn set_position(&mut self, code: &str, volume: f64) -> Result<(), CChanException> {
+        self.get_member(code)?.state.volume = volume;
+        Ok(())
+    }
+
+    // 按券商持仓同步，不在持仓里的成员仓位清零
+    pub fn set_holdings(&mut self, holdings: &[CHolding]) {
+        for member in self.members.values_mut() {
+            member.state.volume = holdings
+                .iter()
+                .filter(|h| h.code == member.state.code)
+                .map(|h| h.volume)
+                .sum();
+        }
+    }
+
+    // 给某个成员推送新K线，只重算这个成员的状态；K线加载失败时状态不变
+    pub fn push_bars(
+        &mut self,
+        code: &str,
+        bars: HashMap<KlType, Vec<CKLineUnit>>,
+    ) -> Result<(), CChanException> {
+        let lv_list: Vec<KlType> = self.config.lv_list.clone();
+        // 收益率比收盘价少一个
+        let keep = self.config.corr_window + 1;
+        let member = self.get_member(code)?;
+        let closes: Vec<(String, f64)> = bars.get(&lv_list[0]).map_or_else(Vec::new, |klu_lst| {
+            klu_lst
+                .iter()
+                .map(|klu| (klu.time.to_string(), klu.close))
+                .collect()
+        });
+        member.chan.trigger_load(bars)?;
+        for (time, close) in closes {
+            member.state.add_close(time, close, keep);
+        }
+
+        for (lv_idx, lv) in lv_list.iter().enumerate() {
+            let kl_list = match member.chan.get(*lv) {
+                Some(kl_list) => kl_list,
+                None => continue,
+            };
+            let cur_idx = kl_list
+                .lst
+                .last()
+                .and_then(|klc| klc.borrow().lst.last().map(|klu| klu.borrow().idx));
+            member.state.signals[lv_idx] = match (kl_list.bs_point_lst.last(), cur_idx) {
+                (Some(bsp), Some(cur_idx)) => {
+                    let bsp = bsp.borrow();
+                    let klu = bsp.klu.borrow();
+                    Some(CMemberSignal {
+                        bsp_type: bsp.type2str(),
+                        is_buy: bsp.is_buy,
+                        time: klu.time.to_string(),
+                        bars_ago: cur_idx - klu.idx,
+                    })
+                }
+                _ => None,
+            };
+        }
+        Ok(())
+    }
+
+    // 各级别买/卖点的品种数
+    pub fn breadth(&self) -> Vec<CBreadth> {
+        cal_breadth(&self.states(), &self.lv_names(), self.config.signal_window)
+    }
+
+    pub fn sectors(&self) -> Vec<CSectorRow> {
+        cal_sectors(&self.states(), &self.sector_map, self.config.signal_window)
+    }
+
+    // codes 为 None 时对第一个级别上有买点的品种给出仓位建议
+    pub fn suggest_sizes(&self, codes: Option<&[String]>) -> Vec<CSizeSuggestion> {
+        let states: Vec<&CMemberState> = match codes {
+            Some(codes) => codes
+                .iter()
+                .filter_map(|code| self.members.get(code).map(|m| &m.state))
+                .collect(),
+            None => self
+                .states()
+                .into_iter()
+                .filter(|s| {
+                    s.recent_signal(0, self.config.signal_window)
+                        .is_some_and(|sig| sig.is_buy)
+                })
+                .collect(),
+        };
+        let window = self.config.corr_window;
+        let vols: Vec<Option<f64>> = states
+            .iter()
+            .map(|s| volatility(&s.returns(window)))
+            .collect();
+        let corr: Vec<Vec<Option<f64>>> = states
+            .iter()
+            .map(|a| {
+                states
+                    .iter()
+                    .map(|b| correlation(&a.closes, &b.closes, window))
+                    .collect()
+            })
+            .collect();
+        let weights = suggest_weights(&vols, &corr, self.config.max_weight);
+        states
+            .iter()
+            .enumerate()
+            .map(|(i, state)| {
+                let others: Vec<f64> = (0..states.len())
+                    .filter(|j| *j != i)
+                    .filter_map(|j| corr[i][j])
+                    .map(|c| c.max(0.0))
+                    .collect();
+                let amount = weights[i] * self.config.capital;
+                let volume = match state.last_price {
+                    Some(price) if price > 0.0 => {
+                        (amount / price / self.config.lot_size).floor() * self.config.lot_size
+                    }
+                    _ => 0.0,
+                };
+                CSizeSuggestion {
+                    code: state.code.clone(),
+                    volatility: vols[i],
+                    avg_corr: if others.is_empty() {
+                        0.0
+                    } else {
+                        others.iter().sum::<f64>() / others.len() as f64
+                    },
+                    weight: weights[i],
+                    amount,
+                    volume,
+                }
+            })
+            .collect()
+    }
+
+    pub fn exposure(&self) -> CExposureReport {
+        cal_exposure(
+            &self.states(),
+            &self.lv_names(),
+            &self.sector_map,
+            self.config.signal_window,
+        )
+    }
+}
+
+pub fn cal_exposure(
+    states: &[&CMemberState],
+    lv_list: &[String],
+    sector_map: &HashMap<String, String>,
+    signal_window: Option<i32>,
+) -> CExposureReport {
+    let total_value: f64 = states.iter().map(|s| s.market_value()).sum();
+    let rows: Vec<CExposureRow> = states
+        .iter()
+        .filter(|s| s.volume != 0.0)
+        .map(|s| CExposureRow {
+            code: s.code.clone(),
+            sector: sector_map
+                .get(&s.code)
+                .cloned()
+                .unwrap_or_else(|| UNKNOWN_SECTOR.to_string()),
+            volume: s.volume,
+            price: s.last_price,
+            market_value: s.market_value(),
+            weight: if total_value > 0.0 {
+                s.market_value() / total_value
+            } else {
+                0.0
+            },
+            signals: (0..lv_list.len())
+                .map(|lv_idx| s.recent_signal(lv_idx, signal_window).map(|sig| sig.is_buy))
+                .collect(),
+        })
+        .collect();
+    let at_risk_value = rows
+        .iter()
+        .filter(|row| row.signals.first() == Some(&Some(false)))
+        .map(|row| row.market_value)
+        .sum();
+    CExposureReport {
+        lv_list: lv_list.to_vec(),
+        total_value,
+        rows,
+        sectors: cal_sectors(states, sector_map, signal_window),
+        at_risk_value,
+        breadth: cal_breadth(states, lv_list, signal_window),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(
+        code: &str,
+        closes: &[f64],
+        signals: Vec<Option<(bool, i32)>>,
+        volume: f64,
+    ) -> CMemberState {
+        CMemberState {
+            code: code.to_string(),
+            closes: closes
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (format!("2024/01/{:02}", i + 1), *c))
+                .collect(),
+            last_price: closes.last().cloned(),
+            signals: signals
+                .into_iter()
+                .map(|s| {
+                    s.map(|(is_buy, bars_ago)| CMemberSignal {
+                        bsp_type: "T1".to_string(),
+                        is_buy,
+                        time: String::new(),
+                        bars_ago,
+                    })
+                })
+                .collect(),
+            volume,
+        }
+    }
+
+    #[test]
+    fn test_breadth_sector_exposure() {
+        let a = state(
+            "a",
+            &[10.0, 11.0],
+            vec![Some((true, 1)), Some((false, 0))],
+            100.0,
+        );
+        let b = state("b", &[20.0, 20.0], vec![Some((false, 2)), None], 100.0);
+        let c = state(
+            "c",
+            &[5.0, 4.0],
+            vec![Some((true, 9)), Some((true, 1))],
+            0.0,
+        );
+        let states = vec![&a, &b, &c];
+        let lv_list = vec!["K_DAY".to_string(), "K_60M".to_string()];
+        let breadth = cal_breadth(&states, &lv_list, Some(5));
+        assert_eq!(
+            (breadth[0].buy_cnt, breadth[0].sell_cnt, breadth[0].none_cnt),
+            (1, 1, 1)
+        );
+        assert_eq!(breadth[0].ratio(), Some(0.0));
+        assert_eq!(
+            (breadth[1].buy_cnt, breadth[1].sell_cnt, breadth[1].none_cnt),
+            (1, 1, 1)
+        );
+
+        let sector_map = HashMap::from([
+            ("a".to_string(), "bank".to_string()),
+            ("c".to_string(), "bank".to_string()),
+        ]);
+        let report = cal_exposure(&states, &lv_list, &sector_map, Some(5));
+        assert_eq!(report.total_value, 3100.0);
+        assert_eq!(report.rows.len(), 2);
+        assert_eq!(report.rows[0].signals, vec![Some(true), Some(false)]);
+        assert_eq!(report.at_risk_value, 2000.0);
+        assert_eq!(report.sectors[0].sector, UNKNOWN_SECTOR);
+        assert!((report.sectors[0].weight - 2000.0 / 3100.0).abs() < 1e-9);
+        assert_eq!(report.sectors[1].sector, "bank");
+        assert_eq!(report.sectors[1].codes, vec!["a", "c"]);
+        assert_eq!(
+            (report.sectors[1].buy_cnt, report.sectors[1].sell_cnt),
+            (1, 0)
+        );
+        let json = report.to_json();
+        assert_eq!(json["positions"][1]["signals"]["K_DAY"], "sell");
+        assert_eq!(json["breadth"][0]["ratio"], 0.0);
+    }
+
+    #[test]
+    fn test_correlation_and_weights() {
+        let a = state("a", &[10.0, 11.0, 10.5, 11.5, 12.0], vec![], 0.0);
+        let b = state("b", &[20.0, 22.0, 21.0, 23.0, 24.0], vec![], 0.0);
+        let c = state("c", &[10.0, 9.0, 9.5, 8.5, 8.0], vec![], 0.0);
+        assert!((correlation(&a.closes, &b.closes, 60).unwrap() - 1.0).abs() < 1e-9);
+        assert!(correlation(&a.closes, &c.closes, 60).unwrap() < 0.0);
+        assert!(correlation(&a.closes, &b.closes, 2).is_none());
+
+        // a/b 完全正相关，权重被打折；c 与两者负相关
+        let vols = vec![Some(0.1), Some(0.1), Some(0.1), None];
+        let corr = vec![
+            vec![Some(1.0), Some(1.0), Some(-0.5), None],
+            vec![Some(1.0), Some(1.0), Some(-0.5), None],
+            vec![Some(-0.5), Some(-0.5), Some(1.0), None],
+            vec![None, None, None, None],
+        ];
+        let weights = suggest_weights(&vols, &corr, 1.0);
+        assert!((weights[0] - 0.25).abs() < 1e-9);
+        assert!((weights[2] - 0.5).abs() < 1e-9);
+        assert_eq!(weights[3], 0.0);
+
+        let weights = suggest_weights(&vols, &corr, 0.4);
+        assert!((weights[2] - 0.4).abs() < 1e-9);
+        assert!((weights[0] - 0.3).abs() < 1e-9);
+        let weights = suggest_weights(&vols[..1], &corr[..1], 0.4);
+        assert_eq!(weights, vec![0.4]);
+    }
+
+    #[test]
+    fn test_add_close_and_config() {
+        let mut a = state("a", &[], vec![], 0.0);
+        for i in 0..10 {
+            a.add_close(format!("2024/01/{:02}", i + 1), 10.0 + i as f64, 4);
+        }
+        assert_eq!(a.closes.len(), 4);
+        assert_eq!(a.closes.keys().next().unwrap(), "2024/01/07");
+        assert_eq!(a.last_price, Some(19.0));
+        assert_eq!(a.returns(60).len(), 3);
+
+        assert!(CPortfolio::new(CPortfolioConfig::default()).is_ok());
+        let err = CPortfolio::new(CPortfolioConfig {
+            lv_list: vec![],
+            ..CPortfolioConfig::default()
+        });
+        assert_eq!(err.err().unwrap().errcode, ErrCode::ParaError);
+    }
+}
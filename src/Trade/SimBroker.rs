@@ -0,0 +1,626 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::Common::CEnum::DataField;
+use crate::Common::ChanException::{CChanException, ErrCode};
+use crate::KLine::KLine_Unit::CKLineUnit;
+use crate::Trade::BrokerGateway::{
+    BrokerGateway, BrokerOrderStatus, CBrokerOrder, CHolding, CQuote,
+};
+
+// 时间格式与 CTime::to_str 一致（YYYY/MM/DD 或 YYYY/MM/DD HH:MM），按字符串即可排序
+#[derive(Clone, Debug)]
+pub struct CSimBar {
+    pub time: String,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl From<&CKLineUnit> for CSimBar {
+    fn from(klu: &CKLineUnit) -> Self {
+        CSimBar {
+            time: klu.time.to_string(),
+            open: klu.open,
+            high: klu.high,
+            low: klu.low,
+            close: klu.close,
+            volume: klu
+                .trade_info
+                .metric
+                .get(DataField::FIELD_VOLUME)
+                .copied()
+                .flatten()
+                .unwrap_or(0.0),
+        }
+    }
+}
+
+pub struct CSimBrokerConfig {
+    pub init_cash: f64,
+    // 为 None 时不需要解锁
+    pub password: Option<String>,
+    pub lot_sizes: HashMap<String, f64>,
+    // 没有单独配置的品种使用的每手股数，为 None 时查询报错
+    pub default_lot_size: Option<f64>,
+    // 委托提交后要再过多少根K线才开始撮合，0 表示下一根K线
+    pub latency_bars: usize,
+    // 每根K线最多成交该K线成交量的比例，为 None 时不限制（一次全部成交）
+    // 设置后成交量为0的K线（如停牌）不成交，委托留到后续K线，不会自动撤单
+    pub max_fill_rate: Option<f64>,
+}
+
+impl Default for CSimBrokerConfig {
+    fn default() -> Self {
+        CSimBrokerConfig {
+            init_cash: 1_000_000.0,
+            password: None,
+            lot_sizes: HashMap::new(),
+            default_lot_size: Some(100.0),
+            latency_bars: 0,
+            max_fill_rate: None,
+        }
+    }
+}
+
+struct CSimOrder {
+    order: CBrokerOrder,
+    // 提交时该品种已走到的K线下标，尚未开始时为 None
+    submit_pos: Option<usize>,
+}
+
+// 进程内模拟券商：用事先给定的K线逐根撮合，不依赖时钟和随机数，结果可复现
+pub struct CSimBroker {
+    pub config: CSimBrokerConfig,
+    pub cash: f64,
+    bars: BTreeMap<String, Vec<CSimBar>>,
+    // 每个品种当前走到的K线下标
+    cursor: BTreeMap<String, usize>,
+    orders: Vec<CSimOrder>,
+    // code -> (持仓数量, 持仓成本)
+    holdings: BTreeMap<String, (f64, f64)>,
+    unlocked: bool,
+}
+
+impl CSimBroker {
+    pub fn new(config: CSimBrokerConfig) -> Self {
+        CSimBroker {
+            cash: config.init_cash,
+            unlocked: config.password.is_none(),
+            config,
+            bars: BTreeMap::new(),
+            cursor: BTreeMap::new(),
+            orders: Vec::new(),
+            holdings: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_bars(&mut self, code: &str, bars: Vec<CSimBar>) {
+        self.bars.insert(code.to_string(), bars);
+        self.cursor.remove(code);
+    }
+
+    fn cur_bar(&self, code: &str) -> Option<&CSimBar> {
+        self.bars.get(code)?.get(*self.cursor.get(code)?)
+    }
+
+    // 推进到所有品种中最早的下一根K线时间，该时间有K线的品种都前进一根并撮合委托
+    // 返回推进到的时间，K线全部走完时返回 None
+    pub fn step(&mut self) -> Option<String> {
+        let next_time = self
+            .bars
+            .iter()
+            .filter_map(|(code, bars)| {
+                let pos = self.cursor.get(code).map_or(0, |p| p + 1);
+                bars.get(pos).map(|bar| bar.time.clone())
+            })
+            .min()?;
+        let codes: Vec<String> = self.bars.keys().cloned().collect();
+        for code in codes {
+            let pos = self.cursor.get(&code).map_or(0, |p| p + 1);
+            if self.bars[&code]
+                .get(pos)
+                .map_or(false, |bar| bar.time == next_time)
+            {
+                self.cursor.insert(code.clone(), pos);
+                self.match_orders(&code, pos);
+            }
+        }
+        Some(next_time)
+    }
+
+    fn match_orders(&mut self, code: &str, pos: usize) {
+        let bar = self.bars[code][pos].clone();
+        let lot_size = self.get_lot_size(code).unwrap_or(1.0);
+        let mut bar_capacity = self
+            .config
+            .max_fill_rate
+            .map(|rate| (bar.volume * rate / lot_size).floor() * lot_size);
+        let latency = self.config.latency_bars;
+
+        for i in 0..self.orders.len() {
+            let sim_order = &self.orders[i];
+            let order = &sim_order.order;
+            if order.code != code || order.status.is_final() {
+                continue;
+            }
+            let ready_pos = sim_order.submit_pos.map_or(latency, |p| p + 1 + latency);
+            if pos < ready_pos {
+                continue;
+            }
+            let fill_price = match (order.price, order.is_buy) {
+                (None, _) => bar.open,
+                (Some(p), true) if bar.open <= p => bar.open,
+                (Some(p), true) if bar.low <= p => p,
+                (Some(p), false) if bar.open >= p => bar.open,
+                (Some(p), false) if bar.high >= p => p,
+                _ => continue,
+            };
+            let mut fill_volume = order.volume - order.filled_volume;
+            // 市价单按开盘价成交，跳空高开时资金可能不够，只成交买得起的整手，剩余部分继续挂单
+            if order.is_buy {
+                let affordable = (self.cash / fill_price / lot_size).floor() * lot_size;
+                fill_volume = fill_volume.min(affordable);
+            }
+            if let Some(capacity) = bar_capacity.as_mut() {
+                fill_volume = fill_volume.min(*capacity);
+                *capacity -= fill_volume;
+            }
+            if fill_volume <= 0.0 {
+                continue;
+            }
+            self.fill(i, fill_price, fill_volume);
+        }
+    }
+
+    fn fill(&mut self, order_idx: usize, price: f64, volume: f64) {
+        let order = &mut self.orders[order_idx].order;
+        order.avg_fill_price = (order.avg_fill_price * order.filled_volume + price * volume)
+            / (order.filled_volume + volume);
+        order.filled_volume += volume;
+        order.status = if order.filled_volume >= order.volume {
+            BrokerOrderStatus::Filled
+        } else {
+            BrokerOrderStatus::PartFilled
+        };
+
+        let holding = self
+            .holdings
+            .entry(order.code.clone())
+            .or_insert((0.0, 0.0));
+        if order.is_buy {
+            self.cash -= price * volume;
+            holding.1 = (holding.0 * holding.1 + price * volume) / (holding.0 + volume);
+            holding.0 += volume;
+        } else {
+            self.cash += price * volume;
+            holding.0 -= volume;
+        }
+        if holding.0 <= 0.0 {
+            let code = order.code.clone();
+            self.holdings.remove(&code);
+        }
+    }
+
+    // 未成交部分占用的资金/持仓
+    fn pending_amount(&self, code: &str, is_buy: bool) -> f64 {
+        self.orders
+            .iter()
+            .map(|o| &o.order)
+            .filter(|o| !o.status.is_final() && o.is_buy == is_buy && (is_buy || o.code == code))
+            .map(|o| {
+                let remain = o.volume - o.filled_volume;
+                if is_buy {
+                    remain * o.price.unwrap_or_else(|| self.last_price(&o.code))
+                } else {
+                    remain
+                }
+            })
+            .sum()
+    }
+
+    fn last_price(&self, code: &str) -> f64 {
+        self.cur_bar(code).map_or(0.0, |bar| bar.close)
+    }
+
+    fn check_unlocked(&self, errcode: ErrCode) -> Result<(), CChanException> {
+        if self.unlocked {
+            Ok(())
+        } else {
+            Err(CChanException::new(
+                "trade is locked, unlock first".to_string(),
+                errcode,
+            ))
+        }
+    }
+}
+
+impl BrokerGateway for CSimBroker {
+    fn unlock(&mut self, password: &str) -> Result<(), CChanException> {
+        match &self.config.password {
+            Some(p) if p != password => Err(CChanException::new(
+                "unlock trade fail: wrong password".to_string(),
+                ErrCode::TradeUnlockFail,
+            )),
+            _ => {
+                self.unlocked = true;
+                Ok(())
+            }
+        }
+    }
+
+    fn place_order(
+        &mut self,
+        code: &str,
+        is_buy: bool,
+        volume: f64,
+        price: Option<f64>,
+    ) -> Result<String, CChanException> {
+        self.check_unlocked(ErrCode::TradeUnlockFail)?;
+        let place_err = |msg: String| {
+            CChanException::new(
+                format!("place order fail: {}", msg),
+                ErrCode::PlaceOrderFail,
+            )
+        };
+        if !self.bars.contains_key(code) {
+            return Err(place_err(format!("unknown code {}", code)));
+        }
+        let lot_size = self.get_lot_size(code)?;
+        if volume <= 0.0 || volume % lot_size != 0.0 {
+            return Err(place_err(format!(
+                "volume {} not multiple of lot size {}",
+                volume, lot_size
+            )));
+        }
+        if price.map_or(false, |p| p <= 0.0) {
+            return Err(place_err(format!("invalid price {:?}", price)));
+        }
+        if is_buy {
+            let amount = volume * price.unwrap_or_else(|| self.last_price(code));
+            if amount + self.pending_amount(code, true) > self.cash {
+                return Err(place_err(format!(
+                    "cash {} not enough for {}",
+                    self.cash, amount
+                )));
+            }
+        } else {
+            let holding = self.holdings.get(code).map_or(0.0, |h| h.0);
+            if volume + self.pending_amount(code, false) > holding {
+                return Err(place_err(format!(
+                    "holding {} of {} not enough",
+                    holding, code
+                )));
+            }
+        }
+
+        let order_id = format!("SIM{:06}", self.orders.len());
+        self.orders.push(CSimOrder {
+            order: CBrokerOrder {
+                order_id: order_id.clone(),
+                code: code.to_string(),
+                is_buy,
+                price,
+                volume,
+                filled_volume: 0.0,
+                avg_fill_price: 0.0,
+                status: BrokerOrderStatus::Submitted,
+                submit_time: self
+                    .cur_bar(code)
+                    .map_or(String::new(), |bar| bar.time.clone()),
+            },
+            submit_pos: self.cursor.get(code).copied(),
+        });
+        Ok(order_id)
+    }
+
+    fn cancel_order(&mut self, order_id: &str) -> Result<(), CChanException> {
+        self.check_unlocked(ErrCode::TradeUnlockFail)?;
+        let order = self
+            .orders
+            .iter_mut()
+            .map(|o| &mut o.order)
+            .find(|o| o.order_id == order_id)
+            .ok_or_else(|| {
+                CChanException::new(
+                    format!("cancel order fail: {} not exist", order_id),
+                    ErrCode::CandelOrderFail,
+                )
+            })?;
+        if order.status.is_final() {
+            return Err(CChanException::new(
+                format!("cancel order fail: {} is {:?}", order_id, order.status),
+                ErrCode::CandelOrderFail,
+            ));
+        }
+        order.status = BrokerOrderStatus::Canceled;
+        Ok(())
+    }
+
+    fn list_orders(&self, code: Option<&str>) -> Result<Vec<CBrokerOrder>, CChanException> {
+        if let Some(code) = code {
+            if !self.bars.contains_key(code) {
+                return Err(CChanException::new(
+                    format!("list order fail: unknown code {}", code),
+                    ErrCode::ListOrderFail,
+                ));
+            }
+        }
+        Ok(self
+            .orders
+            .iter()
+            .map(|o| &o.order)
+            .filter(|o| code.map_or(true, |c| o.code == c))
+            .cloned()
+            .collect())
+    }
+
+    fn get_holdings(&self) -> Result<Vec<CHolding>, CChanException> {
+        Ok(self
+            .holdings
+            .iter()
+            .map(|(code, (volume, avg_cost))| CHolding {
+                code: code.clone(),
+                volume: *volume,
+                avg_cost: *avg_cost,
+            })
+            .collect())
+    }
+
+    fn get_holding_qty(&self, code: &str) -> Result<f64, CChanException> {
+        if !self.bars.contains_key(code) {
+            return Err(CChanException::new(
+                format!("get holding fail: unknown code {}", code),
+                ErrCode::GetHoldingQtyFail,
+            ));
+        }
+        Ok(self.holdings.get(code).map_or(0.0, |h| h.0))
+    }
+
+    fn get_lot_size(&self, code: &str) -> Result<f64, CChanException> {
+        self.config
+            .lot_sizes
+            .get(code)
+            .copied()
+            .or(self.config.default_lot_size)
+            .ok_or_else(|| {
+                CChanException::new(
+                    format!("get lot size of {} fail", code),
+                    ErrCode::GetFutuLotSizeFail,
+                )
+            })
+    }
+
+    fn get_quote(&self, code: &str) -> Result<CQuote, CChanException> {
+        let bar = self.cur_bar(code).ok_or_else(|| {
+            CChanException::new(
+                format!("get price of {} fail", code),
+                ErrCode::GetFutuPriceFail,
+            )
+        })?;
+        Ok(CQuote {
+            code: code.to_string(),
+            time: bar.time.clone(),
+            last_price: bar.close,
+        })
+    }
+
+    fn get_trading_days(&self, begin: &str, end: &str) -> Result<Vec<String>, CChanException> {
+        if begin > end || self.bars.is_empty() {
+            return Err(CChanException::new(
+                format!("request trading days [{}, {}] fail", begin, end),
+                ErrCode::RequestTradingDaysFail,
+            ));
+        }
+        let days: BTreeSet<String> = self
+            .bars
+            .values()
+            .flatten()
+            .map(|bar| bar.time.split(' ').next().unwrap_or_default().to_string())
+            .filter(|day| day.as_str() >= begin && day.as_str() <= end)
+            .collect();
+        Ok(days.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(time: &str, open: f64, high: f64, low: f64, close: f64, volume: f64) -> CSimBar {
+        CSimBar {
+            time: time.to_string(),
+            open,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+
+    fn broker(latency_bars: usize, max_fill_rate: Option<f64>) -> CSimBroker {
+        let mut broker = CSimBroker::new(CSimBrokerConfig {
+            init_cash: 100_000.0,
+            password: Some("pwd".to_string()),
+            latency_bars,
+            max_fill_rate,
+            ..CSimBrokerConfig::default()
+        });
+        broker.add_bars(
+            "A",
+            vec![
+                bar("2024/01/02", 10.0, 10.5, 9.8, 10.2, 1000.0),
+                bar("2024/01/03", 10.3, 10.8, 10.1, 10.6, 1000.0),
+                bar("2024/01/04", 10.6, 11.0, 10.4, 10.9, 1000.0),
+            ],
+        );
+        broker.add_bars("B", vec![bar("2024/01/03", 5.0, 5.2, 4.9, 5.1, 500.0)]);
+        broker
+    }
+
+    #[test]
+    fn test_unlock_and_errors() {
+        let mut broker = broker(0, None);
+        let err = broker.place_order("A", true, 100.0, None).unwrap_err();
+        assert_eq!(err.errcode, ErrCode::TradeUnlockFail);
+        assert_eq!(
+            broker.unlock("x").unwrap_err().errcode,
+            ErrCode::TradeUnlockFail
+        );
+        broker.unlock("pwd").unwrap();
+
+        assert_eq!(
+            broker.get_quote("A").unwrap_err().errcode,
+            ErrCode::GetFutuPriceFail
+        );
+        assert_eq!(
+            broker
+                .place_order("A", true, 150.0, Some(10.0))
+                .unwrap_err()
+                .errcode,
+            ErrCode::PlaceOrderFail
+        );
+        assert_eq!(
+            broker
+                .place_order("A", false, 100.0, None)
+                .unwrap_err()
+                .errcode,
+            ErrCode::PlaceOrderFail
+        );
+        assert_eq!(
+            broker.list_orders(Some("C")).unwrap_err().errcode,
+            ErrCode::ListOrderFail
+        );
+        assert_eq!(
+            broker.cancel_order("SIM999").unwrap_err().errcode,
+            ErrCode::CandelOrderFail
+        );
+        broker.config.default_lot_size = None;
+        assert_eq!(
+            broker.get_lot_size("A").unwrap_err().errcode,
+            ErrCode::GetFutuLotSizeFail
+        );
+        assert_eq!(
+            broker
+                .get_trading_days("2024/02/01", "2024/01/01")
+                .unwrap_err()
+                .errcode,
+            ErrCode::RequestTradingDaysFail
+        );
+    }
+
+    #[test]
+    fn test_latency_and_partial_fill() {
+        let mut broker = broker(1, Some(0.3));
+        broker.unlock("pwd").unwrap();
+        assert_eq!(broker.step(), Some("2024/01/02".to_string()));
+        let id = broker.place_order("A", true, 500.0, None).unwrap();
+
+        // 延迟1根K线，01/03 不撮合
+        assert_eq!(broker.step(), Some("2024/01/03".to_string()));
+        assert_eq!(broker.get_quote("B").unwrap().last_price, 5.1);
+        assert_eq!(broker.list_orders(Some("A")).unwrap()[0].filled_volume, 0.0);
+
+        // 01/04 最多成交 1000*0.3 -> 300
+        assert_eq!(broker.step(), Some("2024/01/04".to_string()));
+        let order = &broker.list_orders(None).unwrap()[0];
+        assert_eq!(order.status, BrokerOrderStatus::PartFilled);
+        assert_eq!(order.filled_volume, 300.0);
+        assert_eq!(order.avg_fill_price, 10.6);
+        assert_eq!(broker.get_holding_qty("A").unwrap(), 300.0);
+        assert!((broker.cash - (100_000.0 - 3180.0)).abs() < 1e-9);
+
+        broker.cancel_order(&id).unwrap();
+        assert_eq!(
+            broker.cancel_order(&id).unwrap_err().errcode,
+            ErrCode::CandelOrderFail
+        );
+        assert_eq!(broker.step(), None);
+        assert_eq!(
+            broker.get_trading_days("2024/01/01", "2024/01/03").unwrap(),
+            vec!["2024/01/02".to_string(), "2024/01/03".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_limit_order() {
+        let mut broker = broker(0, None);
+        broker.unlock("pwd").unwrap();
+        broker.step();
+        broker.place_order("A", true, 200.0, Some(10.2)).unwrap();
+        broker.place_order("A", true, 100.0, Some(9.0)).unwrap();
+        broker.step();
+        let orders = broker.list_orders(Some("A")).unwrap();
+        assert_eq!(orders[0].status, BrokerOrderStatus::Filled);
+        assert_eq!(orders[0].avg_fill_price, 10.2);
+        assert_eq!(orders[1].status, BrokerOrderStatus::Submitted);
+
+        broker.place_order("A", false, 200.0, Some(10.8)).unwrap();
+        broker.step();
+        let holdings = broker.get_holdings().unwrap();
+        assert!(holdings.is_empty());
+        assert_eq!(
+            broker.list_orders(Some("A")).unwrap()[2].avg_fill_price,
+            10.8
+        );
+    }
+
+    #[test]
+    fn test_buy_capped_by_cash() {
+        let mut broker = CSimBroker::new(CSimBrokerConfig {
+            init_cash: 10_200.0,
+            ..CSimBrokerConfig::default()
+        });
+        broker.add_bars(
+            "A",
+            vec![
+                bar("2024/01/02", 10.0, 10.5, 9.8, 10.2, 1000.0),
+                bar("2024/01/03", 10.3, 10.8, 10.1, 10.6, 1000.0),
+                bar("2024/01/04", 10.6, 11.0, 10.4, 10.9, 1000.0),
+            ],
+        );
+        broker.step();
+        // 按最新价 10.2 刚好够买 1000 股
+        broker.place_order("A", true, 1000.0, None).unwrap();
+
+        // 开盘 10.3 只够买 900 股
+        broker.step();
+        let order = &broker.list_orders(None).unwrap()[0];
+        assert_eq!(order.status, BrokerOrderStatus::PartFilled);
+        assert_eq!(order.filled_volume, 900.0);
+        assert!((broker.cash - (10_200.0 - 9270.0)).abs() < 1e-9);
+
+        // 剩余资金不够一手，不再成交，资金不会变成负数
+        broker.step();
+        assert_eq!(broker.list_orders(None).unwrap()[0].filled_volume, 900.0);
+        assert!(broker.cash >= 0.0);
+    }
+
+    #[test]
+    fn test_zero_volume_bar() {
+        let mut broker = broker(0, Some(0.5));
+        broker.unlock("pwd").unwrap();
+        broker.add_bars(
+            "A",
+            vec![
+                bar("2024/01/02", 10.0, 10.5, 9.8, 10.2, 1000.0),
+                bar("2024/01/03", 10.3, 10.3, 10.3, 10.3, 0.0),
+                bar("2024/01/04", 10.6, 11.0, 10.4, 10.9, 1000.0),
+            ],
+        );
+        broker.step();
+        broker.place_order("A", true, 200.0, None).unwrap();
+
+        // 成交量为0的K线不成交，委托保持挂单
+        broker.step();
+        let order = &broker.list_orders(Some("A")).unwrap()[0];
+        assert_eq!(order.status, BrokerOrderStatus::Submitted);
+        assert_eq!(order.filled_volume, 0.0);
+
+        broker.step();
+        let order = &broker.list_orders(Some("A")).unwrap()[0];
+        assert_eq!(order.status, BrokerOrderStatus::Filled);
+        assert_eq!(order.avg_fill_price, 10.6);
+    }
+}
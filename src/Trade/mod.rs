@@ -0,0 +1,4 @@
+pub mod BrokerGateway;
+pub mod PaperLedger;
+pub mod Portfolio;
+pub mod SimBroker;
@@ -1,12 +1,23 @@
 use crate::BuySellPoint::BSPointConfig::CPointConfig;
+use crate::BuySellPoint::Divergence::CDivergenceReport;
 use crate::Common::func_util::has_overlap;
-use crate::Common::types::{LineType, SharedCell};
+use crate::Common::types::{ChanLine, LineType, SharedCell};
+use crate::Common::CEnum::ZsLevelUpReason;
 use crate::Common::ChanException::{CChanException, ErrCode};
 use crate::KLine::KLine_Unit::CKLineUnit;
 use crate::Seg::Seg::CSeg;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+// 中枢升级记录，sub_zs_lst 为升级时参与合并的子中枢
+#[derive(Clone)]
+pub struct CZSLevelUp {
+    pub reason: ZsLevelUpReason,
+    pub sub_zs_lst: Vec<SharedCell<CZS>>,
+    pub klu: Option<SharedCell<CKLineUnit>>,
+    pub bi_cnt: i32,
+}
+
 pub struct CZS {
     pub is_sure: bool,
     pub sub_zs_lst: Vec<SharedCell<CZS>>,
@@ -22,9 +33,14 @@ pub struct CZS {
     pub bi_in: Option<LineType>,
     pub bi_out: Option<LineType>,
     pub bi_lst: Vec<LineType>,
+    pub ext_cnt: i32,
+    pub level_up_lst: Vec<CZSLevelUp>,
 }
 
 impl CZS {
+    // 中枢延伸到9笔即升级
+    pub const LEVEL_UP_BI_CNT: i32 = 9;
+
     pub fn new(lst: Option<Vec<LineType>>, is_sure: bool) -> Self {
         let mut zs = CZS {
             is_sure,
@@ -41,6 +57,8 @@ impl CZS {
             bi_in: None,
             bi_out: None,
             bi_lst: Vec::new(),
+            ext_cnt: 0,
+            level_up_lst: Vec::new(),
         };
 
         if let Some(lst) = lst {
@@ -91,9 +109,46 @@ impl CZS {
         if item._high() > self.peak_high {
             self.peak_high = item._high();
         }
+        self.update_extension(self.get_bi_cnt());
         self.clean_cache();
     }
 
+    pub fn get_bi_cnt(&self) -> i32 {
+        match (&self.begin_bi, &self.end_bi) {
+            (Some(begin_bi), Some(end_bi)) => end_bi.idx() - begin_bi.idx() + 1,
+            _ => 0,
+        }
+    }
+
+    // 超出构成中枢的3笔之后的每一笔都算一次延伸
+    fn update_extension(&mut self, bi_cnt: i32) {
+        self.ext_cnt = (bi_cnt - 3).max(0);
+        if bi_cnt >= Self::LEVEL_UP_BI_CNT && !self.has_level_up(ZsLevelUpReason::NINE_BI) {
+            self.level_up_lst.push(CZSLevelUp {
+                reason: ZsLevelUpReason::NINE_BI,
+                sub_zs_lst: self.sub_zs_lst.clone(),
+                klu: self.end.clone(),
+                bi_cnt,
+            });
+        }
+    }
+
+    pub fn has_level_up(&self, reason: ZsLevelUpReason) -> bool {
+        self.level_up_lst.iter().any(|e| e.reason == reason)
+    }
+
+    pub fn is_level_up(&self) -> bool {
+        !self.level_up_lst.is_empty()
+    }
+
+    pub fn level_up2str(&self) -> String {
+        self.level_up_lst
+            .iter()
+            .map(|e| e.reason.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
     pub fn combine(&mut self, zs2: &CZS, combine_mode: &str) -> Result<bool, CChanException> {
         if zs2.is_one_bi_zs() {
             return Ok(false);
@@ -131,6 +186,15 @@ impl CZS {
     }
 
     pub fn do_combine(&mut self, zs2: &CZS) {
+        // 中枢区间不重叠、仅波动区间重叠才是中枢扩展；中枢区间重叠的合并只是延伸
+        let is_expansion = !has_overlap(self.low, self.high, zs2.low, zs2.high, true)
+            && has_overlap(
+                self.peak_low,
+                self.peak_high,
+                zs2.peak_low,
+                zs2.peak_high,
+                true,
+            );
         if self.sub_zs_lst.is_empty() {
             self.sub_zs_lst
                 .push(Rc::new(RefCell::new(self.make_copy())));
@@ -144,6 +208,15 @@ impl CZS {
         self.end = zs2.end.clone();
         self.bi_out = zs2.bi_out.clone();
         self.end_bi = zs2.end_bi.clone();
+        self.update_extension(self.get_bi_cnt());
+        if is_expansion {
+            self.level_up_lst.push(CZSLevelUp {
+                reason: ZsLevelUpReason::EXPANSION,
+                sub_zs_lst: self.sub_zs_lst.clone(),
+                klu: self.end.clone(),
+                bi_cnt: self.get_bi_cnt(),
+            });
+        }
         self.clean_cache();
     }
 
@@ -162,9 +235,9 @@ impl CZS {
         has_overlap(self.low, self.high, item._low(), item._high(), false)
     }
 
-    pub fn is_inside<T>(&self, seg: &CSeg<T>) -> bool {
-        seg.start_bi.borrow().idx <= self.begin_bi.as_ref().unwrap().idx()
-            && self.begin_bi.as_ref().unwrap().idx() <= seg.end_bi.borrow().idx
+    pub fn is_inside<T: ChanLine>(&self, seg: &CSeg<T>) -> bool {
+        seg.start_bi.borrow().idx() <= self.begin_bi.as_ref().unwrap().idx()
+            && self.begin_bi.as_ref().unwrap().idx() <= seg.end_bi.borrow().idx()
     }
 
     pub fn is_divergence(
@@ -172,25 +245,25 @@ impl CZS {
         config: &CPointConfig,
         out_bi: Option<&LineType>,
     ) -> (bool, Option<f64>) {
-        if !self.end_bi_break(out_bi) {
+        let report = self.divergence_report(config, out_bi);
+        if !report.end_bi_break {
             return (false, None);
         }
-        let in_metric = self.get_bi_in().cal_macd_metric(config.macd_algo, false);
-        let out_metric = if let Some(out_bi) = out_bi {
-            out_bi.cal_macd_metric(config.macd_algo, true)
-        } else {
-            self.get_bi_out().cal_macd_metric(config.macd_algo, true)
-        };
+        (report.is_diver, report.ratio)
+    }
 
-        let ratio = out_metric / in_metric;
-        if config.divergence_rate > 100.0 {
-            (true, Some(ratio))
-        } else {
-            (
-                out_metric <= config.divergence_rate * in_metric,
-                Some(ratio),
-            )
-        }
+    pub fn divergence_report(
+        &self,
+        config: &CPointConfig,
+        out_bi: Option<&LineType>,
+    ) -> CDivergenceReport {
+        let out_bi = out_bi.unwrap_or_else(|| self.get_bi_out());
+        CDivergenceReport::new(
+            self.get_bi_in(),
+            out_bi,
+            config,
+            self.end_bi_break(Some(out_bi)),
+        )
     }
 
     pub fn init_from_zs(&mut self, zs: &CZS) {
@@ -204,6 +277,8 @@ impl CZS {
         self.end_bi = zs.end_bi.clone();
         self.bi_in = zs.bi_in.clone();
         self.bi_out = zs.bi_out.clone();
+        self.ext_cnt = zs.ext_cnt;
+        self.level_up_lst = zs.level_up_lst.clone();
     }
 
     pub fn make_copy(&self) -> CZS {
@@ -286,3 +361,54 @@ impl std::fmt::Display for CZS {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zs(low: f64, high: f64, peak_low: f64, peak_high: f64) -> CZS {
+        let mut zs = CZS::new(None, true);
+        zs.low = low;
+        zs.high = high;
+        zs.peak_low = peak_low;
+        zs.peak_high = peak_high;
+        zs
+    }
+
+    #[test]
+    fn test_extension_and_nine_bi_level_up() {
+        let mut zs = zs(10.0, 12.0, 9.0, 13.0);
+        for bi_cnt in 3..CZS::LEVEL_UP_BI_CNT {
+            zs.update_extension(bi_cnt);
+            assert_eq!(zs.ext_cnt, bi_cnt - 3);
+            assert!(!zs.is_level_up());
+        }
+        zs.update_extension(CZS::LEVEL_UP_BI_CNT);
+        assert_eq!(zs.ext_cnt, 6);
+        assert!(zs.has_level_up(ZsLevelUpReason::NINE_BI));
+        assert_eq!(zs.level_up_lst[0].bi_cnt, 9);
+        // 继续延伸不重复记录
+        zs.update_extension(11);
+        assert_eq!(zs.ext_cnt, 8);
+        assert_eq!(zs.level_up_lst.len(), 1);
+        assert_eq!(zs.level_up2str(), "NINE_BI");
+    }
+
+    #[test]
+    fn test_do_combine_expansion() {
+        // 中枢区间重叠：合并但不是扩展
+        let mut zs1 = zs(10.0, 12.0, 9.0, 13.0);
+        zs1.do_combine(&zs(11.0, 13.0, 10.5, 14.0));
+        assert!(!zs1.has_level_up(ZsLevelUpReason::EXPANSION));
+        assert_eq!((zs1.low, zs1.high), (10.0, 13.0));
+        assert_eq!((zs1.peak_low, zs1.peak_high), (9.0, 14.0));
+        assert_eq!(zs1.sub_zs_lst.len(), 2);
+
+        // 中枢区间不重叠、波动区间重叠：扩展
+        let mut zs2 = zs(10.0, 12.0, 9.0, 13.0);
+        zs2.do_combine(&zs(13.5, 15.0, 12.5, 16.0));
+        assert!(zs2.has_level_up(ZsLevelUpReason::EXPANSION));
+        assert_eq!(zs2.level_up_lst.len(), 1);
+        assert_eq!(zs2.level_up_lst[0].sub_zs_lst.len(), 2);
+    }
+}
@@ -1,19 +1,17 @@
-use crate::Bi::Bi::CBi;
-use crate::Bi::BiList::CBiList;
 use crate::Common::func_util::revert_BiDir;
-use crate::Common::types::SharedCell;
+use crate::Common::types::{ChanLine, LineType, SharedCell};
+use crate::Common::CEnum::BiDir;
 use crate::Seg::Seg::CSeg;
 use crate::Seg::SegListChan::CSegListChan;
 use crate::ZS::ZSConfig::CZSConfig;
 use crate::ZS::ZS::CZS;
-use std::any::Any;
 use std::cell::RefCell;
 use std::rc::Rc;
 
 pub struct CZSList {
     zs_lst: Vec<SharedCell<CZS>>,
     config: CZSConfig,
-    free_item_lst: Vec<SharedCell<dyn Any>>,
+    free_item_lst: Vec<LineType>,
     last_sure_pos: i32,
 }
 
@@ -27,29 +25,28 @@ impl CZSList {
         }
     }
 
-    pub fn update_last_pos<T>(&mut self, seg_list: &CSegListChan<T>) {
+    pub fn update_last_pos<T: ChanLine>(&mut self, seg_list: &CSegListChan<T>) {
         self.last_sure_pos = -1;
         for seg in seg_list.iter().rev() {
-            if seg.is_sure {
-                self.last_sure_pos = seg.start_bi.borrow().idx;
+            if seg.borrow().is_sure {
+                self.last_sure_pos = seg.borrow().start_bi.borrow().idx();
                 return;
             }
         }
     }
 
-    pub fn seg_need_cal<T>(&self, seg: &CSeg<T>) -> bool {
-        seg.start_bi.borrow().idx >= self.last_sure_pos
+    pub fn seg_need_cal<T: ChanLine>(&self, seg: &CSeg<T>) -> bool {
+        seg.start_bi.borrow().idx() >= self.last_sure_pos
     }
 
-    pub fn add_to_free_lst(&mut self, item: SharedCell<dyn Any>, is_sure: bool, zs_algo: &str) {
-        if !self.free_item_lst.is_empty()
-            && item.borrow().idx == self.free_item_lst.last().unwrap().borrow().idx
+    pub fn add_to_free_lst(&mut self, item: LineType, is_sure: bool, zs_algo: &str) {
+        if !self.free_item_lst.is_empty() && item.idx() == self.free_item_lst.last().unwrap().idx()
         {
             self.free_item_lst.pop();
         }
-        self.free_item_lst.push(item.clone());
+        self.free_item_lst.push(item);
         if let Some(res) = self.try_construct_zs(&self.free_item_lst, is_sure, zs_algo) {
-            if res.begin_bi.borrow().idx > 0 {
+            if res.begin_bi.as_ref().unwrap().idx() > 0 {
                 self.zs_lst.push(Rc::new(RefCell::new(res)));
                 self.clear_free_lst();
                 self.try_combine();
@@ -61,7 +58,7 @@ impl CZSList {
         self.free_item_lst.clear();
     }
 
-    pub fn update(&mut self, bi: SharedCell<CBi>, is_sure: bool) {
+    pub fn update(&mut self, bi: LineType, is_sure: bool) {
         if self.free_item_lst.is_empty() && self.try_add_to_end(&bi) {
             self.try_combine();
             return;
@@ -69,7 +66,7 @@ impl CZSList {
         self.add_to_free_lst(bi, is_sure, "normal");
     }
 
-    pub fn try_add_to_end(&mut self, bi: &SharedCell<CBi>) -> bool {
+    pub fn try_add_to_end(&mut self, bi: &LineType) -> bool {
         if self.zs_lst.is_empty() {
             false
         } else {
@@ -81,32 +78,27 @@ impl CZSList {
         }
     }
 
-    pub fn add_zs_from_bi_range(
+    pub fn add_zs_from_bi_range<T: ChanLine>(
         &mut self,
-        seg_bi_lst: &[SharedCell<CBi>],
-        seg_dir: i32,
+        seg_bi_lst: &[SharedCell<T>],
+        seg_dir: BiDir,
         seg_is_sure: bool,
     ) {
         let mut deal_bi_cnt = 0;
         for bi in seg_bi_lst {
-            if bi.borrow().dir == seg_dir {
+            if bi.borrow().dir() == seg_dir {
                 continue;
             }
             if deal_bi_cnt < 1 {
-                self.add_to_free_lst(bi.clone(), seg_is_sure, "normal");
+                self.add_to_free_lst(T::to_line_type(bi), seg_is_sure, "normal");
                 deal_bi_cnt += 1;
             } else {
-                self.update(bi.clone(), seg_is_sure);
+                self.update(T::to_line_type(bi), seg_is_sure);
             }
         }
     }
 
-    pub fn try_construct_zs(
-        &self,
-        lst: &[SharedCell<dyn Any>],
-        is_sure: bool,
-        zs_algo: &str,
-    ) -> Option<CZS> {
+    pub fn try_construct_zs(&self, lst: &[LineType], is_sure: bool, zs_algo: &str) -> Option<CZS> {
         let lst = match zs_algo {
             "normal" => {
                 if !self.config.one_bi_zs {
@@ -124,7 +116,7 @@ impl CZSList {
                     return None;
                 }
                 let lst = &lst[lst.len() - 3..];
-                if lst[0].borrow().dir == lst[0].borrow().parent_seg.borrow().dir {
+                if lst[0].parent_seg_dir() == Some(lst[0].dir()) {
                     &lst[1..]
                 } else {
                     lst
@@ -135,12 +127,12 @@ impl CZSList {
 
         let min_high = lst
             .iter()
-            .map(|item| item.borrow()._high())
+            .map(|item| item._high())
             .min_by(|a, b| a.partial_cmp(b).unwrap())
             .unwrap();
         let max_low = lst
             .iter()
-            .map(|item| item.borrow()._low())
+            .map(|item| item._low())
             .max_by(|a, b| a.partial_cmp(b).unwrap())
             .unwrap();
 
@@ -151,9 +143,18 @@ impl CZSList {
         }
     }
 
-    pub fn cal_bi_zs<T>(&mut self, bi_lst: &dyn Any, seg_lst: &CSegListChan<T>) {
+    pub fn cal_bi_zs<T: ChanLine>(&mut self, bi_lst: &[SharedCell<T>], seg_lst: &CSegListChan<T>) {
         while !self.zs_lst.is_empty()
-            && self.zs_lst.last().unwrap().borrow().begin_bi.borrow().idx >= self.last_sure_pos
+            && self
+                .zs_lst
+                .last()
+                .unwrap()
+                .borrow()
+                .begin_bi
+                .as_ref()
+                .unwrap()
+                .idx()
+                >= self.last_sure_pos
         {
             self.zs_lst.pop();
         }
@@ -161,68 +162,63 @@ impl CZSList {
         match self.config.zs_algo.as_str() {
             "normal" => {
                 for seg in seg_lst.iter() {
-                    if !self.seg_need_cal(seg) {
+                    let seg = seg.borrow();
+                    if !self.seg_need_cal(&seg) {
                         continue;
                     }
                     self.clear_free_lst();
-                    let seg_bi_lst = bi_lst
-                        .downcast_ref::<CBiList>()
-                        .unwrap()
-                        .slice(seg.start_bi.borrow().idx, seg.end_bi.borrow().idx + 1);
-                    self.add_zs_from_bi_range(&seg_bi_lst, seg.dir, seg.is_sure);
+                    let seg_bi_lst = &bi_lst
+                        [seg.start_bi.borrow().idx() as usize..=seg.end_bi.borrow().idx() as usize];
+                    self.add_zs_from_bi_range(seg_bi_lst, seg.dir, seg.is_sure);
                 }
 
                 if !seg_lst.is_empty() {
                     self.clear_free_lst();
-                    let last_seg = seg_lst.last().unwrap();
-                    let remaining_bi_lst = bi_lst
-                        .downcast_ref::<CBiList>()
-                        .unwrap()
-                        .slice(last_seg.end_bi.borrow().idx + 1, bi_lst.len());
-                    self.add_zs_from_bi_range(&remaining_bi_lst, revert_BiDir(last_seg.dir), false);
+                    let last_seg = seg_lst.last().unwrap().borrow();
+                    let remaining_bi_lst = &bi_lst[last_seg.end_bi.borrow().idx() as usize + 1..];
+                    self.add_zs_from_bi_range(remaining_bi_lst, revert_BiDir(last_seg.dir), false);
                 }
             }
             "over_seg" => {
                 assert!(!self.config.one_bi_zs);
                 self.clear_free_lst();
                 let begin_bi_idx = if !self.zs_lst.is_empty() {
-                    self.zs_lst.last().unwrap().borrow().end_bi.borrow().idx + 1
+                    self.zs_lst
+                        .last()
+                        .unwrap()
+                        .borrow()
+                        .end_bi
+                        .as_ref()
+                        .unwrap()
+                        .idx()
+                        + 1
                 } else {
                     0
                 };
-                for bi in bi_lst
-                    .downcast_ref::<CBiList>()
-                    .unwrap()
-                    .slice(begin_bi_idx, bi_lst.len())
-                {
-                    self.update_overseg_zs(bi);
+                for bi in &bi_lst[begin_bi_idx as usize..] {
+                    self.update_overseg_zs(T::to_line_type(bi));
                 }
             }
             "auto" => {
                 let mut sure_seg_appear = false;
                 let exist_sure_seg = seg_lst.exist_sure_seg();
                 for seg in seg_lst.iter() {
+                    let seg = seg.borrow();
                     if seg.is_sure {
                         sure_seg_appear = true;
                     }
-                    if !self.seg_need_cal(seg) {
+                    if !self.seg_need_cal(&seg) {
                         continue;
                     }
                     if seg.is_sure || (!sure_seg_appear && exist_sure_seg) {
                         self.clear_free_lst();
-                        let seg_bi_lst = bi_lst
-                            .downcast_ref::<CBiList>()
-                            .unwrap()
-                            .slice(seg.start_bi.borrow().idx, seg.end_bi.borrow().idx + 1);
-                        self.add_zs_from_bi_range(&seg_bi_lst, seg.dir, seg.is_sure);
+                        let seg_bi_lst = &bi_lst[seg.start_bi.borrow().idx() as usize
+                            ..=seg.end_bi.borrow().idx() as usize];
+                        self.add_zs_from_bi_range(seg_bi_lst, seg.dir, seg.is_sure);
                     } else {
                         self.clear_free_lst();
-                        for bi in bi_lst
-                            .downcast_ref::<CBiList>()
-                            .unwrap()
-                            .slice(seg.start_bi.borrow().idx, bi_lst.len())
-                        {
-                            self.update_overseg_zs(bi);
+                        for bi in &bi_lst[seg.start_bi.borrow().idx() as usize..] {
+                            self.update_overseg_zs(T::to_line_type(bi));
                         }
                         break;
                     }
@@ -234,17 +230,16 @@ impl CZSList {
         self.update_last_pos(seg_lst);
     }
 
-    pub fn update_overseg_zs(&mut self, bi: SharedCell<dyn Any>) {
+    pub fn update_overseg_zs(&mut self, bi: LineType) {
         if !self.zs_lst.is_empty() && self.free_item_lst.is_empty() {
-            if bi.borrow().next.is_none() {
-                return;
-            }
+            let next = match bi.next_line() {
+                Some(next) => next,
+                None => return,
+            };
             let last_zs = self.zs_lst.last().unwrap();
-            if bi.borrow().idx - last_zs.borrow().end_bi.borrow().idx <= 1
-                && last_zs
-                    .borrow()
-                    .in_range(&bi.borrow().next.as_ref().unwrap())
-                && last_zs.borrow_mut().try_add_to_end(bi)
+            if bi.idx() - last_zs.borrow().end_bi.as_ref().unwrap().idx() <= 1
+                && last_zs.borrow().in_range(&next.borrow())
+                && last_zs.borrow_mut().try_add_to_end(&bi)
             {
                 return;
             }
@@ -252,11 +247,22 @@ impl CZSList {
         if !self.zs_lst.is_empty()
             && self.free_item_lst.is_empty()
             && self.zs_lst.last().unwrap().borrow().in_range(&bi)
-            && bi.borrow().idx - self.zs_lst.last().unwrap().borrow().end_bi.borrow().idx <= 1
+            && bi.idx()
+                - self
+                    .zs_lst
+                    .last()
+                    .unwrap()
+                    .borrow()
+                    .end_bi
+                    .as_ref()
+                    .unwrap()
+                    .idx()
+                <= 1
         {
             return;
         }
-        self.add_to_free_lst(bi, bi.borrow().is_sure, "over_seg");
+        let is_sure = bi.is_sure();
+        self.add_to_free_lst(bi, is_sure, "over_seg");
     }
 
     pub fn try_combine(&mut self) {
@@ -1,3 +1,4 @@
+pub mod Backtest;
 pub mod Bi;
 pub mod BuySellPoint;
 pub mod Chan;
@@ -8,5 +9,7 @@ pub mod Common;
 pub mod DataAPI;
 pub mod KLine;
 pub mod Math;
+pub mod Screener;
 pub mod Seg;
+pub mod Trade;
 pub mod ZS;